@@ -0,0 +1 @@
+ALTER TABLE queue DROP COLUMN next_attempt_at;
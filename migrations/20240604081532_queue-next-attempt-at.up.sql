@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN next_attempt_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,6 @@
+CREATE TABLE crate_aliases (
+    name TEXT PRIMARY KEY,
+    target TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (name <> target)
+);
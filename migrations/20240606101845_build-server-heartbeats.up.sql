@@ -0,0 +1,4 @@
+CREATE TABLE build_servers (
+    hostname TEXT PRIMARY KEY,
+    last_heartbeat TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1 @@
+DROP TABLE sandbox_target_overrides;
@@ -0,0 +1,6 @@
+CREATE TABLE sandbox_target_overrides (
+    crate_name VARCHAR NOT NULL REFERENCES sandbox_overrides(crate_name) ON DELETE CASCADE,
+    target TEXT NOT NULL,
+    timeout_seconds INTEGER NOT NULL,
+    PRIMARY KEY (crate_name, target)
+);
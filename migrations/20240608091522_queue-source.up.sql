@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN source TEXT;
@@ -0,0 +1,6 @@
+CREATE TABLE archive_index_rebuilds (
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    queued_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (name, version)
+);
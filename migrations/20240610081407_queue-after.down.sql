@@ -0,0 +1,3 @@
+ALTER TABLE queue
+    DROP COLUMN after_name,
+    DROP COLUMN after_version;
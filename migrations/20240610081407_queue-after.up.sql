@@ -0,0 +1,3 @@
+ALTER TABLE queue
+    ADD COLUMN after_name TEXT,
+    ADD COLUMN after_version TEXT;
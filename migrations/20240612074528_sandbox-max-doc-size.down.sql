@@ -0,0 +1 @@
+ALTER TABLE sandbox_overrides DROP COLUMN max_doc_size_bytes;
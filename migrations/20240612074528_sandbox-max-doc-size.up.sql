@@ -0,0 +1 @@
+ALTER TABLE sandbox_overrides ADD COLUMN max_doc_size_bytes BIGINT;
@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN allow_blacklisted BOOLEAN NOT NULL DEFAULT FALSE;
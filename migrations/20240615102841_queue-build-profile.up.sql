@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN build_profile TEXT;
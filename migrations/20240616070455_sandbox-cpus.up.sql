@@ -0,0 +1 @@
+ALTER TABLE sandbox_overrides ADD COLUMN cpus INTEGER;
@@ -0,0 +1,6 @@
+CREATE TABLE web_rebuild_triggers (
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    triggered_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (name, version)
+);
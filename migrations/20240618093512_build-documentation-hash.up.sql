@@ -0,0 +1 @@
+ALTER TABLE builds ADD COLUMN documentation_hash TEXT;
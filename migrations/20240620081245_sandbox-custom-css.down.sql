@@ -0,0 +1 @@
+ALTER TABLE sandbox_overrides DROP COLUMN custom_css;
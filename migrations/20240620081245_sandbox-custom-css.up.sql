@@ -0,0 +1 @@
+ALTER TABLE sandbox_overrides ADD COLUMN custom_css TEXT;
@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN scheduled_at TIMESTAMP WITH TIME ZONE;
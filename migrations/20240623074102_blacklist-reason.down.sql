@@ -0,0 +1,3 @@
+ALTER TABLE blacklisted_crates
+    DROP COLUMN reason,
+    DROP COLUMN added_at;
@@ -0,0 +1,6 @@
+ALTER TABLE blacklisted_crates
+    ADD COLUMN reason TEXT,
+    ADD COLUMN added_at TIMESTAMP WITH TIME ZONE;
+
+-- existing entries keep an unknown timestamp
+ALTER TABLE blacklisted_crates ALTER COLUMN added_at SET DEFAULT NOW();
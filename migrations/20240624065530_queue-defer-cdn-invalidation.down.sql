@@ -0,0 +1 @@
+ALTER TABLE queue DROP COLUMN defer_cdn_invalidation;
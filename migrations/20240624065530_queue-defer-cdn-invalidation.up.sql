@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN defer_cdn_invalidation BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN queued_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
@@ -0,0 +1,3 @@
+-- taken from the `builds` id sequence, so it can be compared with build ids when picking
+-- the key of the locally cached archive indexes.
+ALTER TABLE releases ADD COLUMN archive_index_regeneration INTEGER;
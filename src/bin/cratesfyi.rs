@@ -1,42 +1,61 @@
+use std::collections::HashSet;
 use std::fmt::Write;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 use std::{env, fs};
 
 use anyhow::{anyhow, Context as _, Error, Result};
 use axum::async_trait;
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand, ValueEnum};
-use docs_rs::cdn::CdnBackend;
+use docs_rs::cdn::{self, CdnBackend};
 use docs_rs::db::{self, add_path_into_database, Overrides, Pool, PoolClient};
 use docs_rs::repositories::RepositoryStatsUpdater;
-use docs_rs::storage::{rustdoc_archive_path, source_archive_path, PathNotFoundError};
+use docs_rs::storage::{
+    migrate_storage, rustdoc_archive_path, source_archive_path, PathNotFoundError, StorageKind,
+};
 use docs_rs::utils::{
-    get_config, get_crate_pattern_and_priority, list_crate_priorities, queue_builder,
+    check_config, find_priority_overlaps, get_config, get_crate_pattern_and_priority, index_drift,
+    list_crate_priorities, queue_builder, regenerate_archive_index, regenerate_release_indexes,
     remove_crate_priority, set_config, set_crate_priority, spawn_blocking, ConfigName,
 };
 use docs_rs::{
-    start_background_metrics_webserver, start_web_server, AsyncStorage, BuildQueue, Config,
-    Context, Index, InstanceMetrics, PackageKind, RegistryApi, RustwideBuilder, ServiceMetrics,
-    Storage,
+    apply_docsrs_theme, lint_release_metadata, parse_release_list, resolve_local_metadata,
+    resolve_release_metadata, start_background_metrics_webserver, start_web_server,
+    unpack_crate_file, write_sitemap, AsyncStorage, BuildErrorCategory, BuildQueue, Config,
+    Context, Index, InstanceMetrics, MetadataProblem, PackageKind, QueueOptions,
+    QueueSnapshotEntry, QueueSource, RegistryApi, RustwideBuilder, ServiceMetrics, Storage,
+    TlsConfig,
 };
-use futures_util::StreamExt;
+use futures_util::{future::BoxFuture, FutureExt as _, StreamExt};
 use humantime::Duration;
 use once_cell::sync::OnceCell;
 use rusqlite::{Connection, OpenFlags};
 use sentry::TransactionContext;
+use serde::Serialize;
 use tokio::runtime::{Builder, Runtime};
 use tracing_log::LogTracer;
-use tracing_subscriber::{filter::Directive, prelude::*, EnvFilter};
+use tracing_subscriber::{filter::Directive, fmt::writer::BoxMakeWriter, prelude::*, EnvFilter};
 
 fn main() {
     // set the global log::logger for backwards compatibility
     // through rustwide.
     rustwide::logging::init_with(LogTracer::new());
 
+    let command = CommandLine::parse();
+
+    // commands that print output to be piped log to stderr instead
+    let log_writer = if command.logs_to_stderr() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
     let tracing_registry = tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(log_writer))
         .with(
             EnvFilter::builder()
                 .with_default_directive(Directive::from_str("docs_rs=info").unwrap())
@@ -92,7 +111,7 @@ fn main() {
         None
     };
 
-    if let Err(err) = CommandLine::parse().handle_args() {
+    if let Err(err) = command.handle_args() {
         let mut msg = format!("Error: {err}");
         for cause in err.chain() {
             write!(msg, "\n\nCaused by:\n    {cause}").unwrap();
@@ -119,6 +138,38 @@ enum Toggle {
     Disabled,
 }
 
+/// How commands print the records they list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
+/// Prints `items` in the given format, `row` formats a single item as a table row.
+fn print_formatted<T: Serialize>(
+    format: OutputFormat,
+    items: &[T],
+    row: impl Fn(&T) -> String,
+) -> Result<()> {
+    match format {
+        OutputFormat::Table => {
+            for item in items {
+                println!("{}", row(item));
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(items)?),
+    }
+    Ok(())
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".into())
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Parser)]
 #[command(
     about = env!("CARGO_PKG_DESCRIPTION"),
@@ -135,6 +186,14 @@ enum CommandLine {
     StartWebServer {
         #[arg(name = "SOCKET_ADDR", default_value = "0.0.0.0:3000")]
         socket_addr: SocketAddr,
+
+        /// Path to a PEM encoded certificate chain, serves HTTPS instead of HTTP when given
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+
+        /// Path to the PEM encoded private key for `--tls-cert`
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
     },
 
     StartRegistryWatcher {
@@ -149,6 +208,14 @@ enum CommandLine {
         repository_stats_updater: Toggle,
         #[arg(long = "cdn-invalidator", default_value = "enabled", value_enum)]
         cdn_invalidator: Toggle,
+        /// Store the last seen index reference after every N processed changes instead of
+        /// once per check, so a restart resumes closer to where the watcher stopped
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        batch_size: Option<u64>,
+        /// Queue up to N added releases in one transaction instead of one transaction per
+        /// release
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        enqueue_batch_size: Option<u64>,
     },
 
     StartBuildServer {
@@ -174,9 +241,27 @@ enum CommandLine {
         #[command(subcommand)]
         subcommand: QueueSubcommand,
     },
+
+    /// Loads the configuration and checks that the database, the storage
+    /// and the registry are reachable
+    CheckConfig,
 }
 
 impl CommandLine {
+    /// Whether the command prints its result to stdout to be piped, like
+    /// `build crate --json`.
+    fn logs_to_stderr(&self) -> bool {
+        match self {
+            Self::Build {
+                subcommand: BuildSubcommand::Crate { json, .. },
+            } => *json,
+            Self::Database {
+                subcommand: DatabaseSubcommand::StorageCat { out, .. },
+            } => out.is_none(),
+            _ => false,
+        }
+    }
+
     fn handle_args(self) -> Result<()> {
         let ctx = BinContext::new();
 
@@ -186,6 +271,8 @@ impl CommandLine {
                 metric_server_socket_addr,
                 repository_stats_updater,
                 cdn_invalidator,
+                batch_size,
+                enqueue_batch_size,
             } => {
                 if repository_stats_updater == Toggle::Enabled {
                     docs_rs::utils::daemon::start_background_repository_stats_updater(&ctx)?;
@@ -196,7 +283,13 @@ impl CommandLine {
 
                 start_background_metrics_webserver(Some(metric_server_socket_addr), &ctx)?;
 
-                docs_rs::utils::watch_registry(ctx.build_queue()?, ctx.config()?, ctx.index()?)?;
+                docs_rs::utils::watch_registry(
+                    ctx.build_queue()?,
+                    ctx.config()?,
+                    ctx.index()?,
+                    batch_size.map(|size| size as usize),
+                    enqueue_batch_size.map(|size| size as usize),
+                )?;
             }
             Self::StartBuildServer {
                 metric_server_socket_addr,
@@ -208,15 +301,37 @@ impl CommandLine {
                 let rustwide_builder = RustwideBuilder::init(&ctx)?;
                 queue_builder(&ctx, rustwide_builder, build_queue, config)?;
             }
-            Self::StartWebServer { socket_addr } => {
+            Self::StartWebServer {
+                socket_addr,
+                tls_cert,
+                tls_key,
+            } => {
+                let tls = tls_cert
+                    .zip(tls_key)
+                    .map(|(cert, key)| TlsConfig { cert, key });
+
                 // Blocks indefinitely
-                start_web_server(Some(socket_addr), &ctx)?;
+                start_web_server(Some(socket_addr), tls, &ctx)?;
             }
             Self::Daemon { registry_watcher } => {
                 docs_rs::utils::start_daemon(ctx, registry_watcher == Toggle::Enabled)?;
             }
             Self::Database { subcommand } => subcommand.handle_args(ctx)?,
             Self::Queue { subcommand } => subcommand.handle_args(ctx)?,
+            Self::CheckConfig => {
+                let checks = check_config(&ctx);
+                for check in &checks {
+                    match &check.result {
+                        Ok(()) => println!("{:15} ok", check.name),
+                        Err(err) => println!("{:15} FAILED: {err:#}", check.name),
+                    }
+                }
+
+                let failed = checks.iter().filter(|check| check.result.is_err()).count();
+                if failed > 0 {
+                    anyhow::bail!("{failed} configuration checks failed");
+                }
+            }
         }
 
         Ok(())
@@ -228,11 +343,11 @@ enum QueueSubcommand {
     /// Add a crate to the build queue
     Add {
         /// Name of crate to build
-        #[arg(name = "CRATE_NAME")]
-        crate_name: String,
+        #[arg(name = "CRATE_NAME", required_unless_present = "stdin")]
+        crate_name: Option<String>,
         /// Version of crate to build
-        #[arg(name = "CRATE_VERSION")]
-        crate_version: String,
+        #[arg(name = "CRATE_VERSION", required_unless_present = "stdin")]
+        crate_version: Option<String>,
         /// Priority of build (new crate builds get priority 0)
         #[arg(
             name = "BUILD_PRIORITY",
@@ -242,6 +357,164 @@ enum QueueSubcommand {
             allow_negative_numbers = true
         )]
         build_priority: i32,
+        /// URL that gets a JSON POST request when the build finished
+        #[arg(long)]
+        notify: Option<String>,
+        /// Why the crate is queued (registry-watcher, manual, rebuild, consistency-check or
+        /// web-trigger)
+        #[arg(long, default_value = "manual")]
+        source: QueueSource,
+        /// Queue the release like a rebuild triggered through the web, which is rejected
+        /// when the last one was less than `DOCSRS_WEB_REBUILD_TRIGGER_INTERVAL` ago
+        #[arg(long, conflicts_with = "source")]
+        web_trigger_dedup: bool,
+        /// Build the crate with this toolchain instead of the configured one
+        #[arg(long)]
+        force_toolchain: Option<String>,
+        /// Only build the crate after this queued release finished building
+        #[arg(long, num_args = 2, value_names = ["CRATE_NAME", "CRATE_VERSION"])]
+        after: Option<Vec<String>>,
+        /// Build the release even if the crate is on the blacklist
+        #[arg(long)]
+        allow_blacklisted: bool,
+        /// Build the release with this build profile from `DOCSRS_BUILD_PROFILES`
+        #[arg(long)]
+        build_env_profile: Option<String>,
+        /// Don't build the release before this time, either a RFC 3339 timestamp or a
+        /// duration from now like `6h`
+        #[arg(long, value_parser = parse_schedule_at)]
+        schedule_at: Option<DateTime<Utc>>,
+        /// Don't invalidate the CDN after the build, but together with all other deferred
+        /// invalidations once they were built
+        #[arg(long)]
+        defer_cdn_invalidation: bool,
+        /// Don't queue the release again when a request with this key was already queued,
+        /// for callers that retry or deliver requests more than once
+        #[arg(long, value_name = "KEY", conflicts_with = "web_trigger_dedup")]
+        idempotency_key: Option<String>,
+        /// Read the releases to add from stdin instead, in the format of `add-from-file`
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "CRATE_NAME",
+                "notify",
+                "web_trigger_dedup",
+                "force_toolchain",
+                "after",
+                "allow_blacklisted",
+                "build_env_profile",
+                "schedule_at",
+                "idempotency_key",
+            ]
+        )]
+        stdin: bool,
+        /// With `--stdin`, abort at the first malformed line or failed addition instead of
+        /// skipping it. Nothing is queued then.
+        #[arg(long, requires = "stdin")]
+        fail_fast: bool,
+    },
+
+    /// Add all published versions of a crate to the build queue
+    AddAll {
+        /// Name of crate to build
+        #[arg(name = "CRATE_NAME")]
+        crate_name: String,
+        /// Priority of the builds
+        #[arg(
+            name = "BUILD_PRIORITY",
+            short = 'p',
+            long = "priority",
+            default_value = "5",
+            allow_negative_numbers = true
+        )]
+        build_priority: i32,
+        /// Skip versions that already have a successful build
+        #[arg(long)]
+        only_missing: bool,
+    },
+
+    /// Add the releases listed in a file to the build queue
+    ///
+    /// (one `CRATE_NAME CRATE_VERSION [PRIORITY]` per line, empty lines and lines starting
+    /// with `#` are ignored)
+    AddFromFile {
+        /// Path of the file to read
+        #[arg(name = "PATH")]
+        path: PathBuf,
+        /// Priority of the builds that don't have their own
+        #[arg(
+            name = "BUILD_PRIORITY",
+            short = 'p',
+            long = "priority",
+            default_value = "5",
+            allow_negative_numbers = true
+        )]
+        build_priority: i32,
+        /// Abort at the first malformed line or failed addition instead of skipping it.
+        /// Nothing is queued then.
+        #[arg(long)]
+        fail_fast: bool,
+        /// Invalidate the CDN only once, after all releases were built
+        #[arg(long)]
+        defer_cdn_invalidation: bool,
+    },
+
+    /// List all queued crates, including the ones that ran out of build attempts
+    List {
+        /// Only list the releases that are likely stuck, because they are waiting for longer
+        /// than `--older-than` or failed at least `--min-attempts` times
+        #[arg(long)]
+        stuck: bool,
+
+        /// How long a release can wait before it's listed by `--stuck`
+        #[arg(long, default_value = "6h", requires = "stuck")]
+        older_than: Duration,
+
+        /// How many failed attempts a release can have before it's listed by `--stuck`
+        #[arg(long, default_value = "2", requires = "stuck")]
+        min_attempts: i32,
+
+        /// Print the stuck releases as JSON
+        #[arg(long, requires = "stuck")]
+        json: bool,
+    },
+
+    /// Show the crate that would be built next, without removing it from the queue
+    Peek,
+
+    /// Write all rows of the build queue to a JSON file
+    Export {
+        /// Path of the file to write
+        #[arg(name = "PATH")]
+        path: PathBuf,
+    },
+
+    /// Restore the build queue from a file created by `queue export`
+    ///
+    /// (releases that are already queued are skipped)
+    Import {
+        /// Path of the file to read
+        #[arg(name = "PATH")]
+        path: PathBuf,
+    },
+
+    /// Queue rebuilds for releases whose latest successful build used an older toolchain
+    /// than the currently installed one
+    RebuildOutdatedToolchain {
+        /// Priority of the queued rebuilds
+        #[arg(
+            short = 'p',
+            long = "priority",
+            default_value = "20",
+            allow_negative_numbers = true
+        )]
+        priority: i32,
+        /// Maximum number of releases to queue
+        #[arg(long)]
+        limit: Option<i64>,
+        /// Invalidate the CDN only once, after all rebuilds finished
+        #[arg(long)]
+        defer_cdn_invalidation: bool,
     },
 
     /// Interactions with build queue priorities
@@ -250,6 +523,12 @@ enum QueueSubcommand {
         subcommand: PrioritySubcommand,
     },
 
+    /// Inspect and requeue releases that ran out of build attempts
+    DeadLetter {
+        #[command(subcommand)]
+        subcommand: DeadLetterSubcommand,
+    },
+
     /// Get the registry watcher's last seen reference
     GetLastSeenReference,
 
@@ -273,12 +552,250 @@ impl QueueSubcommand {
                 crate_name,
                 crate_version,
                 build_priority,
-            } => ctx.build_queue()?.add_crate(
-                &crate_name,
-                &crate_version,
+                notify,
+                source,
+                force_toolchain,
+                after,
+                allow_blacklisted,
+                build_env_profile,
+                schedule_at,
+                defer_cdn_invalidation,
+                web_trigger_dedup,
+                idempotency_key,
+                stdin,
+                fail_fast,
+            } => {
+                if stdin {
+                    let content = std::io::read_to_string(std::io::stdin())
+                        .context("failed to read stdin")?;
+                    return queue_release_list(
+                        &ctx,
+                        "stdin",
+                        &content,
+                        build_priority,
+                        source,
+                        fail_fast,
+                        defer_cdn_invalidation,
+                    );
+                }
+                let (Some(crate_name), Some(crate_version)) = (crate_name, crate_version) else {
+                    anyhow::bail!("CRATE_NAME and CRATE_VERSION are required without --stdin");
+                };
+
+                let build_queue = ctx.build_queue()?;
+                let source = if web_trigger_dedup {
+                    if let Some(last_trigger) =
+                        build_queue.record_web_trigger(&crate_name, &crate_version)?
+                    {
+                        anyhow::bail!(
+                            "{crate_name}-{crate_version} was already triggered at {last_trigger}, \
+                             not queueing it again"
+                        );
+                    }
+                    QueueSource::WebTrigger
+                } else {
+                    source
+                };
+                let registry = ctx.config()?.registry_url.clone();
+                let options = QueueOptions {
+                    scheduled_at: schedule_at,
+                    build_after: after
+                        .and_then(|after| <[String; 2]>::try_from(after).ok())
+                        .map(|[name, version]| (name, version)),
+                    toolchain: force_toolchain,
+                    allow_blacklisted,
+                    build_profile: build_env_profile,
+                    notify_url: notify,
+                };
+                if let Some(key) = &idempotency_key {
+                    if let Some(recorded) = build_queue.add_crate_with_idempotency_key(
+                        key,
+                        &crate_name,
+                        &crate_version,
+                        build_priority,
+                        registry.as_deref(),
+                        source,
+                        &options,
+                    )? {
+                        if (recorded.name.as_str(), recorded.version.as_str())
+                            != (crate_name.as_str(), crate_version.as_str())
+                        {
+                            anyhow::bail!(
+                                "idempotency key {key} was already used for {}-{}",
+                                recorded.name,
+                                recorded.version
+                            );
+                        }
+                        let state = if build_queue.has_build_queued(&crate_name, &crate_version)? {
+                            "still queued"
+                        } else {
+                            "not queued anymore"
+                        };
+                        println!(
+                            "{crate_name}-{crate_version} was already queued with key {key} at {}, \
+                             it's {state}",
+                            recorded.created_at
+                        );
+                        return Ok(());
+                    }
+                } else {
+                    build_queue.add_crate_with_options(
+                        &crate_name,
+                        &crate_version,
+                        build_priority,
+                        registry.as_deref(),
+                        source,
+                        &options,
+                    )?;
+                }
+
+                if defer_cdn_invalidation {
+                    build_queue.set_defer_cdn_invalidation(&crate_name, &crate_version)?;
+                }
+            }
+
+            Self::AddAll {
+                crate_name,
+                build_priority,
+                only_missing,
+            } => {
+                let registry_api = ctx.registry_api()?;
+                let versions: Vec<String> = ctx
+                    .runtime()?
+                    .block_on(registry_api.get_crate_versions(&crate_name))?
+                    .into_iter()
+                    .map(|version| version.to_string())
+                    .collect();
+
+                let (added, errors) = ctx.build_queue()?.add_crate_versions(
+                    &crate_name,
+                    &versions,
+                    build_priority,
+                    ctx.config()?.registry_url.as_deref(),
+                    only_missing,
+                    QueueSource::Manual,
+                )?;
+                for error in &errors {
+                    eprintln!("{error:?}");
+                }
+                println!("queued {added} of {} versions", versions.len());
+                if !errors.is_empty() {
+                    anyhow::bail!("{} versions could not be queued", errors.len());
+                }
+            }
+
+            Self::AddFromFile {
+                path,
                 build_priority,
-                ctx.config()?.registry_url.as_deref(),
-            )?,
+                fail_fast,
+                defer_cdn_invalidation,
+            } => {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                queue_release_list(
+                    &ctx,
+                    &path.display().to_string(),
+                    &content,
+                    build_priority,
+                    QueueSource::Manual,
+                    fail_fast,
+                    defer_cdn_invalidation,
+                )?;
+            }
+
+            Self::RebuildOutdatedToolchain {
+                priority,
+                limit,
+                defer_cdn_invalidation,
+            } => {
+                let queued = ctx.build_queue()?.queue_rebuilds_for_outdated_toolchain(
+                    priority,
+                    limit,
+                    defer_cdn_invalidation,
+                )?;
+                println!("queued {queued} rebuilds");
+            }
+
+            Self::List {
+                stuck: true,
+                older_than,
+                min_attempts,
+                json,
+            } => {
+                let stuck = ctx
+                    .build_queue()?
+                    .stuck_crates(older_than.into(), min_attempts)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&stuck)?);
+                } else {
+                    for krate in stuck {
+                        let reasons: Vec<String> =
+                            krate.reasons.iter().map(ToString::to_string).collect();
+                        println!(
+                            "{} {} (priority {}, attempt {}, waiting since {}): {}",
+                            krate.name,
+                            krate.version,
+                            krate.priority,
+                            krate.attempt,
+                            krate.waiting_since.format("%Y-%m-%d %H:%M:%S"),
+                            reasons.join(", "),
+                        );
+                    }
+                }
+            }
+
+            Self::List { stuck: false, .. } => {
+                for entry in ctx.build_queue()?.export()? {
+                    println!(
+                        "{} {} (priority {}, attempt {}, source {})",
+                        entry.name,
+                        entry.version,
+                        entry.priority,
+                        entry.attempt,
+                        entry
+                            .source
+                            .map_or_else(|| "unknown".to_string(), |source| source.to_string())
+                    );
+                }
+            }
+
+            Self::Peek => {
+                if let Some(krate) = ctx.build_queue()?.peek_next_crate()? {
+                    println!(
+                        "Next crate to build: {} {} (priority {})",
+                        krate.name, krate.version, krate.priority
+                    );
+                } else {
+                    println!("No crate is ready to be built");
+                }
+            }
+
+            Self::Export { path } => {
+                let entries = ctx.build_queue()?.export()?;
+                let file = fs::File::create(&path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                serde_json::to_writer_pretty(file, &entries)
+                    .context("failed to write queue snapshot")?;
+                println!(
+                    "Exported {} queued crates to {}",
+                    entries.len(),
+                    path.display()
+                );
+            }
+
+            Self::Import { path } => {
+                let file = fs::File::open(&path)
+                    .with_context(|| format!("failed to open {}", path.display()))?;
+                let entries: Vec<QueueSnapshotEntry> =
+                    serde_json::from_reader(std::io::BufReader::new(file))
+                        .context("failed to parse queue snapshot")?;
+                let added = ctx.build_queue()?.import(&entries)?;
+                println!(
+                    "Imported {added} of {} queued crates from {}",
+                    entries.len(),
+                    path.display()
+                );
+            }
 
             Self::GetLastSeenReference => {
                 if let Some(reference) = ctx.build_queue()?.last_seen_reference()? {
@@ -304,6 +821,135 @@ impl QueueSubcommand {
             }
 
             Self::DefaultPriority { subcommand } => subcommand.handle_args(ctx)?,
+
+            Self::DeadLetter { subcommand } => subcommand.handle_args(ctx)?,
+        }
+        Ok(())
+    }
+}
+
+/// Queues the releases listed in `content`, see [`parse_release_list`], `origin` names the
+/// source of the list in the output.
+///
+/// Malformed lines and releases that can't be queued are skipped and reported at the end.
+/// With `fail_fast`, nothing is queued when one line is malformed or can't be queued.
+fn queue_release_list(
+    ctx: &BinContext,
+    origin: &str,
+    content: &str,
+    default_priority: i32,
+    source: QueueSource,
+    fail_fast: bool,
+    defer_cdn_invalidation: bool,
+) -> Result<()> {
+    let (releases, mut errors) = parse_release_list(content, default_priority);
+    if fail_fast && !errors.is_empty() {
+        return Err(errors.remove(0));
+    }
+
+    let build_queue = ctx.build_queue()?;
+    let registry = ctx.config()?.registry_url.clone();
+    if fail_fast {
+        let releases: Vec<_> = releases
+            .iter()
+            .map(|release| (release.name, release.version, release.priority))
+            .collect();
+        build_queue
+            .add_crates(
+                &releases,
+                registry.as_deref(),
+                source,
+                defer_cdn_invalidation,
+            )
+            .context("no releases were queued")?;
+        println!("queued {} releases from {origin}", releases.len());
+        return Ok(());
+    }
+
+    let mut added = 0;
+    for release in releases {
+        let result = build_queue
+            .add_crate(
+                release.name,
+                release.version,
+                release.priority,
+                registry.as_deref(),
+                source,
+            )
+            .and_then(|()| {
+                if defer_cdn_invalidation {
+                    build_queue.set_defer_cdn_invalidation(release.name, release.version)
+                } else {
+                    Ok(())
+                }
+            });
+        match result {
+            Ok(()) => added += 1,
+            Err(err) => errors.push(err.context(format!("line {}", release.line))),
+        }
+    }
+
+    for error in &errors {
+        eprintln!("{error:?}");
+    }
+    println!("queued {added} releases from {origin}");
+    if !errors.is_empty() {
+        anyhow::bail!("{} lines could not be queued", errors.len());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+enum DeadLetterSubcommand {
+    /// List the releases that ran out of build attempts, the latest failure first
+    List {
+        /// Print the releases as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Add a release that ran out of build attempts to the queue again
+    Requeue {
+        /// Name of the crate
+        #[arg(name = "CRATE_NAME")]
+        crate_name: String,
+        /// Version of the crate
+        #[arg(name = "CRATE_VERSION")]
+        crate_version: String,
+    },
+}
+
+impl DeadLetterSubcommand {
+    fn handle_args(self, ctx: BinContext) -> Result<()> {
+        let build_queue = ctx.build_queue()?;
+        match self {
+            Self::List { json } => {
+                let dead_letters = build_queue.dead_letters()?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&dead_letters)?);
+                } else {
+                    for dead_letter in dead_letters {
+                        println!(
+                            "{} {} (failed {} after {} attempts): {}",
+                            dead_letter.name,
+                            dead_letter.version,
+                            dead_letter.failed_at.format("%Y-%m-%d %H:%M:%S"),
+                            dead_letter.attempts,
+                            dead_letter.error.lines().next().unwrap_or_default(),
+                        );
+                    }
+                }
+            }
+
+            Self::Requeue {
+                crate_name,
+                crate_version,
+            } => {
+                build_queue
+                    .requeue_dead_letter(&crate_name, &crate_version)
+                    .context("Could not requeue the release")?;
+                println!("requeued {crate_name} {crate_version}");
+            }
         }
         Ok(())
     }
@@ -327,6 +973,9 @@ enum PrioritySubcommand {
         /// The priority to give crates matching the given `PATTERN`
         #[arg(allow_negative_numbers = true)]
         priority: i32,
+        /// Warn about existing patterns that match some of the same crates
+        #[arg(long)]
+        check_overlap: bool,
     },
 
     /// Remove the prioritization of crates for a pattern
@@ -357,7 +1006,28 @@ impl PrioritySubcommand {
                 }
             }
 
-            Self::Set { pattern, priority } => {
+            Self::Set {
+                pattern,
+                priority,
+                check_overlap,
+            } => {
+                if check_overlap {
+                    for overlap in find_priority_overlaps(conn, &pattern)? {
+                        println!(
+                            "warning: pattern '{}' (priority {}) also matches {} crates, they might get either priority:",
+                            overlap.pattern,
+                            overlap.priority,
+                            overlap.crates.len(),
+                        );
+                        for name in overlap.crates.iter().take(10) {
+                            println!("    {name}");
+                        }
+                        if overlap.crates.len() > 10 {
+                            println!("    ... and {} more", overlap.crates.len() - 10);
+                        }
+                    }
+                }
+
                 set_crate_priority(conn, &pattern, priority)
                     .context("Could not set pattern's priority")?;
                 println!("Set pattern '{pattern}' to priority {priority}");
@@ -392,6 +1062,108 @@ enum BuildSubcommand {
         /// Build a crate at a specific path
         #[arg(short = 'l', long = "local", conflicts_with_all(&["CRATE_NAME", "CRATE_VERSION"]))]
         local: Option<PathBuf>,
+
+        /// Build a `.crate` file as published to the registry
+        #[arg(
+            long,
+            value_name = "PATH.crate",
+            conflicts_with_all(&["CRATE_NAME", "CRATE_VERSION", "local"])
+        )]
+        crate_file: Option<PathBuf>,
+
+        /// Don't access the network, build against a pre-populated cargo cache or vendored registry
+        #[arg(long)]
+        offline: bool,
+
+        /// Print a JSON report of the build to stdout when it finished
+        #[arg(long)]
+        json: bool,
+
+        /// Also build and store the rustdoc JSON output
+        #[arg(long)]
+        with_json: bool,
+
+        /// Ignore the `[package.metadata.docs.rs]` section and build with the defaults
+        #[arg(long)]
+        ignore_metadata: bool,
+
+        /// Don't store the source archive, the source view won't be available
+        #[arg(long)]
+        skip_source_archive: bool,
+
+        /// Store the cargo timing report of every target next to its build log
+        #[arg(long)]
+        capture_timings: bool,
+
+        /// Run cargo with `-vv` and store the full build log, without the usual size limit
+        #[arg(long)]
+        verbose_cargo: bool,
+
+        /// Build with the toolchain pinned in the `rust-toolchain.toml` or `rust-toolchain`
+        /// file of the package, only for `--local` and `--crate-file`
+        #[arg(long, conflicts_with = "CRATE_NAME")]
+        toolchain_from_lockfile: bool,
+
+        /// Compare the built documentation with the stored documentation and print what
+        /// changed. The stored documentation and release data are kept, unless `--promote`
+        /// is given.
+        #[arg(long)]
+        compare_with_stored: bool,
+
+        /// Replace the stored documentation with the compared build
+        #[arg(long, requires = "compare_with_stored")]
+        promote: bool,
+
+        /// Build with these `RUSTFLAGS`, replacing the `rustc-args` of the crate's metadata.
+        /// Only `--cfg`, `--check-cfg`, lint levels and some codegen options are allowed.
+        #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+        rustflags: Option<String>,
+
+        /// Only build the host target, or the crate's default target from its metadata, and
+        /// skip all other targets
+        #[arg(long)]
+        no_default_targets: bool,
+
+        /// Also copy the generated documentation into this directory
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+
+        /// Rewrite the HTML files in `--out-dir` with the docs.rs header and theme, like the
+        /// web server does. The pages load the docs.rs styles and scripts from absolute `/-/`
+        /// paths, so they have to be served next to them
+        #[arg(long, requires = "out_dir")]
+        theme: bool,
+    },
+
+    /// Prints the docs.rs metadata of a crate the way the builder would use it
+    ShowMetadata {
+        /// Crate name
+        #[arg(name = "CRATE_NAME", requires("CRATE_VERSION"))]
+        crate_name: Option<String>,
+
+        /// Version of crate
+        #[arg(name = "CRATE_VERSION")]
+        crate_version: Option<String>,
+
+        /// Read the metadata of a crate at a specific path
+        #[arg(short = 'l', long = "local", conflicts_with_all(&["CRATE_NAME", "CRATE_VERSION"]))]
+        local: Option<PathBuf>,
+
+        /// Print the metadata as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Checks the docs.rs metadata of many releases for common mistakes, like unknown keys,
+    /// invalid targets or unknown features
+    LintMetadata {
+        /// File with one `CRATE_NAME CRATE_VERSION` per line, like for `queue add-from-file`
+        #[arg(name = "FILE")]
+        from_file: PathBuf,
+
+        /// Print the problems as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// update the currently installed rustup toolchain
@@ -399,6 +1171,11 @@ enum BuildSubcommand {
         /// Update the toolchain only if no toolchain is currently installed
         #[arg(name = "ONLY_FIRST_TIME", long = "only-first-time")]
         only_first_time: bool,
+
+        /// Switch to this toolchain (`nightly`, `beta`, `stable` or a version) and store it
+        /// as the configured toolchain, instead of updating the configured one
+        #[arg(long)]
+        channel: Option<String>,
     },
 
     /// Adds essential files for the installed version of rustc
@@ -406,37 +1183,132 @@ enum BuildSubcommand {
 
     SetToolchain {
         toolchain_name: String,
+
+        /// Check that the toolchain is installed or can be installed before storing it
+        #[arg(long)]
+        verify: bool,
     },
 
     /// Locks the daemon, preventing it from building new crates
-    Lock,
+    Lock {
+        /// Why the queue is locked, logged by the build servers and shown on the queue page
+        #[arg(long)]
+        message: Option<String>,
+    },
 
     /// Unlocks the daemon to continue building new crates
     Unlock,
-}
 
-impl BuildSubcommand {
-    fn handle_args(self, ctx: BinContext) -> Result<()> {
-        let build_queue = ctx.build_queue()?;
+    /// Shows the build status of the releases of a crate, newest first
+    Status {
+        /// Crate name
+        #[arg(name = "CRATE_NAME")]
+        crate_name: String,
 
-        let rustwide_builder = || -> Result<RustwideBuilder> { RustwideBuilder::init(&ctx) };
+        /// Only show the status of this version
+        #[arg(long)]
+        version: Option<String>,
 
-        match self {
-            Self::Crate {
-                crate_name,
+        /// How to print the result
+        #[arg(
+            long,
+            visible_alias = "output-format",
+            value_enum,
+            default_value = "table"
+        )]
+        format: OutputFormat,
+    },
+
+    /// Lists past builds of a crate, newest first
+    History {
+        /// Crate name
+        #[arg(name = "CRATE_NAME")]
+        crate_name: String,
+
+        /// Only list builds of this version
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Maximum number of builds to list
+        #[arg(long, default_value = "20")]
+        limit: i64,
+
+        /// How to print the result
+        #[arg(
+            long,
+            visible_alias = "output-format",
+            value_enum,
+            default_value = "table"
+        )]
+        format: OutputFormat,
+
+        /// Print the builds as JSON, the same as `--format json`
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+    },
+}
+
+impl BuildSubcommand {
+    fn handle_args(self, ctx: BinContext) -> Result<()> {
+        let build_queue = ctx.build_queue()?;
+
+        let rustwide_builder = || -> Result<RustwideBuilder> { RustwideBuilder::init(&ctx) };
+
+        match self {
+            Self::Crate {
+                crate_name,
                 crate_version,
                 local,
+                crate_file,
+                offline,
+                json,
+                with_json,
+                ignore_metadata,
+                skip_source_archive,
+                capture_timings,
+                verbose_cargo,
+                toolchain_from_lockfile,
+                compare_with_stored,
+                promote,
+                rustflags,
+                no_default_targets,
+                out_dir,
+                theme,
             } => {
                 let mut builder = rustwide_builder()?;
+                builder
+                    .set_rustflags(rustflags.as_deref())
+                    .context("invalid --rustflags")?;
+                builder.set_offline(offline);
+                builder.set_ignore_metadata(ignore_metadata);
+                builder.set_capture_timings(capture_timings);
+                builder.set_verbose_cargo(verbose_cargo);
+                builder.set_toolchain_from_file(toolchain_from_lockfile);
+                builder.set_compare_with_stored(compare_with_stored, promote);
+                builder.set_no_default_targets(no_default_targets);
+                builder.set_out_dir(out_dir.clone());
+                if skip_source_archive {
+                    builder.set_skip_source_archive(true);
+                }
+                if with_json {
+                    builder.set_rustdoc_json(true);
+                }
 
-                if let Some(path) = local {
+                let report = if let Some(path) = local {
                     builder
-                        .build_local_package(&path)
-                        .context("Building documentation failed")?;
+                        .build_local_package_with_report(&path)
+                        .context("Building documentation failed")?
+                } else if let Some(crate_file) = crate_file {
+                    let dir = tempfile::tempdir()?;
+                    let path = unpack_crate_file(&crate_file, dir.path())
+                        .with_context(|| format!("invalid crate file {}", crate_file.display()))?;
+                    builder
+                        .build_local_package_with_report(&path)
+                        .context("Building documentation failed")?
                 } else {
                     let registry_url = ctx.config()?.registry_url.clone();
                     builder
-                        .build_package(
+                        .build_package_with_report(
                             &crate_name
                                 .with_context(|| anyhow!("must specify name if not local"))?,
                             &crate_version
@@ -446,34 +1318,199 @@ impl BuildSubcommand {
                                 .map(|s| PackageKind::Registry(s.as_str()))
                                 .unwrap_or(PackageKind::CratesIo),
                         )
-                        .context("Building documentation failed")?;
+                        .context("Building documentation failed")?
+                };
+
+                if let Some(out_dir) = out_dir.filter(|_| theme) {
+                    if out_dir.is_dir() {
+                        let count = ctx
+                            .runtime()?
+                            .block_on(async {
+                                let mut conn = ctx.pool()?.get_async().await?;
+                                apply_docsrs_theme(
+                                    &mut conn,
+                                    &ctx.config()?,
+                                    &report.name,
+                                    &report.version,
+                                    &out_dir,
+                                )
+                                .await
+                            })
+                            .context("failed to apply the docs.rs theme")?;
+                        eprintln!("applied the docs.rs theme to {count} pages");
+                    } else {
+                        eprintln!("no documentation was built, nothing to theme");
+                    }
+                }
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else if let Some(comparison) = &report.docs_comparison {
+                    for path in &comparison.added {
+                        println!("added    {path}");
+                    }
+                    for path in &comparison.removed {
+                        println!("removed  {path}");
+                    }
+                    for file in &comparison.changed {
+                        println!(
+                            "changed  {} ({} -> {} bytes)",
+                            file.path, file.stored_size, file.new_size
+                        );
+                    }
+                    if !comparison.had_stored_docs {
+                        println!("there was no stored documentation to compare with");
+                    }
+                    println!(
+                        "{} added, {} removed, {} changed, {} unchanged files{}",
+                        comparison.added.len(),
+                        comparison.removed.len(),
+                        comparison.changed.len(),
+                        comparison.unchanged,
+                        if promote {
+                            ", the new documentation was stored"
+                        } else {
+                            ", the stored documentation was kept"
+                        }
+                    );
+                } else if compare_with_stored {
+                    println!("no documentation was built, nothing to compare");
                 }
             }
 
-            Self::UpdateToolchain { only_first_time } => {
-                if only_first_time {
-                    let mut conn = ctx
-                        .pool()?
-                        .get()
-                        .context("failed to get a database connection")?;
+            Self::ShowMetadata {
+                crate_name,
+                crate_version,
+                local,
+                json,
+            } => {
+                let metadata = if let Some(path) = local {
+                    resolve_local_metadata(&ctx.config()?, &path)?
+                } else {
+                    resolve_release_metadata(
+                        &ctx,
+                        &crate_name.with_context(|| anyhow!("must specify name if not local"))?,
+                        &crate_version
+                            .with_context(|| anyhow!("must specify version if not local"))?,
+                    )?
+                };
 
-                    if get_config::<String>(&mut conn, ConfigName::RustcVersion)?.is_some() {
-                        println!("update-toolchain was already called in the past, exiting");
-                        return Ok(());
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&metadata)?);
+                } else {
+                    println!("proc-macro:     {}", metadata.proc_macro);
+                    println!("default target: {}", metadata.default_target);
+                    println!("other targets:  {}", metadata.other_targets.join(", "));
+                    println!("cargo args:     {}", metadata.cargo_args.join(" "));
+                }
+            }
+
+            Self::LintMetadata { from_file, json } => {
+                let content = fs::read_to_string(&from_file)
+                    .with_context(|| format!("failed to read {}", from_file.display()))?;
+
+                // the targets of the local toolchain, only the format of targets is checked
+                // without one.
+                let known_targets: Option<HashSet<String>> = std::process::Command::new("rustc")
+                    .args(["--print", "target-list"])
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())
+                    .map(|output| {
+                        String::from_utf8_lossy(&output.stdout)
+                            .lines()
+                            .map(str::to_owned)
+                            .collect()
+                    });
+                if known_targets.is_none() {
+                    eprintln!("warning: rustc is not available, targets are not fully checked");
+                }
+
+                #[derive(Serialize)]
+                struct LintedRelease<'a> {
+                    name: &'a str,
+                    version: &'a str,
+                    problems: Vec<MetadataProblem>,
+                }
+
+                let mut linted = Vec::new();
+                let mut checked = 0;
+                for (index, line) in content.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
                     }
+                    // an optional priority is allowed, to reuse files of `queue add-from-file`
+                    let (name, version) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                        [name, version] | [name, version, _] => (name, version),
+                        _ => anyhow::bail!(
+                            "line {}: expected `CRATE_NAME CRATE_VERSION`, found `{line}`",
+                            index + 1
+                        ),
+                    };
+                    checked += 1;
+
+                    let problems =
+                        match lint_release_metadata(&ctx, name, version, known_targets.as_ref()) {
+                            Ok(problems) => problems,
+                            Err(err) => {
+                                eprintln!("{name} {version}: {err:#}");
+                                continue;
+                            }
+                        };
+                    if problems.is_empty() {
+                        continue;
+                    }
+                    if !json {
+                        for problem in &problems {
+                            println!("{name} {version}: {problem}");
+                        }
+                    }
+                    linted.push(LintedRelease {
+                        name,
+                        version,
+                        problems,
+                    });
                 }
 
-                rustwide_builder()?
-                    .update_toolchain()
-                    .context("failed to update toolchain")?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&linted)?);
+                } else {
+                    eprintln!("found problems in {} of {checked} releases", linted.len());
+                }
+            }
 
-                rustwide_builder()?
-                    .purge_caches()
-                    .context("failed to purge caches")?;
+            Self::UpdateToolchain {
+                only_first_time,
+                channel,
+            } => {
+                let mut conn = ctx
+                    .pool()?
+                    .get()
+                    .context("failed to get a database connection")?;
 
-                rustwide_builder()?
-                    .add_essential_files()
-                    .context("failed to add essential files")?;
+                if only_first_time
+                    && get_config::<String>(&mut conn, ConfigName::RustcVersion)?.is_some()
+                {
+                    println!("update-toolchain was already called in the past, exiting");
+                    return Ok(());
+                }
+
+                let previous_toolchain = get_config::<String>(&mut conn, ConfigName::Toolchain)?;
+                if let Some(channel) = &channel {
+                    set_config(&mut conn, ConfigName::Toolchain, channel)
+                        .context("failed to set toolchain in database")?;
+                }
+
+                if let Err(err) = rustwide_builder()?.update_toolchain_and_add_essential_files() {
+                    if channel.is_some() {
+                        // the builder falls back to nightly when no toolchain is configured
+                        let previous = previous_toolchain.unwrap_or_else(|| "nightly".into());
+                        set_config(&mut conn, ConfigName::Toolchain, previous)
+                            .context("failed to restore the previous toolchain")?;
+                    }
+                    return Err(err);
+                }
             }
 
             Self::AddEssentialFiles => {
@@ -482,7 +1519,16 @@ impl BuildSubcommand {
                     .context("failed to add essential files")?;
             }
 
-            Self::SetToolchain { toolchain_name } => {
+            Self::SetToolchain {
+                toolchain_name,
+                verify,
+            } => {
+                if verify {
+                    rustwide_builder()?
+                        .verify_toolchain(&toolchain_name)
+                        .context("failed to verify toolchain")?;
+                }
+
                 let mut conn = ctx
                     .pool()?
                     .get()
@@ -491,8 +1537,61 @@ impl BuildSubcommand {
                     .context("failed to set toolchain in database")?;
             }
 
-            Self::Lock => build_queue.lock().context("Failed to lock")?,
+            Self::Lock { message } => build_queue
+                .lock(message.as_deref())
+                .context("Failed to lock")?,
             Self::Unlock => build_queue.unlock().context("Failed to unlock")?,
+
+            Self::Status {
+                crate_name,
+                version,
+                format,
+            } => {
+                let statuses = db::build_history::release_build_statuses(
+                    &mut *ctx.read_conn()?,
+                    &crate_name,
+                    version.as_deref(),
+                )
+                .context("failed to load the build status")?;
+
+                print_formatted(format, &statuses, |status| {
+                    format!(
+                        "{:<16} {:<12} {:<20} {}",
+                        status.version,
+                        status.build_status,
+                        format_time(status.last_build_time),
+                        if status.has_docs { "docs" } else { "no docs" },
+                    )
+                })?;
+            }
+
+            Self::History {
+                crate_name,
+                version,
+                limit,
+                format,
+                json,
+            } => {
+                let builds = db::build_history::list_builds(
+                    &mut *ctx.read_conn()?,
+                    &crate_name,
+                    version.as_deref(),
+                    limit,
+                )
+                .context("failed to list builds")?;
+
+                let format = if json { OutputFormat::Json } else { format };
+                print_formatted(format, &builds, |build| {
+                    format!(
+                        "{:>8} {:<16} {:<12} {:<20} {}",
+                        build.id,
+                        build.version,
+                        build.build_status,
+                        format_time(build.build_time),
+                        build.rustc_version.as_deref().unwrap_or("-"),
+                    )
+                })?;
+            }
         }
 
         Ok(())
@@ -503,27 +1602,142 @@ impl BuildSubcommand {
 enum DatabaseSubcommand {
     /// Run database migration
     Migrate {
-        /// The database version to migrate to
+        /// The database version to migrate back to
+        #[arg(name = "VERSION", conflicts_with_all = ["steps", "to_latest"])]
+        version: Option<i64>,
+
+        /// Apply the next N pending migrations, or revert the last N ones with a negative N
+        #[arg(
+            long,
+            value_name = "N",
+            allow_negative_numbers = true,
+            conflicts_with = "to_latest"
+        )]
+        steps: Option<i64>,
+
+        /// Apply all pending migrations, the same as running without arguments
+        #[arg(long)]
+        to_latest: bool,
+
+        /// Only print the migrations that would be applied or reverted, with their SQL
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Verify that the database schema is still consistent with the migrations
+    VerifyMigrations {
+        /// Only check the record of this migration, the schema is always compared
         #[arg(name = "VERSION")]
         version: Option<i64>,
+
+        /// Query returning a single boolean that has to be `true`
+        #[arg(long, value_name = "SQL")]
+        check_query: Option<String>,
+    },
+
+    /// Recomputes the cached build status of the releases and the latest version of a
+    /// crate, or of all crates
+    RecountReleases {
+        /// Only recount this crate
+        #[arg(name = "CRATE_NAME")]
+        crate_name: Option<String>,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
     },
 
     /// temporary commant to update the `crates.latest_version_id` field
-    UpdateLatestVersionId,
+    UpdateLatestVersionId {
+        /// Whether the newest yanked release is the latest version of crates that only have
+        /// yanked releases. Stored for all future updates.
+        #[arg(long, value_name = "BOOL")]
+        allow_yanked: Option<bool>,
+
+        /// Only update this many crates, ordered by name
+        #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+        limit: Option<i64>,
+
+        /// Only update crates whose name comes after this one, to continue an earlier batch
+        #[arg(long, value_name = "NAME")]
+        after: Option<String>,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
+    },
+
+    /// Lists crates whose latest version isn't the one `update-latest-version-id` would
+    /// pick, without fixing them
+    VerifyLatestPointers {
+        /// Print the wrong latest versions as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
+    },
 
     /// temporary command to rebuild a subset of the archive indexes
-    FixBrokenArchiveIndexes,
+    FixBrokenArchiveIndexes {
+        /// Only re-check releases whose rebuild queued by an earlier run of this command
+        /// didn't succeed.
+        #[arg(long)]
+        retry_failed_only: bool,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
+    },
 
     /// Updates Github/Gitlab stats for crates.
     UpdateRepositoryFields,
 
     /// Backfill GitHub/Gitlab stats for crates.
-    BackfillRepositoryStats,
+    BackfillRepositoryStats {
+        /// How many repositories to fetch at the same time
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+        parallel: u16,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
+    },
 
     /// Updates info for a crate from the registry's API
     UpdateCrateRegistryFields {
         #[arg(name = "CRATE")]
         name: String,
+
+        /// Fetch the data from the API of this registry instead of the configured one
+        #[arg(long, value_name = "URL")]
+        registry: Option<url::Url>,
+    },
+
+    /// Refreshes the description, keywords and categories of crates from the registry's API
+    RefreshCrateMetadata {
+        /// Only refresh this crate instead of all crates
+        #[arg(name = "CRATE")]
+        name: Option<String>,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
+    },
+
+    /// Fetches the owners of crates from the registry's API and stores them
+    UpdateOwners {
+        /// Only update this crate instead of all crates
+        #[arg(name = "CRATE")]
+        name: Option<String>,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
+    },
+
+    /// Updates the download counts of crates from the registry's API
+    UpdateDownloadCounts {
+        /// Only update this crate instead of all crates
+        #[arg(name = "CRATE")]
+        crate_name: Option<String>,
+
+        #[command(flatten)]
+        max_runtime: MaxRuntime,
     },
 
     AddDirectory {
@@ -544,50 +1758,374 @@ enum DatabaseSubcommand {
         command: BlacklistSubcommand,
     },
 
+    /// Crate alias operations, aliases are crate names that redirect to another crate
+    CrateAliases {
+        #[command(subcommand)]
+        command: CrateAliasesSubcommand,
+    },
+
     /// Limit overrides operations
     Limits {
         #[command(subcommand)]
         command: LimitsSubcommand,
     },
 
+    /// Recomputes the priority of all queued crates from the current priority patterns
+    ReassignPriority,
+
+    /// Lists crates without any release
+    OrphanedCrates {
+        /// Delete the crates, including their blacklist entries, aliases and priority patterns
+        #[arg(long)]
+        delete: bool,
+
+        /// Only print which crates would be deleted
+        #[arg(long, requires = "delete")]
+        dry_run: bool,
+    },
+
+    /// Prints the tables, columns and indexes of the database schema
+    DumpSchema {
+        /// Print the schema as JSON instead of SQL
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Lists releases whose latest build failed with the given error category, or counts
+    /// the failures of every category
+    FailuresByCategory {
+        /// Error category, like `out_of_memory` or `timeout`
+        #[arg(name = "CATEGORY")]
+        category: Option<String>,
+
+        /// Maximum number of releases to list
+        #[arg(long, default_value = "50")]
+        limit: i64,
+
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Lists releases of a crate that are the same version, differing only in build metadata
+    FindDuplicateReleases {
+        /// Delete the duplicates, keeping the newest release with a successful build
+        #[arg(long)]
+        fix: bool,
+
+        /// Print the duplicates as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Moves a release to another crate, to repair releases that point to the wrong crate.
+    /// Files in storage are not moved.
+    ReassignCrateId {
+        /// Id of the release to move
+        #[arg(long)]
+        release_id: i32,
+
+        /// Id of the crate the release belongs to
+        #[arg(long)]
+        crate_id: i32,
+
+        /// Only print the release before and after the change, without changing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't ask for confirmation before moving the release
+        #[arg(long, conflicts_with = "dry_run")]
+        yes: bool,
+    },
+
+    /// Recreates the archive indexes and the search index of a release from its stored
+    /// archives, without rebuilding its documentation
+    RegenerateArchiveIndex {
+        /// Name of the crate
+        #[arg(
+            name = "CRATE_NAME",
+            requires = "CRATE_VERSION",
+            required_unless_present = "path"
+        )]
+        name: Option<String>,
+
+        /// Version of the crate
+        #[arg(name = "CRATE_VERSION")]
+        version: Option<String>,
+
+        /// Only regenerate the index of the archive at this path in storage
+        #[arg(long, value_name = "ARCHIVE_PATH", conflicts_with = "CRATE_NAME")]
+        path: Option<String>,
+    },
+
+    /// Copies the files of one storage backend to another, keeping their compression
+    MigrateStorage {
+        /// Backend to copy from, `database` or `s3`
+        #[arg(long)]
+        from: StorageKind,
+
+        /// Backend to copy to, `database` or `s3`
+        #[arg(long)]
+        to: StorageKind,
+
+        /// Only copy the files under this prefix
+        #[arg(long, default_value = "")]
+        prefix: String,
+
+        /// How many files to copy at the same time
+        #[arg(long, default_value = "8", value_parser = clap::value_parser!(u16).range(1..))]
+        concurrency: u16,
+
+        /// Skip files that already exist in the destination, to continue an earlier run
+        #[arg(long)]
+        resume: bool,
+    },
+
+    /// Copies a file to another path in storage, without downloading it. The index of an
+    /// archive is copied with it.
+    StorageCopy {
+        /// Path of the file in storage
+        #[arg(name = "FROM")]
+        from: String,
+
+        /// Path of the copy, an existing file there is replaced
+        #[arg(name = "TO")]
+        to: String,
+    },
+
+    /// Writes a single file from storage to stdout, decompressing it if needed
+    StorageCat {
+        /// Path of the file in storage
+        #[arg(name = "PATH")]
+        path: String,
+
+        /// Write the file to this path instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Writes the log of a build to stdout, the latest build of the release by default
+    ShowBuildLog {
+        /// Name of the crate
+        #[arg(name = "CRATE_NAME")]
+        name: String,
+
+        /// Version of the crate
+        #[arg(name = "CRATE_VERSION")]
+        version: String,
+
+        /// Id of the build, instead of the latest build of the release
+        #[arg(long)]
+        build_id: Option<i32>,
+
+        /// Target of the log, instead of the default target of the release
+        #[arg(long)]
+        target: Option<String>,
+
+        /// Write the log to this path instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Writes sitemaps of the documentation roots of all documented releases, split into
+    /// files of at most 50,000 URLs, and a sitemap index of them
+    ExportSitemap {
+        /// Directory to write the sitemap files and their index into
+        #[arg(name = "DIR")]
+        path: PathBuf,
+
+        /// URL the sitemap files are served under, used in the sitemap index
+        #[arg(long, default_value = "https://docs.rs")]
+        base_url: String,
+    },
+
+    /// Export the metadata of all releases as JSON lines, ordered by crate name
+    ExportMetadata {
+        /// Path of the file to write
+        #[arg(name = "PATH")]
+        path: PathBuf,
+
+        /// Continue an interrupted export at this crate, appending to the file
+        #[arg(long, value_name = "CRATE")]
+        resume_from: Option<String>,
+    },
+
+    /// Writes SQL statements recreating a sanitized subset of the database, with hashed
+    /// crate names and without descriptions, URLs, owners or build logs, to share it with
+    /// contributors reproducing a bug
+    AnonymizeExport {
+        /// Path of the file to write
+        #[arg(name = "PATH")]
+        path: PathBuf,
+
+        /// Export this crate, can be given multiple times
+        #[arg(long = "crate", value_name = "CRATE")]
+        crates: Vec<String>,
+
+        /// Without `--crate`, export this many crates with the most recent releases
+        #[arg(long, default_value = "100")]
+        limit: i64,
+
+        /// Only export the newest releases of every crate
+        #[arg(long, default_value = "10")]
+        releases_per_crate: i64,
+
+        /// Salt of the hashed crate names, a random one is used by default
+        #[arg(long)]
+        salt: Option<String>,
+    },
+
+    /// Reports how many crates are only in the index or only in the database, without
+    /// changing anything
+    IndexDrift {
+        /// Also print the names of the crates
+        #[arg(long)]
+        list: bool,
+    },
+
     /// Compares the database with the index and resolves inconsistencies
     #[cfg(feature = "consistency_check")]
     Synchronize {
         /// Don't actually resolve the inconsistencies, just log them
         #[arg(long)]
         dry_run: bool,
+
+        /// Don't resolve the inconsistencies, and fail when any are found
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Cross-checks a sample of releases between the database, the storage and the index
+    #[cfg(feature = "consistency_check")]
+    IntegrityReport {
+        /// Amount of random releases to check, checks all releases when omitted
+        #[arg(long)]
+        sample: Option<usize>,
+    },
+
+    /// Updates the yank status of releases to the yank status in the local index
+    #[cfg(feature = "consistency_check")]
+    ReconcileYanks {
+        /// Only update the releases of this crate
+        #[arg(name = "CRATE_NAME")]
+        crate_name: Option<String>,
     },
 }
 
 impl DatabaseSubcommand {
     fn handle_args(self, ctx: BinContext) -> Result<()> {
         match self {
-            Self::Migrate { version } => {
+            Self::Migrate {
+                version,
+                steps,
+                to_latest: _,
+                dry_run,
+            } => {
                 let pool = ctx.pool()?;
                 ctx.runtime()?
                     .block_on(async {
                         let mut conn = pool.get_async().await?;
-                        db::migrate(&mut conn, version).await
+                        if dry_run {
+                            match db::current_version(&mut conn).await? {
+                                Some(current) => println!("current version: {current}"),
+                                None => println!("current version: none"),
+                            }
+                            let planned = db::plan_migrations(&mut conn, version, steps).await?;
+                            if planned.is_empty() {
+                                println!("nothing to migrate");
+                            }
+                            for migration in planned {
+                                println!(
+                                    "\nwould {} migration {} {}\n{}",
+                                    if migration.revert { "revert" } else { "apply" },
+                                    migration.version,
+                                    migration.description,
+                                    migration.sql.trim_end(),
+                                );
+                            }
+                            Ok(())
+                        } else if let Some(steps) = steps {
+                            for (version, description) in
+                                db::migrate_steps(&mut conn, steps).await?
+                            {
+                                if steps >= 0 {
+                                    println!("applied migration {version} {description}");
+                                } else {
+                                    println!("reverted migration {version} {description}");
+                                }
+                            }
+                            Ok(())
+                        } else {
+                            db::migrate(&mut conn, version).await
+                        }
                     })
                     .context("Failed to run database migrations")?
             }
 
-            Self::FixBrokenArchiveIndexes => {
+            Self::VerifyMigrations {
+                version,
+                check_query,
+            } => {
+                let pool = ctx.pool()?;
+                let problems = ctx.runtime()?.block_on(async {
+                    let mut conn = pool.get_async().await?;
+                    db::migration_check::verify_migrations(
+                        &mut conn,
+                        version,
+                        check_query.as_deref(),
+                    )
+                    .await
+                })?;
+
+                for problem in &problems {
+                    println!("{problem}");
+                }
+                if !problems.is_empty() {
+                    anyhow::bail!("found {} problems with the migrations", problems.len());
+                }
+                println!("the database is consistent with the migrations");
+            }
+
+            Self::FixBrokenArchiveIndexes {
+                retry_failed_only,
+                max_runtime,
+            } => {
                 let pool = ctx.pool()?;
                 let build_queue = ctx.build_queue()?;
                 ctx.runtime()?
                     .block_on(async {
                         async fn queue_rebuild(
+                            pool: &Pool,
                             build_queue: Arc<BuildQueue>,
                             name: &str,
                             version: &str,
                         ) -> Result<()> {
+                            // remember the rebuild so `--retry-failed-only` can find it again
+                            // when it doesn't succeed.
+                            let mut conn = pool.get_async().await?;
+                            sqlx::query!(
+                                "INSERT INTO archive_index_rebuilds (name, version)
+                                 VALUES ($1, $2)
+                                 ON CONFLICT (name, version) DO UPDATE
+                                 SET queued_at = NOW()",
+                                name,
+                                version,
+                            )
+                            .execute(&mut *conn)
+                            .await?;
+
                             spawn_blocking({
                                 let name = name.to_owned();
                                 let version = version.to_owned();
                                 move || {
                                     if !build_queue.has_build_queued(&name, &version)? {
-                                        build_queue.add_crate(&name, &version, 5, None)?;
+                                        build_queue.add_crate(
+                                            &name,
+                                            &version,
+                                            5,
+                                            None,
+                                            QueueSource::Rebuild,
+                                        )?;
                                     }
                                     Ok(())
                                 }
@@ -596,18 +2134,42 @@ impl DatabaseSubcommand {
                         }
                         let storage = ctx.async_storage().await?;
                         let mut conn = pool.get_async().await?;
+                        // with `--retry-failed-only`, only releases we queued a rebuild for,
+                        // which didn't get a successful build since then.
                         let mut result_stream = sqlx::query!(
                             "
                             SELECT c.name, r.version, r.release_time
                             FROM crates c, releases r
-                            WHERE c.id = r.crate_id AND r.release_time IS NOT NULL
+                            WHERE
+                                c.id = r.crate_id AND
+                                r.release_time IS NOT NULL AND (
+                                    NOT $1 OR
+                                    EXISTS (
+                                        SELECT 1 FROM archive_index_rebuilds a
+                                        WHERE
+                                            a.name = c.name AND
+                                            a.version = r.version AND
+                                            NOT EXISTS (
+                                                SELECT 1 FROM builds b
+                                                WHERE
+                                                    b.rid = r.id AND
+                                                    b.build_status = 'success' AND
+                                                    b.build_time > a.queued_at
+                                            )
+                                    )
+                                )
                             ORDER BY r.release_time DESC
-                        "
+                        ",
+                            retry_failed_only,
                         )
                         .fetch(&mut *conn);
 
+                        let mut progress = max_runtime.start();
                         while let Some(row) = result_stream.next().await {
                             let row = row?;
+                            if !progress.next() {
+                                break;
+                            }
 
                             println!(
                                 "checking index for {} {} ({:?})",
@@ -640,7 +2202,7 @@ impl DatabaseSubcommand {
                                         Ok(conn) => conn,
                                         Err(err) => {
                                             println!("... error opening sqlite db, queueing rebuild: {:?}", err);
-                                            queue_rebuild(build_queue.clone(), &row.name, &row.version).await?;
+                                            queue_rebuild(&pool, build_queue.clone(), &row.name, &row.version).await?;
                                             continue;
                                         }
                                     };
@@ -654,55 +2216,266 @@ impl DatabaseSubcommand {
 
                                 if count >= 65000 {
                                     println!("...big index, queueing rebuild");
-                                    queue_rebuild(build_queue.clone(), &row.name, &row.version)
+                                    queue_rebuild(&pool, build_queue.clone(), &row.name, &row.version)
                                         .await?;
                                 }
                             }
                         }
+                        progress.print_summary("releases", None);
 
                         Ok::<(), anyhow::Error>(())
                     })
                     .context("Failed to queue rebuilds for big documentation sizes")?
             }
 
-            Self::UpdateLatestVersionId => {
-                let pool = ctx.pool()?;
+            Self::RecountReleases {
+                crate_name,
+                max_runtime,
+            } => {
+                let pool = ctx.pool()?;
+                let mut progress = max_runtime.start();
+                let (crates, releases, failed) = ctx.runtime()?.block_on(async {
+                    let mut conn = pool.get_async().await?;
+                    let crates = sqlx::query!(
+                        "SELECT id, name FROM crates
+                         WHERE $1::TEXT IS NULL OR name = $1
+                         ORDER BY name",
+                        crate_name,
+                    )
+                    .fetch_all(&mut *conn)
+                    .await?;
+                    if let Some(crate_name) = &crate_name {
+                        if crates.is_empty() {
+                            anyhow::bail!("crate {crate_name} doesn't exist");
+                        }
+                    }
+
+                    let mut releases = 0;
+                    let mut failed = 0;
+                    for krate in &crates {
+                        if !progress.next() {
+                            break;
+                        }
+                        match db::recount_releases(&mut conn, krate.id).await {
+                            Ok(count) => releases += count,
+                            Err(err) => {
+                                eprintln!("failed to recount {}: {err:#}", krate.name);
+                                failed += 1;
+                            }
+                        }
+                    }
+                    Ok((crates.len(), releases, failed))
+                })?;
+                println!("recounted {releases} releases");
+                progress.print_summary("crates", Some(crates));
+                if failed > 0 {
+                    eprintln!("failed to recount {failed} crates");
+                }
+            }
+
+            Self::UpdateLatestVersionId {
+                allow_yanked,
+                limit,
+                after,
+                max_runtime,
+            } => {
+                if let Some(allow_yanked) = allow_yanked {
+                    set_config(
+                        &mut *ctx.conn()?,
+                        ConfigName::YankedLatestVersion,
+                        allow_yanked,
+                    )?;
+                }
+
+                let pool = ctx.pool()?;
+                ctx.runtime()?
+                    .block_on(async {
+                        let mut list_conn = pool.get_async().await?;
+                        let mut update_conn = pool.get_async().await?;
+
+                        let mut result_stream = sqlx::query!(
+                            "SELECT id, name
+                             FROM crates
+                             WHERE $1::TEXT IS NULL OR name > $1
+                             ORDER BY name
+                             LIMIT $2",
+                            after,
+                            limit,
+                        )
+                        .fetch(&mut *list_conn);
+
+                        let mut progress = max_runtime.start();
+                        let mut last_name = None;
+                        while let Some(row) = result_stream.next().await {
+                            let row = row?;
+                            if !progress.next() {
+                                break;
+                            }
+
+                            println!("handling crate {} ", row.name);
+
+                            db::update_latest_version_id(&mut update_conn, row.id).await?;
+                            last_name = Some(row.name);
+                        }
+                        progress.print_summary("crates", None);
+                        if let Some(last_name) = last_name {
+                            if limit.is_some() || progress.stopped {
+                                eprintln!("continue with --after {last_name}");
+                            }
+                        }
+
+                        Ok::<(), anyhow::Error>(())
+                    })
+                    .context("Failed to update latest version id")?
+            }
+
+            Self::VerifyLatestPointers { json, max_runtime } => {
+                let pool = ctx.pool()?;
+                let wrong = ctx.runtime()?.block_on(async {
+                    let mut conn = pool.get_async().await?;
+                    let crate_ids: Vec<i32> =
+                        sqlx::query_scalar!("SELECT id FROM crates ORDER BY name")
+                            .fetch_all(&mut *conn)
+                            .await?;
+
+                    let mut wrong = Vec::new();
+                    let mut progress = max_runtime.start();
+                    let mut failed = 0;
+                    for &crate_id in &crate_ids {
+                        if !progress.next() {
+                            break;
+                        }
+                        match db::latest_version_audit::verify_latest_version_id(
+                            &mut conn, crate_id,
+                        )
+                        .await
+                        {
+                            Ok(Some(crate_)) => {
+                                if !json {
+                                    println!(
+                                        "{}: latest version is {}, expected {}",
+                                        crate_.name,
+                                        crate_.stored.as_deref().unwrap_or("none"),
+                                        crate_.expected.as_deref().unwrap_or("none"),
+                                    );
+                                }
+                                wrong.push(crate_);
+                            }
+                            Ok(None) => {}
+                            Err(err) => {
+                                eprintln!("failed to verify crate {crate_id}: {err:#}");
+                                failed += 1;
+                            }
+                        }
+                    }
+                    progress.print_summary("crates", Some(crate_ids.len()));
+                    if failed > 0 {
+                        eprintln!("failed to verify {failed} crates");
+                    }
+
+                    Ok::<_, anyhow::Error>(wrong)
+                })?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&wrong)?);
+                } else {
+                    println!(
+                        "found {} crates with a wrong latest version, fix them with \
+                         `database update-latest-version-id`",
+                        wrong.len()
+                    );
+                }
+            }
+
+            Self::UpdateRepositoryFields => {
                 ctx.runtime()?
-                    .block_on(async {
-                        let mut list_conn = pool.get_async().await?;
-                        let mut update_conn = pool.get_async().await?;
+                    .block_on(ctx.repository_stats_updater()?.update_all_crates())?;
+            }
 
-                        let mut result_stream =
-                            sqlx::query!("SELECT id, name FROM crates ORDER BY name")
-                                .fetch(&mut *list_conn);
+            Self::BackfillRepositoryStats {
+                parallel,
+                max_runtime,
+            } => {
+                let mut progress = max_runtime.start();
+                progress.processed = ctx.runtime()?.block_on(
+                    ctx.repository_stats_updater()?
+                        .backfill_repositories(parallel.into(), progress.deadline()),
+                )?;
+                progress.stopped = progress
+                    .deadline()
+                    .is_some_and(|deadline| Instant::now() >= deadline);
+                progress.print_summary("repositories", None);
+            }
 
-                        while let Some(row) = result_stream.next().await {
-                            let row = row?;
+            Self::UpdateCrateRegistryFields { name, registry } => {
+                let registry_api = match registry {
+                    Some(api_base) => Arc::new(RegistryApi::new(
+                        api_base,
+                        ctx.config()?.crates_io_api_call_retries,
+                    )?),
+                    None => ctx.registry_api()?,
+                };
+                ctx.runtime()?.block_on(async move {
+                    let mut conn = ctx.pool()?.get_async().await?;
+                    let registry_data = registry_api.get_crate_data(&name).await?;
+                    db::update_crate_data_in_database(&mut conn, &name, &registry_data).await
+                })?
+            }
 
-                            println!("handling crate {} ", row.name);
+            Self::RefreshCrateMetadata { name, max_runtime } => {
+                ctx.runtime()?.block_on(async move {
+                    let mut conn = ctx.pool()?.get_async().await?;
+                    let registry_api = ctx.registry_api()?;
 
-                            db::update_latest_version_id(&mut update_conn, row.id).await?;
+                    sweep_crates(&mut conn, name, max_runtime, "metadata", |conn, name| {
+                        let registry_api = registry_api.clone();
+                        async move {
+                            let metadata = registry_api.get_crate_metadata(name).await?;
+                            db::update_crate_metadata_in_database(conn, name, &metadata).await
                         }
-
-                        Ok::<(), anyhow::Error>(())
+                        .boxed()
                     })
-                    .context("Failed to update latest version id")?
+                    .await
+                })?
             }
 
-            Self::UpdateRepositoryFields => {
-                ctx.runtime()?
-                    .block_on(ctx.repository_stats_updater()?.update_all_crates())?;
-            }
+            Self::UpdateOwners { name, max_runtime } => ctx.runtime()?.block_on(async move {
+                let mut conn = ctx.pool()?.get_async().await?;
+                let registry_api = ctx.registry_api()?;
 
-            Self::BackfillRepositoryStats => {
-                ctx.runtime()?
-                    .block_on(ctx.repository_stats_updater()?.backfill_repositories())?;
-            }
+                sweep_crates(&mut conn, name, max_runtime, "owners", |conn, name| {
+                    let registry_api = registry_api.clone();
+                    async move {
+                        let registry_data = registry_api.get_crate_data(name).await?;
+                        db::update_crate_data_in_database(conn, name, &registry_data).await
+                    }
+                    .boxed()
+                })
+                .await
+            })?,
 
-            Self::UpdateCrateRegistryFields { name } => ctx.runtime()?.block_on(async move {
+            Self::UpdateDownloadCounts {
+                crate_name,
+                max_runtime,
+            } => ctx.runtime()?.block_on(async move {
                 let mut conn = ctx.pool()?.get_async().await?;
-                let registry_data = ctx.registry_api()?.get_crate_data(&name).await?;
-                db::update_crate_data_in_database(&mut conn, &name, &registry_data).await
+                let registry_api = ctx.registry_api()?;
+
+                sweep_crates(
+                    &mut conn,
+                    crate_name,
+                    max_runtime,
+                    "downloads",
+                    |conn, name| {
+                        let registry_api = registry_api.clone();
+                        async move {
+                            let downloads = registry_api.get_crate_downloads(name).await?;
+                            db::update_crate_downloads(conn, name, downloads).await
+                        }
+                        .boxed()
+                    },
+                )
+                .await
             })?,
 
             Self::AddDirectory { directory } => {
@@ -716,13 +2489,19 @@ impl DatabaseSubcommand {
             }
 
             Self::Delete {
-                command: DeleteSubcommand::Version { name, version },
+                command:
+                    DeleteSubcommand::Version {
+                        name,
+                        version,
+                        include_builds,
+                    },
             } => db::delete_version(
                 &mut *ctx.pool()?.get()?,
                 &*ctx.storage()?,
                 &*ctx.config()?,
                 &name,
                 &version,
+                db::DeleteVersionOptions { include_builds },
             )
             .context("failed to delete the version")?,
             Self::Delete {
@@ -734,13 +2513,458 @@ impl DatabaseSubcommand {
                 &name,
             )
             .context("failed to delete the crate")?,
+            Self::Delete {
+                command: DeleteSubcommand::Pattern { pattern, yes },
+            } => {
+                let mut conn = ctx.conn()?;
+                let names = db::crates_matching(&mut conn, &pattern)?;
+                if names.is_empty() {
+                    println!("no crates match {pattern}");
+                    return Ok(());
+                }
+
+                for name in &names {
+                    println!("{name}");
+                }
+                if !yes {
+                    print!("delete these {} crates? [y/N] ", names.len());
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                    let mut answer = String::new();
+                    std::io::stdin().read_line(&mut answer)?;
+                    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                        println!("aborted");
+                        return Ok(());
+                    }
+                }
+
+                let storage = ctx.storage()?;
+                let config = ctx.config()?;
+                let mut failed = Vec::new();
+                for name in &names {
+                    match db::delete_crate(&mut conn, &storage, &config, name) {
+                        Ok(()) => println!("deleted {name}"),
+                        Err(err) => {
+                            eprintln!("failed to delete {name}: {err:#}");
+                            failed.push(name.as_str());
+                        }
+                    }
+                }
+                if !failed.is_empty() {
+                    anyhow::bail!(
+                        "failed to delete {} of {} crates: {}",
+                        failed.len(),
+                        names.len(),
+                        failed.join(", ")
+                    );
+                }
+            }
+            Self::ReassignCrateId {
+                release_id,
+                crate_id,
+                dry_run,
+                yes,
+            } => {
+                let runtime = ctx.runtime()?;
+                let pool = ctx.pool()?;
+                let (before, after) = runtime.block_on(async {
+                    let mut conn = pool.get_async().await?;
+                    db::release_reassignment::plan_release_reassignment(
+                        &mut conn, release_id, crate_id,
+                    )
+                    .await
+                })?;
+                println!("before: {before}");
+                println!("after:  {after}");
+                if dry_run {
+                    return Ok(());
+                }
+                if !yes {
+                    print!("move the release? [y/N] ");
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                    let mut answer = String::new();
+                    std::io::stdin().read_line(&mut answer)?;
+                    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                        println!("aborted");
+                        return Ok(());
+                    }
+                }
+
+                let (_, after) = runtime.block_on(async {
+                    let mut conn = pool.get_async().await?;
+                    db::release_reassignment::reassign_release(&mut conn, release_id, crate_id)
+                        .await
+                })?;
+                println!("moved, now {after}");
+            }
             Self::Blacklist { command } => command.handle_args(ctx)?,
+            Self::CrateAliases { command } => command.handle_args(ctx)?,
+            Self::ReassignPriority => {
+                let updated = ctx
+                    .build_queue()?
+                    .reassign_priorities()
+                    .context("failed to reassign queue priorities")?;
+                println!("Updated the priority of {updated} queued crates");
+            }
+            Self::OrphanedCrates { delete, dry_run } => {
+                let mut conn = ctx.conn()?;
+                let orphans = db::orphaned_crates::find_orphaned_crates(&mut conn)
+                    .context("failed to find orphaned crates")?;
+
+                let mut failed = 0;
+                for name in &orphans {
+                    if !delete {
+                        println!("{name}");
+                    } else if dry_run {
+                        println!("would delete {name}");
+                    } else {
+                        match db::orphaned_crates::delete_orphaned_crate(
+                            &mut conn,
+                            &*ctx.storage()?,
+                            &*ctx.config()?,
+                            name,
+                        ) {
+                            Ok(()) => println!("deleted {name}"),
+                            Err(err) => {
+                                eprintln!("failed to delete orphaned crate {name}: {err:#}");
+                                failed += 1;
+                            }
+                        }
+                    }
+                }
+                println!("found {} orphaned crates", orphans.len());
+                if failed > 0 {
+                    eprintln!("failed to delete {failed} orphaned crates");
+                }
+            }
+            Self::DumpSchema { json } => {
+                let pool = ctx.pool()?;
+                let tables = ctx.runtime()?.block_on(async {
+                    let mut conn = pool.get_async().await?;
+                    let schema = db::schema::current_schema(&mut conn).await?;
+                    db::schema::load_schema(&mut conn, &schema).await
+                })?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&tables)?);
+                } else {
+                    print!("{}", db::schema::schema_to_sql(&tables));
+                }
+            }
+
+            Self::FailuresByCategory {
+                category,
+                limit,
+                json,
+            } => {
+                let mut conn = ctx.read_conn()?;
+                if let Some(category) = category {
+                    BuildErrorCategory::from_str(&category)
+                        .with_context(|| format!("unknown error category `{category}`"))?;
+                    let failures =
+                        db::build_history::list_failures(&mut conn, Some(&category), limit)
+                            .context("failed to list failed releases")?;
+
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&failures)?);
+                    } else {
+                        for failure in &failures {
+                            println!(
+                                "{:<40} {:<16} build {}",
+                                failure.name, failure.version, failure.build_id
+                            );
+                        }
+                    }
+                } else {
+                    let counts = db::build_history::count_failures_by_category(&mut conn)
+                        .context("failed to count failed releases")?;
+
+                    if json {
+                        let counts: serde_json::Map<String, serde_json::Value> = counts
+                            .into_iter()
+                            .map(|(category, count)| {
+                                (
+                                    category.unwrap_or_else(|| "uncategorized".into()),
+                                    count.into(),
+                                )
+                            })
+                            .collect();
+                        println!("{}", serde_json::to_string_pretty(&counts)?);
+                    } else {
+                        for (category, count) in counts {
+                            println!(
+                                "{:<16} {count:>8}",
+                                category.as_deref().unwrap_or("uncategorized")
+                            );
+                        }
+                    }
+                }
+            }
+
+            Self::FindDuplicateReleases { fix, json } => {
+                let mut conn = ctx.conn()?;
+                let duplicates = db::duplicate_releases::find_duplicate_releases(&mut conn)
+                    .context("failed to find duplicate releases")?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&duplicates)?);
+                } else {
+                    for group in &duplicates {
+                        println!("{} {}:", group.name, group.version);
+                        for release in &group.releases {
+                            println!(
+                                "    {} (id {}, successful build: {})",
+                                release.version, release.id, release.has_successful_build
+                            );
+                        }
+                    }
+                    println!("found {} duplicated releases", duplicates.len());
+                }
+
+                if fix {
+                    let deleted = db::duplicate_releases::fix_duplicate_releases(
+                        &mut conn,
+                        &*ctx.storage()?,
+                        &*ctx.config()?,
+                        &duplicates,
+                    )
+                    .context("failed to delete duplicate releases")?;
+                    eprintln!("deleted {deleted} duplicate releases");
+                }
+            }
+            Self::RegenerateArchiveIndex {
+                name,
+                version,
+                path,
+            } => {
+                if let Some(archive_path) = path {
+                    let file_count = ctx.runtime()?.block_on(async {
+                        let mut conn = ctx.pool()?.get_async().await?;
+                        regenerate_archive_index(
+                            &mut conn,
+                            &*ctx.async_storage().await?,
+                            &archive_path,
+                        )
+                        .await
+                    })?;
+                    println!("regenerated the index of {archive_path} with {file_count} files");
+                    return Ok(());
+                }
+
+                let (Some(name), Some(version)) = (name, version) else {
+                    anyhow::bail!("CRATE_NAME and CRATE_VERSION are required without --path");
+                };
+                let regenerated = ctx.runtime()?.block_on(async {
+                    let mut conn = ctx.pool()?.get_async().await?;
+                    regenerate_release_indexes(
+                        &mut conn,
+                        &*ctx.async_storage().await?,
+                        &name,
+                        &version,
+                    )
+                    .await
+                })?;
+                for (archive_path, file_count) in &regenerated.archives {
+                    println!("regenerated the index of {archive_path} with {file_count} files");
+                }
+
+                if !regenerated.search_indexes.is_empty() {
+                    // search-index.js is cached forever in the CDN, the regenerated one is
+                    // only served once the cached copies are invalidated.
+                    cdn::queue_crate_invalidation(&mut *ctx.conn()?, &*ctx.config()?, &name)
+                        .context("failed to queue the CDN invalidation")?;
+                    println!(
+                        "regenerated {} and queued its CDN invalidation",
+                        regenerated.search_indexes.join(", ")
+                    );
+                }
+            }
+            Self::MigrateStorage {
+                from,
+                to,
+                prefix,
+                concurrency,
+                resume,
+            } => {
+                if from == to {
+                    anyhow::bail!("the source and the destination are the same backend");
+                }
+                let summary = ctx.runtime()?.block_on(async {
+                    let storage = |kind| async move {
+                        AsyncStorage::with_backend(
+                            kind,
+                            ctx.pool()?,
+                            ctx.instance_metrics()?,
+                            ctx.config()?,
+                        )
+                        .await
+                    };
+                    let (source, destination) = (storage(from).await?, storage(to).await?);
+                    migrate_storage(&source, &destination, &prefix, concurrency.into(), resume)
+                        .await
+                })?;
+
+                println!(
+                    "copied {} files ({} bytes), skipped {} existing files",
+                    summary.copied_objects, summary.copied_bytes, summary.skipped_objects
+                );
+                for (path, err) in &summary.failed {
+                    eprintln!("failed to copy {path}: {err:#}");
+                }
+                if !summary.failed.is_empty() {
+                    anyhow::bail!(
+                        "{} files couldn't be copied, run again with `--resume` to retry them",
+                        summary.failed.len()
+                    );
+                }
+            }
+            Self::StorageCopy { from, to } => {
+                let storage = ctx.storage()?;
+                storage
+                    .copy(&from, &to)
+                    .with_context(|| format!("failed to copy {from} to {to}"))?;
+                println!("copied {from} to {to}");
+
+                if from.ends_with(".zip") {
+                    let (from, to) = (format!("{from}.index"), format!("{to}.index"));
+                    match storage.copy(&from, &to) {
+                        Ok(()) => println!("copied {from} to {to}"),
+                        Err(err) if err.downcast_ref::<PathNotFoundError>().is_some() => {}
+                        Err(err) => {
+                            return Err(err.context(format!("failed to copy {from} to {to}")))
+                        }
+                    }
+                }
+            }
+            Self::StorageCat { path, out } => {
+                let mut writer: Box<dyn std::io::Write + Send> = match out {
+                    Some(out) => Box::new(std::io::BufWriter::new(
+                        fs::File::create(&out)
+                            .with_context(|| format!("failed to create {}", out.display()))?,
+                    )),
+                    None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+                };
+                ctx.storage()?
+                    .copy_to(&path, &mut writer)
+                    .with_context(|| format!("failed to read {path} from storage"))?;
+            }
+            Self::ShowBuildLog {
+                name,
+                version,
+                build_id,
+                target,
+                out,
+            } => {
+                let log = db::build_history::find_build_log(
+                    &mut *ctx.read_conn()?,
+                    &name,
+                    &version,
+                    build_id,
+                    target.as_deref(),
+                )?;
+
+                let mut writer: Box<dyn std::io::Write + Send> = match out {
+                    Some(out) => Box::new(std::io::BufWriter::new(
+                        fs::File::create(&out)
+                            .with_context(|| format!("failed to create {}", out.display()))?,
+                    )),
+                    None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+                };
+                match log {
+                    db::build_history::BuildLog::Database(output) => {
+                        writer.write_all(output.as_bytes())?
+                    }
+                    db::build_history::BuildLog::Storage(path) => ctx
+                        .storage()?
+                        .copy_to(&path, &mut writer)
+                        .with_context(|| format!("failed to read {path} from storage"))?,
+                }
+                writer.flush()?;
+            }
+            Self::ExportSitemap { path, base_url } => {
+                let count = ctx
+                    .runtime()?
+                    .block_on(async {
+                        let mut conn = ctx.read_pool()?.get_async().await?;
+                        write_sitemap(&mut conn, &path, &base_url).await
+                    })
+                    .context("failed to export the sitemap")?;
+                println!("Wrote {count} URLs to {}", path.display());
+            }
 
             Self::Limits { command } => command.handle_args(ctx)?,
 
+            Self::ExportMetadata { path, resume_from } => {
+                let file = if let Some(resume_from) = &resume_from {
+                    db::metadata_export::prepare_resume(&path, resume_from)?
+                } else {
+                    fs::File::create(&path)
+                        .with_context(|| format!("could not create {}", path.display()))?
+                };
+
+                let exported = db::metadata_export::export_metadata(
+                    &mut *ctx.read_conn()?,
+                    std::io::BufWriter::new(file),
+                    resume_from.as_deref(),
+                )?;
+                println!("exported {exported} releases");
+            }
+
+            Self::AnonymizeExport {
+                path,
+                crates,
+                limit,
+                releases_per_crate,
+                salt,
+            } => {
+                let file = fs::File::create(&path)
+                    .with_context(|| format!("could not create {}", path.display()))?;
+                let options = db::anonymized_export::AnonymizedExportOptions {
+                    crates,
+                    limit,
+                    releases_per_crate,
+                    salt: salt.unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()),
+                };
+
+                let exported = db::anonymized_export::export_anonymized(
+                    &mut *ctx.read_conn()?,
+                    std::io::BufWriter::new(file),
+                    &options,
+                )?;
+                println!("exported {exported} crates to {}", path.display());
+            }
+
+            Self::IndexDrift { list } => {
+                let drift = index_drift(&ctx)?;
+                println!("crates not in the database: {:6}", drift.not_in_db.len());
+                println!("crates not in the index:    {:6}", drift.not_in_index.len());
+
+                if list {
+                    for name in &drift.not_in_db {
+                        println!("not in the database: {name}");
+                    }
+                    for name in &drift.not_in_index {
+                        println!("not in the index: {name}");
+                    }
+                }
+            }
+
+            #[cfg(feature = "consistency_check")]
+            Self::Synchronize { dry_run, strict } => {
+                let inconsistencies =
+                    docs_rs::utils::consistency::run_check(&ctx, dry_run || strict)?;
+                if strict && inconsistencies > 0 {
+                    anyhow::bail!("found {inconsistencies} inconsistencies");
+                }
+            }
+            #[cfg(feature = "consistency_check")]
+            Self::IntegrityReport { sample } => {
+                docs_rs::utils::consistency::run_integrity_report(&ctx, sample)?;
+            }
             #[cfg(feature = "consistency_check")]
-            Self::Synchronize { dry_run } => {
-                docs_rs::utils::consistency::run_check(&ctx, dry_run)?;
+            Self::ReconcileYanks { crate_name } => {
+                let changed =
+                    docs_rs::utils::consistency::reconcile_yanks(&ctx, crate_name.as_deref())?;
+                println!("{changed} releases changed their yank status");
             }
         }
         Ok(())
@@ -750,7 +2974,13 @@ impl DatabaseSubcommand {
 #[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
 enum LimitsSubcommand {
     /// Get sandbox limit overrides for a crate
-    Get { crate_name: String },
+    Get {
+        crate_name: String,
+
+        /// Print the overrides as JSON (`null` when the crate has none)
+        #[arg(long)]
+        json: bool,
+    },
 
     /// List sandbox limit overrides for all crates
     List,
@@ -764,6 +2994,18 @@ enum LimitsSubcommand {
         targets: Option<usize>,
         #[arg(long)]
         timeout: Option<Duration>,
+        /// Maximum size of the generated documentation in bytes, larger builds fail
+        #[arg(long)]
+        max_doc_size: Option<usize>,
+        /// Number of CPUs the sandbox may use
+        #[arg(long)]
+        cpus: Option<u32>,
+        /// CSS file that's added to the crate's documentation, it's uploaded to the storage
+        #[arg(long)]
+        custom_css: Option<PathBuf>,
+        /// Timeout for a single target, as `TRIPLE=DURATION`, can be given multiple times
+        #[arg(long = "target-timeout", value_parser = parse_target_timeout)]
+        target_timeouts: Vec<(String, Duration)>,
     },
 
     /// Remove sandbox limits overrides for a crate
@@ -777,9 +3019,49 @@ impl LimitsSubcommand {
             let mut conn = pool.get_async().await?;
 
             match self {
-                Self::Get { crate_name } => {
+                Self::Get { crate_name, json } => {
                     let overrides = Overrides::for_crate(&mut conn, &crate_name).await?;
-                    println!("sandbox limit overrides for {crate_name} = {overrides:?}");
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&overrides)?);
+                    } else if let Some(overrides) = overrides {
+                        let or_default =
+                            |value: Option<String>| value.unwrap_or_else(|| "default".to_string());
+                        println!(
+                            "memory  : {}",
+                            or_default(overrides.memory.map(|bytes| format!("{bytes} bytes")))
+                        );
+                        println!(
+                            "targets : {}",
+                            or_default(overrides.targets.map(|targets| targets.to_string()))
+                        );
+                        println!(
+                            "timeout : {}",
+                            or_default(
+                                overrides
+                                    .timeout
+                                    .map(|timeout| humantime::format_duration(timeout).to_string())
+                            )
+                        );
+                        println!(
+                            "doc size: {}",
+                            or_default(
+                                overrides.max_doc_size.map(|bytes| format!("{bytes} bytes"))
+                            )
+                        );
+                        println!(
+                            "cpus    : {}",
+                            or_default(overrides.cpus.map(|cpus| cpus.to_string()))
+                        );
+                        println!("css     : {}", or_default(overrides.custom_css));
+                        for (target, timeout) in &overrides.target_timeouts {
+                            println!(
+                                "timeout : {} ({target})",
+                                humantime::format_duration(*timeout)
+                            );
+                        }
+                    } else {
+                        println!("no sandbox limit overrides for {crate_name}");
+                    }
                 }
 
                 Self::List => {
@@ -793,13 +3075,34 @@ impl LimitsSubcommand {
                     memory,
                     targets,
                     timeout,
+                    max_doc_size,
+                    cpus,
+                    custom_css,
+                    target_timeouts,
                 } => {
                     let overrides = Overrides::for_crate(&mut conn, &crate_name).await?;
                     println!("previous sandbox limit overrides for {crate_name} = {overrides:?}");
+                    let custom_css = match custom_css {
+                        Some(path) => {
+                            let css = std::fs::read(&path).with_context(|| {
+                                format!("could not read the custom CSS at {}", path.display())
+                            })?;
+                            let storage = ctx.async_storage().await?;
+                            Some(storage.store_custom_css(&crate_name, css).await?)
+                        }
+                        None => None,
+                    };
                     let overrides = Overrides {
                         memory,
                         targets,
                         timeout: timeout.map(Into::into),
+                        max_doc_size,
+                        cpus,
+                        custom_css,
+                        target_timeouts: target_timeouts
+                            .into_iter()
+                            .map(|(target, timeout)| (target, timeout.into()))
+                            .collect(),
                     };
                     Overrides::save(&mut conn, &crate_name, overrides).await?;
                     let overrides = Overrides::for_crate(&mut conn, &crate_name).await?;
@@ -817,16 +3120,148 @@ impl LimitsSubcommand {
     }
 }
 
+fn parse_schedule_at(arg: &str) -> Result<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(arg) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    let delay = humantime::parse_duration(arg)
+        .context("expected a RFC 3339 timestamp or a duration like `6h`")?;
+    Ok(Utc::now() + chrono::Duration::from_std(delay)?)
+}
+
+/// Time budget of a maintenance sweep over many crates or releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::Args)]
+struct MaxRuntime {
+    /// Stop after this much time, the current item is still finished
+    #[arg(long, value_name = "DURATION")]
+    max_runtime: Option<Duration>,
+}
+
+impl MaxRuntime {
+    fn start(self) -> SweepProgress {
+        SweepProgress {
+            max_runtime: self.max_runtime,
+            started: Instant::now(),
+            processed: 0,
+            stopped: false,
+        }
+    }
+}
+
+/// Runs `update` for the crate `name`, or for all crates when it's `None`, until the
+/// time budget is used up. A failing crate is reported and the sweep continues.
+async fn sweep_crates<F>(
+    conn: &mut sqlx::PgConnection,
+    name: Option<String>,
+    max_runtime: MaxRuntime,
+    what: &str,
+    mut update: F,
+) -> Result<()>
+where
+    F: for<'a> FnMut(&'a mut sqlx::PgConnection, &'a str) -> BoxFuture<'a, Result<()>>,
+{
+    let names: Vec<String> = if let Some(name) = name {
+        vec![name]
+    } else {
+        sqlx::query_scalar!("SELECT name FROM crates ORDER BY name")
+            .fetch_all(&mut *conn)
+            .await?
+    };
+
+    let mut progress = max_runtime.start();
+    let mut failed = 0;
+    for name in &names {
+        if !progress.next() {
+            break;
+        }
+        if let Err(err) = update(&mut *conn, name).await {
+            eprintln!("failed to update {what} of {name}: {err:#}");
+            failed += 1;
+        }
+    }
+    progress.print_summary("crates", Some(names.len()));
+    if failed > 0 {
+        eprintln!("failed to update {what} of {failed} crates");
+    }
+
+    Ok(())
+}
+
+struct SweepProgress {
+    max_runtime: Option<Duration>,
+    started: Instant,
+    processed: usize,
+    stopped: bool,
+}
+
+impl SweepProgress {
+    fn deadline(&self) -> Option<Instant> {
+        self.max_runtime
+            .map(|max_runtime| self.started + *max_runtime)
+    }
+
+    /// Called before every item, returns `false` when the time budget is used up.
+    fn next(&mut self) -> bool {
+        if self
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.stopped = true;
+            return false;
+        }
+        self.processed += 1;
+        true
+    }
+
+    fn print_summary(&self, what: &str, total: Option<usize>) {
+        let elapsed = Duration::from(std::time::Duration::from_secs(
+            self.started.elapsed().as_secs(),
+        ));
+        match total {
+            Some(total) => eprint!(
+                "processed {} of {total} {what} in {elapsed}",
+                self.processed
+            ),
+            None => eprint!("processed {} {what} in {elapsed}", self.processed),
+        }
+        match self.max_runtime {
+            Some(max_runtime) if self.stopped => {
+                eprintln!(", stopped after the max runtime of {max_runtime}")
+            }
+            _ => eprintln!(),
+        }
+    }
+}
+
+fn parse_target_timeout(arg: &str) -> Result<(String, Duration)> {
+    let (target, timeout) = arg
+        .split_once('=')
+        .context("expected a target timeout in the form `TRIPLE=DURATION`")?;
+    Ok((target.to_owned(), timeout.parse()?))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
 enum BlacklistSubcommand {
     /// List all crates on the blacklist
-    List,
+    List {
+        /// Also print why and when the crates were added
+        #[arg(long)]
+        with_reason: bool,
+
+        /// Print the blacklist as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Add a crate to the blacklist
     Add {
         /// Crate name
         #[arg(name = "CRATE_NAME")]
         crate_name: String,
+
+        /// Why the crate is blacklisted
+        #[arg(long)]
+        reason: Option<String>,
     },
 
     /// Remove a crate from the blacklist
@@ -841,15 +3276,42 @@ impl BlacklistSubcommand {
     fn handle_args(self, ctx: BinContext) -> Result<()> {
         let conn = &mut *ctx.conn()?;
         match self {
-            Self::List => {
-                let crates = db::blacklist::list_crates(conn)
-                    .context("failed to list crates on blacklist")?;
+            Self::List { with_reason, json } => {
+                if with_reason {
+                    let entries = db::blacklist::list_entries(conn)
+                        .context("failed to list crates on blacklist")?;
+
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&entries)?);
+                    } else {
+                        for entry in entries {
+                            println!(
+                                "{}\t{}\t{}",
+                                entry.crate_name,
+                                entry.added_at.map_or_else(
+                                    || "-".to_string(),
+                                    |added_at| added_at.to_rfc3339()
+                                ),
+                                entry.reason.as_deref().unwrap_or("-"),
+                            );
+                        }
+                    }
+                } else {
+                    let crates = db::blacklist::list_crates(conn)
+                        .context("failed to list crates on blacklist")?;
 
-                println!("{}", crates.join("\n"));
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&crates)?);
+                    } else {
+                        println!("{}", crates.join("\n"));
+                    }
+                }
             }
 
-            Self::Add { crate_name } => db::blacklist::add_crate(conn, &crate_name)
-                .context("failed to add crate to blacklist")?,
+            Self::Add { crate_name, reason } => {
+                db::blacklist::add_crate(conn, &crate_name, reason.as_deref())
+                    .context("failed to add crate to blacklist")?
+            }
 
             Self::Remove { crate_name } => db::blacklist::remove_crate(conn, &crate_name)
                 .context("failed to remove crate from blacklist")?,
@@ -858,6 +3320,58 @@ impl BlacklistSubcommand {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+enum CrateAliasesSubcommand {
+    /// List all crate aliases
+    List,
+
+    /// Make a crate name redirect to another crate
+    Add {
+        /// The aliased crate name
+        #[arg(name = "CRATE_NAME")]
+        crate_name: String,
+        /// The crate the alias redirects to
+        #[arg(name = "TARGET")]
+        target: String,
+    },
+
+    /// Remove the alias of a crate name
+    Remove {
+        /// The aliased crate name
+        #[arg(name = "CRATE_NAME")]
+        crate_name: String,
+    },
+}
+
+impl CrateAliasesSubcommand {
+    fn handle_args(self, ctx: BinContext) -> Result<()> {
+        let conn = &mut *ctx.conn()?;
+        match self {
+            Self::List => {
+                for alias in
+                    db::crate_aliases::list_aliases(conn).context("failed to list crate aliases")?
+                {
+                    println!(
+                        "{} -> {} (added {})",
+                        alias.name,
+                        alias.target,
+                        alias.created_at.format("%Y-%m-%d")
+                    );
+                }
+            }
+
+            Self::Add { crate_name, target } => {
+                db::crate_aliases::add_alias(conn, &crate_name, &target)
+                    .context("failed to add crate alias")?
+            }
+
+            Self::Remove { crate_name } => db::crate_aliases::remove_alias(conn, &crate_name)
+                .context("failed to remove crate alias")?,
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
 enum DeleteSubcommand {
     /// Delete a whole crate
@@ -875,6 +3389,21 @@ enum DeleteSubcommand {
         /// The version of the crate to delete
         #[arg(name = "VERSION")]
         version: String,
+
+        /// Also delete the builds and build logs. When set to `false`, the release is kept
+        /// in the database, without documentation, so its build history stays available
+        #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+        include_builds: bool,
+    },
+    /// Delete all crates whose name matches a SQL `LIKE` pattern, after listing them
+    Pattern {
+        /// `LIKE` pattern, like `spam-%`
+        #[arg(name = "PATTERN")]
+        pattern: String,
+
+        /// Don't ask for confirmation before deleting the listed crates
+        #[arg(long)]
+        yes: bool,
     },
 }
 
@@ -884,6 +3413,7 @@ struct BinContext {
     cdn: OnceCell<Arc<CdnBackend>>,
     config: OnceCell<Arc<Config>>,
     pool: OnceCell<Pool>,
+    read_pool: OnceCell<Pool>,
     service_metrics: OnceCell<Arc<ServiceMetrics>>,
     instance_metrics: OnceCell<Arc<InstanceMetrics>>,
     index: OnceCell<Arc<Index>>,
@@ -900,6 +3430,7 @@ impl BinContext {
             cdn: OnceCell::new(),
             config: OnceCell::new(),
             pool: OnceCell::new(),
+            read_pool: OnceCell::new(),
             service_metrics: OnceCell::new(),
             instance_metrics: OnceCell::new(),
             index: OnceCell::new(),
@@ -912,19 +3443,53 @@ impl BinContext {
     fn conn(&self) -> Result<PoolClient> {
         Ok(self.pool()?.get()?)
     }
+
+    /// The pool of the read replica, or the primary pool when no replica is configured.
+    ///
+    /// Used by listing and stats commands that only read.
+    fn read_pool(&self) -> Result<Pool> {
+        Ok(self
+            .read_pool
+            .get_or_try_init::<_, Error>(|| {
+                match Pool::new_read_replica(
+                    &*self.config()?,
+                    self.runtime()?,
+                    self.instance_metrics()?,
+                )? {
+                    Some(pool) => Ok(pool),
+                    None => self.pool(),
+                }
+            })
+            .with_context(|| init_error("read_pool"))?
+            .clone())
+    }
+
+    fn read_conn(&self) -> Result<PoolClient> {
+        Ok(self.read_pool()?.get()?)
+    }
 }
 
+/// Defines getters that initialize the value on first use.
+///
+/// Failures are not cached: when the initialization returns an error or panics, the cell
+/// stays empty and the next call tries again, so long-running commands recover from
+/// transient failures like an unreachable database.
 macro_rules! lazy {
     ( $(fn $name:ident($self:ident) -> $type:ty = $init:expr);+ $(;)? ) => {
         $(fn $name(&$self) -> Result<Arc<$type>> {
             Ok($self
                 .$name
-                .get_or_try_init::<_, Error>(|| Ok(Arc::new($init)))?
+                .get_or_try_init::<_, Error>(|| Ok(Arc::new($init)))
+                .with_context(|| init_error(stringify!($name)))?
                 .clone())
         })*
     }
 }
 
+fn init_error(name: &str) -> String {
+    format!("failed to initialize the {}", name.replace('_', " "))
+}
+
 #[async_trait]
 impl Context for BinContext {
     lazy! {
@@ -983,7 +3548,8 @@ impl Context for BinContext {
                     self.runtime()?,
                     self.instance_metrics()?,
                 )?)
-            })?
+            })
+            .with_context(|| init_error("pool"))?
             .clone())
     }
 
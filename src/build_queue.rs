@@ -1,26 +1,281 @@
 use crate::cdn;
-use crate::db::{delete_crate, delete_version, update_latest_version_id, Pool};
+use crate::db::{
+    delete_crate, delete_version, update_build_status, update_latest_version_id,
+    DeleteVersionOptions, Pool,
+};
 use crate::docbuilder::PackageKind;
 use crate::error::Result;
 use crate::storage::Storage;
-use crate::utils::{get_config, get_crate_priority, report_error, retry, set_config, ConfigName};
+use crate::utils::{
+    get_config, get_crate_pattern_and_priority, get_crate_priority, parse_rustc_date, report_error,
+    retry, set_config, ConfigName,
+};
 use crate::Context;
 use crate::{Config, Index, InstanceMetrics, RustwideBuilder};
 use anyhow::Context as _;
+use chrono::{DateTime, Utc};
 use fn_error_context::context;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::runtime::Runtime;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+/// Why a release was added to the build queue.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    strum::Display,
+    strum::EnumString,
+    strum::IntoStaticStr,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum QueueSource {
+    /// a new release was published to the registry
+    RegistryWatcher,
+    /// an operator added it through the CLI
+    Manual,
+    /// a rebuild of an existing release was requested
+    Rebuild,
+    /// the consistency check found a release missing in our database
+    ConsistencyCheck,
+    /// a rebuild was requested through the web, see [`BuildQueue::record_web_trigger`]
+    WebTrigger,
+}
+
+impl QueueSource {
+    fn from_row(row: &postgres::Row) -> Option<Self> {
+        row.get::<_, Option<&str>>("source")
+            .and_then(|source| source.parse().ok())
+    }
+}
 
 #[derive(Debug, Clone, Eq, PartialEq, serde::Serialize)]
-pub(crate) struct QueuedCrate {
+pub struct QueuedCrate {
     #[serde(skip)]
     id: i32,
-    pub(crate) name: String,
-    pub(crate) version: String,
-    pub(crate) priority: i32,
-    pub(crate) registry: Option<String>,
+    pub name: String,
+    pub version: String,
+    pub priority: i32,
+    pub registry: Option<String>,
+    pub notify_url: Option<String>,
+    /// `None` for releases queued before the source was tracked
+    pub source: Option<QueueSource>,
+    /// Build with this toolchain instead of the configured one
+    pub toolchain: Option<String>,
+    /// Build the release even if the crate is on the blacklist
+    pub allow_blacklisted: bool,
+    /// Build with this build profile from the config
+    pub build_profile: Option<String>,
+    /// Don't invalidate the CDN caches of the crate after the build, see
+    /// [`BuildQueue::set_defer_cdn_invalidation`]
+    pub defer_cdn_invalidation: bool,
+}
+
+impl QueuedCrate {
+    fn from_row(row: &postgres::Row) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            version: row.get("version"),
+            priority: row.get("priority"),
+            registry: row.get("registry"),
+            notify_url: row.get("notify_url"),
+            source: QueueSource::from_row(row),
+            toolchain: row.get("toolchain"),
+            allow_blacklisted: row.get("allow_blacklisted"),
+            build_profile: row.get("build_profile"),
+            defer_cdn_invalidation: row.get("defer_cdn_invalidation"),
+        }
+    }
+}
+
+/// Selects the next crate the builder should build.
+///
+/// `$1` is the maximum amount of attempts.
+/// Releases of a crate are held back while `$2` other releases of the same crate are
+/// ahead of them in the queue, including the ones that are currently being built.
+const NEXT_CRATE_QUERY: &str = "
+    SELECT id, name, version, priority, registry, notify_url, source, toolchain, allow_blacklisted,
+           build_profile, defer_cdn_invalidation
+    FROM queue
+    WHERE
+        attempt < $1 AND
+        (next_attempt_at IS NULL OR next_attempt_at <= NOW()) AND
+        (scheduled_at IS NULL OR scheduled_at <= NOW()) AND
+        (
+            $2::BIGINT IS NULL OR
+            (
+                SELECT COUNT(*)
+                FROM queue AS ahead
+                WHERE
+                    ahead.name = queue.name AND
+                    ahead.attempt < $1 AND
+                    (ahead.next_attempt_at IS NULL OR ahead.next_attempt_at <= NOW()) AND
+                    (ahead.scheduled_at IS NULL OR ahead.scheduled_at <= NOW()) AND
+                    (ahead.priority, ahead.attempt, ahead.id) < (queue.priority, queue.attempt, queue.id)
+            ) < $2
+        ) AND
+        NOT EXISTS (
+            SELECT 1
+            FROM queue AS prerequisite
+            WHERE
+                prerequisite.name = queue.after_name AND
+                prerequisite.version = queue.after_version AND
+                prerequisite.attempt < $1
+        )
+    ORDER BY priority ASC, attempt ASC, id ASC
+    LIMIT 1";
+
+/// The delay before the next attempt doubles with every failed attempt, up to
+/// `2^MAX_BACKOFF_EXPONENT` times the configured delay between build attempts.
+const MAX_BACKOFF_EXPONENT: i32 = 10;
+
+/// A single row of the build queue, as written by `queue export` and read by `queue import`.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QueueSnapshotEntry {
+    pub name: String,
+    pub version: String,
+    pub priority: i32,
+    pub registry: Option<String>,
+    pub attempt: i32,
+    #[serde(default)]
+    pub source: Option<QueueSource>,
+}
+
+/// A release that ran out of build attempts, together with the error of its last attempt.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize)]
+pub struct DeadLetter {
+    pub name: String,
+    pub version: String,
+    pub priority: i32,
+    pub registry: Option<String>,
+    pub source: Option<QueueSource>,
+    pub attempts: i32,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Why a queued release is listed by [`BuildQueue::stuck_crates`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, serde::Serialize, strum::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum StuckReason {
+    /// the release is waiting to be built for longer than the threshold
+    QueuedTooLong,
+    /// the release failed to build at least as often as the threshold
+    ManyAttempts,
+}
+
+/// A queued release that is likely to be stuck.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize)]
+pub struct StuckCrate {
+    pub name: String,
+    pub version: String,
+    pub priority: i32,
+    pub attempt: i32,
+    /// When the release was queued, or when it was scheduled for if that's later
+    pub waiting_since: DateTime<Utc>,
+    pub last_attempt: Option<DateTime<Utc>>,
+    pub reasons: Vec<StuckReason>,
+}
+
+/// An idempotency key of `queue add`, see [`BuildQueue::add_crate_with_idempotency_key`].
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize)]
+pub struct IdempotencyKey {
+    pub key: String,
+    pub name: String,
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl IdempotencyKey {
+    fn from_row(row: &postgres::Row) -> Self {
+        Self {
+            key: row.get("key"),
+            name: row.get("name"),
+            version: row.get("version"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
+
+/// Settings of a release that [`BuildQueue::add_crate_with_options`] stores together with
+/// the queued release, so no build server can pick it up without them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueOptions {
+    /// Don't build the release before this time, see [`BuildQueue::set_scheduled_at`].
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// Only build the release after this `(name, version)`, see
+    /// [`BuildQueue::set_build_after`].
+    pub build_after: Option<(String, String)>,
+    /// Build the release with this toolchain, see [`BuildQueue::set_toolchain`].
+    pub toolchain: Option<String>,
+    /// Build the release even though its crate is on the blacklist, see
+    /// [`BuildQueue::set_allow_blacklisted`].
+    pub allow_blacklisted: bool,
+    /// Build the release with the named build profile from the config, see
+    /// [`BuildQueue::set_build_profile`].
+    pub build_profile: Option<String>,
+    /// Notify this URL once the release finished building, see
+    /// [`BuildQueue::set_notify_url`].
+    pub notify_url: Option<String>,
+}
+
+/// A release of a list parsed by [`parse_release_list`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ListedRelease<'a> {
+    /// The line of the release in the list, starting at 1
+    pub line: usize,
+    pub name: &'a str,
+    pub version: &'a str,
+    pub priority: i32,
+}
+
+/// Parses a list of releases to queue, as read by `queue add-from-file` and
+/// `queue add --stdin`, one `CRATE_NAME CRATE_VERSION [PRIORITY]` per line.
+///
+/// Empty lines and lines starting with `#` are ignored, releases without a priority get
+/// `default_priority`. Returns the releases and the errors of the malformed lines.
+pub fn parse_release_list(
+    content: &str,
+    default_priority: i32,
+) -> (Vec<ListedRelease<'_>>, Vec<anyhow::Error>) {
+    let mut releases = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let listed = |name, version, priority| ListedRelease {
+            line: index + 1,
+            name,
+            version,
+            priority,
+        };
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, version] => releases.push(listed(name, version, default_priority)),
+            [name, version, priority] => match priority.parse() {
+                Ok(priority) => releases.push(listed(name, version, priority)),
+                Err(_) => errors.push(anyhow::anyhow!(
+                    "line {}: invalid priority `{priority}`",
+                    index + 1
+                )),
+            },
+            _ => errors.push(anyhow::anyhow!(
+                "line {}: expected `CRATE_NAME CRATE_VERSION [PRIORITY]`, found `{line}`",
+                index + 1
+            )),
+        }
+    }
+    (releases, errors)
 }
 
 #[derive(Debug)]
@@ -31,6 +286,7 @@ pub struct BuildQueue {
     metrics: Arc<InstanceMetrics>,
     runtime: Arc<Runtime>,
     max_attempts: i32,
+    max_queued_per_crate: Option<i64>,
 }
 
 impl BuildQueue {
@@ -43,6 +299,7 @@ impl BuildQueue {
     ) -> Self {
         BuildQueue {
             max_attempts: config.build_attempts.into(),
+            max_queued_per_crate: config.max_queued_builds_per_crate.map(Into::into),
             config,
             db,
             metrics,
@@ -72,27 +329,463 @@ impl BuildQueue {
     }
 
     #[context("error trying to add {name}-{version} to build queue")]
+    /// Adds a release to the queue.
+    ///
+    /// When the release is already queued, the entry keeps the higher of both priorities
+    /// and its build attempts are reset. Releases that ran out of build attempts are
+    /// re-queued with the new priority, and removed from the dead letters. The stored source is always the one of the
+    /// latest call, and a toolchain set with [`BuildQueue::set_toolchain`], a build profile
+    /// from [`BuildQueue::set_build_profile`], an override from
+    /// [`BuildQueue::set_allow_blacklisted`], a schedule from
+    /// [`BuildQueue::set_scheduled_at`] or [`BuildQueue::set_defer_cdn_invalidation`]
+    /// is reset.
+    ///
+    /// Priorities outside of the configured range are clamped, with a warning.
     pub fn add_crate(
         &self,
         name: &str,
         version: &str,
         priority: i32,
         registry: Option<&str>,
+        source: QueueSource,
     ) -> Result<()> {
-        self.db.get()?.execute(
-            "INSERT INTO queue (name, version, priority, registry)
-             VALUES ($1, $2, $3, $4)
+        let mut conn = self.db.get()?;
+        self.queue_release(
+            &mut *conn,
+            name,
+            version,
+            priority,
+            registry,
+            source,
+            &QueueOptions::default(),
+        )
+    }
+
+    /// Adds a release to the queue like [`BuildQueue::add_crate`], together with its
+    /// `options`.
+    ///
+    /// The options are written with the queued release in a single transaction, unlike the
+    /// `set_*` methods for already queued releases that a build server might have started
+    /// to build in the meantime.
+    pub fn add_crate_with_options(
+        &self,
+        name: &str,
+        version: &str,
+        priority: i32,
+        registry: Option<&str>,
+        source: QueueSource,
+        options: &QueueOptions,
+    ) -> Result<()> {
+        let mut conn = self.db.get()?;
+        let mut transaction = conn.transaction()?;
+        self.queue_release(
+            &mut transaction,
+            name,
+            version,
+            priority,
+            registry,
+            source,
+            options,
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Adds all `(name, version, priority)` releases to the queue like
+    /// [`BuildQueue::add_crate`], in a single transaction. When one of them can't be queued,
+    /// none of them are.
+    ///
+    /// With `defer_cdn_invalidation`, the releases are also marked like with
+    /// [`BuildQueue::set_defer_cdn_invalidation`].
+    pub fn add_crates(
+        &self,
+        releases: &[(&str, &str, i32)],
+        registry: Option<&str>,
+        source: QueueSource,
+        defer_cdn_invalidation: bool,
+    ) -> Result<()> {
+        let mut conn = self.db.get()?;
+        let mut transaction = conn.transaction()?;
+        for &(name, version, priority) in releases {
+            self.queue_release(
+                &mut transaction,
+                name,
+                version,
+                priority,
+                registry,
+                source,
+                &QueueOptions::default(),
+            )
+            .and_then(|()| {
+                if defer_cdn_invalidation {
+                    transaction.execute(
+                        "UPDATE queue SET defer_cdn_invalidation = TRUE
+                         WHERE name = $1 AND version = $2",
+                        &[&name, &version],
+                    )?;
+                }
+                Ok(())
+            })
+            .with_context(|| format!("failed to queue {name}-{version}"))?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn queue_release(
+        &self,
+        conn: &mut impl postgres::GenericClient,
+        name: &str,
+        version: &str,
+        priority: i32,
+        registry: Option<&str>,
+        source: QueueSource,
+        options: &QueueOptions,
+    ) -> Result<()> {
+        let source: &'static str = source.into();
+        let priority = self.clamp_priority(&format!("{name}-{version}"), priority);
+        if let Some(build_profile) = &options.build_profile {
+            if !self.config.build_profiles.contains_key(build_profile) {
+                anyhow::bail!("unknown build profile {build_profile}");
+            }
+        }
+        let (after_name, after_version) = match &options.build_after {
+            Some((after_name, after_version)) => {
+                check_build_after(&mut *conn, name, version, after_name, after_version)?;
+                (Some(after_name), Some(after_version))
+            }
+            None => (None, None),
+        };
+        conn.execute(
+            "INSERT INTO queue (
+                name, version, priority, registry, source, scheduled_at, after_name, after_version,
+                toolchain, allow_blacklisted, build_profile, notify_url
+             )
+             VALUES ($1, $2, $3, $4, $6, $7, $8, $9, $10, $11, $12, $13)
              ON CONFLICT (name, version) DO UPDATE
-                SET priority = EXCLUDED.priority,
+                SET priority = CASE
+                        WHEN queue.attempt >= $5 THEN EXCLUDED.priority
+                        ELSE LEAST(queue.priority, EXCLUDED.priority)
+                    END,
                     registry = EXCLUDED.registry,
+                    source = EXCLUDED.source,
+                    toolchain = EXCLUDED.toolchain,
+                    allow_blacklisted = EXCLUDED.allow_blacklisted,
+                    defer_cdn_invalidation = FALSE,
+                    build_profile = EXCLUDED.build_profile,
+                    scheduled_at = EXCLUDED.scheduled_at,
+                    after_name = COALESCE(EXCLUDED.after_name, queue.after_name),
+                    after_version = COALESCE(EXCLUDED.after_version, queue.after_version),
+                    notify_url = COALESCE(EXCLUDED.notify_url, queue.notify_url),
                     attempt = 0,
-                    last_attempt = NULL
+                    last_attempt = NULL,
+                    next_attempt_at = NULL
             ;",
-            &[&name, &version, &priority, &registry],
+            &[
+                &name,
+                &version,
+                &priority,
+                &registry,
+                &self.max_attempts,
+                &source,
+                &options.scheduled_at,
+                &after_name,
+                &after_version,
+                &options.toolchain,
+                &options.allow_blacklisted,
+                &options.build_profile,
+                &options.notify_url,
+            ],
+        )?;
+        if options.allow_blacklisted {
+            warn!("{name}-{version} will be built even though it is blacklisted");
+        }
+        conn.execute(
+            "DELETE FROM build_dead_letter WHERE name = $1 AND version = $2",
+            &[&name, &version],
+        )?;
+        Ok(())
+    }
+
+    /// Limits `priority` to the configured range, `what` names the queued releases in the
+    /// warning.
+    fn clamp_priority(&self, what: &str, priority: i32) -> i32 {
+        let min = self.config.build_queue_min_priority;
+        let max = self.config.build_queue_max_priority;
+        let clamped = priority.max(min).min(max);
+        if clamped != priority {
+            warn!("priority {priority} of {what} is outside of {min}..={max}, using {clamped}");
+        }
+        clamped
+    }
+
+    /// Adds multiple releases of a crate to the queue, returns how many were added and the
+    /// errors of the versions that couldn't be queued. A failing version doesn't prevent
+    /// the others from being queued.
+    ///
+    /// With `only_missing`, versions that already have a successful build are skipped.
+    pub fn add_crate_versions(
+        &self,
+        name: &str,
+        versions: &[String],
+        priority: i32,
+        registry: Option<&str>,
+        only_missing: bool,
+        source: QueueSource,
+    ) -> Result<(usize, Vec<anyhow::Error>)> {
+        let built: HashSet<String> = if only_missing {
+            self.db
+                .get()?
+                .query(
+                    "SELECT releases.version
+                     FROM releases
+                     INNER JOIN crates ON crates.id = releases.crate_id
+                     WHERE
+                        crates.name = $1 AND
+                        EXISTS (
+                            SELECT 1 FROM builds
+                            WHERE builds.rid = releases.id AND builds.build_status = 'success'
+                        )",
+                    &[&name],
+                )?
+                .into_iter()
+                .map(|row| row.get(0))
+                .collect()
+        } else {
+            HashSet::new()
+        };
+
+        let mut added = 0;
+        let mut errors = Vec::new();
+        for version in versions.iter().filter(|v| !built.contains(*v)) {
+            match self.add_crate(name, version, priority, registry, source) {
+                Ok(()) => added += 1,
+                Err(err) => errors.push(err.context(format!("failed to queue {name}-{version}"))),
+            }
+        }
+        Ok((added, errors))
+    }
+
+    /// Stores a URL that is notified with a JSON POST request once the queued release
+    /// finished building, or ran out of build attempts.
+    pub fn set_notify_url(&self, name: &str, version: &str, url: &str) -> Result<()> {
+        let updated = self.db.get()?.execute(
+            "UPDATE queue SET notify_url = $3 WHERE name = $1 AND version = $2",
+            &[&name, &version, &url],
+        )?;
+        if updated == 0 {
+            anyhow::bail!("{name}-{version} is not queued");
+        }
+        Ok(())
+    }
+
+    /// Builds the queued release with the given toolchain instead of the configured one.
+    ///
+    /// Adding the release to the queue again resets the toolchain.
+    pub fn set_toolchain(&self, name: &str, version: &str, toolchain: &str) -> Result<()> {
+        let updated = self.db.get()?.execute(
+            "UPDATE queue SET toolchain = $3 WHERE name = $1 AND version = $2",
+            &[&name, &version, &toolchain],
+        )?;
+        if updated == 0 {
+            anyhow::bail!("{name}-{version} is not queued");
+        }
+        Ok(())
+    }
+
+    /// Builds the queued release with the named build profile from the config.
+    ///
+    /// Adding the release to the queue again resets the build profile.
+    pub fn set_build_profile(&self, name: &str, version: &str, build_profile: &str) -> Result<()> {
+        if !self.config.build_profiles.contains_key(build_profile) {
+            anyhow::bail!("unknown build profile {build_profile}");
+        }
+        let updated = self.db.get()?.execute(
+            "UPDATE queue SET build_profile = $3 WHERE name = $1 AND version = $2",
+            &[&name, &version, &build_profile],
+        )?;
+        if updated == 0 {
+            anyhow::bail!("{name}-{version} is not queued");
+        }
+        Ok(())
+    }
+
+    /// Builds the queued release even though its crate is on the blacklist.
+    ///
+    /// Adding the release to the queue again removes the override.
+    pub fn set_allow_blacklisted(&self, name: &str, version: &str) -> Result<()> {
+        let updated = self.db.get()?.execute(
+            "UPDATE queue SET allow_blacklisted = TRUE WHERE name = $1 AND version = $2",
+            &[&name, &version],
+        )?;
+        if updated == 0 {
+            anyhow::bail!("{name}-{version} is not queued");
+        }
+        warn!("{name}-{version} will be built even though it is blacklisted");
+        Ok(())
+    }
+
+    /// Only builds the queued release once the `after_name`-`after_version` build finished,
+    /// either successfully or by running out of build attempts.
+    ///
+    /// When the other release isn't queued, this has no effect. Dependencies that would
+    /// end up waiting on each other are rejected.
+    pub fn set_build_after(
+        &self,
+        name: &str,
+        version: &str,
+        after_name: &str,
+        after_version: &str,
+    ) -> Result<()> {
+        let mut conn = self.db.get()?;
+        let mut transaction = conn.transaction()?;
+
+        check_build_after(&mut transaction, name, version, after_name, after_version)?;
+
+        let updated = transaction.execute(
+            "UPDATE queue
+             SET after_name = $3, after_version = $4
+             WHERE name = $1 AND version = $2",
+            &[&name, &version, &after_name, &after_version],
+        )?;
+        if updated == 0 {
+            anyhow::bail!("{name}-{version} is not queued");
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Doesn't invalidate the CDN caches of the crate after the queued release was built.
+    ///
+    /// Meant for bulk rebuilds, which would otherwise exceed the invalidation limits of the
+    /// CDN. Once all releases with deferred invalidations are built, the background CDN
+    /// invalidator invalidates everything at once.
+    ///
+    /// Adding the release to the queue again resets this.
+    pub fn set_defer_cdn_invalidation(&self, name: &str, version: &str) -> Result<()> {
+        let updated = self.db.get()?.execute(
+            "UPDATE queue SET defer_cdn_invalidation = TRUE WHERE name = $1 AND version = $2",
+            &[&name, &version],
         )?;
+        if updated == 0 {
+            anyhow::bail!("{name}-{version} is not queued");
+        }
+        Ok(())
+    }
+
+    /// Doesn't build the queued release before `scheduled_at`, e.g. to move rebuilds to
+    /// off-peak hours.
+    ///
+    /// Adding the release to the queue again removes the schedule.
+    pub fn set_scheduled_at(
+        &self,
+        name: &str,
+        version: &str,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let updated = self.db.get()?.execute(
+            "UPDATE queue SET scheduled_at = $3 WHERE name = $1 AND version = $2",
+            &[&name, &version, &scheduled_at],
+        )?;
+        if updated == 0 {
+            anyhow::bail!("{name}-{version} is not queued");
+        }
+        Ok(())
+    }
+
+    /// Sends the build notification for a queued crate in the background, if it has a
+    /// notification URL.
+    ///
+    /// The status is `success` or `failure` after a build, and `failed` when the crate ran
+    /// out of build attempts.
+    fn notify(&self, krate: &QueuedCrate, status: &'static str) {
+        let Some(url) = krate.notify_url.clone() else {
+            return;
+        };
+
+        self.send_notification(
+            url,
+            serde_json::json!({
+                "name": krate.name,
+                "version": krate.version,
+                "status": status,
+            }),
+        );
+    }
+
+    /// How many of the latest finished builds of a crate failed in a row, counting at most
+    /// `limit` builds.
+    fn consecutive_failed_builds(&self, name: &str, limit: u32) -> Result<u32> {
+        let failed: Vec<bool> = self
+            .db
+            .get()?
+            .query(
+                "SELECT builds.build_status = 'failure'
+                 FROM builds
+                 INNER JOIN releases ON releases.id = builds.rid
+                 INNER JOIN crates ON crates.id = releases.crate_id
+                 WHERE
+                    crates.name = $1 AND
+                    builds.build_status != 'in_progress'
+                 ORDER BY builds.build_time DESC, builds.id DESC
+                 LIMIT $2",
+                &[&name, &i64::from(limit)],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(failed.into_iter().take_while(|failed| *failed).count() as u32)
+    }
+
+    /// Posts to the configured build failure webhook when the latest builds of the crate
+    /// reached the failure threshold. It's sent once per streak of failures, further
+    /// failures don't send it again until the crate built successfully.
+    fn notify_repeated_failures(
+        &self,
+        name: &str,
+        version: &str,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let Some(url) = self.config.build_failure_webhook_url.clone() else {
+            return Ok(());
+        };
+        let threshold = self.config.build_failure_notification_threshold;
+        if threshold == 0 || self.consecutive_failed_builds(name, threshold + 1)? != threshold {
+            return Ok(());
+        }
+
+        self.send_notification(
+            url,
+            serde_json::json!({
+                "name": name,
+                "version": version,
+                "status": "repeatedly_failed",
+                "failed_builds": threshold,
+                "error": error,
+            }),
+        );
         Ok(())
     }
 
+    fn send_notification(&self, url: String, payload: serde_json::Value) {
+        self.runtime.spawn(async move {
+            let result = reqwest::Client::new()
+                .post(&url)
+                .json(&payload)
+                .timeout(Duration::from_secs(10))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => info!(%url, %payload, "sent build notification"),
+                Err(err) => warn!(%url, %payload, ?err, "failed to send build notification"),
+            }
+        });
+    }
+
     pub(crate) fn pending_count(&self) -> Result<usize> {
         Ok(self.pending_count_by_priority()?.values().sum::<usize>())
     }
@@ -124,86 +817,435 @@ impl BuildQueue {
 
     pub(crate) fn failed_count(&self) -> Result<usize> {
         let res = self.db.get()?.query(
-            "SELECT COUNT(*) FROM queue WHERE attempt >= $1;",
+            "SELECT
+                (SELECT COUNT(*) FROM queue WHERE attempt >= $1) +
+                (SELECT COUNT(*) FROM build_dead_letter);",
             &[&self.max_attempts],
         )?;
         Ok(res[0].get::<_, i64>(0) as usize)
     }
 
-    pub(crate) fn queued_crates(&self) -> Result<Vec<QueuedCrate>> {
-        let query = self.db.get()?.query(
-            "SELECT id, name, version, priority, registry
-             FROM queue
-             WHERE attempt < $1
-             ORDER BY priority ASC, attempt ASC, id ASC",
-            &[&self.max_attempts],
+    /// Returns the queued releases that are waiting for longer than `waiting_longer_than`,
+    /// or that failed to build at least `min_attempts` times, the longest waiting first.
+    ///
+    /// Releases scheduled for later are only counted as waiting from their scheduled time.
+    pub fn stuck_crates(
+        &self,
+        waiting_longer_than: Duration,
+        min_attempts: i32,
+    ) -> Result<Vec<StuckCrate>> {
+        let rows = self.db.get()?.query(
+            "SELECT
+                name,
+                version,
+                priority,
+                attempt,
+                last_attempt,
+                waiting_since,
+                waiting_since < NOW() - make_interval(secs => $1) AS queued_too_long,
+                attempt >= $2 AS many_attempts
+             FROM (
+                SELECT *, GREATEST(queued_at, scheduled_at) AS waiting_since
+                FROM queue
+             ) AS queue
+             WHERE
+                waiting_since < NOW() - make_interval(secs => $1) OR
+                attempt >= $2
+             ORDER BY waiting_since, id",
+            &[&waiting_longer_than.as_secs_f64(), &min_attempts],
         )?;
 
-        Ok(query
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let mut reasons = Vec::new();
+                if row.get("queued_too_long") {
+                    reasons.push(StuckReason::QueuedTooLong);
+                }
+                if row.get("many_attempts") {
+                    reasons.push(StuckReason::ManyAttempts);
+                }
+                StuckCrate {
+                    name: row.get("name"),
+                    version: row.get("version"),
+                    priority: row.get("priority"),
+                    attempt: row.get("attempt"),
+                    waiting_since: row.get("waiting_since"),
+                    last_attempt: row.get("last_attempt"),
+                    reasons,
+                }
+            })
+            .collect())
+    }
+
+    /// Returns the releases that ran out of build attempts, the latest failure first.
+    pub fn dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        let rows = self.db.get()?.query(
+            "SELECT name, version, priority, registry, source, attempts, error, failed_at
+             FROM build_dead_letter
+             ORDER BY failed_at DESC, name, version",
+            &[],
+        )?;
+
+        Ok(rows
             .into_iter()
-            .map(|row| QueuedCrate {
-                id: row.get("id"),
+            .map(|row| DeadLetter {
                 name: row.get("name"),
                 version: row.get("version"),
                 priority: row.get("priority"),
                 registry: row.get("registry"),
+                source: QueueSource::from_row(&row),
+                attempts: row.get("attempts"),
+                error: row.get("error"),
+                failed_at: row.get("failed_at"),
             })
             .collect())
     }
 
-    pub fn has_build_queued(&self, name: &str, version: &str) -> Result<bool> {
-        Ok(self
+    /// Adds a release that ran out of build attempts to the queue again, with its previous
+    /// priority and registry.
+    pub fn requeue_dead_letter(&self, name: &str, version: &str) -> Result<()> {
+        let row = self
             .db
             .get()?
             .query_opt(
-                "SELECT id
-                 FROM queue
-                 WHERE
-                    attempt < $1 AND
-                    name = $2 AND
-                    version = $3
-                 ",
-                &[&self.max_attempts, &name, &version],
+                "SELECT priority, registry
+                 FROM build_dead_letter
+                 WHERE name = $1 AND version = $2",
+                &[&name, &version],
             )?
-            .is_some())
+            .with_context(|| format!("{name}-{version} is not a dead letter"))?;
+
+        self.add_crate(
+            name,
+            version,
+            row.get("priority"),
+            row.get::<_, Option<&str>>("registry"),
+            QueueSource::Manual,
+        )
     }
 
-    fn process_next_crate(&self, f: impl FnOnce(&QueuedCrate) -> Result<()>) -> Result<()> {
-        let mut conn = self.db.get()?;
-        let mut transaction = conn.transaction()?;
+    pub(crate) fn queued_crates(&self) -> Result<Vec<QueuedCrate>> {
+        let query = self.db.get()?.query(
+            "SELECT id, name, version, priority, registry, notify_url, source, toolchain, allow_blacklisted,
+                build_profile, defer_cdn_invalidation
+             FROM queue
+             WHERE attempt < $1
+             ORDER BY priority ASC, attempt ASC, id ASC",
+            &[&self.max_attempts],
+        )?;
 
-        // fetch the next available crate from the queue table.
-        // We are using `SELECT FOR UPDATE` inside a transaction so
-        // the QueuedCrate is locked until we are finished with it.
-        // `SKIP LOCKED` here will enable another build-server to just
-        // skip over taken (=locked) rows and start building the first
-        // available one.
-        let to_process = match transaction
+        Ok(query.iter().map(QueuedCrate::from_row).collect())
+    }
+
+    /// Returns the crate the builder would build next, without claiming it.
+    ///
+    /// In contrast to the builder, this doesn't skip crates that are currently being built.
+    pub fn peek_next_crate(&self) -> Result<Option<QueuedCrate>> {
+        Ok(self
+            .db
+            .get()?
             .query_opt(
-                "SELECT id, name, version, priority, registry
-                 FROM queue
-                 WHERE
-                    attempt < $1 AND
-                    (last_attempt IS NULL OR last_attempt < NOW() - make_interval(secs => $2))
-                 ORDER BY priority ASC, attempt ASC, id ASC
-                 LIMIT 1
-                 FOR UPDATE SKIP LOCKED",
-                &[
-                    &self.max_attempts,
-                    &self.config.delay_between_build_attempts.as_secs_f64(),
-                ],
+                NEXT_CRATE_QUERY,
+                &[&self.max_attempts, &self.max_queued_per_crate],
             )?
-            .map(|row| QueuedCrate {
-                id: row.get("id"),
+            .as_ref()
+            .map(QueuedCrate::from_row))
+    }
+
+    /// Returns all rows of the queue, including the ones that exceeded the maximum
+    /// amount of build attempts.
+    pub fn export(&self) -> Result<Vec<QueueSnapshotEntry>> {
+        let query = self.db.get()?.query(
+            "SELECT name, version, priority, registry, attempt, source
+             FROM queue
+             ORDER BY priority ASC, attempt ASC, id ASC",
+            &[],
+        )?;
+
+        Ok(query
+            .into_iter()
+            .map(|row| QueueSnapshotEntry {
                 name: row.get("name"),
                 version: row.get("version"),
                 priority: row.get("priority"),
                 registry: row.get("registry"),
-            }) {
-            Some(krate) => krate,
-            None => return Ok(()),
-        };
-
+                attempt: row.get("attempt"),
+                source: QueueSource::from_row(&row),
+            })
+            .collect())
+    }
+
+    /// Restores rows previously returned by [`BuildQueue::export`].
+    ///
+    /// Releases that are already queued are skipped. Priorities outside of the configured
+    /// range are clamped like in [`BuildQueue::add_crate`]. Returns the number of added rows.
+    pub fn import(&self, entries: &[QueueSnapshotEntry]) -> Result<usize> {
+        let mut conn = self.db.get()?;
+        let mut added = 0;
+
+        for entry in entries {
+            if self.has_build_queued(&entry.name, &entry.version)? {
+                debug!(
+                    "{}-{} is already queued, skipping",
+                    entry.name, entry.version
+                );
+                continue;
+            }
+
+            let priority =
+                self.clamp_priority(&format!("{}-{}", entry.name, entry.version), entry.priority);
+            added += conn.execute(
+                "INSERT INTO queue (name, version, priority, registry, attempt, source)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (name, version) DO NOTHING",
+                &[
+                    &entry.name,
+                    &entry.version,
+                    &priority,
+                    &entry.registry,
+                    &entry.attempt,
+                    &entry.source.map(<&'static str>::from),
+                ],
+            )? as usize;
+        }
+
+        Ok(added)
+    }
+
+    /// Re-evaluates the priority of all queued crates against the current priority patterns.
+    ///
+    /// Crates that don't match any pattern keep their priority, the priorities of patterns are
+    /// clamped to the configured range. Returns the number of updated rows.
+    pub fn reassign_priorities(&self) -> Result<usize> {
+        let mut conn = self.db.get()?;
+        let names: Vec<String> = conn
+            .query("SELECT DISTINCT name FROM queue", &[])?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let mut updated = 0;
+        for name in names {
+            if let Some((_, priority)) = get_crate_pattern_and_priority(&mut conn, &name)? {
+                let priority = self.clamp_priority(&name, priority);
+                updated += conn.execute(
+                    "UPDATE queue SET priority = $2 WHERE name = $1 AND priority <> $2",
+                    &[&name, &priority],
+                )? as usize;
+            }
+        }
+
+        Ok(updated)
+    }
+
+    /// Queues rebuilds for releases whose latest successful build used a rustc version older
+    /// than the currently installed one, most downloaded crates first.
+    ///
+    /// Releases that are already queued are skipped. Returns the number of queued releases.
+    /// With `defer_cdn_invalidation`, the CDN caches are only invalidated once all rebuilds
+    /// finished, see [`BuildQueue::set_defer_cdn_invalidation`].
+    pub fn queue_rebuilds_for_outdated_toolchain(
+        &self,
+        priority: i32,
+        limit: Option<i64>,
+        defer_cdn_invalidation: bool,
+    ) -> Result<usize> {
+        let mut conn = self.db.get()?;
+        let current_version: String = get_config(&mut conn, ConfigName::RustcVersion)?
+            .context("no rustc version is stored, run `build update-toolchain` first")?;
+        let current_date = parse_rustc_date(&current_version)?;
+
+        let releases = conn.query(
+            r"SELECT crates.name, releases.version
+              FROM releases
+              INNER JOIN crates ON crates.id = releases.crate_id
+              INNER JOIN LATERAL (
+                  SELECT builds.rustc_version
+                  FROM builds
+                  WHERE builds.rid = releases.id AND builds.build_status = 'success'
+                  ORDER BY builds.build_time DESC NULLS LAST, builds.id DESC
+                  LIMIT 1
+              ) AS latest_build ON TRUE
+              WHERE
+                  substring(latest_build.rustc_version from ' (\d+-\d+-\d+)\)$')::DATE < $1 AND
+                  NOT EXISTS (
+                      SELECT 1 FROM queue
+                      WHERE queue.name = crates.name AND queue.version = releases.version
+                  )
+              ORDER BY crates.downloads DESC NULLS LAST, releases.release_time DESC NULLS LAST
+              LIMIT $2",
+            &[&current_date, &limit],
+        )?;
+
+        for row in &releases {
+            self.add_crate(
+                row.get("name"),
+                row.get("version"),
+                priority,
+                None,
+                QueueSource::Rebuild,
+            )?;
+            if defer_cdn_invalidation {
+                self.set_defer_cdn_invalidation(row.get("name"), row.get("version"))?;
+            }
+        }
+
+        Ok(releases.len())
+    }
+
+    /// Records a rebuild of the release that was triggered through the web, unless the last
+    /// one was less than `DOCSRS_WEB_REBUILD_TRIGGER_INTERVAL` ago.
+    ///
+    /// Returns the time of the last trigger when the rebuild has to be rejected.
+    pub fn record_web_trigger(&self, name: &str, version: &str) -> Result<Option<DateTime<Utc>>> {
+        let mut conn = self.db.get()?;
+        let recorded = conn.query_opt(
+            "INSERT INTO web_rebuild_triggers (name, version, triggered_at)
+             VALUES ($1, $2, NOW())
+             ON CONFLICT (name, version) DO UPDATE
+                SET triggered_at = EXCLUDED.triggered_at
+                WHERE web_rebuild_triggers.triggered_at <= NOW() - make_interval(secs => $3)
+             RETURNING triggered_at",
+            &[
+                &name,
+                &version,
+                &self.config.web_rebuild_trigger_interval.as_secs_f64(),
+            ],
+        )?;
+        if recorded.is_some() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            conn.query_one(
+                "SELECT triggered_at FROM web_rebuild_triggers WHERE name = $1 AND version = $2",
+                &[&name, &version],
+            )?
+            .get(0),
+        ))
+    }
+
+    /// Returns the release the idempotency key was recorded for, if it was used before.
+    pub fn find_idempotency_key(&self, key: &str) -> Result<Option<IdempotencyKey>> {
+        Ok(self
+            .db
+            .get()?
+            .query_opt(
+                "SELECT key, name, version, created_at
+                 FROM queue_idempotency_keys
+                 WHERE key = $1",
+                &[&key],
+            )?
+            .map(|row| IdempotencyKey::from_row(&row)))
+    }
+
+    /// Adds a release to the queue like [`BuildQueue::add_crate_with_options`], unless a
+    /// request with the same idempotency key was already queued, for callers that deliver
+    /// requests more than once.
+    ///
+    /// The key is claimed in the same transaction that queues the release, so concurrent
+    /// requests with the same key queue it only once, and a failed request can be retried
+    /// with the same key. Returns the recorded key without touching the queue when it was
+    /// used before, possibly for another release.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_crate_with_idempotency_key(
+        &self,
+        key: &str,
+        name: &str,
+        version: &str,
+        priority: i32,
+        registry: Option<&str>,
+        source: QueueSource,
+        options: &QueueOptions,
+    ) -> Result<Option<IdempotencyKey>> {
+        let mut conn = self.db.get()?;
+        let mut transaction = conn.transaction()?;
+
+        let claimed = transaction.query_opt(
+            "INSERT INTO queue_idempotency_keys (key, name, version)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (key) DO NOTHING
+             RETURNING key",
+            &[&key, &name, &version],
+        )?;
+        if claimed.is_none() {
+            let recorded = transaction.query_one(
+                "SELECT key, name, version, created_at
+                 FROM queue_idempotency_keys
+                 WHERE key = $1",
+                &[&key],
+            )?;
+            return Ok(Some(IdempotencyKey::from_row(&recorded)));
+        }
+
+        self.queue_release(
+            &mut transaction,
+            name,
+            version,
+            priority,
+            registry,
+            source,
+            options,
+        )?;
+        transaction.commit()?;
+        Ok(None)
+    }
+
+    pub fn has_build_queued(&self, name: &str, version: &str) -> Result<bool> {
+        Ok(self
+            .db
+            .get()?
+            .query_opt(
+                "SELECT id
+                 FROM queue
+                 WHERE
+                    attempt < $1 AND
+                    name = $2 AND
+                    version = $3
+                 ",
+                &[&self.max_attempts, &name, &version],
+            )?
+            .is_some())
+    }
+
+    fn process_next_crate(&self, f: impl FnOnce(&QueuedCrate) -> Result<()>) -> Result<()> {
+        self.process_next_crate_with_outcome(|krate| {
+            f(krate).map(|()| ProcessedCrate {
+                successful: true,
+                invalidate_cdn: true,
+            })
+        })
+    }
+
+    /// Like [`BuildQueue::process_next_crate`], but `f` returns whether the build succeeded
+    /// and whether the CDN caches of the crate have to be invalidated. They are always
+    /// invalidated when `f` fails.
+    fn process_next_crate_with_outcome(
+        &self,
+        f: impl FnOnce(&QueuedCrate) -> Result<ProcessedCrate>,
+    ) -> Result<()> {
+        let mut conn = self.db.get()?;
+        let mut transaction = conn.transaction()?;
+
+        // fetch the next available crate from the queue table.
+        // We are using `SELECT FOR UPDATE` inside a transaction so
+        // the QueuedCrate is locked until we are finished with it.
+        // `SKIP LOCKED` here will enable another build-server to just
+        // skip over taken (=locked) rows and start building the first
+        // available one.
+        let to_process = match transaction
+            .query_opt(
+                &format!("{NEXT_CRATE_QUERY} FOR UPDATE SKIP LOCKED"),
+                &[&self.max_attempts, &self.max_queued_per_crate],
+            )?
+            .as_ref()
+            .map(QueuedCrate::from_row)
+        {
+            Some(krate) => krate,
+            None => return Ok(()),
+        };
+
         let res = self.metrics.build_time.observe_closure_duration(|| {
             f(&to_process).with_context(|| {
                 format!(
@@ -213,32 +1255,75 @@ impl BuildQueue {
             })
         });
         self.metrics.total_builds.inc();
-        if let Err(err) =
-            cdn::queue_crate_invalidation(&mut transaction, &self.config, &to_process.name)
-        {
-            report_error(&err);
+        if to_process.defer_cdn_invalidation {
+            debug!(
+                name = %to_process.name,
+                "deferring CDN invalidation until the bulk rebuild finished"
+            );
+        } else if res.as_ref().map_or(true, |outcome| outcome.invalidate_cdn) {
+            if let Err(err) =
+                cdn::queue_crate_invalidation(&mut transaction, &self.config, &to_process.name)
+            {
+                report_error(&err);
+            }
         }
 
+        // only sent after the transaction was committed, the queue entry might still
+        // be retried otherwise.
+        let mut notification = None;
         match res {
-            Ok(()) => {
+            Ok(outcome) => {
                 transaction.execute("DELETE FROM queue WHERE id = $1;", &[&to_process.id])?;
+                notification = Some(if outcome.successful {
+                    "success"
+                } else {
+                    "failure"
+                });
             }
             Err(e) => {
-                // Increase attempt count
+                // Increase attempt count, and back off exponentially with some jitter
+                // so failing crates don't keep the builder busy.
                 let attempt: i32 = transaction
                     .query_one(
                         "UPDATE queue
                          SET
                             attempt = attempt + 1,
-                            last_attempt = NOW()
+                            last_attempt = NOW(),
+                            next_attempt_at = NOW() + make_interval(
+                                secs => $2 * power(2, LEAST(attempt, $3)) * (1 + random() / 2)
+                            )
                          WHERE id = $1
                          RETURNING attempt;",
-                        &[&to_process.id],
+                        &[
+                            &to_process.id,
+                            &self.config.delay_between_build_attempts.as_secs_f64(),
+                            &MAX_BACKOFF_EXPONENT,
+                        ],
                     )?
                     .get(0);
 
                 if attempt >= self.max_attempts {
+                    // keep the release and its last error around, so it can be inspected
+                    // and requeued later.
+                    transaction.execute(
+                        "INSERT INTO build_dead_letter
+                            (name, version, priority, registry, source, attempts, error)
+                         SELECT name, version, priority, registry, source, attempt, $2
+                         FROM queue
+                         WHERE id = $1
+                         ON CONFLICT (name, version) DO UPDATE
+                            SET priority = EXCLUDED.priority,
+                                registry = EXCLUDED.registry,
+                                source = EXCLUDED.source,
+                                attempts = EXCLUDED.attempts,
+                                error = EXCLUDED.error,
+                                failed_at = NOW()",
+                        &[&to_process.id, &format!("{e:#}")],
+                    )?;
+                    transaction.execute("DELETE FROM queue WHERE id = $1;", &[&to_process.id])?;
+
                     self.metrics.failed_builds.inc();
+                    notification = Some("failed");
                 }
 
                 report_error(&e);
@@ -247,10 +1332,26 @@ impl BuildQueue {
 
         transaction.commit()?;
 
+        if let Some(status) = notification {
+            self.notify(&to_process, status);
+        }
+
+        if to_process.defer_cdn_invalidation {
+            set_config(&mut conn, ConfigName::DeferredCdnInvalidation, true)?;
+        }
+
         Ok(())
     }
 }
 
+/// What happened to a crate that was taken from the queue.
+struct ProcessedCrate {
+    /// Whether the documentation was built successfully.
+    successful: bool,
+    /// Whether the CDN caches of the crate have to be invalidated.
+    invalidate_cdn: bool,
+}
+
 /// Locking functions.
 impl BuildQueue {
     /// Checks for the lock and returns whether it currently exists.
@@ -261,15 +1362,118 @@ impl BuildQueue {
     }
 
     /// lock the queue. Daemon will check this lock and stop operating if it exists.
-    pub fn lock(&self) -> Result<()> {
+    ///
+    /// The optional message explains why the queue is locked, it's logged by the build
+    /// servers and shown on the queue page.
+    pub fn lock(&self, message: Option<&str>) -> Result<()> {
+        let was_locked = self.is_locked()?;
         let mut conn = self.db.get()?;
+        set_config(&mut conn, ConfigName::QueueLockMessage, message)?;
+        if !was_locked {
+            set_config(&mut conn, ConfigName::QueueLockedSince, Utc::now())?;
+        }
         set_config(&mut conn, ConfigName::QueueLocked, true)
     }
 
     /// unlock the queue.
     pub fn unlock(&self) -> Result<()> {
         let mut conn = self.db.get()?;
-        set_config(&mut conn, ConfigName::QueueLocked, false)
+        set_config(&mut conn, ConfigName::QueueLocked, false)?;
+        set_config(
+            &mut conn,
+            ConfigName::QueueLockedSince,
+            None::<DateTime<Utc>>,
+        )?;
+        set_config(&mut conn, ConfigName::QueueLockMessage, None::<&str>)
+    }
+
+    /// Returns when the queue was locked, if it's locked.
+    ///
+    /// Locking an already locked queue again keeps the original time.
+    pub fn locked_since(&self) -> Result<Option<DateTime<Utc>>> {
+        if !self.is_locked()? {
+            return Ok(None);
+        }
+
+        let mut conn = self.db.get()?;
+        Ok(get_config::<Option<DateTime<Utc>>>(&mut conn, ConfigName::QueueLockedSince)?.flatten())
+    }
+
+    /// Returns the message the queue was locked with, if it's locked and has one.
+    pub fn lock_message(&self) -> Result<Option<String>> {
+        if !self.is_locked()? {
+            return Ok(None);
+        }
+
+        let mut conn = self.db.get()?;
+        Ok(get_config::<Option<String>>(&mut conn, ConfigName::QueueLockMessage)?.flatten())
+    }
+}
+
+/// Build server heartbeats.
+///
+/// A build server that crashes releases its claim on the queued crate, since the claim is a
+/// row lock held by its database transaction. Its `in_progress` build stays behind though,
+/// so every build server regularly stores a heartbeat, and the builds of servers that
+/// stopped sending heartbeats are marked as failed.
+impl BuildQueue {
+    /// Stores that the given build server is still alive.
+    pub fn send_heartbeat(&self, hostname: &str) -> Result<()> {
+        self.db.get()?.execute(
+            "INSERT INTO build_servers (hostname, last_heartbeat)
+             VALUES ($1, NOW())
+             ON CONFLICT (hostname) DO UPDATE SET last_heartbeat = NOW()",
+            &[&hostname],
+        )?;
+        Ok(())
+    }
+
+    /// Marks the `in_progress` builds of build servers without a recent heartbeat as failed.
+    ///
+    /// The builds of `restarted_server` are always marked as failed, a freshly started build
+    /// server can't have running builds. Returns the number of failed builds.
+    pub fn fail_orphaned_builds(&self, restarted_server: Option<&str>) -> Result<usize> {
+        let release_ids: Vec<i32> = self
+            .db
+            .get()?
+            .query(
+                "UPDATE builds
+                 SET
+                     build_status = 'failure',
+                     errors = 'the build server stopped responding during the build'
+                 WHERE
+                     builds.build_status = 'in_progress' AND
+                     (
+                         builds.build_server = $2 OR
+                         EXISTS (
+                             SELECT 1 FROM build_servers
+                             WHERE
+                                 build_servers.hostname = builds.build_server AND
+                                 build_servers.last_heartbeat < NOW() - make_interval(secs => $1)
+                         )
+                     )
+                 RETURNING builds.rid",
+                &[
+                    &self.config.build_server_heartbeat_timeout.as_secs_f64(),
+                    &restarted_server,
+                ],
+            )?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        if !release_ids.is_empty() {
+            warn!("marked {} orphaned builds as failed", release_ids.len());
+            self.runtime.block_on(async {
+                let mut conn = self.db.get_async().await?;
+                for release_id in &release_ids {
+                    update_build_status(&mut conn, *release_id).await?;
+                }
+                Ok::<_, anyhow::Error>(())
+            })?;
+        }
+
+        Ok(release_ids.len())
     }
 }
 
@@ -279,22 +1483,120 @@ impl BuildQueue {
     ///
     /// Returns the number of crates added
     pub fn get_new_crates(&self, index: &Index) -> Result<usize> {
+        self.get_new_crates_in_batches(index, None, None)
+    }
+
+    /// Like [`BuildQueue::get_new_crates`], but with a `batch_size` the index commits are
+    /// diffed one after another, and the last seen reference is stored every time at
+    /// least `batch_size` changes were processed.
+    ///
+    /// When the registry watcher stops while processing a large diff, it resumes after the
+    /// last stored batch instead of processing the whole diff again.
+    ///
+    /// With an `enqueue_batch_size`, up to that many added releases are queued in one
+    /// transaction, instead of one transaction per release.
+    pub fn get_new_crates_in_batches(
+        &self,
+        index: &Index,
+        batch_size: Option<usize>,
+        enqueue_batch_size: Option<usize>,
+    ) -> Result<usize> {
         let mut conn = self.db.get()?;
         let diff = index.diff()?;
+        let registry = index.repository_url();
+        let enqueue_batch_size = enqueue_batch_size.unwrap_or(1);
 
         let last_seen_reference = self
             .last_seen_reference()?
             .context("no last_seen_reference set in database")?;
         diff.set_last_seen_reference(last_seen_reference)?;
 
-        let (changes, new_reference) = diff.peek_changes_ordered()?;
+        let commits = match batch_size {
+            Some(_) => {
+                let new_reference = index.fetch_latest_commit()?;
+                let commits = commits_between(&diff, last_seen_reference, new_reference)?;
+                if commits.is_none() {
+                    warn!(
+                        "{last_seen_reference} is not an ancestor of {new_reference}, \
+                         queueing the changes without batches"
+                    );
+                }
+                commits
+            }
+            None => None,
+        };
+        let (Some(batch_size), Some(commits)) = (batch_size, commits) else {
+            let (changes, new_reference) = diff.peek_changes_ordered()?;
+
+            debug!("queueing changes from {last_seen_reference} to {new_reference}");
+
+            let crates_added =
+                self.queue_index_changes(&mut conn, registry, &changes, enqueue_batch_size)?;
+
+            // set the reference in the database
+            // so this survives recreating the registry watcher
+            // server.
+            self.set_last_seen_reference(new_reference)?;
+
+            return Ok(crates_added);
+        };
+
+        debug!(
+            "queueing changes of {} commits after {last_seen_reference}",
+            commits.len()
+        );
+
+        let mut crates_added = 0;
+        let mut unsaved_changes = 0;
+        let mut previous = last_seen_reference;
+        for (i, &commit) in commits.iter().enumerate() {
+            let changes = diff
+                .changes_between_commits(previous, commit)
+                .with_context(|| format!("failed to diff the index from {previous} to {commit}"))?;
+            crates_added +=
+                self.queue_index_changes(&mut conn, registry, &changes, enqueue_batch_size)?;
+            unsaved_changes += changes.len();
+            previous = commit;
+
+            if unsaved_changes >= batch_size || i + 1 == commits.len() {
+                debug!("processed {unsaved_changes} changes up to {commit}");
+                self.set_last_seen_reference(commit)?;
+                unsaved_changes = 0;
+            }
+        }
+
+        Ok(crates_added)
+    }
+
+    /// Queues the added releases and handles deletions and yanks, returns the number of
+    /// crates added to the queue.
+    ///
+    /// Added releases are queued in transactions of up to `enqueue_batch_size` releases.
+    fn queue_index_changes(
+        &self,
+        conn: &mut postgres::Client,
+        registry: Option<&str>,
+        changes: &[crates_index_diff::Change],
+        enqueue_batch_size: usize,
+    ) -> Result<usize> {
         let mut crates_added = 0;
+        let mut added = Vec::new();
+
+        for change in changes {
+            if let Some(release) = change.added() {
+                added.push(release);
+                if change.yanked().is_none() {
+                    continue;
+                }
+            }
 
-        debug!("queueing changes from {last_seen_reference} to {new_reference}");
+            // the other changes might affect the added releases, which are queued first
+            crates_added +=
+                self.queue_added_releases(conn, registry, &added, enqueue_batch_size)?;
+            added.clear();
 
-        for change in &changes {
             if let Some((ref krate, ..)) = change.crate_deleted() {
-                match delete_crate(&mut conn, &self.storage, &self.config, krate)
+                match delete_crate(conn, &self.storage, &self.config, krate)
                     .with_context(|| format!("failed to delete crate {krate}"))
                 {
                     Ok(_) => info!(
@@ -311,11 +1613,12 @@ impl BuildQueue {
 
             if let Some(release) = change.version_deleted() {
                 match delete_version(
-                    &mut conn,
+                    conn,
                     &self.storage,
                     &self.config,
                     &release.name,
                     &release.version,
+                    DeleteVersionOptions::default(),
                 )
                 .with_context(|| {
                     format!(
@@ -337,15 +1640,65 @@ impl BuildQueue {
                 continue;
             }
 
-            if let Some(release) = change.added() {
-                let priority = get_crate_priority(&mut conn, &release.name)?;
+            let yanked = change.yanked();
+            let unyanked = change.unyanked();
+            if let Some(release) = yanked.or(unyanked) {
+                // FIXME: delay yanks of crates that have not yet finished building
+                // https://github.com/rust-lang/docs.rs/issues/1934
+                if let Err(err) = self.set_yanked(
+                    conn,
+                    release.name.as_str(),
+                    release.version.as_str(),
+                    yanked.is_some(),
+                ) {
+                    report_error(&err);
+                }
+
+                if let Err(err) =
+                    cdn::queue_crate_invalidation(&mut *conn, &self.config, &release.name)
+                {
+                    report_error(&err);
+                }
+            }
+        }
+
+        crates_added += self.queue_added_releases(conn, registry, &added, enqueue_batch_size)?;
+
+        Ok(crates_added)
+    }
+
+    /// Queues releases added to the index, up to `enqueue_batch_size` of them in one
+    /// transaction. A release that can't be queued is reported and skipped, the others
+    /// are still queued.
+    fn queue_added_releases(
+        &self,
+        conn: &mut postgres::Client,
+        registry: Option<&str>,
+        releases: &[&crates_index_diff::CrateVersion],
+        enqueue_batch_size: usize,
+    ) -> Result<usize> {
+        let mut crates_added = 0;
 
+        for chunk in releases.chunks(enqueue_batch_size.max(1)) {
+            let priorities = chunk
+                .iter()
+                .map(|release| get_crate_priority(conn, &release.name))
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut transaction = conn.transaction()?;
+            let mut added = 0;
+            for (release, priority) in chunk.iter().zip(priorities) {
+                // a savepoint, so a failed release doesn't abort the whole transaction
+                let mut savepoint = transaction.transaction()?;
                 match self
-                    .add_crate(
+                    .queue_release(
+                        &mut savepoint,
                         &release.name,
                         &release.version,
                         priority,
-                        index.repository_url(),
+                        registry,
+                        QueueSource::RegistryWatcher,
+                        &QueueOptions::default(),
                     )
                     .with_context(|| {
                         format!(
@@ -354,44 +1707,22 @@ impl BuildQueue {
                         )
                     }) {
                     Ok(()) => {
+                        savepoint.commit()?;
                         debug!(
                             "{}-{} added into build queue",
                             release.name, release.version
                         );
-                        self.metrics.queued_builds.inc();
-                        crates_added += 1;
+                        added += 1;
                     }
                     Err(err) => report_error(&err),
                 }
             }
+            transaction.commit()?;
 
-            let yanked = change.yanked();
-            let unyanked = change.unyanked();
-            if let Some(release) = yanked.or(unyanked) {
-                // FIXME: delay yanks of crates that have not yet finished building
-                // https://github.com/rust-lang/docs.rs/issues/1934
-                if let Err(err) = self.set_yanked(
-                    &mut conn,
-                    release.name.as_str(),
-                    release.version.as_str(),
-                    yanked.is_some(),
-                ) {
-                    report_error(&err);
-                }
-
-                if let Err(err) =
-                    cdn::queue_crate_invalidation(&mut *conn, &self.config, &release.name)
-                {
-                    report_error(&err);
-                }
-            }
+            self.metrics.queued_builds.inc_by(added as u64);
+            crates_added += added;
         }
 
-        // set the reference in the database
-        // so this survives recreating the registry watcher
-        // server.
-        self.set_last_seen_reference(new_reference)?;
-
         Ok(crates_added)
     }
 
@@ -490,7 +1821,7 @@ impl BuildQueue {
         builder: &mut RustwideBuilder,
     ) -> Result<bool> {
         let mut processed = false;
-        self.process_next_crate(|krate| {
+        self.process_next_crate_with_outcome(|krate| {
             processed = true;
 
             let kind = krate
@@ -508,7 +1839,7 @@ impl BuildQueue {
                 3,
             ) {
                 report_error(&err);
-                self.lock()?;
+                self.lock(Some("reinitializing the build workspace failed"))?;
                 return Err(err);
             }
 
@@ -517,35 +1848,247 @@ impl BuildQueue {
                 .context("Updating toolchain failed, locking queue")
             {
                 report_error(&err);
-                self.lock()?;
+                self.lock(Some("updating the toolchain failed"))?;
                 return Err(err);
             }
 
-            builder.build_package(&krate.name, &krate.version, kind)?;
-            Ok(())
+            builder.set_allow_blacklisted(krate.allow_blacklisted);
+            builder.set_build_profile(krate.build_profile.clone());
+            let report = if let Some(toolchain) = &krate.toolchain {
+                builder.with_toolchain(toolchain, |builder| {
+                    builder.build_package_with_report(&krate.name, &krate.version, kind)
+                })?
+            } else {
+                builder.build_package_with_report(&krate.name, &krate.version, kind)?
+            };
+            if !report.successful {
+                if let Err(err) = self.notify_repeated_failures(
+                    &krate.name,
+                    &krate.version,
+                    report.error.as_deref(),
+                ) {
+                    report_error(&err);
+                }
+            }
+            Ok(ProcessedCrate {
+                successful: report.successful,
+                // the cached pages are still up to date when the documentation didn't change
+                invalidate_cdn: !report.docs_unchanged,
+            })
         })?;
 
         Ok(processed)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Returns the commits after `from` up to and including `to`, oldest first, following
+/// the first parents of `to`.
+///
+/// Returns `None` when `from` isn't one of them, for example after the history of the
+/// index was squashed.
+fn commits_between(
+    diff: &crates_index_diff::Index,
+    from: crates_index_diff::gix::ObjectId,
+    to: crates_index_diff::gix::ObjectId,
+) -> Result<Option<Vec<crates_index_diff::gix::ObjectId>>> {
+    let mut commits = Vec::new();
+    for info in diff.repository().rev_walk([to]).first_parent_only().all()? {
+        let id = info?.id;
+        if id == from {
+            commits.reverse();
+            return Ok(Some(commits));
+        }
+        commits.push(id);
+    }
+    Ok(None)
+}
+
+/// Checks that `name`-`version` can wait for `after_name`-`after_version`, without
+/// dependencies that would end up waiting on each other.
+fn check_build_after(
+    conn: &mut impl postgres::GenericClient,
+    name: &str,
+    version: &str,
+    after_name: &str,
+    after_version: &str,
+) -> Result<()> {
+    if (name, version) == (after_name, after_version) {
+        anyhow::bail!("{name}-{version} can't be built after itself");
+    }
+
+    // walk the releases the other one is waiting for, this one can't be among them.
+    let cycle: bool = conn
+        .query_one(
+            "WITH RECURSIVE chain (name, version) AS (
+                SELECT after_name, after_version
+                FROM queue
+                WHERE name = $3 AND version = $4 AND after_name IS NOT NULL
+                UNION
+                SELECT queue.after_name, queue.after_version
+                FROM queue
+                INNER JOIN chain ON chain.name = queue.name AND chain.version = queue.version
+                WHERE queue.after_name IS NOT NULL
+             )
+             SELECT EXISTS (SELECT 1 FROM chain WHERE name = $1 AND version = $2)",
+            &[&name, &version, &after_name, &after_version],
+        )?
+        .get(0);
+    if cycle {
+        anyhow::bail!(
+            "{after_name}-{after_version} is already waiting for {name}-{version}, \
+             they can't be built after each other"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::types::BuildStatus;
+    use crate::test::FakeBuild;
+    use crate::utils::set_crate_priority;
     use chrono::{DateTime, Utc};
     use std::time::Duration;
 
     #[test]
-    fn test_add_duplicate_doesnt_fail_last_priority_wins() {
+    fn test_parse_release_list() {
+        let (releases, errors) = parse_release_list(
+            "# comment\n\
+             foo 1.0.0\n\
+             \n\
+             bar 0.2.0 -3\n\
+             baz\n\
+             qux 0.1.0 high\n  \
+             quux 1.0.0-beta.1 7  \n",
+            5,
+        );
+        assert_eq!(
+            releases,
+            vec![
+                ListedRelease {
+                    line: 2,
+                    name: "foo",
+                    version: "1.0.0",
+                    priority: 5,
+                },
+                ListedRelease {
+                    line: 4,
+                    name: "bar",
+                    version: "0.2.0",
+                    priority: -3,
+                },
+                ListedRelease {
+                    line: 7,
+                    name: "quux",
+                    version: "1.0.0-beta.1",
+                    priority: 7,
+                },
+            ]
+        );
+        let errors: Vec<_> = errors.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "line 5: expected `CRATE_NAME CRATE_VERSION [PRIORITY]`, found `baz`",
+                "line 6: invalid priority `high`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_duplicate_keeps_priority_in_any_order() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            // the registry watcher and a manual `queue add` can race in any order
+            queue.add_crate("some_crate", "0.1.1", 0, None, QueueSource::Manual)?;
+            queue.add_crate("some_crate", "0.1.1", 9, None, QueueSource::Manual)?;
+            queue.add_crate("other_crate", "0.1.1", 9, None, QueueSource::Manual)?;
+            queue.add_crate("other_crate", "0.1.1", 0, None, QueueSource::Manual)?;
+
+            let queued_crates = queue.queued_crates()?;
+            assert_eq!(
+                queued_crates
+                    .iter()
+                    .map(|krate| (krate.name.as_str(), krate.priority))
+                    .collect::<Vec<_>>(),
+                vec![("some_crate", 0), ("other_crate", 0)]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_duplicate_updates_source() {
         crate::test::wrapper(|env| {
             let queue = env.build_queue();
 
-            queue.add_crate("some_crate", "0.1.1", 0, None)?;
-            queue.add_crate("some_crate", "0.1.1", 9, None)?;
+            queue.add_crate("some_crate", "0.1.1", 0, None, QueueSource::RegistryWatcher)?;
+            assert_eq!(
+                queue.queued_crates()?[0].source,
+                Some(QueueSource::RegistryWatcher)
+            );
+
+            queue.add_crate("some_crate", "0.1.1", 5, None, QueueSource::Rebuild)?;
+            assert_eq!(queue.queued_crates()?[0].source, Some(QueueSource::Rebuild));
+
+            // rows queued before the source was tracked
+            env.db()
+                .conn()
+                .execute("UPDATE queue SET source = NULL", &[])?;
+            assert_eq!(queue.queued_crates()?[0].source, None);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_concurrently() {
+        crate::test::wrapper(|env| {
+            let queue = &env.build_queue();
+
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = [5, 0, 20]
+                    .into_iter()
+                    .map(|priority| {
+                        scope.spawn(move || {
+                            queue.add_crate(
+                                "some_crate",
+                                "0.1.1",
+                                priority,
+                                None,
+                                QueueSource::Manual,
+                            )
+                        })
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .try_for_each(|handle| handle.join().unwrap())
+            })?;
 
             let queued_crates = queue.queued_crates()?;
             assert_eq!(queued_crates.len(), 1);
-            assert_eq!(queued_crates[0].priority, 9);
+            assert_eq!(queued_crates[0].priority, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_duplicate_doesnt_fail_highest_priority_wins() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("some_crate", "0.1.1", 0, None, QueueSource::Manual)?;
+            queue.add_crate("some_crate", "0.1.1", 9, None, QueueSource::Manual)?;
+
+            let queued_crates = queue.queued_crates()?;
+            assert_eq!(queued_crates.len(), 1);
+            assert_eq!(queued_crates[0].priority, 0);
 
             Ok(())
         })
@@ -570,7 +2113,7 @@ mod tests {
 
             assert_eq!(queue.pending_count()?, 0);
 
-            queue.add_crate("failed_crate", "0.1.1", 9, None)?;
+            queue.add_crate("failed_crate", "0.1.1", 9, None, QueueSource::Manual)?;
 
             assert_eq!(queue.pending_count()?, 1);
 
@@ -594,7 +2137,7 @@ mod tests {
         crate::test::wrapper(|env| {
             let queue = env.build_queue();
 
-            queue.add_crate("dummy", "0.1.1", 0, None)?;
+            queue.add_crate("dummy", "0.1.1", 0, None, QueueSource::Manual)?;
             assert!(queue.has_build_queued("dummy", "0.1.1")?);
 
             env.db()
@@ -607,6 +2150,730 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_max_queued_builds_per_crate() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.max_queued_builds_per_crate = Some(1);
+            });
+
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("foo", "1.0.1", 0, None, QueueSource::Manual)?;
+            queue.add_crate("bar", "1.0.0", 0, None, QueueSource::Manual)?;
+
+            assert_eq!(
+                queue.peek_next_crate()?.map(|krate| krate.name),
+                Some("foo".into())
+            );
+
+            let mut built = Vec::new();
+            queue.process_next_crate(|krate| {
+                built.push((krate.name.clone(), krate.version.clone()));
+
+                // while `foo 1.0.0` is being built, a second builder has to skip `foo 1.0.1`
+                queue.process_next_crate(|krate| {
+                    built.push((krate.name.clone(), krate.version.clone()));
+                    Ok(())
+                })?;
+
+                Ok(())
+            })?;
+
+            queue.process_next_crate(|krate| {
+                built.push((krate.name.clone(), krate.version.clone()));
+                Ok(())
+            })?;
+
+            assert_eq!(
+                built,
+                vec![
+                    ("foo".into(), "1.0.0".into()),
+                    ("bar".into(), "1.0.0".into()),
+                    ("foo".into(), "1.0.1".into()),
+                ]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_versions() {
+        crate::test::wrapper(|env| {
+            env.fake_release().name("foo").version("1.0.0").create()?;
+            env.fake_release()
+                .name("foo")
+                .version("1.1.0")
+                .build_result_failed()
+                .create()?;
+
+            let queue = env.build_queue();
+            let versions = vec!["1.0.0".to_string(), "1.1.0".into(), "1.2.0".into()];
+
+            assert_eq!(
+                queue
+                    .add_crate_versions("foo", &versions, 5, None, true, QueueSource::Manual)?
+                    .0,
+                2
+            );
+            let mut queued: Vec<_> = queue
+                .queued_crates()?
+                .into_iter()
+                .map(|krate| krate.version)
+                .collect();
+            queued.sort();
+            assert_eq!(queued, vec!["1.1.0", "1.2.0"]);
+
+            assert_eq!(
+                queue
+                    .add_crate_versions("foo", &versions, 5, None, false, QueueSource::Manual)?
+                    .0,
+                3
+            );
+            assert_eq!(queue.pending_count()?, 3);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_versions_skips_invalid_versions() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+            let versions = vec!["1.0.0".to_string(), "1.1.0\0".into(), "1.2.0".into()];
+
+            let (added, errors) =
+                queue.add_crate_versions("foo", &versions, 5, None, false, QueueSource::Manual)?;
+            assert_eq!(added, 2);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].to_string(), "failed to queue foo-1.1.0\0");
+
+            let mut queued: Vec<_> = queue
+                .queued_crates()?
+                .into_iter()
+                .map(|krate| krate.version)
+                .collect();
+            queued.sort();
+            assert_eq!(queued, vec!["1.0.0", "1.2.0"]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_clamps_priority() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_queue_min_priority = -10;
+                config.build_queue_max_priority = 10;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("low", "1.0.0", 500, None, QueueSource::Manual)?;
+            queue.add_crate("high", "1.0.0", -500, None, QueueSource::Manual)?;
+            queue.add_crate("normal", "1.0.0", 3, None, QueueSource::Manual)?;
+
+            let priorities: Vec<_> = queue
+                .queued_crates()?
+                .into_iter()
+                .map(|krate| (krate.name, krate.priority))
+                .collect();
+            assert_eq!(
+                priorities,
+                vec![
+                    ("high".into(), -10),
+                    ("normal".into(), 3),
+                    ("low".into(), 10)
+                ]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_set_toolchain() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.set_toolchain("foo", "1.0.0", "nightly-2020-01-01")?;
+            assert!(queue
+                .set_toolchain("bar", "1.0.0", "nightly-2020-01-01")
+                .is_err());
+
+            assert_eq!(
+                queue.peek_next_crate()?.unwrap().toolchain.as_deref(),
+                Some("nightly-2020-01-01")
+            );
+
+            // re-adding the crate builds it with the configured toolchain again
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            assert!(queue.peek_next_crate()?.unwrap().toolchain.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_with_toolchain() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &QueueOptions {
+                    toolchain: Some("nightly-2020-01-01".into()),
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(
+                queue.peek_next_crate()?.unwrap().toolchain.as_deref(),
+                Some("nightly-2020-01-01")
+            );
+
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &QueueOptions::default(),
+            )?;
+            assert!(queue.peek_next_crate()?.unwrap().toolchain.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_set_allow_blacklisted() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            assert!(!queue.peek_next_crate()?.unwrap().allow_blacklisted);
+
+            queue.set_allow_blacklisted("foo", "1.0.0")?;
+            assert!(queue.set_allow_blacklisted("bar", "1.0.0").is_err());
+            assert!(queue.peek_next_crate()?.unwrap().allow_blacklisted);
+
+            // the override only applies to a single addition
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            assert!(!queue.peek_next_crate()?.unwrap().allow_blacklisted);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_with_allow_blacklisted() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &QueueOptions {
+                    allow_blacklisted: true,
+                    ..Default::default()
+                },
+            )?;
+            assert!(queue.peek_next_crate()?.unwrap().allow_blacklisted);
+
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &QueueOptions::default(),
+            )?;
+            assert!(!queue.peek_next_crate()?.unwrap().allow_blacklisted);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_set_build_profile() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config
+                    .build_profiles
+                    .insert("wasm".into(), Default::default());
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            assert_eq!(queue.peek_next_crate()?.unwrap().build_profile, None);
+
+            queue.set_build_profile("foo", "1.0.0", "wasm")?;
+            assert!(queue.set_build_profile("foo", "1.0.0", "unknown").is_err());
+            assert!(queue.set_build_profile("bar", "1.0.0", "wasm").is_err());
+            assert_eq!(
+                queue.peek_next_crate()?.unwrap().build_profile.as_deref(),
+                Some("wasm")
+            );
+
+            // the profile only applies to a single addition
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            assert_eq!(queue.peek_next_crate()?.unwrap().build_profile, None);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_with_build_profile() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config
+                    .build_profiles
+                    .insert("wasm".into(), Default::default());
+            });
+            let queue = env.build_queue();
+            let profile = |name: &str| QueueOptions {
+                build_profile: Some(name.into()),
+                ..Default::default()
+            };
+
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &profile("wasm"),
+            )?;
+            assert_eq!(
+                queue.peek_next_crate()?.unwrap().build_profile.as_deref(),
+                Some("wasm")
+            );
+
+            assert!(queue
+                .add_crate_with_options(
+                    "bar",
+                    "1.0.0",
+                    0,
+                    None,
+                    QueueSource::Manual,
+                    &profile("unknown")
+                )
+                .is_err());
+            assert!(!queue.has_build_queued("bar", "1.0.0")?);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_record_web_trigger() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            assert_eq!(queue.record_web_trigger("foo", "1.0.0")?, None);
+            assert_eq!(queue.record_web_trigger("bar", "1.0.0")?, None);
+            let last_trigger = queue.record_web_trigger("foo", "1.0.0")?;
+            assert!(last_trigger.is_some());
+
+            // rejected triggers don't extend the window
+            assert_eq!(queue.record_web_trigger("foo", "1.0.0")?, last_trigger);
+
+            env.db().conn().execute(
+                "UPDATE web_rebuild_triggers SET triggered_at = NOW() - INTERVAL '2 days'",
+                &[],
+            )?;
+            assert_eq!(queue.record_web_trigger("foo", "1.0.0")?, None);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crates() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crates(
+                &[("foo", "1.0.0", 0), ("bar", "1.0.0", 5)],
+                None,
+                QueueSource::Manual,
+                true,
+            )?;
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued.len(), 2);
+            assert!(queued.iter().all(|krate| krate.defer_cdn_invalidation));
+
+            // nothing is queued when one of the releases fails
+            assert!(queue
+                .add_crates(
+                    &[("baz", "1.0.0", 0), ("invalid", "1.0.0\0", 0)],
+                    None,
+                    QueueSource::Manual,
+                    false,
+                )
+                .is_err());
+            assert!(!queue.has_build_queued("baz", "1.0.0")?);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_queue_index_changes_in_transactions() {
+        crate::test::wrapper(|env| {
+            env.fake_release().name("old").version("0.1.0").create()?;
+            let queue = env.build_queue();
+
+            let release = |name: &str, version: &str| crates_index_diff::CrateVersion {
+                name: name.into(),
+                version: version.into(),
+                ..Default::default()
+            };
+            let changes = vec![
+                crates_index_diff::Change::Added(release("foo", "1.0.0")),
+                crates_index_diff::Change::Added(release("invalid", "1.0.0\0")),
+                crates_index_diff::Change::Added(release("bar", "1.0.0")),
+                crates_index_diff::Change::Yanked(release("old", "0.1.0")),
+                crates_index_diff::Change::Added(release("baz", "1.0.0")),
+            ];
+
+            let mut conn = env.db().conn();
+            // the failed release doesn't prevent the others of its transaction
+            assert_eq!(queue.queue_index_changes(&mut conn, None, &changes, 2)?, 3);
+
+            let mut queued: Vec<_> = queue
+                .queued_crates()?
+                .into_iter()
+                .map(|krate| krate.name)
+                .collect();
+            queued.sort();
+            assert_eq!(queued, ["bar", "baz", "foo"]);
+
+            let yanked: Option<bool> = conn
+                .query_one(
+                    "SELECT releases.yanked
+                     FROM releases
+                     INNER JOIN crates ON crates.id = releases.crate_id
+                     WHERE crates.name = 'old' AND releases.version = '0.1.0'",
+                    &[],
+                )?
+                .get(0);
+            assert_eq!(yanked, Some(true));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_idempotency_keys() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            assert_eq!(queue.find_idempotency_key("key")?, None);
+            assert_eq!(
+                queue.add_crate_with_idempotency_key(
+                    "key",
+                    "foo",
+                    "1.0.0",
+                    0,
+                    None,
+                    QueueSource::Manual,
+                    &QueueOptions::default(),
+                )?,
+                None
+            );
+            assert_eq!(queue.queued_crates()?.len(), 1);
+
+            // repeated keys don't touch the queue, and the first release of a key is kept
+            queue.process_next_crate(|_| Ok(()))?;
+            let recorded = queue
+                .add_crate_with_idempotency_key(
+                    "key",
+                    "bar",
+                    "1.0.0",
+                    0,
+                    None,
+                    QueueSource::Manual,
+                    &QueueOptions::default(),
+                )?
+                .unwrap();
+            assert_eq!(recorded.name, "foo");
+            assert_eq!(recorded.version, "1.0.0");
+            assert!(queue.queued_crates()?.is_empty());
+
+            assert_eq!(queue.find_idempotency_key("key")?, Some(recorded));
+            assert_eq!(queue.find_idempotency_key("other")?, None);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_set_build_after() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("bar", "1.0.0", 10, None, QueueSource::Manual)?;
+            queue.set_build_after("foo", "1.0.0", "bar", "1.0.0")?;
+            assert!(queue
+                .set_build_after("foo", "1.0.0", "foo", "1.0.0")
+                .is_err());
+            assert!(queue
+                .set_build_after("baz", "1.0.0", "bar", "1.0.0")
+                .is_err());
+
+            let mut built = Vec::new();
+            queue.process_next_crate(|krate| {
+                built.push(krate.name.clone());
+
+                // `foo` has to wait until `bar` finished building
+                queue.process_next_crate(|krate| {
+                    built.push(krate.name.clone());
+                    Ok(())
+                })?;
+
+                Ok(())
+            })?;
+
+            queue.process_next_crate(|krate| {
+                built.push(krate.name.clone());
+                Ok(())
+            })?;
+
+            assert_eq!(built, vec!["bar".to_string(), "foo".to_string()]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_set_build_after_rejects_cycles() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            for name in ["foo", "bar", "baz"] {
+                queue.add_crate(name, "1.0.0", 0, None, QueueSource::Manual)?;
+            }
+            queue.set_build_after("foo", "1.0.0", "bar", "1.0.0")?;
+            queue.set_build_after("bar", "1.0.0", "baz", "1.0.0")?;
+
+            assert!(queue
+                .set_build_after("bar", "1.0.0", "foo", "1.0.0")
+                .is_err());
+            assert!(queue
+                .set_build_after("baz", "1.0.0", "foo", "1.0.0")
+                .is_err());
+
+            let mut built = Vec::new();
+            while queue.pending_count()? > 0 {
+                queue.process_next_crate(|krate| {
+                    built.push(krate.name.clone());
+                    Ok(())
+                })?;
+            }
+            assert_eq!(built, vec!["baz", "bar", "foo"]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_with_build_after() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+            let after = |name: &str| QueueOptions {
+                build_after: Some((name.into(), "1.0.0".into())),
+                ..Default::default()
+            };
+
+            queue.add_crate("bar", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                -10,
+                None,
+                QueueSource::Manual,
+                &after("bar"),
+            )?;
+
+            // the prerequisite is built first, even with a lower priority
+            assert_eq!(queue.peek_next_crate()?.unwrap().name, "bar");
+
+            assert!(queue
+                .add_crate_with_options("foo", "1.0.0", 0, None, QueueSource::Manual, &after("foo"))
+                .is_err());
+            assert!(queue
+                .add_crate_with_options("bar", "1.0.0", 0, None, QueueSource::Manual, &after("foo"))
+                .is_err());
+
+            // a rejected dependency doesn't queue the release
+            assert!(queue
+                .add_crate_with_options("baz", "1.0.0", 0, None, QueueSource::Manual, &after("baz"))
+                .is_err());
+            assert!(!queue.has_build_queued("baz", "1.0.0")?);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_set_scheduled_at() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("later", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("now", "1.0.0", 10, None, QueueSource::Manual)?;
+            queue.set_scheduled_at(
+                "later",
+                "1.0.0",
+                Utc::now() + chrono::Duration::try_hours(1).unwrap(),
+            )?;
+            assert!(queue
+                .set_scheduled_at("unknown", "1.0.0", Utc::now())
+                .is_err());
+
+            let next = queue.peek_next_crate()?.unwrap();
+            assert_eq!(next.name, "now");
+
+            queue.set_scheduled_at(
+                "later",
+                "1.0.0",
+                Utc::now() - chrono::Duration::try_seconds(1).unwrap(),
+            )?;
+            let next = queue.peek_next_crate()?.unwrap();
+            assert_eq!(next.name, "later");
+
+            // adding it again removes the schedule
+            queue.set_scheduled_at(
+                "now",
+                "1.0.0",
+                Utc::now() + chrono::Duration::try_hours(1).unwrap(),
+            )?;
+            queue.add_crate("now", "1.0.0", -10, None, QueueSource::Manual)?;
+            let next = queue.peek_next_crate()?.unwrap();
+            assert_eq!(next.name, "now");
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_crate_with_scheduled_at() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            let scheduled_at = Utc::now() + chrono::Duration::try_hours(1).unwrap();
+            queue.add_crate_with_options(
+                "later",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &QueueOptions {
+                    scheduled_at: Some(scheduled_at),
+                    ..Default::default()
+                },
+            )?;
+            assert!(queue.peek_next_crate()?.is_none());
+
+            let stored: Option<DateTime<Utc>> = env
+                .db()
+                .conn()
+                .query_one("SELECT scheduled_at FROM queue WHERE name = 'later'", &[])?
+                .get(0);
+            assert_eq!(
+                stored.map(|at| at.timestamp_micros()),
+                Some(scheduled_at.timestamp_micros())
+            );
+
+            // adding it again without a schedule removes it
+            queue.add_crate_with_options(
+                "later",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &QueueOptions::default(),
+            )?;
+            assert_eq!(queue.peek_next_crate()?.unwrap().name, "later");
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fail_orphaned_builds() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            for name in ["alive", "crashed", "restarted", "unknown"] {
+                env.fake_release()
+                    .name(name)
+                    .version("1.0.0")
+                    .builds(vec![
+                        FakeBuild::default().build_status(BuildStatus::InProgress)
+                    ])
+                    .create()?;
+                queue.send_heartbeat(name)?;
+            }
+
+            let mut conn = env.db().conn();
+            conn.execute(
+                "UPDATE builds
+                 SET build_server = crates.name
+                 FROM releases, crates
+                 WHERE builds.rid = releases.id AND releases.crate_id = crates.id",
+                &[],
+            )?;
+            conn.execute(
+                "UPDATE build_servers
+                 SET last_heartbeat = NOW() - INTERVAL '1 day'
+                 WHERE hostname = 'crashed'",
+                &[],
+            )?;
+            conn.execute("DELETE FROM build_servers WHERE hostname = 'unknown'", &[])?;
+
+            assert_eq!(queue.fail_orphaned_builds(Some("restarted"))?, 2);
+            assert_eq!(queue.fail_orphaned_builds(None)?, 0);
+
+            let statuses: Vec<(String, String)> = conn
+                .query(
+                    "SELECT crates.name, release_build_status.build_status::TEXT
+                     FROM release_build_status
+                     INNER JOIN releases ON releases.id = release_build_status.rid
+                     INNER JOIN crates ON crates.id = releases.crate_id
+                     ORDER BY crates.name",
+                    &[],
+                )?
+                .into_iter()
+                .map(|row| (row.get(0), row.get(1)))
+                .collect();
+            assert_eq!(
+                statuses,
+                vec![
+                    ("alive".into(), "in_progress".into()),
+                    ("crashed".into(), "failure".into()),
+                    ("restarted".into(), "failure".into()),
+                    ("unknown".into(), "in_progress".into()),
+                ]
+            );
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_wait_between_build_attempts() {
         crate::test::wrapper(|env| {
@@ -617,7 +2884,7 @@ mod tests {
 
             let queue = env.build_queue();
 
-            queue.add_crate("krate", "1.0.0", 0, None)?;
+            queue.add_crate("krate", "1.0.0", 0, None, QueueSource::Manual)?;
 
             // first let it fail
             queue.process_next_crate(|krate| {
@@ -631,26 +2898,68 @@ mod tests {
             })?;
 
             {
-                // fake the build-attempt timestamp so it's older
+                // fake the next-attempt timestamp so it's in the past
                 let mut conn = env.db().conn();
                 conn.execute(
-                    "UPDATE queue SET last_attempt = $1",
+                    "UPDATE queue SET next_attempt_at = $1",
                     &[&(Utc::now() - chrono::Duration::try_seconds(60).unwrap())],
                 )?;
             }
 
-            let mut handled = false;
-            // now we can process it again
-            queue.process_next_crate(|krate| {
-                assert_eq!(krate.name, "krate");
-                handled = true;
-                Ok(())
-            })?;
+            let mut handled = false;
+            // now we can process it again
+            queue.process_next_crate(|krate| {
+                assert_eq!(krate.name, "krate");
+                handled = true;
+                Ok(())
+            })?;
+
+            assert!(handled);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_exponential_backoff_between_build_attempts() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_attempts = 99;
+                config.delay_between_build_attempts = Duration::from_secs(60);
+            });
+
+            let queue = env.build_queue();
+            queue.add_crate("krate", "1.0.0", 0, None, QueueSource::Manual)?;
+
+            let backoff = || -> Result<f64> {
+                Ok(env
+                    .db()
+                    .conn()
+                    .query_one(
+                        "SELECT EXTRACT(EPOCH FROM next_attempt_at - last_attempt)::FLOAT8
+                         FROM queue",
+                        &[],
+                    )?
+                    .get(0))
+            };
+
+            for expected_delay in [60.0, 120.0, 240.0] {
+                queue.process_next_crate(|_| anyhow::bail!("simulate a failure"))?;
 
-            assert!(handled);
+                // the jitter adds up to half of the delay
+                let delay = backoff()?;
+                assert!(
+                    (expected_delay..=expected_delay * 1.5).contains(&delay),
+                    "{delay} not in backoff range of {expected_delay}"
+                );
+
+                env.db()
+                    .conn()
+                    .execute("UPDATE queue SET next_attempt_at = NOW()", &[])?;
+            }
 
             Ok(())
-        })
+        });
     }
 
     #[test]
@@ -674,7 +2983,7 @@ mod tests {
                 ("high-priority-baz", "1.0.0", -1000),
             ];
             for krate in &test_crates {
-                queue.add_crate(krate.0, krate.1, krate.2, None)?;
+                queue.add_crate(krate.0, krate.1, krate.2, None, QueueSource::Manual)?;
             }
 
             let assert_next = |name| -> Result<()> {
@@ -747,8 +3056,8 @@ mod tests {
 
             let queue = env.build_queue();
 
-            queue.add_crate("will_succeed", "1.0.0", -1, None)?;
-            queue.add_crate("will_fail", "1.0.0", 0, None)?;
+            queue.add_crate("will_succeed", "1.0.0", -1, None, QueueSource::Manual)?;
+            queue.add_crate("will_fail", "1.0.0", 0, None, QueueSource::Manual)?;
 
             let mut conn = env.db().conn();
             cdn::queued_or_active_crate_invalidations(&mut *conn)?.is_empty();
@@ -780,15 +3089,60 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_defer_cdn_invalidation() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.cloudfront_distribution_id_web = Some("distribution_id_web".into());
+                config.cloudfront_distribution_id_static = Some("distribution_id_static".into());
+            });
+
+            let queue = env.build_queue();
+            queue.add_crate("first", "1.0.0", 0, None, QueueSource::Rebuild)?;
+            queue.add_crate("second", "1.0.0", 1, None, QueueSource::Rebuild)?;
+            queue.set_defer_cdn_invalidation("first", "1.0.0")?;
+            queue.set_defer_cdn_invalidation("second", "1.0.0")?;
+            assert!(queue
+                .set_defer_cdn_invalidation("unknown", "1.0.0")
+                .is_err());
+
+            let mut conn = env.db().conn();
+            queue.process_next_crate(|krate| {
+                assert_eq!(krate.name, "first");
+                assert!(krate.defer_cdn_invalidation);
+                Ok(())
+            })?;
+            assert!(cdn::queued_or_active_crate_invalidations(&mut *conn)?.is_empty());
+
+            // the other rebuild is still queued
+            assert!(!cdn::queue_deferred_invalidation(&mut conn, &env.config())?);
+
+            queue.process_next_crate(|_| Ok(()))?;
+            assert!(cdn::queued_or_active_crate_invalidations(&mut *conn)?.is_empty());
+
+            assert!(cdn::queue_deferred_invalidation(&mut conn, &env.config())?);
+            let patterns: Vec<_> = cdn::queued_or_active_crate_invalidations(&mut *conn)?
+                .into_iter()
+                .map(|i| i.path_pattern)
+                .collect();
+            assert_eq!(patterns, vec!["/*", "/rustdoc/*"]);
+
+            // only queued once
+            assert!(!cdn::queue_deferred_invalidation(&mut conn, &env.config())?);
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_pending_count() {
         crate::test::wrapper(|env| {
             let queue = env.build_queue();
 
             assert_eq!(queue.pending_count()?, 0);
-            queue.add_crate("foo", "1.0.0", 0, None)?;
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
             assert_eq!(queue.pending_count()?, 1);
-            queue.add_crate("bar", "1.0.0", 0, None)?;
+            queue.add_crate("bar", "1.0.0", 0, None, QueueSource::Manual)?;
             assert_eq!(queue.pending_count()?, 2);
 
             queue.process_next_crate(|krate| {
@@ -807,11 +3161,11 @@ mod tests {
             let queue = env.build_queue();
 
             assert_eq!(queue.prioritized_count()?, 0);
-            queue.add_crate("foo", "1.0.0", 0, None)?;
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
             assert_eq!(queue.prioritized_count()?, 1);
-            queue.add_crate("bar", "1.0.0", -100, None)?;
+            queue.add_crate("bar", "1.0.0", -100, None, QueueSource::Manual)?;
             assert_eq!(queue.prioritized_count()?, 2);
-            queue.add_crate("baz", "1.0.0", 100, None)?;
+            queue.add_crate("baz", "1.0.0", 100, None, QueueSource::Manual)?;
             assert_eq!(queue.prioritized_count()?, 2);
 
             queue.process_next_crate(|krate| {
@@ -831,9 +3185,9 @@ mod tests {
 
             assert!(queue.pending_count_by_priority()?.is_empty());
 
-            queue.add_crate("one", "1.0.0", 1, None)?;
-            queue.add_crate("two", "2.0.0", 2, None)?;
-            queue.add_crate("two_more", "2.0.0", 2, None)?;
+            queue.add_crate("one", "1.0.0", 1, None, QueueSource::Manual)?;
+            queue.add_crate("two", "2.0.0", 2, None, QueueSource::Manual)?;
+            queue.add_crate("two_more", "2.0.0", 2, None, QueueSource::Manual)?;
 
             assert_eq!(
                 queue.pending_count_by_priority()?,
@@ -860,9 +3214,9 @@ mod tests {
             let queue = env.build_queue();
 
             assert_eq!(queue.failed_count()?, 0);
-            queue.add_crate("foo", "1.0.0", -100, None)?;
+            queue.add_crate("foo", "1.0.0", -100, None, QueueSource::Manual)?;
             assert_eq!(queue.failed_count()?, 0);
-            queue.add_crate("bar", "1.0.0", 0, None)?;
+            queue.add_crate("bar", "1.0.0", 0, None, QueueSource::Manual)?;
 
             for _ in 0..MAX_ATTEMPTS {
                 assert_eq!(queue.failed_count()?, 0);
@@ -883,6 +3237,93 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_stuck_crates() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_attempts = 5;
+                config.delay_between_build_attempts = Duration::ZERO;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("fresh", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("old", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("failing", "1.0.0", -1, None, QueueSource::Manual)?;
+            env.db().conn().execute(
+                "UPDATE queue SET queued_at = NOW() - INTERVAL '2 days' WHERE name = 'old'",
+                &[],
+            )?;
+            for _ in 0..2 {
+                queue.process_next_crate(|krate| {
+                    assert_eq!(krate.name, "failing");
+                    anyhow::bail!("this failed")
+                })?;
+            }
+
+            let stuck = queue.stuck_crates(Duration::from_secs(24 * 60 * 60), 2)?;
+            assert_eq!(stuck.len(), 2);
+            assert_eq!(stuck[0].name, "old");
+            assert_eq!(stuck[0].reasons, vec![StuckReason::QueuedTooLong]);
+            assert_eq!(stuck[1].name, "failing");
+            assert_eq!(stuck[1].attempt, 2);
+            assert_eq!(stuck[1].reasons, vec![StuckReason::ManyAttempts]);
+
+            // a release scheduled for later isn't waiting yet
+            env.db().conn().execute(
+                "UPDATE queue SET scheduled_at = NOW() + INTERVAL '1 day' WHERE name = 'old'",
+                &[],
+            )?;
+            let stuck = queue.stuck_crates(Duration::from_secs(60 * 60), 3)?;
+            assert!(stuck.is_empty());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_dead_letters() {
+        const MAX_ATTEMPTS: u16 = 2;
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_attempts = MAX_ATTEMPTS;
+                config.delay_between_build_attempts = Duration::ZERO;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate(
+                "foo",
+                "1.0.0",
+                5,
+                Some("https://registry"),
+                QueueSource::Manual,
+            )?;
+            for _ in 0..MAX_ATTEMPTS {
+                assert!(queue.dead_letters()?.is_empty());
+                queue.process_next_crate(|_| anyhow::bail!("this failed"))?;
+            }
+
+            let dead_letters = queue.dead_letters()?;
+            assert_eq!(dead_letters.len(), 1);
+            assert_eq!(dead_letters[0].name, "foo");
+            assert_eq!(dead_letters[0].priority, 5);
+            assert_eq!(dead_letters[0].attempts, MAX_ATTEMPTS as i32);
+            assert_eq!(dead_letters[0].source, Some(QueueSource::Manual));
+            assert!(dead_letters[0].error.contains("this failed"));
+            assert!(queue.export()?.is_empty());
+            assert_eq!(queue.failed_count()?, 1);
+
+            assert!(queue.requeue_dead_letter("foo", "2.0.0").is_err());
+            queue.requeue_dead_letter("foo", "1.0.0")?;
+            assert!(queue.dead_letters()?.is_empty());
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued.len(), 1);
+            assert_eq!(queued[0].priority, 5);
+            assert_eq!(queued[0].registry.as_deref(), Some("https://registry"));
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_queued_crates() {
         crate::test::wrapper(|env| {
@@ -894,7 +3335,7 @@ mod tests {
                 ("baz", "1.0.0", 10),
             ];
             for krate in &test_crates {
-                queue.add_crate(krate.0, krate.1, krate.2, None)?;
+                queue.add_crate(krate.0, krate.1, krate.2, None, QueueSource::Manual)?;
             }
 
             assert_eq!(
@@ -914,6 +3355,375 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_peek_next_crate() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.delay_between_build_attempts = Duration::from_secs(60);
+            });
+            let queue = env.build_queue();
+
+            assert!(queue.peek_next_crate()?.is_none());
+
+            queue.add_crate("low", "1.0.0", 10, None, QueueSource::Manual)?;
+            queue.add_crate("high", "1.0.0", -10, None, QueueSource::Manual)?;
+
+            let next = queue.peek_next_crate()?.unwrap();
+            assert_eq!(next.name, "high");
+
+            // peeking doesn't claim the crate
+            assert_eq!(queue.peek_next_crate()?.unwrap().name, "high");
+            assert_eq!(queue.pending_count()?, 2);
+
+            // crates waiting for their next attempt are skipped, like in the builder
+            queue.process_next_crate(|krate| {
+                assert_eq!(krate.name, "high");
+                anyhow::bail!("simulate a failure");
+            })?;
+            assert_eq!(queue.peek_next_crate()?.unwrap().name, "low");
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate(
+                "bar",
+                "1.0.0",
+                -10,
+                Some("https://example.com/index"),
+                QueueSource::Manual,
+            )?;
+            env.db()
+                .conn()
+                .execute("UPDATE queue SET attempt = 2 WHERE name = 'foo'", &[])?;
+
+            let snapshot = queue.export()?;
+            assert_eq!(
+                snapshot,
+                vec![
+                    QueueSnapshotEntry {
+                        name: "bar".into(),
+                        version: "1.0.0".into(),
+                        priority: -10,
+                        registry: Some("https://example.com/index".into()),
+                        attempt: 0,
+                        source: Some(QueueSource::Manual),
+                    },
+                    QueueSnapshotEntry {
+                        name: "foo".into(),
+                        version: "1.0.0".into(),
+                        priority: 0,
+                        registry: None,
+                        attempt: 2,
+                        source: Some(QueueSource::Manual),
+                    },
+                ]
+            );
+
+            // importing into the same queue doesn't add anything
+            assert_eq!(queue.import(&snapshot)?, 0);
+
+            env.db().conn().execute("DELETE FROM queue", &[])?;
+            queue.add_crate("bar", "1.0.0", 5, None, QueueSource::Manual)?;
+
+            // only the missing row is added
+            assert_eq!(queue.import(&snapshot)?, 1);
+            assert_eq!(queue.export()?.len(), 2);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_import_clamps_priority() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_queue_min_priority = -10;
+                config.build_queue_max_priority = 10;
+            });
+            let queue = env.build_queue();
+
+            let entry = |name: &str, priority| QueueSnapshotEntry {
+                name: name.into(),
+                version: "1.0.0".into(),
+                priority,
+                registry: None,
+                attempt: 0,
+                source: None,
+            };
+            assert_eq!(
+                queue.import(&[entry("low", 500), entry("high", -500), entry("normal", 3)])?,
+                3
+            );
+
+            let priorities: Vec<_> = queue
+                .export()?
+                .into_iter()
+                .map(|entry| (entry.name, entry.priority))
+                .collect();
+            assert_eq!(
+                priorities,
+                vec![
+                    ("high".into(), -10),
+                    ("normal".into(), 3),
+                    ("low".into(), 10)
+                ]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_consecutive_failed_builds() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+            assert_eq!(queue.consecutive_failed_builds("foo", 3)?, 0);
+
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .builds(vec![
+                    FakeBuild::default().successful(false),
+                    FakeBuild::default().successful(true),
+                ])
+                .create()?;
+            assert_eq!(queue.consecutive_failed_builds("foo", 3)?, 0);
+
+            env.fake_release()
+                .name("foo")
+                .version("0.2.0")
+                .builds(vec![
+                    FakeBuild::default().successful(false),
+                    FakeBuild::default().successful(false),
+                    FakeBuild::default().build_status(BuildStatus::InProgress),
+                ])
+                .create()?;
+            assert_eq!(queue.consecutive_failed_builds("foo", 3)?, 2);
+
+            env.fake_release()
+                .name("foo")
+                .version("0.3.0")
+                .build_result_failed()
+                .create()?;
+            assert_eq!(queue.consecutive_failed_builds("foo", 5)?, 3);
+            assert_eq!(queue.consecutive_failed_builds("foo", 2)?, 2);
+            assert_eq!(queue.consecutive_failed_builds("bar", 3)?, 0);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_set_notify_url() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.set_notify_url("foo", "1.0.0", "https://example.com/hook")?;
+            assert!(queue
+                .set_notify_url("bar", "1.0.0", "https://example.com/hook")
+                .is_err());
+
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued.len(), 1);
+            assert_eq!(
+                queued[0].notify_url.as_deref(),
+                Some("https://example.com/hook")
+            );
+
+            // re-adding the crate keeps the notification URL
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            assert!(queue.peek_next_crate()?.unwrap().notify_url.is_some());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_add_crate_with_notify_url() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+            let notify = |url: &str| QueueOptions {
+                notify_url: Some(url.into()),
+                ..Default::default()
+            };
+
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &notify("https://example.com/hook"),
+            )?;
+            assert_eq!(
+                queue.peek_next_crate()?.unwrap().notify_url.as_deref(),
+                Some("https://example.com/hook")
+            );
+
+            queue.add_crate_with_options(
+                "foo",
+                "1.0.0",
+                0,
+                None,
+                QueueSource::Manual,
+                &notify("https://example.com/other"),
+            )?;
+            assert_eq!(
+                queue.peek_next_crate()?.unwrap().notify_url.as_deref(),
+                Some("https://example.com/other")
+            );
+
+            // re-adding the crate without a URL keeps the last one
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            assert_eq!(
+                queue.peek_next_crate()?.unwrap().notify_url.as_deref(),
+                Some("https://example.com/other")
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_queue_rebuilds_for_outdated_toolchain() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            // no toolchain installed yet
+            assert!(queue
+                .queue_rebuilds_for_outdated_toolchain(20, None, false)
+                .is_err());
+
+            set_config(
+                &mut env.db().conn(),
+                ConfigName::RustcVersion,
+                "rustc 1.70.0-nightly (000000000 2023-01-01)",
+            )?;
+
+            env.fake_release()
+                .name("old")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default()
+                    .rustc_version("rustc 1.60.0-nightly (000000000 2022-01-01)")])
+                .create()?;
+            env.fake_release()
+                .name("old-popular")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default()
+                    .rustc_version("rustc 1.60.0-nightly (000000000 2022-01-01)")])
+                .create()?;
+            env.fake_release()
+                .name("current")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default()
+                    .rustc_version("rustc 1.70.0-nightly (000000000 2023-01-01)")])
+                .create()?;
+            env.fake_release()
+                .name("old-failed")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default()
+                    .rustc_version("rustc 1.60.0-nightly (000000000 2022-01-01)")
+                    .successful(false)])
+                .create()?;
+            env.db().conn().execute(
+                "UPDATE crates SET downloads = 100 WHERE name = 'old-popular'",
+                &[],
+            )?;
+
+            assert_eq!(
+                queue.queue_rebuilds_for_outdated_toolchain(20, Some(1), false)?,
+                1
+            );
+            assert_eq!(
+                queue.queue_rebuilds_for_outdated_toolchain(20, None, false)?,
+                1
+            );
+            assert_eq!(
+                queue.queue_rebuilds_for_outdated_toolchain(20, None, false)?,
+                0
+            );
+
+            assert_eq!(
+                queue
+                    .queued_crates()?
+                    .iter()
+                    .map(|c| (c.name.as_str(), c.priority))
+                    .collect::<Vec<_>>(),
+                vec![("old-popular", 20), ("old", 20)]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_reassign_priorities() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("docsrs-foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("docsrs-bar", "1.0.0", -10, None, QueueSource::Manual)?;
+            queue.add_crate("other", "1.0.0", 5, None, QueueSource::Manual)?;
+
+            set_crate_priority(&mut env.db().conn(), "docsrs-%", -10)?;
+            assert_eq!(queue.reassign_priorities()?, 1);
+
+            let priorities: Vec<_> = queue
+                .export()?
+                .into_iter()
+                .map(|entry| (entry.name, entry.priority))
+                .collect();
+            assert_eq!(
+                priorities,
+                vec![
+                    ("docsrs-foo".to_owned(), -10),
+                    ("docsrs-bar".to_owned(), -10),
+                    ("other".to_owned(), 5),
+                ]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_reassign_priorities_clamps_priority() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_queue_min_priority = -10;
+                config.build_queue_max_priority = 10;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("docsrs-foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("other", "1.0.0", 0, None, QueueSource::Manual)?;
+
+            set_crate_priority(&mut env.db().conn(), "docsrs-%", -500)?;
+            set_crate_priority(&mut env.db().conn(), "other", 500)?;
+            assert_eq!(queue.reassign_priorities()?, 2);
+
+            let priorities: Vec<_> = queue
+                .export()?
+                .into_iter()
+                .map(|entry| (entry.name, entry.priority))
+                .collect();
+            assert_eq!(
+                priorities,
+                vec![("docsrs-foo".to_owned(), -10), ("other".to_owned(), 10)]
+            );
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_last_seen_reference_in_db() {
         crate::test::wrapper(|env| {
@@ -956,7 +3766,7 @@ mod tests {
             // unlocked without config
             assert!(!queue.is_locked()?);
 
-            queue.lock()?;
+            queue.lock(None)?;
             assert!(queue.is_locked()?);
 
             queue.unlock()?;
@@ -965,4 +3775,43 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn test_queue_lock_message() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.lock(None)?;
+            assert_eq!(queue.lock_message()?, None);
+
+            queue.lock(Some("maintenance"))?;
+            assert!(queue.is_locked()?);
+            assert_eq!(queue.lock_message()?.as_deref(), Some("maintenance"));
+
+            queue.unlock()?;
+            assert_eq!(queue.lock_message()?, None);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_queue_locked_since() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+            assert_eq!(queue.locked_since()?, None);
+
+            queue.lock(None)?;
+            let locked_since = queue.locked_since()?.unwrap();
+
+            // locking again keeps the time the queue was locked first
+            queue.lock(Some("still maintenance"))?;
+            assert_eq!(queue.locked_since()?, Some(locked_since));
+
+            queue.unlock()?;
+            assert_eq!(queue.locked_since()?, None);
+
+            Ok(())
+        });
+    }
 }
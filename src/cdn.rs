@@ -1,4 +1,8 @@
-use crate::{metrics::duration_to_seconds, utils::report_error, Config, InstanceMetrics};
+use crate::{
+    metrics::duration_to_seconds,
+    utils::{get_config, report_error, set_config, ConfigName},
+    Config, InstanceMetrics,
+};
 use anyhow::{anyhow, bail, Context, Error, Result};
 use aws_config::BehaviorVersion;
 use aws_sdk_cloudfront::{
@@ -440,8 +444,70 @@ pub(crate) fn handle_queued_invalidation_requests(
     Ok(())
 }
 
+/// Queues a single invalidation of all pages once all builds that deferred their
+/// invalidation finished, see [`BuildQueue::set_defer_cdn_invalidation`](crate::BuildQueue::set_defer_cdn_invalidation).
+///
+/// Returns whether the invalidation was queued.
 #[instrument(skip(conn, config))]
-pub(crate) fn queue_crate_invalidation(
+pub(crate) fn queue_deferred_invalidation(
+    conn: &mut postgres::Client,
+    config: &Config,
+) -> Result<bool> {
+    if !get_config::<bool>(conn, ConfigName::DeferredCdnInvalidation)?.unwrap_or(false) {
+        return Ok(false);
+    }
+
+    let pending: bool = conn
+        .query_one(
+            "SELECT EXISTS (
+                SELECT 1 FROM queue WHERE defer_cdn_invalidation AND attempt < $1
+             )",
+            &[&(config.build_attempts as i32)],
+        )?
+        .get(0);
+    if pending {
+        debug!("deferred CDN invalidation waits for the remaining builds");
+        return Ok(false);
+    }
+
+    // reset first, builds finishing after this set it again and are covered by the
+    // next run
+    set_config(conn, ConfigName::DeferredCdnInvalidation, false)?;
+
+    info!("queueing deferred CDN invalidation of all pages");
+    let mut transaction = conn.transaction()?;
+    if let Some(distribution_id) = config.cloudfront_distribution_id_web.as_ref() {
+        enqueue_invalidation(&mut transaction, "*", distribution_id, &["/*"])
+            .context("error enqueueing deferred web CDN invalidation")?;
+    }
+    if let Some(distribution_id) = config.cloudfront_distribution_id_static.as_ref() {
+        enqueue_invalidation(&mut transaction, "*", distribution_id, &["/rustdoc/*"])
+            .context("error enqueueing deferred static CDN invalidation")?;
+    }
+    transaction.commit()?;
+
+    Ok(true)
+}
+
+fn enqueue_invalidation(
+    conn: &mut impl postgres::GenericClient,
+    name: &str,
+    distribution_id: &str,
+    path_patterns: &[&str],
+) -> Result<()> {
+    for pattern in path_patterns {
+        debug!(distribution_id, pattern, "enqueueing CDN invalidation");
+        conn.execute(
+            "INSERT INTO cdn_invalidation_queue (crate, cdn_distribution_id, path_pattern)
+             VALUES ($1, $2, $3)",
+            &[&name, &distribution_id, pattern],
+        )?;
+    }
+    Ok(())
+}
+
+#[instrument(skip(conn, config))]
+pub fn queue_crate_invalidation(
     conn: &mut impl postgres::GenericClient,
     config: &Config,
     name: &str,
@@ -451,27 +517,23 @@ pub(crate) fn queue_crate_invalidation(
         return Ok(());
     }
 
-    let mut add = |distribution_id: &str, path_patterns: &[&str]| -> Result<()> {
-        for pattern in path_patterns {
-            debug!(distribution_id, pattern, "enqueueing web CDN invalidation");
-            conn.execute(
-                "INSERT INTO cdn_invalidation_queue (crate, cdn_distribution_id, path_pattern)
-                 VALUES ($1, $2, $3)",
-                &[&name, &distribution_id, pattern],
-            )?;
-        }
-        Ok(())
-    };
     if let Some(distribution_id) = config.cloudfront_distribution_id_web.as_ref() {
-        add(
+        enqueue_invalidation(
+            &mut *conn,
+            name,
             distribution_id,
             &[&format!("/{name}*"), &format!("/crate/{name}*")],
         )
         .context("error enqueueing web CDN invalidation")?;
     }
     if let Some(distribution_id) = config.cloudfront_distribution_id_static.as_ref() {
-        add(distribution_id, &[&format!("/rustdoc/{name}*")])
-            .context("error enqueueing static CDN invalidation")?;
+        enqueue_invalidation(
+            &mut *conn,
+            name,
+            distribution_id,
+            &[&format!("/rustdoc/{name}*")],
+        )
+        .context("error enqueueing static CDN invalidation")?;
     }
 
     Ok(())
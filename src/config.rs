@@ -1,6 +1,12 @@
-use crate::{cdn::CdnKind, storage::StorageKind};
+use crate::{
+    cdn::CdnKind,
+    docbuilder::{load_build_profiles, BuildProfile},
+    storage::StorageKind,
+};
 use anyhow::{anyhow, bail, Context, Result};
-use std::{env::VarError, error::Error, path::PathBuf, str::FromStr, time::Duration};
+use std::{
+    collections::HashMap, env::VarError, error::Error, path::PathBuf, str::FromStr, time::Duration,
+};
 use tracing::trace;
 use url::Url;
 
@@ -13,6 +19,8 @@ pub struct Config {
 
     // Database connection params
     pub(crate) database_url: String,
+    // Optional read replica for read-heavy commands, the primary is used when unset
+    pub(crate) read_replica_database_url: Option<String>,
     pub(crate) max_legacy_pool_size: u32,
     pub(crate) max_pool_size: u32,
     pub(crate) min_pool_idle: u32,
@@ -27,6 +35,9 @@ pub struct Config {
     pub(crate) s3_bucket: String,
     pub(crate) s3_region: String,
     pub(crate) s3_endpoint: Option<String>,
+    // Prefix added to all keys, so multiple instances can share a bucket.
+    // `s3_static_root_path` has to include it for the public files.
+    pub(crate) s3_prefix: Option<String>,
     #[cfg(test)]
     pub(crate) s3_bucket_is_temporary: bool,
 
@@ -44,6 +55,8 @@ pub struct Config {
     // Access token for APIs for crates.io (careful: use
     // constant_time_eq for comparisons!)
     pub(crate) cratesio_token: Option<String>,
+    // Minimum time between two rebuilds of the same release triggered through the web
+    pub(crate) web_rebuild_trigger_interval: Duration,
 
     // amount of retries for external API calls, mostly crates.io
     pub crates_io_api_call_retries: u32,
@@ -52,6 +65,10 @@ pub struct Config {
     pub(crate) request_timeout: Option<Duration>,
     pub(crate) report_request_timeouts: bool,
 
+    // Max size of request bodies and request URIs accepted by the web server
+    pub(crate) max_request_body_size: usize,
+    pub(crate) max_uri_length: usize,
+
     // Max size of the files served by the docs.rs frontend
     pub(crate) max_file_size: usize,
     pub(crate) max_file_size_html: usize,
@@ -101,14 +118,39 @@ pub struct Config {
     // Build params
     pub(crate) build_attempts: u16,
     pub(crate) delay_between_build_attempts: Duration,
+    // Maximum number of releases of a single crate that can be built or be
+    // waiting ahead in the queue at the same time, the rest is held back.
+    pub(crate) max_queued_builds_per_crate: Option<u32>,
+    // When the latest builds of a crate failed this many times in a row, a notification
+    // is posted to `build_failure_webhook_url`, if one is configured.
+    pub(crate) build_failure_notification_threshold: u32,
+    pub(crate) build_failure_webhook_url: Option<String>,
+    // Priorities outside of this range are clamped when a release is added to the queue.
+    pub(crate) build_queue_min_priority: i32,
+    pub(crate) build_queue_max_priority: i32,
+    // How often build servers report that they are still alive, and after how long
+    // without a report the builds of a build server are considered orphaned.
+    pub(crate) build_server_heartbeat_interval: Duration,
+    pub(crate) build_server_heartbeat_timeout: Duration,
     pub(crate) rustwide_workspace: PathBuf,
     pub(crate) temp_dir: PathBuf,
     pub(crate) inside_docker: bool,
     pub(crate) docker_image: Option<String>,
     pub(crate) build_cpu_limit: Option<u32>,
     pub(crate) build_default_memory_limit: Option<usize>,
+    // Maximum size of the documentation of a single release, in bytes.
+    // Builds producing more are failed instead of uploaded.
+    pub(crate) build_default_max_doc_size: Option<usize>,
     pub(crate) include_default_targets: bool,
     pub(crate) disable_memory_limit: bool,
+    // Also build and store the rustdoc JSON output for the default target
+    pub(crate) build_rustdoc_json: bool,
+    // Don't store the source archive of the built crates, for instances without a
+    // source view
+    pub(crate) skip_source_archive: bool,
+    // Named build settings queued releases can be built with, loaded from the TOML file
+    // at `DOCSRS_BUILD_PROFILES`.
+    pub(crate) build_profiles: HashMap<String, BuildProfile>,
 }
 
 impl Config {
@@ -141,6 +183,22 @@ impl Config {
                 "DOCSRS_DELAY_BETWEEN_BUILD_ATTEMPTS",
                 60,
             )?),
+            max_queued_builds_per_crate: maybe_env("DOCSRS_MAX_QUEUED_BUILDS_PER_CRATE")?,
+            build_failure_notification_threshold: env(
+                "DOCSRS_BUILD_FAILURE_NOTIFICATION_THRESHOLD",
+                3,
+            )?,
+            build_failure_webhook_url: maybe_env("DOCSRS_BUILD_FAILURE_WEBHOOK_URL")?,
+            build_queue_min_priority: env("DOCSRS_BUILD_QUEUE_MIN_PRIORITY", -100)?,
+            build_queue_max_priority: env("DOCSRS_BUILD_QUEUE_MAX_PRIORITY", 100)?,
+            build_server_heartbeat_interval: Duration::from_secs(env(
+                "DOCSRS_BUILD_SERVER_HEARTBEAT_INTERVAL",
+                60,
+            )?),
+            build_server_heartbeat_timeout: Duration::from_secs(env(
+                "DOCSRS_BUILD_SERVER_HEARTBEAT_TIMEOUT",
+                10 * 60,
+            )?),
 
             crates_io_api_call_retries: env("DOCSRS_CRATESIO_API_CALL_RETRIES", 3)?,
 
@@ -153,6 +211,7 @@ impl Config {
             prefix: prefix.clone(),
 
             database_url: require_env("DOCSRS_DATABASE_URL")?,
+            read_replica_database_url: maybe_env("DOCSRS_READ_REPLICA_DATABASE_URL")?,
             max_legacy_pool_size: env("DOCSRS_MAX_LEGACY_POOL_SIZE", 45)?,
             max_pool_size: env("DOCSRS_MAX_POOL_SIZE", 45)?,
             min_pool_idle: env("DOCSRS_MIN_POOL_IDLE", 10)?,
@@ -164,6 +223,7 @@ impl Config {
             s3_bucket: env("DOCSRS_S3_BUCKET", "rust-docs-rs".to_string())?,
             s3_region: env("S3_REGION", "us-west-1".to_string())?,
             s3_endpoint: maybe_env("S3_ENDPOINT")?,
+            s3_prefix: maybe_env("DOCSRS_S3_PREFIX")?,
             // DO NOT CONFIGURE THIS THROUGH AN ENVIRONMENT VARIABLE!
             // Accidentally turning this on outside of the test suite might cause data loss in the
             // production environment.
@@ -181,6 +241,10 @@ impl Config {
             gitlab_accesstoken: maybe_env("DOCSRS_GITLAB_ACCESSTOKEN")?,
 
             cratesio_token: maybe_env("DOCSRS_CRATESIO_TOKEN")?,
+            web_rebuild_trigger_interval: Duration::from_secs(env(
+                "DOCSRS_WEB_REBUILD_TRIGGER_INTERVAL",
+                24 * 60 * 60,
+            )?),
 
             max_file_size: env("DOCSRS_MAX_FILE_SIZE", 50 * 1024 * 1024)?,
             max_file_size_html: env("DOCSRS_MAX_FILE_SIZE_HTML", 50 * 1024 * 1024)?,
@@ -191,6 +255,8 @@ impl Config {
             render_threads: env("DOCSRS_RENDER_THREADS", num_cpus::get())?,
             request_timeout: maybe_env::<u64>("DOCSRS_REQUEST_TIMEOUT")?.map(Duration::from_secs),
             report_request_timeouts: env("DOCSRS_REPORT_REQUEST_TIMEOUTS", false)?,
+            max_request_body_size: env("DOCSRS_MAX_REQUEST_BODY_SIZE", 1024 * 1024)?,
+            max_uri_length: env("DOCSRS_MAX_URI_LENGTH", 8 * 1024)?,
 
             random_crate_search_view_size: env("DOCSRS_RANDOM_CRATE_SEARCH_VIEW_SIZE", 500)?,
 
@@ -220,8 +286,15 @@ impl Config {
                 .or(maybe_env("DOCSRS_DOCKER_IMAGE")?),
             build_cpu_limit: maybe_env("DOCSRS_BUILD_CPU_LIMIT")?,
             build_default_memory_limit: maybe_env("DOCSRS_BUILD_DEFAULT_MEMORY_LIMIT")?,
+            build_default_max_doc_size: maybe_env("DOCSRS_BUILD_DEFAULT_MAX_DOC_SIZE")?,
             include_default_targets: env("DOCSRS_INCLUDE_DEFAULT_TARGETS", true)?,
             disable_memory_limit: env("DOCSRS_DISABLE_MEMORY_LIMIT", false)?,
+            build_rustdoc_json: env("DOCSRS_BUILD_RUSTDOC_JSON", false)?,
+            skip_source_archive: env("DOCSRS_SKIP_SOURCE_ARCHIVE", false)?,
+            build_profiles: match maybe_env::<PathBuf>("DOCSRS_BUILD_PROFILES")? {
+                Some(path) => load_build_profiles(&path)?,
+                None => HashMap::new(),
+            },
             build_workspace_reinitialization_interval: Duration::from_secs(env(
                 "DOCSRS_BUILD_WORKSPACE_REINITIALIZATION_INTERVAL",
                 86400,
@@ -2,12 +2,12 @@ use crate::{
     db::types::{BuildStatus, Feature},
     docbuilder::DocCoverage,
     error::Result,
-    registry_api::{CrateData, CrateOwner, ReleaseData},
+    registry_api::{CrateData, CrateMetadata, CrateOwner, ReleaseData},
     storage::CompressionAlgorithm,
-    utils::MetadataPackage,
-    web::crate_details::{latest_release, releases_for_crate},
+    utils::{ConfigName, MetadataPackage},
+    web::crate_details::{latest_release, releases_for_crate, Release},
 };
-use anyhow::Context;
+use anyhow::{bail, Context};
 use futures_util::stream::TryStreamExt;
 use serde_json::Value;
 use slug::slugify;
@@ -119,7 +119,7 @@ pub(crate) async fn add_package_into_database(
     .fetch_one(&mut *conn)
     .await?;
 
-    add_keywords_into_database(conn, metadata_pkg, release_id).await?;
+    add_keywords_into_database(conn, &metadata_pkg.keywords, release_id).await?;
     add_compression_into_database(conn, compression_algorithms.into_iter(), release_id).await?;
 
     update_latest_version_id(&mut *conn, crate_id)
@@ -131,15 +131,20 @@ pub(crate) async fn add_package_into_database(
     Ok(release_id)
 }
 
+/// Points `crates.latest_version_id` to the latest release, see [`latest_release`].
+///
+/// When the [`ConfigName::YankedLatestVersion`] config is `false`, yanked releases are never
+/// picked, and a crate that only has yanked releases has no latest version. The web always
+/// falls back to the newest release.
 pub async fn update_latest_version_id(conn: &mut sqlx::PgConnection, crate_id: i32) -> Result<()> {
-    let releases = releases_for_crate(conn, crate_id).await?;
+    let latest = expected_latest_release(&mut *conn, crate_id).await?;
 
     sqlx::query!(
         "UPDATE crates
          SET latest_version_id = $2
          WHERE id = $1",
         crate_id,
-        latest_release(&releases).map(|release| release.id),
+        latest.map(|release| release.id),
     )
     .execute(&mut *conn)
     .await?;
@@ -147,6 +152,31 @@ pub async fn update_latest_version_id(conn: &mut sqlx::PgConnection, crate_id: i
     Ok(())
 }
 
+/// The release [`update_latest_version_id`] stores as the latest version of the crate.
+pub(crate) async fn expected_latest_release(
+    conn: &mut sqlx::PgConnection,
+    crate_id: i32,
+) -> Result<Option<Release>> {
+    let mut releases = releases_for_crate(&mut *conn, crate_id).await?;
+    if !yanked_latest_version_allowed(&mut *conn).await? {
+        releases.retain(|release| release.yanked == Some(false));
+    }
+    Ok(latest_release(&releases).cloned())
+}
+
+async fn yanked_latest_version_allowed(conn: &mut sqlx::PgConnection) -> Result<bool> {
+    let name: &'static str = ConfigName::YankedLatestVersion.into();
+    let value: Option<Value> =
+        sqlx::query_scalar!("SELECT value FROM config WHERE name = $1", name)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    Ok(match value {
+        Some(value) => serde_json::from_value(value)?,
+        None => true,
+    })
+}
+
 pub async fn update_build_status(conn: &mut sqlx::PgConnection, release_id: i32) -> Result<()> {
     sqlx::query!(
         "INSERT INTO release_build_status(rid, last_build_time, build_status)
@@ -190,6 +220,27 @@ pub async fn update_build_status(conn: &mut sqlx::PgConnection, release_id: i32)
     Ok(())
 }
 
+/// Recomputes the cached aggregates of a crate from its releases and builds, the build
+/// status of every release and `crates.latest_version_id`.
+///
+/// Returns the number of releases of the crate.
+pub async fn recount_releases(conn: &mut sqlx::PgConnection, crate_id: i32) -> Result<usize> {
+    let release_ids: Vec<i32> =
+        sqlx::query_scalar!("SELECT id FROM releases WHERE crate_id = $1", crate_id)
+            .fetch_all(&mut *conn)
+            .await?;
+
+    for &release_id in &release_ids {
+        update_build_status(&mut *conn, release_id).await?;
+    }
+    // also covers crates without any releases left
+    update_latest_version_id(&mut *conn, crate_id)
+        .await
+        .context("couldn't update latest version id")?;
+
+    Ok(release_ids.len())
+}
+
 async fn crate_id_from_release_id(conn: &mut sqlx::PgConnection, release_id: i32) -> Result<i32> {
     Ok(sqlx::query_scalar!(
         "SELECT crate_id
@@ -297,6 +348,68 @@ pub(crate) async fn update_build_with_error(
     Ok(build_id)
 }
 
+/// Stores why a build failed, as the `snake_case` name of a `BuildErrorCategory`.
+pub(crate) async fn set_build_error_category(
+    conn: &mut sqlx::PgConnection,
+    build_id: i32,
+    error_category: Option<&str>,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE builds SET error_category = $1 WHERE id = $2",
+        error_category,
+        build_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Stores the content hash of the documentation a build generated.
+pub(crate) async fn set_build_documentation_hash(
+    conn: &mut sqlx::PgConnection,
+    build_id: i32,
+    documentation_hash: &str,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE builds SET documentation_hash = $1 WHERE id = $2",
+        documentation_hash,
+        build_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Returns the documentation hash of the latest successful build of the release, other
+/// than `build_id`. Builds from before the hash was stored have none.
+pub(crate) async fn previous_documentation_hash(
+    conn: &mut sqlx::PgConnection,
+    name: &str,
+    version: &str,
+    build_id: i32,
+) -> Result<Option<String>> {
+    let hash: Option<Option<String>> = sqlx::query_scalar!(
+        "SELECT builds.documentation_hash
+         FROM builds
+         INNER JOIN releases ON releases.id = builds.rid
+         INNER JOIN crates ON crates.id = releases.crate_id
+         WHERE
+            crates.name = $1 AND
+            releases.version = $2 AND
+            releases.rustdoc_status = TRUE AND
+            builds.id != $3 AND
+            builds.build_status = 'success'
+         ORDER BY builds.id DESC
+         LIMIT 1",
+        name,
+        version,
+        build_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(hash.flatten())
+}
+
 pub(crate) async fn initialize_crate(conn: &mut sqlx::PgConnection, name: &str) -> Result<i32> {
     sqlx::query_scalar!(
         "INSERT INTO crates (name)
@@ -456,11 +569,10 @@ fn read_rust_doc(file_path: &Path) -> Result<Option<String>> {
 /// Adds keywords into database
 async fn add_keywords_into_database(
     conn: &mut sqlx::PgConnection,
-    pkg: &MetadataPackage,
+    keywords: &[String],
     release_id: i32,
 ) -> Result<()> {
-    let wanted_keywords: HashMap<String, String> = pkg
-        .keywords
+    let wanted_keywords: HashMap<String, String> = keywords
         .iter()
         .map(|kw| (slugify(kw), kw.clone()))
         .collect();
@@ -521,6 +633,75 @@ pub async fn update_crate_data_in_database(
     Ok(())
 }
 
+/// Replaces the description and keywords of the latest release of a crate, and the
+/// categories of the crate, with the ones from the registry's API.
+///
+/// The data from the registry is usually more recent than the one from the manifest of
+/// the release, which is only read when the release is built.
+pub async fn update_crate_metadata_in_database(
+    conn: &mut sqlx::PgConnection,
+    name: &str,
+    metadata: &CrateMetadata,
+) -> Result<()> {
+    info!("Updating crate metadata for {}", name);
+    let Some(krate) = sqlx::query!(
+        "SELECT id, latest_version_id FROM crates WHERE name = $1",
+        name
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        bail!("crate {name} doesn't exist");
+    };
+
+    sqlx::query!(
+        "UPDATE crates SET categories = $2 WHERE id = $1",
+        krate.id,
+        serde_json::to_value(&metadata.categories)?,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if let Some(release_id) = krate.latest_version_id {
+        sqlx::query!(
+            "UPDATE releases SET description = $2, keywords = $3 WHERE id = $1",
+            release_id,
+            metadata.description,
+            serde_json::to_value(&metadata.keywords)?,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!("DELETE FROM keyword_rels WHERE rid = $1", release_id)
+            .execute(&mut *conn)
+            .await?;
+        add_keywords_into_database(conn, &metadata.keywords, release_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Stores the total download count of a crate, as reported by the registry.
+pub async fn update_crate_downloads(
+    conn: &mut sqlx::PgConnection,
+    name: &str,
+    downloads: i64,
+) -> Result<()> {
+    let result = sqlx::query!(
+        "UPDATE crates SET downloads = $2 WHERE name = $1",
+        name,
+        downloads,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        bail!("crate {name} doesn't exist");
+    }
+
+    Ok(())
+}
+
 /// Adds owners into database
 async fn update_owners_in_database(
     conn: &mut sqlx::PgConnection,
@@ -608,6 +789,116 @@ mod test {
     use crate::utils::CargoMetadata;
     use test_case::test_case;
 
+    #[test]
+    fn test_update_latest_version_id_only_yanked() {
+        async fn latest_version(conn: &mut sqlx::PgConnection) -> Result<Option<String>> {
+            Ok(sqlx::query_scalar!(
+                "SELECT releases.version
+                 FROM crates
+                 LEFT JOIN releases ON releases.id = crates.latest_version_id
+                 WHERE crates.name = 'krate'",
+            )
+            .fetch_one(conn)
+            .await?)
+        }
+
+        async_wrapper(|env| async move {
+            for version in ["0.1.0", "0.2.0"] {
+                env.async_fake_release()
+                    .await
+                    .name("krate")
+                    .version(version)
+                    .yanked(true)
+                    .create_async()
+                    .await?;
+            }
+
+            let mut conn = env.async_db().await.async_conn().await;
+            let crate_id = sqlx::query_scalar!("SELECT id FROM crates WHERE name = 'krate'")
+                .fetch_one(&mut *conn)
+                .await?;
+
+            assert_eq!(latest_version(&mut conn).await?.as_deref(), Some("0.2.0"));
+
+            sqlx::query!(
+                "INSERT INTO config (name, value) VALUES ($1, 'false')",
+                <&'static str>::from(ConfigName::YankedLatestVersion),
+            )
+            .execute(&mut *conn)
+            .await?;
+            update_latest_version_id(&mut conn, crate_id).await?;
+            assert_eq!(latest_version(&mut conn).await?, None);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_recount_releases() {
+        async fn aggregates(
+            conn: &mut sqlx::PgConnection,
+            crate_id: i32,
+        ) -> Result<(Option<i32>, Vec<String>)> {
+            let row = sqlx::query!(
+                r#"SELECT
+                    crates.latest_version_id,
+                    ARRAY(
+                        SELECT release_build_status.build_status::TEXT
+                        FROM releases
+                        INNER JOIN release_build_status ON release_build_status.rid = releases.id
+                        WHERE releases.crate_id = crates.id
+                        ORDER BY releases.version
+                    ) AS "build_statuses!"
+                 FROM crates
+                 WHERE crates.id = $1"#,
+                crate_id,
+            )
+            .fetch_one(conn)
+            .await?;
+            Ok((row.latest_version_id, row.build_statuses))
+        }
+
+        async_wrapper(|env| async move {
+            env.async_fake_release()
+                .await
+                .name("krate")
+                .version("0.1.0")
+                .create_async()
+                .await?;
+            env.async_fake_release()
+                .await
+                .name("krate")
+                .version("0.2.0")
+                .build_result_failed()
+                .create_async()
+                .await?;
+
+            let mut conn = env.async_db().await.async_conn().await;
+            let crate_id = sqlx::query_scalar!("SELECT id FROM crates WHERE name = 'krate'")
+                .fetch_one(&mut *conn)
+                .await?;
+
+            let expected = aggregates(&mut conn, crate_id).await?;
+            assert_eq!(expected.1, vec!["success", "failure"]);
+
+            sqlx::query!(
+                "UPDATE crates SET latest_version_id = NULL WHERE id = $1",
+                crate_id
+            )
+            .execute(&mut *conn)
+            .await?;
+            sqlx::query!("UPDATE release_build_status SET build_status = 'in_progress'")
+                .execute(&mut *conn)
+                .await?;
+            assert_ne!(aggregates(&mut conn, crate_id).await?, expected);
+
+            assert_eq!(recount_releases(&mut conn, crate_id).await?, 2);
+            assert_eq!(aggregates(&mut conn, crate_id).await?, expected);
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_set_build_to_error() {
         async_wrapper(|env| async move {
@@ -720,6 +1011,52 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_previous_documentation_hash() {
+        async_wrapper(|env| async move {
+            let mut conn = env.async_db().await.async_conn().await;
+            let crate_id = initialize_crate(&mut conn, "krate").await?;
+            let release_id = initialize_release(&mut conn, crate_id, "0.1.0").await?;
+            sqlx::query!(
+                "UPDATE releases SET rustdoc_status = TRUE WHERE id = $1",
+                release_id
+            )
+            .execute(&mut *conn)
+            .await?;
+
+            let first = initialize_build(&mut conn, release_id).await?;
+            finish_build(
+                &mut conn,
+                first,
+                "rustc_version",
+                "docsrs_version",
+                BuildStatus::Success,
+                None,
+            )
+            .await?;
+            set_build_documentation_hash(&mut conn, first, "abc").await?;
+
+            let second = initialize_build(&mut conn, release_id).await?;
+            assert_eq!(
+                previous_documentation_hash(&mut conn, "krate", "0.1.0", second).await?,
+                Some("abc".into())
+            );
+            // the running build is not successful yet
+            assert!(
+                previous_documentation_hash(&mut conn, "krate", "0.1.0", first)
+                    .await?
+                    .is_none()
+            );
+            assert!(
+                previous_documentation_hash(&mut conn, "krate", "0.2.0", second)
+                    .await?
+                    .is_none()
+            );
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn new_keywords() {
         wrapper(|env| {
@@ -833,6 +1170,64 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_update_crate_metadata_in_database() {
+        async_wrapper(|env| async move {
+            let release_id = env
+                .async_fake_release()
+                .await
+                .name("krate")
+                .version("1.0.0")
+                .description("outdated")
+                .keywords(vec!["kw 1".into(), "kw 2".into()])
+                .create_async()
+                .await?;
+
+            let mut conn = env.async_db().await.async_conn().await;
+            let metadata = CrateMetadata {
+                description: Some("recent".into()),
+                keywords: vec!["kw 2".into(), "kw 3".into()],
+                categories: vec!["science".into()],
+            };
+            update_crate_metadata_in_database(&mut conn, "krate", &metadata).await?;
+
+            let release = sqlx::query!(
+                "SELECT description, keywords FROM releases WHERE id = $1",
+                release_id
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+            assert_eq!(release.description.as_deref(), Some("recent"));
+            assert_eq!(release.keywords, Some(serde_json::json!(["kw 2", "kw 3"])));
+
+            let slugs: Vec<String> = sqlx::query_scalar!(
+                "SELECT keywords.slug
+                 FROM keywords
+                 INNER JOIN keyword_rels ON keyword_rels.kid = keywords.id
+                 WHERE keyword_rels.rid = $1
+                 ORDER BY keywords.slug",
+                release_id,
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            assert_eq!(slugs, vec!["kw-2", "kw-3"]);
+
+            let categories: Option<Value> =
+                sqlx::query_scalar!("SELECT categories FROM crates WHERE name = 'krate'")
+                    .fetch_one(&mut *conn)
+                    .await?;
+            assert_eq!(categories, Some(serde_json::json!(["science"])));
+
+            assert!(
+                update_crate_metadata_in_database(&mut conn, "missing", &metadata)
+                    .await
+                    .is_err()
+            );
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn new_owners() {
         async_wrapper(|env| async move {
@@ -1148,4 +1543,26 @@ mod test {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_update_crate_downloads() {
+        async_wrapper(|env| async move {
+            let mut conn = env.async_db().await.async_conn().await;
+
+            let crate_id = initialize_crate(&mut conn, "krate").await?;
+            update_crate_downloads(&mut conn, "krate", 1234).await?;
+
+            let downloads: Option<i64> =
+                sqlx::query_scalar!("SELECT downloads FROM crates WHERE id = $1", crate_id)
+                    .fetch_one(&mut *conn)
+                    .await?;
+            assert_eq!(downloads, Some(1234));
+
+            assert!(update_crate_downloads(&mut conn, "missing", 1)
+                .await
+                .is_err());
+
+            Ok(())
+        })
+    }
 }
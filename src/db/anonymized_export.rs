@@ -0,0 +1,254 @@
+use crate::error::Result;
+use anyhow::bail;
+use postgres::{
+    fallible_iterator::FallibleIterator,
+    types::{ToSql, Type},
+    Client,
+};
+use std::io::Write;
+
+/// Which crates [`export_anonymized`] exports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnonymizedExportOptions {
+    /// Export these crates, by their real name. When empty, the `limit` crates with the
+    /// most recent releases are exported.
+    pub crates: Vec<String>,
+    pub limit: i64,
+    /// Only the newest releases of every crate are exported.
+    pub releases_per_crate: i64,
+    /// Mixed into the hashed crate names, otherwise they could be recovered by hashing
+    /// all crate names from the index.
+    pub salt: String,
+}
+
+/// The hashed name of the crate in `column`, `$1` is the salt.
+fn hashed_name(column: &str) -> String {
+    format!("('crate-' || left(md5($1 || {column}), 16))")
+}
+
+/// Writes SQL statements that recreate a sanitized subset of the database, meant to be
+/// loaded into an empty, migrated database.
+///
+/// Crate names are hashed, and everything that could identify a crate or a person is
+/// dropped: descriptions, readmes, URLs, keywords, dependencies, owners and build logs.
+/// Returns how many crates were exported.
+pub fn export_anonymized(
+    conn: &mut Client,
+    mut writer: impl Write,
+    options: &AnonymizedExportOptions,
+) -> Result<usize> {
+    let crate_ids: Vec<i32> = if options.crates.is_empty() {
+        conn.query(
+            "SELECT crates.id
+             FROM crates
+             ORDER BY (
+                SELECT MAX(releases.release_time)
+                FROM releases
+                WHERE releases.crate_id = crates.id
+             ) DESC NULLS LAST, crates.id
+             LIMIT $1",
+            &[&options.limit],
+        )?
+        .iter()
+        .map(|row| row.get(0))
+        .collect()
+    } else {
+        let rows = conn.query(
+            "SELECT id, name FROM crates WHERE name = ANY($1)",
+            &[&options.crates],
+        )?;
+        let missing: Vec<&str> = options
+            .crates
+            .iter()
+            .filter(|name| {
+                !rows
+                    .iter()
+                    .any(|row| row.get::<_, &str>(1) == name.as_str())
+            })
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            bail!("unknown crates: {}", missing.join(", "));
+        }
+        rows.iter().map(|row| row.get(0)).collect()
+    };
+
+    let release_ids: Vec<i32> = conn
+        .query(
+            "SELECT id
+             FROM (
+                SELECT
+                    id,
+                    ROW_NUMBER() OVER (PARTITION BY crate_id ORDER BY release_time DESC, id DESC) AS n
+                FROM releases
+                WHERE crate_id = ANY($1)
+             ) AS newest
+             WHERE n <= $2",
+            &[&crate_ids, &options.releases_per_crate],
+        )?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let insert = |table: &str, overrides: &str, join: &str, filter: &str| {
+        format!(
+            "SELECT format(
+                'INSERT INTO {table} SELECT * FROM jsonb_populate_record(NULL::{table}, %L);',
+                to_jsonb({table}) || jsonb_build_object({overrides})
+             )
+             FROM {table} {join}
+             WHERE {filter}"
+        )
+    };
+    let statements = [
+        (
+            insert(
+                "crates",
+                &format!("'name', {}", hashed_name("crates.name")),
+                "",
+                "crates.id = ANY($2)",
+            ),
+            &crate_ids,
+        ),
+        (
+            insert(
+                "releases",
+                &format!(
+                    "'target_name', replace({}, '-', '_'),
+                     'dependencies', '[]'::jsonb,
+                     'keywords', '[]'::jsonb,
+                     'description', NULL,
+                     'description_long', NULL,
+                     'readme', NULL,
+                     'repository_url', NULL,
+                     'homepage_url', NULL,
+                     'documentation_url', NULL,
+                     'repository_id', NULL,
+                     'files', NULL",
+                    hashed_name("crates.name")
+                ),
+                "INNER JOIN crates ON crates.id = releases.crate_id",
+                "releases.id = ANY($2)",
+            ),
+            &release_ids,
+        ),
+        (
+            insert(
+                "builds",
+                "'output', NULL, 'errors', NULL, 'build_server', ''",
+                "",
+                "builds.rid = ANY($2)",
+            ),
+            &release_ids,
+        ),
+        (
+            insert(
+                "release_build_status",
+                "",
+                "",
+                "release_build_status.rid = ANY($2)",
+            ),
+            &release_ids,
+        ),
+        (
+            insert("doc_coverage", "", "", "doc_coverage.release_id = ANY($2)"),
+            &release_ids,
+        ),
+        (
+            insert(
+                "compression_rels",
+                "",
+                "",
+                "compression_rels.release = ANY($2)",
+            ),
+            &release_ids,
+        ),
+    ];
+
+    writeln!(writer, "BEGIN;")?;
+    for (query, ids) in &statements {
+        // not every query uses the salt, so the parameter types have to be given
+        let statement = conn.prepare_typed(query, &[Type::TEXT, Type::INT4_ARRAY])?;
+        let params: [&(dyn ToSql + Sync); 2] = [&options.salt, *ids];
+        let mut rows = conn.query_raw(&statement, params)?;
+        while let Some(row) = rows.next()? {
+            writeln!(writer, "{}", row.get::<_, &str>(0))?;
+        }
+    }
+    // the sequences would otherwise hand out the exported ids again
+    for table in ["crates", "releases", "builds"] {
+        writeln!(
+            writer,
+            "SELECT setval(pg_get_serial_sequence('{table}', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {table};"
+        )?;
+    }
+    writeln!(writer, "COMMIT;")?;
+    writer.flush()?;
+
+    Ok(crate_ids.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+
+    #[test]
+    fn test_export_anonymized() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("secret-crate")
+                .version("0.1.0")
+                .description("a very secret crate")
+                .create()?;
+            env.fake_release()
+                .name("secret-crate")
+                .version("0.2.0")
+                .create()?;
+            env.fake_release()
+                .name("unrelated-crate")
+                .version("1.0.0")
+                .create()?;
+
+            let mut conn = env.db().conn();
+            let options = AnonymizedExportOptions {
+                crates: vec!["secret-crate".into()],
+                limit: 10,
+                releases_per_crate: 1,
+                salt: "salt".into(),
+            };
+
+            let mut export = Vec::new();
+            assert_eq!(export_anonymized(&mut conn, &mut export, &options)?, 1);
+            let export = String::from_utf8(export)?;
+            assert!(!export.contains("secret"));
+            assert!(!export.contains("unrelated-crate"));
+
+            let unknown = AnonymizedExportOptions {
+                crates: vec!["unknown".into()],
+                ..options
+            };
+            assert!(export_anonymized(&mut conn, Vec::new(), &unknown).is_err());
+
+            // the export can be loaded into an empty database
+            conn.batch_execute(
+                "TRUNCATE crates, releases, builds, release_build_status, doc_coverage,
+                    compression_rels CASCADE",
+            )?;
+            conn.batch_execute(&export)?;
+
+            let releases = conn.query(
+                "SELECT crates.name, releases.version, releases.description
+                 FROM releases
+                 INNER JOIN crates ON crates.id = releases.crate_id",
+                &[],
+            )?;
+            assert_eq!(releases.len(), 1);
+            assert!(releases[0].get::<_, &str>(0).starts_with("crate-"));
+            assert_eq!(releases[0].get::<_, &str>(1), "0.2.0");
+            assert_eq!(releases[0].get::<_, Option<&str>>(2), None);
+
+            Ok(())
+        })
+    }
+}
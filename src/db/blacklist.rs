@@ -1,5 +1,7 @@
 use crate::error::Result;
+use chrono::{DateTime, Utc};
 use postgres::Client;
+use serde::Serialize;
 
 #[derive(Debug, thiserror::Error)]
 enum BlacklistError {
@@ -31,15 +33,41 @@ pub fn list_crates(conn: &mut Client) -> Result<Vec<String>> {
     Ok(rows.into_iter().map(|row| row.get(0)).collect())
 }
 
+/// A crate on the blacklist, with why and when it was added.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlacklistEntry {
+    pub crate_name: String,
+    pub reason: Option<String>,
+    /// Unknown for crates added before the time was recorded
+    pub added_at: Option<DateTime<Utc>>,
+}
+
+/// Returns the entries of the blacklist, sorted by crate name.
+pub fn list_entries(conn: &mut Client) -> Result<Vec<BlacklistEntry>> {
+    let rows = conn.query(
+        "SELECT crate_name, reason, added_at FROM blacklisted_crates ORDER BY crate_name asc;",
+        &[],
+    )?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| BlacklistEntry {
+            crate_name: row.get(0),
+            reason: row.get(1),
+            added_at: row.get(2),
+        })
+        .collect())
+}
+
 /// Adds a crate to the blacklist.
-pub fn add_crate(conn: &mut Client, name: &str) -> Result<()> {
+pub fn add_crate(conn: &mut Client, name: &str, reason: Option<&str>) -> Result<()> {
     if is_blacklisted(conn, name)? {
         return Err(BlacklistError::CrateAlreadyOnBlacklist(name.into()).into());
     }
 
     conn.execute(
-        "INSERT INTO blacklisted_crates (crate_name) VALUES ($1);",
-        &[&name],
+        "INSERT INTO blacklisted_crates (crate_name, reason) VALUES ($1, $2);",
+        &[&name, &reason],
     )?;
 
     Ok(())
@@ -69,22 +97,41 @@ mod tests {
             let db = env.db();
 
             // crates are added out of order to verify sorting
-            add_crate(&mut db.conn(), "crate A")?;
-            add_crate(&mut db.conn(), "crate C")?;
-            add_crate(&mut db.conn(), "crate B")?;
+            add_crate(&mut db.conn(), "crate A", None)?;
+            add_crate(&mut db.conn(), "crate C", None)?;
+            add_crate(&mut db.conn(), "crate B", None)?;
 
             assert!(list_crates(&mut db.conn())? == vec!["crate A", "crate B", "crate C"]);
             Ok(())
         });
     }
 
+    #[test]
+    fn test_list_blacklist_entries() {
+        crate::test::wrapper(|env| {
+            let db = env.db();
+
+            add_crate(&mut db.conn(), "crate B", Some("abuse"))?;
+            add_crate(&mut db.conn(), "crate A", None)?;
+
+            let entries = list_entries(&mut db.conn())?;
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].crate_name, "crate A");
+            assert_eq!(entries[0].reason, None);
+            assert_eq!(entries[1].crate_name, "crate B");
+            assert_eq!(entries[1].reason.as_deref(), Some("abuse"));
+            assert!(entries.iter().all(|entry| entry.added_at.is_some()));
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_add_to_and_remove_from_blacklist() {
         crate::test::wrapper(|env| {
             let db = env.db();
 
             assert!(!is_blacklisted(&mut db.conn(), "crate foo")?);
-            add_crate(&mut db.conn(), "crate foo")?;
+            add_crate(&mut db.conn(), "crate foo", None)?;
             assert!(is_blacklisted(&mut db.conn(), "crate foo")?);
             remove_crate(&mut db.conn(), "crate foo")?;
             assert!(!is_blacklisted(&mut db.conn(), "crate foo")?);
@@ -97,9 +144,9 @@ mod tests {
         crate::test::wrapper(|env| {
             let db = env.db();
 
-            add_crate(&mut db.conn(), "crate foo")?;
-            assert!(add_crate(&mut db.conn(), "crate foo").is_err());
-            add_crate(&mut db.conn(), "crate bar")?;
+            add_crate(&mut db.conn(), "crate foo", None)?;
+            assert!(add_crate(&mut db.conn(), "crate foo", None).is_err());
+            add_crate(&mut db.conn(), "crate bar", None)?;
 
             Ok(())
         });
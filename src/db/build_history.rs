@@ -0,0 +1,432 @@
+use crate::error::Result;
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use postgres::Client;
+use serde::Serialize;
+
+/// A single past build of a release, as stored in the `builds` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildHistoryEntry {
+    pub id: i32,
+    pub version: String,
+    pub build_status: String,
+    pub build_time: Option<DateTime<Utc>>,
+    pub rustc_version: Option<String>,
+    pub docsrs_version: Option<String>,
+    pub errors: Option<String>,
+}
+
+/// Returns the newest `limit` builds of a crate, optionally restricted to a single version.
+pub fn list_builds(
+    conn: &mut Client,
+    name: &str,
+    version: Option<&str>,
+    limit: i64,
+) -> Result<Vec<BuildHistoryEntry>> {
+    let rows = conn.query(
+        "SELECT
+            builds.id,
+            releases.version,
+            builds.build_status::TEXT,
+            builds.build_time,
+            builds.rustc_version,
+            builds.docsrs_version,
+            builds.errors
+         FROM builds
+         INNER JOIN releases ON releases.id = builds.rid
+         INNER JOIN crates ON crates.id = releases.crate_id
+         WHERE
+            crates.name = $1 AND
+            ($2::TEXT IS NULL OR releases.version = $2)
+         ORDER BY builds.id DESC
+         LIMIT $3",
+        &[&name, &version, &limit],
+    )?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| BuildHistoryEntry {
+            id: row.get(0),
+            version: row.get(1),
+            build_status: row.get(2),
+            build_time: row.get(3),
+            rustc_version: row.get(4),
+            docsrs_version: row.get(5),
+            errors: row.get(6),
+        })
+        .collect())
+}
+
+/// The build status of a release, summarized over all its builds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReleaseBuildStatus {
+    pub version: String,
+    pub build_status: String,
+    pub last_build_time: Option<DateTime<Utc>>,
+    pub has_docs: bool,
+}
+
+/// Returns the build status of the releases of a crate, newest first, optionally
+/// restricted to a single version.
+pub fn release_build_statuses(
+    conn: &mut Client,
+    name: &str,
+    version: Option<&str>,
+) -> Result<Vec<ReleaseBuildStatus>> {
+    let rows = conn.query(
+        "SELECT
+            releases.version,
+            COALESCE(release_build_status.build_status::TEXT, 'in_progress'),
+            release_build_status.last_build_time,
+            COALESCE(releases.rustdoc_status, FALSE)
+         FROM releases
+         INNER JOIN crates ON crates.id = releases.crate_id
+         LEFT JOIN release_build_status ON release_build_status.rid = releases.id
+         WHERE
+            crates.name = $1 AND
+            ($2::TEXT IS NULL OR releases.version = $2)
+         ORDER BY releases.id DESC",
+        &[&name, &version],
+    )?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ReleaseBuildStatus {
+            version: row.get(0),
+            build_status: row.get(1),
+            last_build_time: row.get(2),
+            has_docs: row.get(3),
+        })
+        .collect())
+}
+
+/// Where the log of a build is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildLog {
+    /// Old builds stored their log in the database.
+    Database(String),
+    /// The storage path of the log of the build target.
+    Storage(String),
+}
+
+/// Finds the log of a build of a release, the latest one if `build_id` isn't given.
+///
+/// Without `target`, the log of the release's default target is used.
+pub fn find_build_log(
+    conn: &mut Client,
+    name: &str,
+    version: &str,
+    build_id: Option<i32>,
+    target: Option<&str>,
+) -> Result<BuildLog> {
+    let row = conn
+        .query_opt(
+            "SELECT builds.id, builds.output, releases.default_target
+             FROM builds
+             INNER JOIN releases ON releases.id = builds.rid
+             INNER JOIN crates ON crates.id = releases.crate_id
+             WHERE
+                crates.name = $1 AND
+                releases.version = $2 AND
+                ($3::INT IS NULL OR builds.id = $3)
+             ORDER BY builds.id DESC
+             LIMIT 1",
+            &[&name, &version, &build_id],
+        )?
+        .with_context(|| format!("no build found for {name} {version}"))?;
+
+    let id: i32 = row.get(0);
+    if let Some(output) = row.get::<_, Option<String>>(1) {
+        return Ok(BuildLog::Database(output));
+    }
+
+    let target = match target {
+        Some(target) => target.to_owned(),
+        None => row
+            .get::<_, Option<String>>(2)
+            .context("the release has no default target, pass a target")?,
+    };
+    Ok(BuildLog::Storage(format!("build-logs/{id}/{target}.txt")))
+}
+
+/// A release whose latest build failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailedRelease {
+    pub name: String,
+    pub version: String,
+    pub build_id: i32,
+    pub build_time: Option<DateTime<Utc>>,
+    /// `None` for builds from before the categories were stored
+    pub error_category: Option<String>,
+}
+
+/// The latest build of every release.
+const LATEST_BUILDS: &str = "
+    SELECT DISTINCT ON (builds.rid)
+        builds.rid,
+        builds.id,
+        builds.build_status,
+        builds.build_time,
+        builds.error_category
+    FROM builds
+    ORDER BY builds.rid, builds.id DESC";
+
+/// Returns the newest `limit` releases whose latest build failed, optionally only the
+/// ones that failed with the given error category.
+pub fn list_failures(
+    conn: &mut Client,
+    category: Option<&str>,
+    limit: i64,
+) -> Result<Vec<FailedRelease>> {
+    let rows = conn.query(
+        &format!(
+            "SELECT
+                crates.name,
+                releases.version,
+                latest.id,
+                latest.build_time,
+                latest.error_category
+             FROM ({LATEST_BUILDS}) AS latest
+             INNER JOIN releases ON releases.id = latest.rid
+             INNER JOIN crates ON crates.id = releases.crate_id
+             WHERE
+                latest.build_status = 'failure' AND
+                ($1::TEXT IS NULL OR latest.error_category = $1)
+             ORDER BY latest.id DESC
+             LIMIT $2"
+        ),
+        &[&category, &limit],
+    )?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| FailedRelease {
+            name: row.get(0),
+            version: row.get(1),
+            build_id: row.get(2),
+            build_time: row.get(3),
+            error_category: row.get(4),
+        })
+        .collect())
+}
+
+/// Counts the releases whose latest build failed, grouped by the error category.
+pub fn count_failures_by_category(conn: &mut Client) -> Result<Vec<(Option<String>, i64)>> {
+    let rows = conn.query(
+        &format!(
+            "SELECT latest.error_category, COUNT(*)
+             FROM ({LATEST_BUILDS}) AS latest
+             WHERE latest.build_status = 'failure'
+             GROUP BY latest.error_category
+             ORDER BY COUNT(*) DESC, latest.error_category"
+        ),
+        &[],
+    )?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapper, FakeBuild};
+
+    #[test]
+    fn test_list_builds() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .builds(vec![
+                    FakeBuild::default().rustc_version("rustc 1.0.0"),
+                    FakeBuild::default()
+                        .successful(false)
+                        .rustc_version("rustc 2.0.0"),
+                ])
+                .create()?;
+            env.fake_release()
+                .name("foo")
+                .version("0.2.0")
+                .builds(vec![FakeBuild::default().rustc_version("rustc 3.0.0")])
+                .create()?;
+            env.fake_release().name("bar").version("0.1.0").create()?;
+
+            let mut conn = env.db().conn();
+
+            let builds = list_builds(&mut conn, "foo", None, 10)?;
+            assert_eq!(
+                builds
+                    .iter()
+                    .map(|b| (b.version.as_str(), b.rustc_version.as_deref()))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("0.2.0", Some("rustc 3.0.0")),
+                    ("0.1.0", Some("rustc 2.0.0")),
+                    ("0.1.0", Some("rustc 1.0.0")),
+                ]
+            );
+            assert_eq!(builds[1].build_status, "failure");
+
+            let builds = list_builds(&mut conn, "foo", Some("0.1.0"), 1)?;
+            assert_eq!(builds.len(), 1);
+            assert_eq!(builds[0].rustc_version.as_deref(), Some("rustc 2.0.0"));
+
+            assert!(list_builds(&mut conn, "baz", None, 10)?.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_release_build_statuses() {
+        wrapper(|env| {
+            env.fake_release().name("foo").version("0.1.0").create()?;
+            env.fake_release()
+                .name("foo")
+                .version("0.2.0")
+                .builds(vec![FakeBuild::default().successful(false)])
+                .create()?;
+            env.fake_release().name("bar").version("0.1.0").create()?;
+
+            let mut conn = env.db().conn();
+
+            let statuses = release_build_statuses(&mut conn, "foo", None)?;
+            assert_eq!(
+                statuses
+                    .iter()
+                    .map(|s| (s.version.as_str(), s.build_status.as_str()))
+                    .collect::<Vec<_>>(),
+                vec![("0.2.0", "failure"), ("0.1.0", "success")]
+            );
+            assert!(statuses[1].has_docs);
+            assert!(statuses[1].last_build_time.is_some());
+
+            let statuses = release_build_statuses(&mut conn, "foo", Some("0.1.0"))?;
+            assert_eq!(statuses.len(), 1);
+
+            assert!(release_build_statuses(&mut conn, "baz", None)?.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_find_build_log() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .default_target("x86_64-unknown-linux-gnu")
+                .builds(vec![
+                    FakeBuild::default()
+                        .no_s3_build_log()
+                        .db_build_log("old log"),
+                    FakeBuild::default(),
+                ])
+                .create()?;
+
+            let mut conn = env.db().conn();
+            let ids: Vec<i32> = conn
+                .query("SELECT id FROM builds ORDER BY id", &[])?
+                .into_iter()
+                .map(|row| row.get(0))
+                .collect();
+
+            assert_eq!(
+                find_build_log(&mut conn, "foo", "0.1.0", None, None)?,
+                BuildLog::Storage(format!(
+                    "build-logs/{}/x86_64-unknown-linux-gnu.txt",
+                    ids[1]
+                ))
+            );
+            assert_eq!(
+                find_build_log(
+                    &mut conn,
+                    "foo",
+                    "0.1.0",
+                    None,
+                    Some("i686-pc-windows-msvc")
+                )?,
+                BuildLog::Storage(format!("build-logs/{}/i686-pc-windows-msvc.txt", ids[1]))
+            );
+            assert_eq!(
+                find_build_log(&mut conn, "foo", "0.1.0", Some(ids[0]), None)?,
+                BuildLog::Database("old log".into())
+            );
+            assert!(find_build_log(&mut conn, "foo", "0.2.0", None, None).is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_failures_by_category() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("oom")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default().successful(false)])
+                .create()?;
+            env.fake_release()
+                .name("timeout")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default().successful(false)])
+                .create()?;
+            env.fake_release()
+                .name("old")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default().successful(false)])
+                .create()?;
+            // only the latest build of a release counts
+            env.fake_release()
+                .name("fixed")
+                .version("0.1.0")
+                .builds(vec![
+                    FakeBuild::default().successful(false),
+                    FakeBuild::default(),
+                ])
+                .create()?;
+
+            let mut conn = env.db().conn();
+            for (name, category) in [
+                ("oom", "out_of_memory"),
+                ("timeout", "timeout"),
+                ("fixed", "out_of_memory"),
+            ] {
+                conn.execute(
+                    "UPDATE builds
+                     SET error_category = $2
+                     FROM releases, crates
+                     WHERE
+                        releases.id = builds.rid AND
+                        crates.id = releases.crate_id AND
+                        crates.name = $1 AND
+                        builds.build_status = 'failure'",
+                    &[&name, &category],
+                )?;
+            }
+
+            let failures = list_failures(&mut conn, Some("out_of_memory"), 10)?;
+            assert_eq!(
+                failures.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(),
+                vec!["oom"]
+            );
+            assert_eq!(list_failures(&mut conn, None, 10)?.len(), 3);
+            assert_eq!(list_failures(&mut conn, None, 1)?.len(), 1);
+
+            assert_eq!(
+                count_failures_by_category(&mut conn)?,
+                vec![
+                    (Some("out_of_memory".into()), 1),
+                    (Some("timeout".into()), 1),
+                    (None, 1),
+                ]
+            );
+
+            Ok(())
+        })
+    }
+}
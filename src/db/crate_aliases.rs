@@ -0,0 +1,128 @@
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use postgres::Client;
+
+#[derive(Debug, thiserror::Error)]
+enum CrateAliasError {
+    #[error("crate {0} already has an alias")]
+    AliasAlreadyExists(String),
+
+    #[error("crate {0} has no alias")]
+    AliasNotFound(String),
+
+    #[error("crate {0} can't be an alias of itself")]
+    AliasOfItself(String),
+}
+
+/// A crate name that redirects to another crate, e.g. after the crate was renamed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateAlias {
+    pub name: String,
+    pub target: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returns the crate the given name is an alias of, if any.
+pub fn get_alias(conn: &mut Client, name: &str) -> Result<Option<String>> {
+    Ok(conn
+        .query_opt(
+            "SELECT target FROM crate_aliases WHERE name = $1;",
+            &[&name],
+        )?
+        .map(|row| row.get(0)))
+}
+
+/// Returns all aliases, sorted by name.
+pub fn list_aliases(conn: &mut Client) -> Result<Vec<CrateAlias>> {
+    let rows = conn.query(
+        "SELECT name, target, created_at FROM crate_aliases ORDER BY name ASC;",
+        &[],
+    )?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| CrateAlias {
+            name: row.get(0),
+            target: row.get(1),
+            created_at: row.get(2),
+        })
+        .collect())
+}
+
+/// Makes `name` an alias of the crate `target`.
+pub fn add_alias(conn: &mut Client, name: &str, target: &str) -> Result<()> {
+    if name == target {
+        return Err(CrateAliasError::AliasOfItself(name.into()).into());
+    }
+    if get_alias(conn, name)?.is_some() {
+        return Err(CrateAliasError::AliasAlreadyExists(name.into()).into());
+    }
+
+    conn.execute(
+        "INSERT INTO crate_aliases (name, target) VALUES ($1, $2);",
+        &[&name, &target],
+    )?;
+
+    Ok(())
+}
+
+/// Removes the alias of the given crate name.
+pub fn remove_alias(conn: &mut Client, name: &str) -> Result<()> {
+    if conn.execute("DELETE FROM crate_aliases WHERE name = $1;", &[&name])? == 0 {
+        return Err(CrateAliasError::AliasNotFound(name.into()).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_list_and_remove_aliases() {
+        crate::test::wrapper(|env| {
+            let mut conn = env.db().conn();
+
+            add_alias(&mut conn, "old-name", "new-name")?;
+            add_alias(&mut conn, "another-old-name", "new-name")?;
+
+            assert_eq!(
+                get_alias(&mut conn, "old-name")?.as_deref(),
+                Some("new-name")
+            );
+            assert_eq!(get_alias(&mut conn, "new-name")?, None);
+            assert_eq!(
+                list_aliases(&mut conn)?
+                    .into_iter()
+                    .map(|alias| (alias.name, alias.target))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("another-old-name".into(), "new-name".into()),
+                    ("old-name".into(), "new-name".into()),
+                ]
+            );
+
+            remove_alias(&mut conn, "old-name")?;
+            assert_eq!(get_alias(&mut conn, "old-name")?, None);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_invalid_aliases() {
+        crate::test::wrapper(|env| {
+            let mut conn = env.db().conn();
+
+            assert!(add_alias(&mut conn, "foo", "foo").is_err());
+
+            add_alias(&mut conn, "foo", "bar")?;
+            assert!(add_alias(&mut conn, "foo", "baz").is_err());
+
+            assert!(remove_alias(&mut conn, "bar").is_err());
+
+            Ok(())
+        });
+    }
+}
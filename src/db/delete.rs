@@ -56,6 +56,37 @@ pub fn delete_crate(
     Ok(())
 }
 
+/// Returns the names of all crates matching the SQL `LIKE` pattern, to delete them with
+/// [`delete_crate`].
+pub fn crates_matching(conn: &mut Client, pattern: &str) -> Result<Vec<String>> {
+    Ok(conn
+        .query(
+            "SELECT name FROM crates WHERE name LIKE $1 ORDER BY name",
+            &[&pattern],
+        )?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+/// Controls what [`delete_version`] removes besides the documentation and sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeleteVersionOptions {
+    /// Also remove the build rows and build logs.
+    ///
+    /// The builds reference the release, so when they are kept the release row stays too,
+    /// and is only marked as not having documentation.
+    pub include_builds: bool,
+}
+
+impl Default for DeleteVersionOptions {
+    fn default() -> Self {
+        Self {
+            include_builds: true,
+        }
+    }
+}
+
 #[context("error trying to delete release {name}-{version} from database")]
 pub fn delete_version(
     conn: &mut Client,
@@ -63,8 +94,9 @@ pub fn delete_version(
     config: &Config,
     name: &str,
     version: &str,
+    options: DeleteVersionOptions,
 ) -> Result<()> {
-    let is_library = delete_version_from_database(conn, name, version)?;
+    let (is_library, build_ids) = delete_version_from_database(conn, name, version, options)?;
     let paths = if is_library {
         LIBRARY_STORAGE_PATHS_TO_DELETE
     } else {
@@ -75,6 +107,10 @@ pub fn delete_version(
         storage.delete_prefix(&format!("{prefix}/{name}/{version}/"))?;
     }
 
+    for build_id in build_ids {
+        storage.delete_prefix(&format!("build-logs/{build_id}/"))?;
+    }
+
     let local_archive_cache = &config.local_archive_cache_path;
     let mut paths = vec![source_archive_path(name, version)];
     if is_library {
@@ -115,22 +151,59 @@ const METADATA: &[(&str, &str)] = &[
     ("doc_coverage", "release_id"),
 ];
 
-/// Returns whether this release was a library
-fn delete_version_from_database(conn: &mut Client, name: &str, version: &str) -> Result<bool> {
+/// Returns whether this release was a library, and the ids of the deleted builds
+fn delete_version_from_database(
+    conn: &mut Client,
+    name: &str,
+    version: &str,
+    options: DeleteVersionOptions,
+) -> Result<(bool, Vec<i32>)> {
     let crate_id = get_id(conn, name)?;
     let mut transaction = conn.transaction()?;
+
+    let build_ids: Vec<i32> = if options.include_builds {
+        transaction
+            .query(
+                "SELECT builds.id
+                 FROM builds
+                 INNER JOIN releases ON releases.id = builds.rid
+                 WHERE releases.crate_id = $1 AND releases.version = $2",
+                &[&crate_id, &version],
+            )?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
     for &(table, column) in METADATA {
+        if table == "builds" && !options.include_builds {
+            continue;
+        }
         transaction.execute(
             format!("DELETE FROM {table} WHERE {column} IN (SELECT id FROM releases WHERE crate_id = $1 AND version = $2)").as_str(),
             &[&crate_id, &version],
         )?;
     }
-    let is_library: bool = transaction
-        .query_one(
-            "DELETE FROM releases WHERE crate_id = $1 AND version = $2 RETURNING is_library",
-            &[&crate_id, &version],
-        )?
-        .get("is_library");
+    let is_library: bool = if options.include_builds {
+        transaction
+            .query_one(
+                "DELETE FROM releases WHERE crate_id = $1 AND version = $2 RETURNING is_library",
+                &[&crate_id, &version],
+            )?
+            .get("is_library")
+    } else {
+        transaction
+            .query_one(
+                "UPDATE releases
+                 SET rustdoc_status = false
+                 WHERE crate_id = $1 AND version = $2
+                 RETURNING is_library",
+                &[&crate_id, &version],
+            )?
+            .get("is_library")
+    };
     transaction.execute(
         "UPDATE crates SET latest_version_id = (
             SELECT id FROM releases WHERE release_time = (
@@ -153,7 +226,7 @@ fn delete_version_from_database(conn: &mut Client, name: &str, version: &str) ->
     }
 
     transaction.commit()?;
-    Ok(is_library)
+    Ok((is_library, build_ids))
 }
 
 /// Returns whether any release in this crate was a library
@@ -177,7 +250,7 @@ fn delete_crate_from_database(conn: &mut Client, name: &str, crate_id: i32) -> R
     let has_library = transaction
         .query_one(
             "SELECT
-                BOOL_OR(releases.is_library) AS has_library
+                COALESCE(BOOL_OR(releases.is_library), false) AS has_library
             FROM releases
             WHERE releases.crate_id = $1
             ",
@@ -212,6 +285,25 @@ mod tests {
             .is_empty())
     }
 
+    #[test]
+    fn test_crates_matching() {
+        wrapper(|env| {
+            for name in ["spam-1", "spam-2", "spam_3", "not-spam"] {
+                env.fake_release().name(name).create()?;
+            }
+
+            let mut conn = env.db().conn();
+            assert_eq!(
+                crates_matching(&mut conn, "spam-%")?,
+                vec!["spam-1".to_string(), "spam-2".to_string()]
+            );
+            assert_eq!(crates_matching(&mut conn, "spam_3")?, vec!["spam_3"]);
+            assert!(crates_matching(&mut conn, "other%")?.is_empty());
+
+            Ok(())
+        })
+    }
+
     #[test_case(true)]
     #[test_case(false)]
     fn test_delete_crate(archive_storage: bool) {
@@ -375,7 +467,14 @@ mod tests {
                 vec!["Peter Rabbit".to_string()]
             );
 
-            delete_version(&mut db.conn(), &env.storage(), &env.config(), "a", "1.0.0")?;
+            delete_version(
+                &mut db.conn(),
+                &env.storage(),
+                &env.config(),
+                "a",
+                "1.0.0",
+                DeleteVersionOptions::default(),
+            )?;
             assert!(!release_exists(&mut db.conn(), v1)?);
             if archive_storage {
                 // for archive storage the archive and index files
@@ -420,4 +519,60 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_delete_version_keeping_builds() {
+        wrapper(|env| {
+            let db = env.db();
+            let release_id = env
+                .fake_release()
+                .name("a")
+                .version("1.0.0")
+                .archive_storage(true)
+                .create()?;
+            let build_id: i32 = db
+                .conn()
+                .query_one("SELECT id FROM builds WHERE rid = $1", &[&release_id])?
+                .get(0);
+            let build_log = format!("build-logs/{build_id}/x86_64-unknown-linux-gnu.txt");
+            assert!(env.storage().exists(&build_log)?);
+
+            delete_version(
+                &mut db.conn(),
+                &env.storage(),
+                &env.config(),
+                "a",
+                "1.0.0",
+                DeleteVersionOptions {
+                    include_builds: false,
+                },
+            )?;
+
+            // the docs are gone, but the release and its builds are kept
+            assert!(!env.storage().exists(&rustdoc_archive_path("a", "1.0.0"))?);
+            assert!(release_exists(&mut db.conn(), release_id)?);
+            let rustdoc_status: Option<bool> = db
+                .conn()
+                .query_one(
+                    "SELECT rustdoc_status FROM releases WHERE id = $1",
+                    &[&release_id],
+                )?
+                .get(0);
+            assert_eq!(rustdoc_status, Some(false));
+            assert!(env.storage().exists(&build_log)?);
+
+            delete_version(
+                &mut db.conn(),
+                &env.storage(),
+                &env.config(),
+                "a",
+                "1.0.0",
+                DeleteVersionOptions::default(),
+            )?;
+            assert!(!release_exists(&mut db.conn(), release_id)?);
+            assert!(!env.storage().exists(&build_log)?);
+
+            Ok(())
+        })
+    }
 }
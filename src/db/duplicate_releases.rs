@@ -0,0 +1,181 @@
+use crate::db::{delete_version, DeleteVersionOptions};
+use crate::error::Result;
+use crate::{Config, Storage};
+use postgres::Client;
+use serde::Serialize;
+
+/// Multiple releases of a crate that are the same version.
+///
+/// The `(crate_id, version)` constraint only prevents exact duplicates, versions that only
+/// differ in their build metadata (`1.0.0` and `1.0.0+abc`) are the same version for the
+/// registry though.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateReleases {
+    pub name: String,
+    /// the version without build metadata
+    pub version: String,
+    pub releases: Vec<DuplicateRelease>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateRelease {
+    pub id: i32,
+    pub version: String,
+    pub has_successful_build: bool,
+}
+
+impl DuplicateReleases {
+    /// The release that is kept when fixing the duplicates: the newest release with a
+    /// successful build, or the newest release when none was built successfully.
+    pub fn release_to_keep(&self) -> &DuplicateRelease {
+        self.releases
+            .iter()
+            .filter(|release| release.has_successful_build)
+            .max_by_key(|release| release.id)
+            .or_else(|| self.releases.iter().max_by_key(|release| release.id))
+            .expect("duplicate releases always contain multiple releases")
+    }
+}
+
+/// Returns all groups of duplicate releases, sorted by crate name and version.
+pub fn find_duplicate_releases(conn: &mut Client) -> Result<Vec<DuplicateReleases>> {
+    let rows = conn.query(
+        "WITH releases_without_metadata AS (
+             SELECT
+                 releases.id,
+                 releases.crate_id,
+                 releases.version,
+                 split_part(releases.version, '+', 1) AS base_version
+             FROM releases
+         )
+         SELECT
+             crates.name,
+             releases.base_version,
+             releases.id,
+             releases.version,
+             EXISTS (
+                 SELECT 1 FROM builds
+                 WHERE builds.rid = releases.id AND builds.build_status = 'success'
+             ) AS has_successful_build
+         FROM releases_without_metadata AS releases
+         INNER JOIN crates ON crates.id = releases.crate_id
+         WHERE (releases.crate_id, releases.base_version) IN (
+             SELECT crate_id, base_version
+             FROM releases_without_metadata
+             GROUP BY crate_id, base_version
+             HAVING COUNT(*) > 1
+         )
+         ORDER BY crates.name, releases.base_version, releases.id",
+        &[],
+    )?;
+
+    let mut result: Vec<DuplicateReleases> = Vec::new();
+    for row in rows {
+        let name: String = row.get("name");
+        let base_version: String = row.get("base_version");
+        let release = DuplicateRelease {
+            id: row.get("id"),
+            version: row.get("version"),
+            has_successful_build: row.get("has_successful_build"),
+        };
+
+        match result.last_mut() {
+            Some(group) if group.name == name && group.version == base_version => {
+                group.releases.push(release)
+            }
+            _ => result.push(DuplicateReleases {
+                name,
+                version: base_version,
+                releases: vec![release],
+            }),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Deletes all duplicate releases except for [`DuplicateReleases::release_to_keep`].
+///
+/// Returns the number of deleted releases.
+pub fn fix_duplicate_releases(
+    conn: &mut Client,
+    storage: &Storage,
+    config: &Config,
+    duplicates: &[DuplicateReleases],
+) -> Result<usize> {
+    let mut deleted = 0;
+    for group in duplicates {
+        let keep = group.release_to_keep().id;
+        for release in group.releases.iter().filter(|release| release.id != keep) {
+            delete_version(
+                conn,
+                storage,
+                config,
+                &group.name,
+                &release.version,
+                DeleteVersionOptions::default(),
+            )?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapper, FakeBuild};
+
+    #[test]
+    fn test_find_and_fix_duplicate_releases() {
+        wrapper(|env| {
+            env.fake_release().name("foo").version("1.0.0").create()?;
+            env.fake_release()
+                .name("foo")
+                .version("1.0.0+old")
+                .builds(vec![FakeBuild::default().successful(false)])
+                .create()?;
+            env.fake_release().name("foo").version("1.1.0").create()?;
+            env.fake_release()
+                .name("bar")
+                .version("0.1.0+a")
+                .builds(vec![FakeBuild::default().successful(false)])
+                .create()?;
+            env.fake_release()
+                .name("bar")
+                .version("0.1.0+b")
+                .builds(vec![FakeBuild::default().successful(false)])
+                .create()?;
+
+            let mut conn = env.db().conn();
+            let duplicates = find_duplicate_releases(&mut conn)?;
+            assert_eq!(
+                duplicates
+                    .iter()
+                    .map(|group| (
+                        group.name.as_str(),
+                        group.version.as_str(),
+                        group.releases.len(),
+                        group.release_to_keep().version.as_str()
+                    ))
+                    .collect::<Vec<_>>(),
+                vec![("bar", "0.1.0", 2, "0.1.0+b"), ("foo", "1.0.0", 2, "1.0.0")]
+            );
+
+            assert_eq!(
+                fix_duplicate_releases(&mut conn, &env.storage(), &env.config(), &duplicates)?,
+                2
+            );
+            assert!(find_duplicate_releases(&mut conn)?.is_empty());
+
+            let versions: Vec<String> = conn
+                .query("SELECT version FROM releases ORDER BY version", &[])?
+                .into_iter()
+                .map(|row| row.get(0))
+                .collect();
+            assert_eq!(versions, vec!["0.1.0+b", "1.0.0", "1.1.0"]);
+
+            Ok(())
+        })
+    }
+}
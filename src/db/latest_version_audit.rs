@@ -0,0 +1,101 @@
+use super::expected_latest_release;
+use anyhow::{Context as _, Result};
+use serde::Serialize;
+
+/// A crate whose `latest_version_id` isn't the release
+/// [`update_latest_version_id`](super::update_latest_version_id) would pick.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WrongLatestVersion {
+    pub name: String,
+    /// the version `latest_version_id` points to
+    pub stored: Option<String>,
+    pub expected: Option<String>,
+}
+
+/// Checks the `latest_version_id` of a crate without changing it.
+///
+/// The expected release is picked like in `update_latest_version_id`: the newest unyanked
+/// stable release, then pre-releases and yanked releases, depending on the config.
+pub async fn verify_latest_version_id(
+    conn: &mut sqlx::PgConnection,
+    crate_id: i32,
+) -> Result<Option<WrongLatestVersion>> {
+    let krate = sqlx::query!(
+        r#"SELECT
+            crates.name,
+            crates.latest_version_id,
+            releases.version AS "version?"
+         FROM crates
+         LEFT JOIN releases ON releases.id = crates.latest_version_id
+         WHERE crates.id = $1"#,
+        crate_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .with_context(|| format!("there is no crate with the id {crate_id}"))?;
+
+    let expected = expected_latest_release(&mut *conn, crate_id).await?;
+    if krate.latest_version_id == expected.as_ref().map(|release| release.id) {
+        return Ok(None);
+    }
+
+    Ok(Some(WrongLatestVersion {
+        name: krate.name,
+        stored: krate.version,
+        expected: expected.map(|release| release.version.to_string()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::update_latest_version_id;
+    use crate::test::async_wrapper;
+
+    #[test]
+    fn test_verify_latest_version_id() {
+        async_wrapper(|env| async move {
+            let old = env
+                .async_fake_release()
+                .await
+                .name("foo")
+                .version("1.0.0")
+                .create_async()
+                .await?;
+            env.async_fake_release()
+                .await
+                .name("foo")
+                .version("1.1.0")
+                .create_async()
+                .await?;
+
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+            let crate_id = sqlx::query_scalar!("SELECT id FROM crates WHERE name = 'foo'")
+                .fetch_one(&mut *conn)
+                .await?;
+            assert_eq!(verify_latest_version_id(&mut conn, crate_id).await?, None);
+
+            sqlx::query!(
+                "UPDATE crates SET latest_version_id = $2 WHERE id = $1",
+                crate_id,
+                old
+            )
+            .execute(&mut *conn)
+            .await?;
+            assert_eq!(
+                verify_latest_version_id(&mut conn, crate_id).await?,
+                Some(WrongLatestVersion {
+                    name: "foo".into(),
+                    stored: Some("1.0.0".into()),
+                    expected: Some("1.1.0".into()),
+                })
+            );
+
+            update_latest_version_id(&mut conn, crate_id).await?;
+            assert_eq!(verify_latest_version_id(&mut conn, crate_id).await?, None);
+
+            Ok(())
+        })
+    }
+}
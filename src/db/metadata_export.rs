@@ -0,0 +1,193 @@
+use crate::error::Result;
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use postgres::{fallible_iterator::FallibleIterator, Client};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// The metadata of a single release, one line of the export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReleaseMetadata {
+    pub name: String,
+    pub version: String,
+    pub release_time: Option<DateTime<Utc>>,
+    pub yanked: Option<bool>,
+    pub rustdoc_status: Option<bool>,
+    pub license: Option<String>,
+    pub description: Option<String>,
+    pub repository_url: Option<String>,
+    pub homepage_url: Option<String>,
+    pub documentation_url: Option<String>,
+    pub keywords: Option<Value>,
+    pub default_target: Option<String>,
+    pub doc_targets: Option<Value>,
+}
+
+/// Writes the metadata of all releases to `writer` as JSON lines, ordered by crate name.
+///
+/// With `resume_from`, the export starts at that crate. The writer is flushed after every
+/// crate, so an interrupted export only ends with a partially written crate.
+///
+/// Returns how many releases were exported.
+pub fn export_metadata(
+    conn: &mut Client,
+    mut writer: impl Write,
+    resume_from: Option<&str>,
+) -> Result<usize> {
+    // `COLLATE "C"` compares the names byte-wise, like `prepare_resume` does.
+    let mut rows = conn.query_raw(
+        r#"SELECT
+            crates.name,
+            releases.version,
+            releases.release_time,
+            releases.yanked,
+            releases.rustdoc_status,
+            releases.license,
+            releases.description,
+            releases.repository_url,
+            releases.homepage_url,
+            releases.documentation_url,
+            releases.keywords,
+            releases.default_target,
+            releases.doc_targets
+         FROM releases
+         INNER JOIN crates ON crates.id = releases.crate_id
+         WHERE $1::TEXT IS NULL OR crates.name COLLATE "C" >= $1
+         ORDER BY crates.name COLLATE "C", releases.id"#,
+        [resume_from],
+    )?;
+
+    let mut exported = 0;
+    let mut current_crate: Option<String> = None;
+    while let Some(row) = rows.next()? {
+        let release = ReleaseMetadata {
+            name: row.get("name"),
+            version: row.get("version"),
+            release_time: row.get("release_time"),
+            yanked: row.get("yanked"),
+            rustdoc_status: row.get("rustdoc_status"),
+            license: row.get("license"),
+            description: row.get("description"),
+            repository_url: row.get("repository_url"),
+            homepage_url: row.get("homepage_url"),
+            documentation_url: row.get("documentation_url"),
+            keywords: row.get("keywords"),
+            default_target: row.get("default_target"),
+            doc_targets: row.get("doc_targets"),
+        };
+
+        if current_crate.as_deref() != Some(&release.name) {
+            writer.flush()?;
+            current_crate = Some(release.name.clone());
+        }
+
+        serde_json::to_writer(&mut writer, &release)?;
+        writer.write_all(b"\n")?;
+        exported += 1;
+    }
+    writer.flush()?;
+
+    Ok(exported)
+}
+
+/// Opens an interrupted export to append the releases from `resume_from` on.
+///
+/// A partially written last line and the lines of `resume_from` and all later crates are
+/// removed first, so no release ends up twice in the export.
+pub fn prepare_resume(path: &Path, resume_from: &str) -> Result<File> {
+    #[derive(Deserialize)]
+    struct Line {
+        name: String,
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("could not open {}", path.display()))?;
+
+    let mut keep = 0;
+    {
+        let mut reader = BufReader::new(&mut file);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 || !line.ends_with(b"\n") {
+                break;
+            }
+            let parsed: Line = serde_json::from_slice(&line)
+                .with_context(|| format!("invalid line at byte {keep} of the export"))?;
+            if parsed.name.as_str() >= resume_from {
+                break;
+            }
+            keep += read as u64;
+        }
+    }
+
+    file.set_len(keep)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+    use std::fs;
+
+    #[test]
+    fn test_export_and_resume() {
+        wrapper(|env| {
+            env.fake_release().name("a").version("0.1.0").create()?;
+            env.fake_release().name("b").version("0.1.0").create()?;
+            env.fake_release().name("b").version("0.2.0").create()?;
+            env.fake_release().name("c").version("0.1.0").create()?;
+
+            let mut conn = env.db().conn();
+
+            let mut full = Vec::new();
+            assert_eq!(export_metadata(&mut conn, &mut full, None)?, 4);
+            let lines: Vec<ReleaseMetadata> = full
+                .split(|&b| b == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(serde_json::from_slice)
+                .collect::<Result<_, _>>()?;
+            assert_eq!(
+                lines
+                    .iter()
+                    .map(|r| (r.name.as_str(), r.version.as_str()))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("a", "0.1.0"),
+                    ("b", "0.1.0"),
+                    ("b", "0.2.0"),
+                    ("c", "0.1.0")
+                ]
+            );
+
+            // the export was interrupted while writing the second release of `b`
+            let first_b_line = full.iter().position(|&b| b == b'\n').unwrap() + 1;
+            let second_b_line = first_b_line
+                + full[first_b_line..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .unwrap()
+                + 1;
+            let dir = tempfile::tempdir()?;
+            let path = dir.path().join("export.jsonl");
+            fs::write(&path, &full[..second_b_line + 10])?;
+
+            let file = prepare_resume(&path, "b")?;
+            assert_eq!(export_metadata(&mut conn, file, Some("b"))?, 3);
+            assert_eq!(fs::read(&path)?, full);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,293 @@
+use super::{
+    schema::{current_schema, load_schema},
+    MIGRATOR,
+};
+use anyhow::Result;
+use sqlx::{migrate::Migrate, Connection as _};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+/// Temporary schema the applied migrations are replayed into, it's never committed.
+const SCRATCH_SCHEMA: &str = "docs_rs_migration_check";
+
+/// A difference between the migrations in the code and the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationProblem {
+    /// The migration is older than the latest applied one, but wasn't applied.
+    NotApplied(i64),
+    /// The migration was applied, but doesn't exist in the code.
+    Unknown(i64),
+    /// The migration file changed after it was applied.
+    ChecksumMismatch(i64),
+    /// The migration failed and was only partially applied.
+    Failed(i64),
+    /// A column the migrations create doesn't exist.
+    MissingColumn { table: String, column: String },
+    /// A column exists that the migrations don't create.
+    UnexpectedColumn { table: String, column: String },
+    /// A column has a different type or nullability than the migrations create.
+    ChangedColumn {
+        table: String,
+        column: String,
+        expected: String,
+        actual: String,
+    },
+    /// An index the migrations create doesn't exist.
+    MissingIndex { table: String, index: String },
+    /// An index exists that the migrations don't create.
+    UnexpectedIndex { table: String, index: String },
+    /// The given check query didn't return `true`.
+    CheckFailed(String),
+}
+
+impl fmt::Display for MigrationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotApplied(version) => write!(f, "migration {version} was never applied"),
+            Self::Unknown(version) => write!(f, "migration {version} is unknown to this version"),
+            Self::ChecksumMismatch(version) => {
+                write!(f, "migration {version} changed after it was applied")
+            }
+            Self::Failed(version) => write!(f, "migration {version} was only partially applied"),
+            Self::MissingColumn { table, column } => write!(f, "missing column {table}.{column}"),
+            Self::UnexpectedColumn { table, column } => {
+                write!(f, "unexpected column {table}.{column}")
+            }
+            Self::ChangedColumn {
+                table,
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column {table}.{column} is {actual}, expected {expected}"
+            ),
+            Self::MissingIndex { table, index } => write!(f, "missing index {index} on {table}"),
+            Self::UnexpectedIndex { table, index } => {
+                write!(f, "unexpected index {index} on {table}")
+            }
+            Self::CheckFailed(query) => write!(f, "check query failed: {query}"),
+        }
+    }
+}
+
+/// Verifies that the database is still consistent with the migrations in the code.
+///
+/// The recorded migrations are compared with the migration files, only for `version` if
+/// given. The schema is compared with the schema the applied migrations create, which
+/// is built in a temporary schema that is rolled back afterwards.
+/// `check_query` can be a query returning a single boolean for additional post-conditions.
+pub async fn verify_migrations(
+    conn: &mut sqlx::PgConnection,
+    version: Option<i64>,
+    check_query: Option<&str>,
+) -> Result<Vec<MigrationProblem>> {
+    if let Some(version) = version {
+        if !MIGRATOR.version_exists(version) {
+            anyhow::bail!("there is no migration {version}");
+        }
+    }
+
+    let mut problems = Vec::new();
+
+    let applied: HashMap<i64, Vec<u8>> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect();
+    let latest_applied = applied.keys().max().copied().unwrap_or(0);
+    let selected = |v: i64| version.map_or(true, |version| version == v);
+
+    if let Some(dirty) = conn.dirty_version().await? {
+        if selected(dirty) {
+            problems.push(MigrationProblem::Failed(dirty));
+        }
+    }
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .filter(|m| selected(m.version))
+    {
+        match applied.get(&migration.version) {
+            Some(checksum) if *checksum != *migration.checksum => {
+                problems.push(MigrationProblem::ChecksumMismatch(migration.version))
+            }
+            None if migration.version < latest_applied => {
+                problems.push(MigrationProblem::NotApplied(migration.version))
+            }
+            _ => {}
+        }
+    }
+    let mut unknown: Vec<i64> = applied
+        .keys()
+        .copied()
+        .filter(|v| selected(*v) && !MIGRATOR.version_exists(*v))
+        .collect();
+    unknown.sort();
+    problems.extend(unknown.into_iter().map(MigrationProblem::Unknown));
+
+    problems.extend(schema_drift(conn, &applied).await?);
+
+    if let Some(check_query) = check_query {
+        let passed: Option<bool> = sqlx::query_scalar(check_query)
+            .fetch_one(&mut *conn)
+            .await?;
+        if passed != Some(true) {
+            problems.push(MigrationProblem::CheckFailed(check_query.to_owned()));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Compares the current schema with a schema built from the applied migrations.
+async fn schema_drift(
+    conn: &mut sqlx::PgConnection,
+    applied: &HashMap<i64, Vec<u8>>,
+) -> Result<Vec<MigrationProblem>> {
+    let actual_schema = current_schema(&mut *conn).await?;
+    let actual = comparable_schema(conn, &actual_schema).await?;
+
+    let mut tx = conn.begin().await?;
+    sqlx::query(&format!("CREATE SCHEMA {SCRATCH_SCHEMA}"))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!(
+        "SET LOCAL search_path TO {SCRATCH_SCHEMA}, public"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    tx.ensure_migrations_table().await?;
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .filter(|m| applied.contains_key(&m.version))
+    {
+        tx.apply(migration).await?;
+    }
+    let expected = comparable_schema(&mut tx, SCRATCH_SCHEMA).await?;
+    tx.rollback().await?;
+
+    let mut problems = Vec::new();
+    for ((table, column), expected_type) in &expected.columns {
+        match actual.columns.get(&(table.clone(), column.clone())) {
+            None => problems.push(MigrationProblem::MissingColumn {
+                table: table.clone(),
+                column: column.clone(),
+            }),
+            Some(actual_type) if actual_type != expected_type => {
+                problems.push(MigrationProblem::ChangedColumn {
+                    table: table.clone(),
+                    column: column.clone(),
+                    expected: expected_type.clone(),
+                    actual: actual_type.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (table, column) in actual.columns.keys() {
+        if !expected
+            .columns
+            .contains_key(&(table.clone(), column.clone()))
+        {
+            problems.push(MigrationProblem::UnexpectedColumn {
+                table: table.clone(),
+                column: column.clone(),
+            });
+        }
+    }
+    for (table, index) in expected.indexes.difference(&actual.indexes) {
+        problems.push(MigrationProblem::MissingIndex {
+            table: table.clone(),
+            index: index.clone(),
+        });
+    }
+    for (table, index) in actual.indexes.difference(&expected.indexes) {
+        problems.push(MigrationProblem::UnexpectedIndex {
+            table: table.clone(),
+            index: index.clone(),
+        });
+    }
+
+    Ok(problems)
+}
+
+struct Schema {
+    /// `(table, column)` => `type, NULL / NOT NULL`
+    columns: BTreeMap<(String, String), String>,
+    /// `(table, index)`
+    indexes: BTreeSet<(String, String)>,
+}
+
+async fn comparable_schema(conn: &mut sqlx::PgConnection, schema: &str) -> Result<Schema> {
+    let mut columns = BTreeMap::new();
+    let mut indexes = BTreeSet::new();
+    for table in load_schema(conn, schema).await? {
+        for column in table.columns {
+            let nullability = if column.nullable { "NULL" } else { "NOT NULL" };
+            columns.insert(
+                (table.name.clone(), column.name),
+                format!("{} {nullability}", column.data_type),
+            );
+        }
+        for index in table.indexes {
+            indexes.insert((table.name.clone(), index.name));
+        }
+    }
+
+    Ok(Schema { columns, indexes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::async_wrapper;
+
+    #[test]
+    fn test_verify_migrations() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            assert!(verify_migrations(&mut conn, None, None).await?.is_empty());
+            assert!(verify_migrations(&mut conn, Some(1), None).await.is_err());
+
+            sqlx::query("ALTER TABLE queue DROP COLUMN toolchain")
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query("ALTER TABLE queue ADD COLUMN manual TEXT")
+                .execute(&mut *conn)
+                .await?;
+
+            assert_eq!(
+                verify_migrations(&mut conn, None, Some("SELECT COUNT(*) > 0 FROM queue")).await?,
+                vec![
+                    MigrationProblem::MissingColumn {
+                        table: "queue".into(),
+                        column: "toolchain".into(),
+                    },
+                    MigrationProblem::UnexpectedColumn {
+                        table: "queue".into(),
+                        column: "manual".into(),
+                    },
+                    MigrationProblem::CheckFailed("SELECT COUNT(*) > 0 FROM queue".into()),
+                ]
+            );
+
+            // restore the schema so the down migrations still work
+            sqlx::query("ALTER TABLE queue DROP COLUMN manual")
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query("ALTER TABLE queue ADD COLUMN toolchain TEXT")
+                .execute(&mut *conn)
+                .await?;
+
+            Ok(())
+        })
+    }
+}
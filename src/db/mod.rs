@@ -1,31 +1,202 @@
 //! Database operations
 use anyhow::Result;
-use sqlx::migrate::{Migrate, Migrator};
+use sqlx::migrate::{Migrate, Migration, Migrator};
+use std::collections::HashSet;
 
 pub use self::add_package::update_latest_version_id;
 pub(crate) use self::add_package::{
-    add_doc_coverage, add_package_into_database, finish_build, initialize_build, initialize_crate,
-    initialize_release, update_build_with_error,
+    add_doc_coverage, add_package_into_database, expected_latest_release, finish_build,
+    initialize_build, initialize_crate, initialize_release, previous_documentation_hash,
+    set_build_documentation_hash, set_build_error_category, update_build_with_error,
 };
 pub use self::{
-    add_package::{update_build_status, update_crate_data_in_database},
-    delete::{delete_crate, delete_version},
+    add_package::{
+        recount_releases, update_build_status, update_crate_data_in_database,
+        update_crate_downloads, update_crate_metadata_in_database,
+    },
+    delete::{crates_matching, delete_crate, delete_version, DeleteVersionOptions},
     file::{add_path_into_database, add_path_into_remote_archive},
     overrides::Overrides,
     pool::{AsyncPoolClient, Pool, PoolClient, PoolError},
 };
 
 mod add_package;
+pub mod anonymized_export;
 pub mod blacklist;
+pub mod build_history;
+pub mod crate_aliases;
 pub mod delete;
+pub mod duplicate_releases;
 pub(crate) mod file;
+pub mod latest_version_audit;
+pub mod metadata_export;
+pub mod migration_check;
+pub mod orphaned_crates;
 mod overrides;
 mod pool;
+pub mod release_reassignment;
+pub mod schema;
 pub(crate) mod types;
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
 pub async fn migrate(conn: &mut sqlx::PgConnection, target: Option<i64>) -> Result<()> {
+    migrate_from_schemamama(conn).await?;
+
+    // when we find records
+    if let Some(target) = target {
+        MIGRATOR.undo(conn, target).await?;
+    } else {
+        MIGRATOR.run(conn).await?;
+    }
+    Ok(())
+}
+
+/// Applies the next `steps` pending migrations, or reverts the last `-steps` applied ones
+/// when `steps` is negative.
+///
+/// Fails without changing anything when there are fewer migrations to apply or revert.
+/// Returns the version and description of every migration that was applied or reverted.
+pub async fn migrate_steps(
+    conn: &mut sqlx::PgConnection,
+    steps: i64,
+) -> Result<Vec<(i64, String)>> {
+    migrate_from_schemamama(conn).await?;
+
+    conn.lock().await?;
+    let result = migrate_steps_locked(conn, steps).await;
+    conn.unlock().await?;
+    result
+}
+
+async fn migrate_steps_locked(
+    conn: &mut sqlx::PgConnection,
+    steps: i64,
+) -> Result<Vec<(i64, String)>> {
+    if let Some(version) = conn.dirty_version().await? {
+        anyhow::bail!("migration {version} was only partially applied");
+    }
+
+    let applied = applied_versions(conn).await?;
+
+    let mut done = Vec::new();
+    for migration in select_steps(&applied, steps)? {
+        if steps >= 0 {
+            conn.apply(migration).await?;
+        } else {
+            conn.revert(migration).await?;
+        }
+        done.push((migration.version, migration.description.to_string()));
+    }
+    Ok(done)
+}
+
+async fn applied_versions(conn: &mut sqlx::PgConnection) -> Result<HashSet<i64>> {
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+/// The latest applied migration, `None` for an empty database.
+pub async fn current_version(conn: &mut sqlx::PgConnection) -> Result<Option<i64>> {
+    if !migrations_table_exists(conn).await? {
+        return Ok(None);
+    }
+    Ok(applied_versions(conn).await?.into_iter().max())
+}
+
+async fn migrations_table_exists(conn: &mut sqlx::PgConnection) -> Result<bool> {
+    Ok(
+        sqlx::query_scalar!(r#"SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS "exists!""#)
+            .fetch_one(conn)
+            .await?,
+    )
+}
+
+/// The migrations [`migrate_steps`] applies or reverts for `steps`.
+fn select_steps(applied: &HashSet<i64>, steps: i64) -> Result<Vec<&'static Migration>> {
+    let migrations: Vec<_> = if steps >= 0 {
+        MIGRATOR
+            .iter()
+            .filter(|m| !m.migration_type.is_down_migration())
+            .filter(|m| !applied.contains(&m.version))
+            .collect()
+    } else {
+        MIGRATOR
+            .iter()
+            .rev()
+            .filter(|m| m.migration_type.is_down_migration())
+            .filter(|m| applied.contains(&m.version))
+            .collect()
+    };
+
+    let count = steps.unsigned_abs() as usize;
+    if migrations.len() < count {
+        anyhow::bail!(
+            "can't {} {count} migrations, only {} are {}",
+            if steps >= 0 { "apply" } else { "revert" },
+            migrations.len(),
+            if steps >= 0 { "pending" } else { "applied" },
+        );
+    }
+
+    Ok(migrations.into_iter().take(count).collect())
+}
+
+/// A migration that would be applied or reverted, see [`plan_migrations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedMigration {
+    pub version: i64,
+    pub description: String,
+    /// Whether the migration is reverted instead of applied
+    pub revert: bool,
+    pub sql: String,
+}
+
+/// Returns the migrations [`migrate`] with `target`, or [`migrate_steps`] with `steps`,
+/// would apply or revert, in order, without changing the database.
+pub async fn plan_migrations(
+    conn: &mut sqlx::PgConnection,
+    target: Option<i64>,
+    steps: Option<i64>,
+) -> Result<Vec<PlannedMigration>> {
+    let applied = if migrations_table_exists(conn).await? {
+        applied_versions(conn).await?
+    } else {
+        HashSet::new()
+    };
+
+    let migrations: Vec<&Migration> = match (steps, target) {
+        (Some(steps), _) => select_steps(&applied, steps)?,
+        (None, Some(target)) => MIGRATOR
+            .iter()
+            .rev()
+            .filter(|m| m.migration_type.is_down_migration())
+            .filter(|m| m.version > target && applied.contains(&m.version))
+            .collect(),
+        (None, None) => MIGRATOR
+            .iter()
+            .filter(|m| !m.migration_type.is_down_migration())
+            .filter(|m| !applied.contains(&m.version))
+            .collect(),
+    };
+
+    Ok(migrations
+        .into_iter()
+        .map(|m| PlannedMigration {
+            version: m.version,
+            description: m.description.to_string(),
+            revert: m.migration_type.is_down_migration(),
+            sql: m.sql.to_string(),
+        })
+        .collect())
+}
+
+/// Marks the initial migration as applied on databases that were migrated with `schemamama`.
+async fn migrate_from_schemamama(conn: &mut sqlx::PgConnection) -> Result<()> {
     conn.ensure_migrations_table().await?;
 
     // `database_versions` is the table that tracked the old `schemamama` migrations.
@@ -71,12 +242,81 @@ pub async fn migrate(conn: &mut sqlx::PgConnection, target: Option<i64>) -> Resu
             .execute(&mut *conn)
             .await?;
     }
+    Ok(())
+}
 
-    // when we find records
-    if let Some(target) = target {
-        MIGRATOR.undo(conn, target).await?;
-    } else {
-        MIGRATOR.run(conn).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::async_wrapper;
+
+    #[test]
+    fn test_migrate_steps() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            async fn applied(conn: &mut sqlx::PgConnection) -> Result<Vec<i64>> {
+                Ok(conn
+                    .list_applied_migrations()
+                    .await?
+                    .into_iter()
+                    .map(|m| m.version)
+                    .collect())
+            }
+
+            let all = applied(&mut conn).await?;
+            assert!(all.len() > 2);
+
+            // everything is applied already
+            assert!(migrate_steps(&mut conn, 1).await.is_err());
+            assert_eq!(applied(&mut conn).await?, all);
+
+            let reverted = migrate_steps(&mut conn, -2).await?;
+            assert_eq!(
+                reverted.iter().map(|(v, _)| *v).collect::<Vec<_>>(),
+                all.iter().rev().take(2).copied().collect::<Vec<_>>()
+            );
+            assert_eq!(applied(&mut conn).await?, all[..all.len() - 2]);
+
+            assert_eq!(migrate_steps(&mut conn, 1).await?.len(), 1);
+            assert_eq!(applied(&mut conn).await?, all[..all.len() - 1]);
+
+            assert!(migrate_steps(&mut conn, 2).await.is_err());
+            migrate(&mut conn, None).await?;
+            assert_eq!(applied(&mut conn).await?, all);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_plan_migrations() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            let all: Vec<i64> = applied_versions(&mut conn).await?.into_iter().collect();
+            let latest = *all.iter().max().unwrap();
+            assert_eq!(current_version(&mut conn).await?, Some(latest));
+
+            assert!(plan_migrations(&mut conn, None, None).await?.is_empty());
+
+            let planned = plan_migrations(&mut conn, None, Some(-1)).await?;
+            assert_eq!(planned.len(), 1);
+            assert_eq!(planned[0].version, latest);
+            assert!(planned[0].revert);
+            assert!(!planned[0].sql.is_empty());
+
+            let planned = plan_migrations(&mut conn, Some(20231021111635), None).await?;
+            assert_eq!(planned.len(), all.len() - 1);
+            assert!(planned.windows(2).all(|w| w[0].version > w[1].version));
+
+            // nothing changed
+            assert_eq!(current_version(&mut conn).await?, Some(latest));
+            assert_eq!(applied_versions(&mut conn).await?.len(), all.len());
+
+            Ok(())
+        })
     }
-    Ok(())
 }
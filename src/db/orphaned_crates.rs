@@ -0,0 +1,84 @@
+use crate::db::delete_crate;
+use crate::error::Result;
+use crate::{Config, Storage};
+use postgres::Client;
+
+/// Returns the names of all crates without any release, sorted by name.
+///
+/// These are left behind when all releases of a crate were deleted one by one.
+pub fn find_orphaned_crates(conn: &mut Client) -> Result<Vec<String>> {
+    Ok(conn
+        .query(
+            "SELECT crates.name
+             FROM crates
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM releases WHERE releases.crate_id = crates.id
+             )
+             ORDER BY crates.name",
+            &[],
+        )?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+/// Deletes a crate without releases, together with its blacklist entry, its aliases and
+/// the priority pattern matching only its name.
+pub fn delete_orphaned_crate(
+    conn: &mut Client,
+    storage: &Storage,
+    config: &Config,
+    name: &str,
+) -> Result<()> {
+    delete_crate(conn, storage, config, name)?;
+
+    let mut transaction = conn.transaction()?;
+    transaction.execute(
+        "DELETE FROM blacklisted_crates WHERE crate_name = $1",
+        &[&name],
+    )?;
+    transaction.execute(
+        "DELETE FROM crate_aliases WHERE name = $1 OR target = $1",
+        &[&name],
+    )?;
+    transaction.execute("DELETE FROM crate_priorities WHERE pattern = $1", &[&name])?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{blacklist, crate_aliases};
+    use crate::test::wrapper;
+    use crate::utils::{list_crate_priorities, set_crate_priority};
+
+    #[test]
+    fn test_find_and_delete_orphaned_crates() {
+        wrapper(|env| {
+            env.fake_release().name("krate").version("1.0.0").create()?;
+
+            let mut conn = env.db().conn();
+            conn.execute("INSERT INTO crates (name) VALUES ('ghost')", &[])?;
+            blacklist::add_crate(&mut conn, "ghost", None)?;
+            crate_aliases::add_alias(&mut conn, "old-ghost", "ghost")?;
+            set_crate_priority(&mut conn, "ghost", 10)?;
+            set_crate_priority(&mut conn, "ghost-%", 10)?;
+
+            assert_eq!(find_orphaned_crates(&mut conn)?, vec!["ghost"]);
+
+            delete_orphaned_crate(&mut conn, &env.storage(), &env.config(), "ghost")?;
+
+            assert!(find_orphaned_crates(&mut conn)?.is_empty());
+            assert!(!blacklist::is_blacklisted(&mut conn, "ghost")?);
+            assert!(crate_aliases::list_aliases(&mut conn)?.is_empty());
+            assert_eq!(
+                list_crate_priorities(&mut conn)?,
+                vec![("ghost-%".to_string(), 10)]
+            );
+
+            Ok(())
+        })
+    }
+}
@@ -1,12 +1,22 @@
 use crate::error::Result;
 use futures_util::stream::TryStreamExt;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 
-#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize)]
 pub struct Overrides {
     pub memory: Option<usize>,
     pub targets: Option<usize>,
     pub timeout: Option<Duration>,
+    /// The maximum size of the generated documentation, in bytes
+    pub max_doc_size: Option<usize>,
+    /// The number of CPUs the sandbox may use
+    pub cpus: Option<u32>,
+    /// The storage path of a CSS file that's added to the crate's rustdoc output
+    pub custom_css: Option<String>,
+    /// Timeouts for single targets, these take precedence over `timeout`
+    pub target_timeouts: BTreeMap<String, Duration>,
 }
 
 macro_rules! row_to_overrides {
@@ -15,27 +25,72 @@ macro_rules! row_to_overrides {
             memory: $row.max_memory_bytes.map(|i| i as usize),
             targets: $row.max_targets.map(|i| i as usize),
             timeout: $row.timeout_seconds.map(|i| Duration::from_secs(i as u64)),
+            max_doc_size: $row.max_doc_size_bytes.map(|i| i as usize),
+            cpus: $row.cpus.map(|i| i as u32),
+            custom_css: $row.custom_css,
+            target_timeouts: BTreeMap::new(),
         }
     }};
 }
 
 impl Overrides {
     pub async fn all(conn: &mut sqlx::PgConnection) -> Result<Vec<(String, Self)>> {
-        Ok(sqlx::query!("SELECT * FROM sandbox_overrides")
-            .fetch(conn)
+        let mut all: Vec<(String, Self)> = sqlx::query!("SELECT * FROM sandbox_overrides")
+            .fetch(&mut *conn)
             .map_ok(|row| (row.crate_name, row_to_overrides!(row)))
             .try_collect()
-            .await?)
+            .await?;
+
+        let mut target_timeouts = Self::target_timeouts(conn, None).await?;
+        for (krate, overrides) in &mut all {
+            overrides.target_timeouts = target_timeouts.remove(krate).unwrap_or_default();
+        }
+
+        Ok(all)
     }
 
     pub async fn for_crate(conn: &mut sqlx::PgConnection, krate: &str) -> Result<Option<Self>> {
-        Ok(sqlx::query!(
+        let Some(mut overrides) = sqlx::query!(
             "SELECT * FROM sandbox_overrides WHERE crate_name = $1",
             krate
         )
-        .fetch_optional(conn)
+        .fetch_optional(&mut *conn)
         .await?
-        .map(|row| row_to_overrides!(row)))
+        .map(|row| row_to_overrides!(row)) else {
+            return Ok(None);
+        };
+
+        overrides.target_timeouts = Self::target_timeouts(conn, Some(krate))
+            .await?
+            .remove(krate)
+            .unwrap_or_default();
+
+        Ok(Some(overrides))
+    }
+
+    /// Loads the per-target timeouts of all crates, or only of `krate`.
+    async fn target_timeouts(
+        conn: &mut sqlx::PgConnection,
+        krate: Option<&str>,
+    ) -> Result<HashMap<String, BTreeMap<String, Duration>>> {
+        let mut result: HashMap<String, BTreeMap<String, Duration>> = HashMap::new();
+
+        let mut rows = sqlx::query!(
+            "SELECT crate_name, target, timeout_seconds
+             FROM sandbox_target_overrides
+             WHERE $1::TEXT IS NULL OR crate_name = $1",
+            krate,
+        )
+        .fetch(conn);
+
+        while let Some(row) = rows.try_next().await? {
+            result
+                .entry(row.crate_name)
+                .or_default()
+                .insert(row.target, Duration::from_secs(row.timeout_seconds as u64));
+        }
+
+        Ok(result)
     }
 
     pub async fn save(conn: &mut sqlx::PgConnection, krate: &str, overrides: Self) -> Result<()> {
@@ -54,22 +109,48 @@ impl Overrides {
         sqlx::query!(
             "
             INSERT INTO sandbox_overrides (
-                crate_name, max_memory_bytes, max_targets, timeout_seconds
+                crate_name, max_memory_bytes, max_targets, timeout_seconds, max_doc_size_bytes,
+                cpus, custom_css
             )
-            VALUES ($1, $2, $3, $4)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
             ON CONFLICT (crate_name) DO UPDATE
                 SET
                     max_memory_bytes = $2,
                     max_targets = $3,
-                    timeout_seconds = $4
+                    timeout_seconds = $4,
+                    max_doc_size_bytes = $5,
+                    cpus = $6,
+                    custom_css = $7
             ",
             krate,
             overrides.memory.map(|i| i as i64),
             overrides.targets.map(|i| i as i32),
             overrides.timeout.map(|d| d.as_secs() as i32),
+            overrides.max_doc_size.map(|i| i as i64),
+            overrides.cpus.map(|i| i as i32),
+            overrides.custom_css.as_deref(),
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM sandbox_target_overrides WHERE crate_name = $1",
+            krate
         )
         .execute(&mut *conn)
         .await?;
+        for (target, timeout) in &overrides.target_timeouts {
+            sqlx::query!(
+                "INSERT INTO sandbox_target_overrides (crate_name, target, timeout_seconds)
+                 VALUES ($1, $2, $3)",
+                krate,
+                target,
+                timeout.as_secs() as i32,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
         Ok(())
     }
 
@@ -103,7 +184,7 @@ mod test {
                 targets: Some(1),
                 ..Overrides::default()
             };
-            Overrides::save(&mut conn, krate, expected).await?;
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
             let actual = Overrides::for_crate(&mut conn, krate).await?;
             assert_eq!(actual, Some(expected));
 
@@ -112,8 +193,9 @@ mod test {
                 memory: Some(100_000),
                 targets: Some(1),
                 timeout: Some(Duration::from_secs(300)),
+                ..Overrides::default()
             };
-            Overrides::save(&mut conn, krate, expected).await?;
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
             let actual = Overrides::for_crate(&mut conn, krate).await?;
             assert_eq!(actual, Some(expected));
 
@@ -122,7 +204,7 @@ mod test {
                 memory: Some(1),
                 ..Overrides::default()
             };
-            Overrides::save(&mut conn, krate, expected).await?;
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
             let actual = Overrides::for_crate(&mut conn, krate).await?;
             assert_eq!(actual, Some(expected));
 
@@ -134,4 +216,104 @@ mod test {
             Ok(())
         })
     }
+
+    #[test]
+    fn retrieve_max_doc_size() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            let krate = "hexponent";
+
+            let expected = Overrides {
+                max_doc_size: Some(1_000_000),
+                ..Overrides::default()
+            };
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
+            let actual = Overrides::for_crate(&mut conn, krate).await?;
+            assert_eq!(actual, Some(expected));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn retrieve_cpus() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            let krate = "hexponent";
+
+            let expected = Overrides {
+                cpus: Some(2),
+                ..Overrides::default()
+            };
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
+            let actual = Overrides::for_crate(&mut conn, krate).await?;
+            assert_eq!(actual, Some(expected));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn retrieve_custom_css() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            let krate = "hexponent";
+
+            let expected = Overrides {
+                custom_css: Some("custom-css/hexponent.css".into()),
+                ..Overrides::default()
+            };
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
+            let actual = Overrides::for_crate(&mut conn, krate).await?;
+            assert_eq!(actual, Some(expected));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn retrieve_target_timeouts() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            let krate = "hexponent";
+
+            let expected = Overrides {
+                target_timeouts: [
+                    ("aarch64-apple-darwin".into(), Duration::from_secs(600)),
+                    ("x86_64-pc-windows-msvc".into(), Duration::from_secs(60)),
+                ]
+                .into(),
+                ..Overrides::default()
+            };
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
+            let actual = Overrides::for_crate(&mut conn, krate).await?;
+            assert_eq!(actual, Some(expected.clone()));
+
+            // target timeouts are listed with their crate
+            assert_eq!(
+                Overrides::all(&mut conn).await?,
+                vec![(krate.to_string(), expected)]
+            );
+
+            // saving again replaces the target timeouts
+            let expected = Overrides {
+                target_timeouts: [("x86_64-pc-windows-msvc".into(), Duration::from_secs(120))]
+                    .into(),
+                ..Overrides::default()
+            };
+            Overrides::save(&mut conn, krate, expected.clone()).await?;
+            let actual = Overrides::for_crate(&mut conn, krate).await?;
+            assert_eq!(actual, Some(expected));
+
+            Ok(())
+        })
+    }
 }
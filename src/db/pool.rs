@@ -37,7 +37,29 @@ impl Pool {
         debug!(
             "creating database pool (if this hangs, consider running `docker-compose up -d db s3`)"
         );
-        Self::new_inner(config, runtime, metrics, DEFAULT_SCHEMA)
+        Self::new_inner(
+            config,
+            &config.database_url,
+            runtime,
+            metrics,
+            DEFAULT_SCHEMA,
+        )
+    }
+
+    /// Creates a pool for the read replica at `DOCSRS_READ_REPLICA_DATABASE_URL`, `None`
+    /// when no replica is configured.
+    ///
+    /// Only use it for queries that can work with slightly outdated data.
+    pub fn new_read_replica(
+        config: &Config,
+        runtime: Arc<Runtime>,
+        metrics: Arc<InstanceMetrics>,
+    ) -> Result<Option<Pool>, PoolError> {
+        let Some(url) = &config.read_replica_database_url else {
+            return Ok(None);
+        };
+        debug!("creating read replica database pool");
+        Self::new_inner(config, url, runtime, metrics, DEFAULT_SCHEMA).map(Some)
     }
 
     #[cfg(test)]
@@ -47,17 +69,17 @@ impl Pool {
         metrics: Arc<InstanceMetrics>,
         schema: &str,
     ) -> Result<Pool, PoolError> {
-        Self::new_inner(config, runtime, metrics, schema)
+        Self::new_inner(config, &config.database_url, runtime, metrics, schema)
     }
 
     fn new_inner(
         config: &Config,
+        database_url: &str,
         runtime: Arc<Runtime>,
         metrics: Arc<InstanceMetrics>,
         schema: &str,
     ) -> Result<Pool, PoolError> {
-        let url = config
-            .database_url
+        let url = database_url
             .parse()
             .map_err(PoolError::InvalidDatabaseUrl)?;
 
@@ -103,7 +125,7 @@ impl Pool {
                     })
                 }
             })
-            .connect_lazy(&config.database_url)
+            .connect_lazy(database_url)
             .map_err(PoolError::AsyncPoolCreationFailed)?;
 
         Ok(Pool {
@@ -0,0 +1,184 @@
+use super::recount_releases;
+use anyhow::{bail, Context as _, Result};
+use serde::Serialize;
+use sqlx::Connection as _;
+use std::fmt;
+use tracing::warn;
+
+/// The crate a release belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReleaseAssignment {
+    pub release_id: i32,
+    pub version: String,
+    pub crate_id: i32,
+    pub crate_name: String,
+}
+
+impl fmt::Display for ReleaseAssignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "release {} (version {}) belongs to crate {} ({})",
+            self.release_id, self.version, self.crate_id, self.crate_name,
+        )
+    }
+}
+
+/// Loads the crate the release currently belongs to.
+pub async fn release_assignment(
+    conn: &mut sqlx::PgConnection,
+    release_id: i32,
+) -> Result<ReleaseAssignment> {
+    let release = sqlx::query!(
+        "SELECT releases.version, releases.crate_id, crates.name
+         FROM releases
+         INNER JOIN crates ON crates.id = releases.crate_id
+         WHERE releases.id = $1",
+        release_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .with_context(|| format!("there is no release with the id {release_id}"))?;
+
+    Ok(ReleaseAssignment {
+        release_id,
+        version: release.version,
+        crate_id: release.crate_id,
+        crate_name: release.name,
+    })
+}
+
+/// Checks that the release can be moved to `target_crate_id` and returns its assignment
+/// before and after the move, without changing anything.
+pub async fn plan_release_reassignment(
+    conn: &mut sqlx::PgConnection,
+    release_id: i32,
+    target_crate_id: i32,
+) -> Result<(ReleaseAssignment, ReleaseAssignment)> {
+    let before = release_assignment(&mut *conn, release_id).await?;
+    if before.crate_id == target_crate_id {
+        bail!("release {release_id} already belongs to crate {target_crate_id}");
+    }
+
+    let target_name = sqlx::query_scalar!("SELECT name FROM crates WHERE id = $1", target_crate_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .with_context(|| format!("there is no crate with the id {target_crate_id}"))?;
+
+    let conflict = sqlx::query_scalar!(
+        "SELECT id FROM releases WHERE crate_id = $1 AND version = $2",
+        target_crate_id,
+        before.version,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(conflict) = conflict {
+        bail!(
+            "crate {target_name} already has a release {} with the id {conflict}",
+            before.version
+        );
+    }
+
+    let after = ReleaseAssignment {
+        crate_id: target_crate_id,
+        crate_name: target_name,
+        ..before.clone()
+    };
+    Ok((before, after))
+}
+
+/// Moves a release to another crate, to repair releases that point to the wrong crate.
+///
+/// The release counts, build statuses and latest versions of both crates are recomputed.
+/// Files in storage are not moved, they are stored by crate name and version and stay
+/// where they are. Returns the assignment before and after the move.
+pub async fn reassign_release(
+    conn: &mut sqlx::PgConnection,
+    release_id: i32,
+    target_crate_id: i32,
+) -> Result<(ReleaseAssignment, ReleaseAssignment)> {
+    let mut tx = conn.begin().await?;
+
+    let (before, after) = plan_release_reassignment(&mut tx, release_id, target_crate_id).await?;
+    warn!(%before, %after, "reassigning release to another crate");
+
+    sqlx::query!(
+        "UPDATE releases SET crate_id = $2 WHERE id = $1",
+        release_id,
+        target_crate_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    recount_releases(&mut tx, before.crate_id).await?;
+    recount_releases(&mut tx, target_crate_id).await?;
+
+    tx.commit().await?;
+    Ok((before, after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::async_wrapper;
+
+    #[test]
+    fn test_reassign_release() {
+        async_wrapper(|env| async move {
+            let foo_1 = env
+                .async_fake_release()
+                .await
+                .name("foo")
+                .version("1.0.0")
+                .create_async()
+                .await?;
+            let wrong = env
+                .async_fake_release()
+                .await
+                .name("foo")
+                .version("2.0.0")
+                .create_async()
+                .await?;
+            env.async_fake_release()
+                .await
+                .name("bar")
+                .version("1.0.0")
+                .create_async()
+                .await?;
+
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+            let crate_id = |name: &'static str| {
+                sqlx::query_scalar!("SELECT id FROM crates WHERE name = $1", name)
+            };
+            let foo = crate_id("foo").fetch_one(&mut *conn).await?;
+            let bar = crate_id("bar").fetch_one(&mut *conn).await?;
+
+            // validation
+            assert!(reassign_release(&mut conn, wrong, foo).await.is_err());
+            assert!(reassign_release(&mut conn, wrong, 12345).await.is_err());
+            assert!(reassign_release(&mut conn, foo_1, bar).await.is_err());
+
+            let (before, after) = reassign_release(&mut conn, wrong, bar).await?;
+            assert_eq!(before.crate_name, "foo");
+            assert_eq!(after.crate_name, "bar");
+            assert_eq!(release_assignment(&mut conn, wrong).await?, after);
+
+            let latest_version_id = |crate_id: i32| {
+                sqlx::query_scalar!(
+                    "SELECT latest_version_id FROM crates WHERE id = $1",
+                    crate_id
+                )
+            };
+            assert_eq!(
+                latest_version_id(foo).fetch_one(&mut *conn).await?,
+                Some(foo_1)
+            );
+            assert_eq!(
+                latest_version_id(bar).fetch_one(&mut *conn).await?,
+                Some(wrong)
+            );
+
+            Ok(())
+        })
+    }
+}
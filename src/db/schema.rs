@@ -0,0 +1,166 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt::Write as _;
+
+/// A table of a database schema, with its columns in definition order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub indexes: Vec<Index>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Column {
+    pub name: String,
+    pub data_type: String,
+    pub nullable: bool,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Index {
+    pub name: String,
+    /// The `CREATE INDEX` statement of the index
+    pub definition: String,
+}
+
+pub async fn current_schema(conn: &mut sqlx::PgConnection) -> Result<String> {
+    Ok(
+        sqlx::query_scalar!(r#"SELECT current_schema()::TEXT AS "schema!""#)
+            .fetch_one(conn)
+            .await?,
+    )
+}
+
+/// Loads the tables, columns and indexes of `schema` from the Postgres catalogs, sorted by
+/// table name. The migrations table of sqlx is left out.
+pub async fn load_schema(conn: &mut sqlx::PgConnection, schema: &str) -> Result<Vec<Table>> {
+    let columns = sqlx::query!(
+        r#"SELECT
+            c.relname::TEXT AS "table!",
+            a.attname::TEXT AS "name!",
+            format_type(a.atttypid, a.atttypmod) AS "data_type!",
+            a.attnotnull AS "not_null!",
+            pg_get_expr(d.adbin, d.adrelid) AS "default?"
+         FROM pg_attribute a
+         INNER JOIN pg_class c ON c.oid = a.attrelid
+         INNER JOIN pg_namespace n ON n.oid = c.relnamespace
+         LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+         WHERE
+            n.nspname = $1 AND
+            c.relkind IN ('r', 'p') AND
+            c.relname != '_sqlx_migrations' AND
+            a.attnum > 0 AND
+            NOT a.attisdropped
+         ORDER BY c.relname, a.attnum"#,
+        schema,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut tables: Vec<Table> = Vec::new();
+    for column in columns {
+        if tables.last().map_or(true, |last| last.name != column.table) {
+            tables.push(Table {
+                name: column.table,
+                columns: Vec::new(),
+                indexes: Vec::new(),
+            });
+        }
+        tables.last_mut().unwrap().columns.push(Column {
+            name: column.name,
+            data_type: column.data_type,
+            nullable: !column.not_null,
+            default: column.default,
+        });
+    }
+
+    let indexes = sqlx::query!(
+        r#"SELECT
+            tablename::TEXT AS "table!",
+            indexname::TEXT AS "name!",
+            indexdef AS "definition!"
+         FROM pg_indexes
+         WHERE schemaname = $1 AND tablename != '_sqlx_migrations'
+         ORDER BY tablename, indexname"#,
+        schema,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for index in indexes {
+        if let Some(table) = tables.iter_mut().find(|t| t.name == index.table) {
+            table.indexes.push(Index {
+                name: index.name,
+                definition: index.definition,
+            });
+        }
+    }
+
+    Ok(tables)
+}
+
+/// Renders the tables as `CREATE TABLE` and `CREATE INDEX` statements.
+///
+/// Constraints other than `NOT NULL` are not included, the statements are meant for
+/// reading and comparing, not for recreating the schema.
+pub fn schema_to_sql(tables: &[Table]) -> String {
+    let mut sql = String::new();
+    for table in tables {
+        writeln!(sql, "CREATE TABLE {} (", table.name).unwrap();
+        for (i, column) in table.columns.iter().enumerate() {
+            write!(sql, "    {} {}", column.name, column.data_type).unwrap();
+            if !column.nullable {
+                sql.push_str(" NOT NULL");
+            }
+            if let Some(default) = &column.default {
+                write!(sql, " DEFAULT {default}").unwrap();
+            }
+            sql.push_str(if i + 1 < table.columns.len() {
+                ",\n"
+            } else {
+                "\n"
+            });
+        }
+        sql.push_str(");\n");
+        for index in &table.indexes {
+            writeln!(sql, "{};", index.definition).unwrap();
+        }
+        sql.push('\n');
+    }
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::async_wrapper;
+
+    #[test]
+    fn test_load_schema() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            let schema = current_schema(&mut conn).await?;
+            let tables = load_schema(&mut conn, &schema).await?;
+            assert!(tables.windows(2).all(|w| w[0].name < w[1].name));
+            assert!(!tables.iter().any(|t| t.name == "_sqlx_migrations"));
+
+            let crates = tables.iter().find(|t| t.name == "crates").unwrap();
+            assert_eq!(crates.columns[0].name, "id");
+            assert!(crates.columns[0].default.is_some());
+            assert!(crates
+                .columns
+                .iter()
+                .any(|c| c.name == "name" && c.data_type == "text" && !c.nullable));
+            assert!(!crates.indexes.is_empty());
+
+            let sql = schema_to_sql(&tables);
+            assert!(sql.contains("CREATE TABLE crates (\n    id integer NOT NULL DEFAULT"));
+            assert!(sql.contains(&format!("{};", crates.indexes[0].definition)));
+
+            Ok(())
+        })
+    }
+}
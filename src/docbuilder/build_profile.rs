@@ -0,0 +1,82 @@
+use anyhow::{Context as _, Result};
+use docsrs_metadata::Metadata;
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+/// Named build settings that can be applied to a queued release, see
+/// [`BuildQueue::set_build_profile`](crate::BuildQueue::set_build_profile).
+///
+/// A profile accepts the keys of the `[package.metadata.docs.rs]` section, which it
+/// replaces for the built crate, and additionally:
+/// * `env`, environment variables set for the build
+/// * `networking`, to allow network access in the sandbox
+#[derive(Debug, Default, Clone, Deserialize)]
+pub(crate) struct BuildProfile {
+    #[serde(default)]
+    pub(crate) env: HashMap<String, String>,
+    #[serde(default)]
+    pub(crate) networking: bool,
+    #[serde(flatten)]
+    pub(crate) metadata: Metadata,
+}
+
+/// Loads the build profiles from a TOML file, with one table per profile:
+///
+/// ```toml
+/// [wasm]
+/// targets = ["wasm32-unknown-unknown"]
+/// features = ["web"]
+/// env = { RUSTFLAGS = "--cfg=web_sys_unstable_apis" }
+/// ```
+pub(crate) fn load_build_profiles(path: &Path) -> Result<HashMap<String, BuildProfile>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read the build profiles at {}", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("invalid build profiles in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_build_profiles() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("profiles.toml");
+        std::fs::write(
+            &path,
+            r#"
+                [wasm]
+                targets = ["wasm32-unknown-unknown"]
+                all-features = true
+                env = { FOO = "bar" }
+
+                [online]
+                networking = true
+            "#,
+        )?;
+
+        let profiles = load_build_profiles(&path)?;
+        assert_eq!(profiles.len(), 2);
+
+        let wasm = &profiles["wasm"];
+        assert_eq!(wasm.env["FOO"], "bar");
+        assert!(!wasm.networking);
+        assert_eq!(
+            wasm.metadata.targets(false).default_target,
+            "wasm32-unknown-unknown"
+        );
+        assert!(wasm
+            .metadata
+            .cargo_args(&[], &[])
+            .contains(&"--all-features".to_owned()));
+
+        assert!(profiles["online"].networking);
+        assert!(profiles["online"].env.is_empty());
+
+        std::fs::write(&path, "[broken]\nnetworking = 1")?;
+        assert!(load_build_profiles(&path).is_err());
+
+        Ok(())
+    }
+}
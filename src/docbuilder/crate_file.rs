@@ -0,0 +1,131 @@
+use anyhow::{bail, Context as _, Result};
+use flate2::read::GzDecoder;
+use std::{
+    fs::File,
+    path::{Component, Path, PathBuf},
+};
+use tar::{Archive, EntryType};
+
+/// Unpacks a `.crate` file as published to crates.io into `dest`, and returns the
+/// root directory of the package.
+///
+/// The archive has to contain only regular files and directories, all inside a single
+/// top-level directory that contains a `Cargo.toml`.
+pub fn unpack_crate_file(crate_file: &Path, dest: &Path) -> Result<PathBuf> {
+    let file = File::open(crate_file)
+        .with_context(|| format!("could not open {}", crate_file.display()))?;
+    let mut archive = Archive::new(GzDecoder::new(file));
+
+    let mut root: Option<PathBuf> = None;
+    let mut has_manifest = false;
+    for entry in archive
+        .entries()
+        .context("the crate file is not a gzipped tarball")?
+    {
+        let mut entry = entry.context("could not read the crate file")?;
+        let path = entry.path()?.into_owned();
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Directory => {}
+            // extended headers only contain metadata of the following entries
+            EntryType::XHeader | EntryType::XGlobalHeader => continue,
+            _ => bail!("{} is neither a file nor a directory", path.display()),
+        }
+
+        let mut components = path.components();
+        let top_level = match components.next() {
+            Some(Component::Normal(top_level)) => PathBuf::from(top_level),
+            _ => bail!("{} is not a relative path", path.display()),
+        };
+        if !components.all(|c| matches!(c, Component::Normal(_))) {
+            bail!("{} is not a normalized path", path.display());
+        }
+        match &root {
+            Some(root) if *root != top_level => {
+                bail!("the crate file contains more than one top-level directory")
+            }
+            Some(_) => {}
+            None => root = Some(top_level.clone()),
+        }
+        if path == top_level.join("Cargo.toml") {
+            has_manifest = true;
+        }
+
+        entry
+            .unpack_in(dest)
+            .with_context(|| format!("could not unpack {}", path.display()))?;
+    }
+
+    let root = root.context("the crate file is empty")?;
+    if !has_manifest {
+        bail!(
+            "the crate file doesn't contain {}/Cargo.toml",
+            root.display()
+        );
+    }
+    Ok(dest.join(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    fn crate_file(dir: &Path, files: &[(&str, EntryType)]) -> Result<PathBuf> {
+        let path = dir.join("krate-0.1.0.crate");
+        let mut builder =
+            tar::Builder::new(GzEncoder::new(File::create(&path)?, Compression::default()));
+        for &(name, entry_type) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            header.set_mode(0o644);
+            if entry_type == EntryType::Symlink {
+                header.set_link_name("/etc/passwd")?;
+            }
+            builder.append_data(&mut header, name, std::io::empty())?;
+        }
+        builder.into_inner()?.finish()?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_unpack_crate_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dest = tempfile::tempdir()?;
+
+        let valid = crate_file(
+            dir.path(),
+            &[
+                ("krate-0.1.0/Cargo.toml", EntryType::Regular),
+                ("krate-0.1.0/src", EntryType::Directory),
+                ("krate-0.1.0/src/lib.rs", EntryType::Regular),
+            ],
+        )?;
+        let root = unpack_crate_file(&valid, dest.path())?;
+        assert_eq!(root, dest.path().join("krate-0.1.0"));
+        assert!(root.join("src/lib.rs").is_file());
+
+        let invalid_files: [&[(&str, EntryType)]; 4] = [
+            // no `Cargo.toml`
+            &[("krate-0.1.0/src/lib.rs", EntryType::Regular)],
+            // more than one top-level directory
+            &[
+                ("krate-0.1.0/Cargo.toml", EntryType::Regular),
+                ("other/Cargo.toml", EntryType::Regular),
+            ],
+            // symlinks
+            &[
+                ("krate-0.1.0/Cargo.toml", EntryType::Regular),
+                ("krate-0.1.0/link", EntryType::Symlink),
+            ],
+            &[],
+        ];
+        for files in invalid_files {
+            let invalid = crate_file(dir.path(), files)?;
+            assert!(unpack_crate_file(&invalid, tempfile::tempdir()?.path()).is_err());
+        }
+
+        Ok(())
+    }
+}
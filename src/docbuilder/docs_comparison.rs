@@ -0,0 +1,168 @@
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read, Seek},
+    path::Path,
+};
+
+/// Differences between newly built documentation and the stored documentation of the
+/// same release, added to the [`BuildReport`](crate::docbuilder::BuildReport) by
+/// `build crate --compare-with-stored`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct DocsComparison {
+    /// Whether the release had stored documentation, otherwise every file is added.
+    pub had_stored_docs: bool,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<ChangedFile>,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangedFile {
+    pub path: String,
+    pub stored_size: u64,
+    pub new_size: u64,
+}
+
+impl DocsComparison {
+    pub fn is_unchanged(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Size and hash of a documentation file.
+#[derive(PartialEq, Eq)]
+struct FileDigest {
+    size: u64,
+    hash: Vec<u8>,
+}
+
+impl FileDigest {
+    fn of(mut content: impl Read) -> Result<Self> {
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut content, &mut hasher)?;
+        Ok(Self {
+            size,
+            hash: hasher.finalize().to_vec(),
+        })
+    }
+}
+
+fn local_digests(path: &Path) -> Result<BTreeMap<String, FileDigest>> {
+    let mut digests = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(path)?;
+            // archive paths always use `/`
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            digests.insert(relative, FileDigest::of(fs::File::open(entry.path())?)?);
+        }
+    }
+    Ok(digests)
+}
+
+fn archive_digests(archive: impl Read + Seek) -> Result<BTreeMap<String, FileDigest>> {
+    let mut archive = zip::ZipArchive::new(archive)?;
+    let mut digests = BTreeMap::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if file.is_file() {
+            let name = file.name().to_owned();
+            digests.insert(name, FileDigest::of(file)?);
+        }
+    }
+    Ok(digests)
+}
+
+/// Compares the documentation in `local` with the stored rustdoc archive of the release,
+/// `None` when the release has no stored documentation.
+pub(crate) fn compare_docs(local: &Path, stored_archive: Option<&[u8]>) -> Result<DocsComparison> {
+    let new = local_digests(local)?;
+    let stored = match stored_archive {
+        Some(archive) => archive_digests(io::Cursor::new(archive))?,
+        None => BTreeMap::new(),
+    };
+
+    let mut comparison = DocsComparison {
+        had_stored_docs: stored_archive.is_some(),
+        ..DocsComparison::default()
+    };
+    for (path, digest) in &new {
+        match stored.get(path) {
+            None => comparison.added.push(path.clone()),
+            Some(stored) if stored != digest => comparison.changed.push(ChangedFile {
+                path: path.clone(),
+                stored_size: stored.size,
+                new_size: digest.size,
+            }),
+            Some(_) => comparison.unchanged += 1,
+        }
+    }
+    comparison.removed = stored
+        .into_keys()
+        .filter(|path| !new.contains_key(path))
+        .collect();
+
+    Ok(comparison)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write as _;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_compare_docs() -> Result<()> {
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Bzip2);
+        for (path, content) in [
+            ("krate/index.html", "index"),
+            ("krate/struct.Foo.html", "foo"),
+            ("krate/struct.Bar.html", "bar"),
+        ] {
+            zip.start_file(path, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        let archive = zip.finish()?.into_inner();
+
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("krate"))?;
+        fs::write(dir.path().join("krate/index.html"), "index")?;
+        fs::write(dir.path().join("krate/struct.Foo.html"), "new foo")?;
+        fs::write(dir.path().join("krate/struct.Baz.html"), "baz")?;
+
+        let comparison = compare_docs(dir.path(), Some(&archive))?;
+        assert_eq!(
+            comparison,
+            DocsComparison {
+                had_stored_docs: true,
+                added: vec!["krate/struct.Baz.html".into()],
+                removed: vec!["krate/struct.Bar.html".into()],
+                changed: vec![ChangedFile {
+                    path: "krate/struct.Foo.html".into(),
+                    stored_size: 3,
+                    new_size: 7,
+                }],
+                unchanged: 1,
+            }
+        );
+        assert!(!comparison.is_unchanged());
+
+        let comparison = compare_docs(dir.path(), None)?;
+        assert!(!comparison.had_stored_docs);
+        assert_eq!(comparison.added.len(), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+use crate::{Config, Context};
+use anyhow::{Context as _, Result};
+use docsrs_metadata::Metadata;
+use serde::Serialize;
+use std::{collections::HashSet, fmt, path::Path};
+
+/// The keys of `[package.metadata.docs.rs]` that are used by the builder.
+const METADATA_KEYS: &[&str] = &[
+    "features",
+    "all-features",
+    "no-default-features",
+    "default-target",
+    "targets",
+    "rustc-args",
+    "rustdoc-args",
+    "cargo-args",
+];
+
+/// The `[package.metadata.docs.rs]` configuration of a crate, resolved into
+/// what the builder would actually use.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolvedMetadata {
+    pub proc_macro: bool,
+    pub default_target: String,
+    pub other_targets: Vec<String>,
+    /// the arguments passed to `cargo`, including features, `rustc-args` and `rustdoc-args`.
+    pub cargo_args: Vec<String>,
+}
+
+impl ResolvedMetadata {
+    pub fn new(metadata: &Metadata, config: &Config) -> Self {
+        let targets = metadata.targets(config.include_default_targets);
+        let mut other_targets: Vec<String> = targets
+            .other_targets
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        other_targets.sort();
+
+        Self {
+            proc_macro: metadata.proc_macro,
+            default_target: targets.default_target.to_string(),
+            other_targets,
+            cargo_args: metadata.cargo_args(&[], &[]),
+        }
+    }
+}
+
+/// Resolves the docs.rs metadata of a local crate.
+pub fn resolve_local_metadata(config: &Config, path: &Path) -> Result<ResolvedMetadata> {
+    let metadata = Metadata::from_crate_root(path)
+        .with_context(|| format!("could not parse the docs.rs metadata in {}", path.display()))?;
+    Ok(ResolvedMetadata::new(&metadata, config))
+}
+
+/// Resolves the docs.rs metadata of a release, using the `Cargo.toml` we stored with its source.
+pub fn resolve_release_metadata(
+    ctx: &dyn Context,
+    name: &str,
+    version: &str,
+) -> Result<ResolvedMetadata> {
+    let metadata: Metadata = fetch_release_manifest(ctx, name, version)?
+        .parse()
+        .context("could not parse the docs.rs metadata")?;
+
+    Ok(ResolvedMetadata::new(&metadata, &*ctx.config()?))
+}
+
+fn fetch_release_manifest(ctx: &dyn Context, name: &str, version: &str) -> Result<String> {
+    let row = ctx
+        .pool()?
+        .get()?
+        .query_opt(
+            "SELECT
+                COALESCE(releases.archive_storage, false) AS archive_storage,
+                GREATEST(
+                    (SELECT MAX(builds.id) FROM builds WHERE builds.rid = releases.id),
+                    releases.archive_index_regeneration
+                ) AS latest_build_id
+             FROM releases
+             INNER JOIN crates ON crates.id = releases.crate_id
+             WHERE crates.name = $1 AND releases.version = $2",
+            &[&name, &version],
+        )?
+        .with_context(|| format!("release {name} {version} not found"))?;
+
+    let manifest = ctx
+        .storage()?
+        .fetch_source_file(
+            name,
+            version,
+            row.get::<_, Option<i32>>("latest_build_id").unwrap_or(0),
+            "Cargo.toml",
+            row.get("archive_storage"),
+        )
+        .with_context(|| format!("could not fetch the Cargo.toml of {name} {version}"))?;
+
+    String::from_utf8(manifest.content).context("Cargo.toml is not valid UTF-8")
+}
+
+/// A likely mistake in the `[package.metadata.docs.rs]` section of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MetadataProblem {
+    /// The section can't be parsed, builds of the crate fail.
+    Invalid { error: String },
+    /// The key isn't used by docs.rs.
+    UnknownKey { key: String },
+    /// The target doesn't exist.
+    InvalidTarget { target: String },
+    /// The feature is neither defined by the crate nor an optional dependency.
+    UnknownFeature { feature: String },
+    /// The key has no effect, because `all-features` is set.
+    IgnoredWithAllFeatures { key: String },
+}
+
+impl fmt::Display for MetadataProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid { error } => write!(f, "invalid metadata: {error}"),
+            Self::UnknownKey { key } => {
+                let fixed = key.replace('_', "-");
+                if METADATA_KEYS.contains(&fixed.as_str()) {
+                    write!(f, "unknown key `{key}`, did you mean `{fixed}`?")
+                } else {
+                    write!(f, "unknown key `{key}`")
+                }
+            }
+            Self::InvalidTarget { target } => write!(f, "invalid target `{target}`"),
+            Self::UnknownFeature { feature } => write!(f, "unknown feature `{feature}`"),
+            Self::IgnoredWithAllFeatures { key } => {
+                write!(f, "`{key}` has no effect together with `all-features`")
+            }
+        }
+    }
+}
+
+/// Checks the docs.rs metadata of a manifest for common mistakes.
+///
+/// Targets are compared with `known_targets` when given, otherwise only their format is
+/// checked.
+pub fn lint_metadata(
+    manifest: &str,
+    known_targets: Option<&HashSet<String>>,
+) -> Vec<MetadataProblem> {
+    fn table(parent: Option<&toml::Table>, name: &str) -> Option<toml::Table> {
+        parent?.get(name)?.as_table().cloned()
+    }
+    fn strings<'a>(section: &'a toml::Table, key: &str) -> Vec<&'a str> {
+        section
+            .get(key)
+            .and_then(|value| value.as_array())
+            .map(|values| values.iter().filter_map(|value| value.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    let manifest: toml::Table = match toml::from_str(manifest) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            return vec![MetadataProblem::Invalid {
+                error: err.message().to_owned(),
+            }]
+        }
+    };
+    let metadata =
+        table(Some(&manifest), "package").and_then(|package| table(Some(&package), "metadata"));
+    let Some(section) = metadata.as_ref().and_then(|metadata| {
+        table(table(Some(metadata), "docs").as_ref(), "rs")
+            .or_else(|| table(Some(metadata), "docs.rs"))
+    }) else {
+        return Vec::new();
+    };
+
+    let mut problems = Vec::new();
+    if let Err(err) = toml::Value::Table(section.clone()).try_into::<Metadata>() {
+        problems.push(MetadataProblem::Invalid {
+            error: err.message().to_owned(),
+        });
+    }
+
+    for key in section.keys() {
+        if !METADATA_KEYS.contains(&key.as_str()) {
+            problems.push(MetadataProblem::UnknownKey { key: key.clone() });
+        }
+    }
+
+    let default_target = section.get("default-target").and_then(|t| t.as_str());
+    for target in strings(&section, "targets")
+        .into_iter()
+        .chain(default_target)
+    {
+        let valid = match known_targets {
+            Some(known_targets) => known_targets.contains(target),
+            None => {
+                target.split('-').count() >= 2
+                    && target
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+            }
+        };
+        if !valid {
+            problems.push(MetadataProblem::InvalidTarget {
+                target: target.to_owned(),
+            });
+        }
+    }
+
+    let all_features = section.get("all-features").and_then(|v| v.as_bool()) == Some(true);
+    if all_features {
+        for key in ["features", "no-default-features"] {
+            if section.contains_key(key) {
+                problems.push(MetadataProblem::IgnoredWithAllFeatures {
+                    key: key.to_owned(),
+                });
+            }
+        }
+    }
+
+    let defined_features = table(Some(&manifest), "features").unwrap_or_default();
+    let mut dependency_tables: Vec<toml::Table> =
+        table(Some(&manifest), "dependencies").into_iter().collect();
+    if let Some(targets) = table(Some(&manifest), "target") {
+        dependency_tables.extend(
+            targets
+                .values()
+                .filter_map(|target| target.as_table())
+                .filter_map(|target| table(Some(target), "dependencies")),
+        );
+    }
+    let is_optional_dependency = |name: &str| {
+        dependency_tables.iter().any(|dependencies| {
+            dependencies
+                .get(name)
+                .and_then(|dependency| dependency.get("optional"))
+                .and_then(|optional| optional.as_bool())
+                == Some(true)
+        })
+    };
+    for features in strings(&section, "features") {
+        // features are separated by spaces or commas on the command line as well
+        for feature in features.split([' ', ',']).filter(|f| !f.is_empty()) {
+            // `dependency/feature` is checked by cargo
+            if feature.contains('/') || feature == "default" {
+                continue;
+            }
+            if !defined_features.contains_key(feature) && !is_optional_dependency(feature) {
+                problems.push(MetadataProblem::UnknownFeature {
+                    feature: feature.to_owned(),
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+/// Checks the docs.rs metadata of a release for common mistakes, using the `Cargo.toml` we
+/// stored with its source. See [`lint_metadata`].
+pub fn lint_release_metadata(
+    ctx: &dyn Context,
+    name: &str,
+    version: &str,
+    known_targets: Option<&HashSet<String>>,
+) -> Result<Vec<MetadataProblem>> {
+    let manifest = fetch_release_manifest(ctx, name, version)?;
+    Ok(lint_metadata(&manifest, known_targets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+
+    #[test]
+    fn test_resolve_release_metadata() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("krate")
+                .version("0.1.0")
+                .archive_storage(true)
+                .source_file(
+                    "Cargo.toml",
+                    br#"
+                    [package]
+                    name = "krate"
+                    version = "0.1.0"
+
+                    [package.metadata.docs.rs]
+                    features = ["foo"]
+                    targets = ["x86_64-unknown-linux-gnu", "i686-pc-windows-msvc"]
+                    "#,
+                )
+                .create()?;
+
+            let resolved = resolve_release_metadata(env, "krate", "0.1.0")?;
+            assert!(!resolved.proc_macro);
+            assert_eq!(resolved.default_target, "x86_64-unknown-linux-gnu");
+            assert_eq!(resolved.other_targets, vec!["i686-pc-windows-msvc"]);
+            assert!(resolved
+                .cargo_args
+                .windows(2)
+                .any(|args| args == ["--features", "foo"]));
+
+            assert!(resolve_release_metadata(env, "krate", "0.2.0").is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_lint_metadata() {
+        let manifest = r#"
+            [package]
+            name = "krate"
+            version = "0.1.0"
+
+            [package.metadata.docs.rs]
+            all-features = true
+            features = ["foo", "bar", "serde", "serde/derive", "default"]
+            targets = ["x86_64-unknown-linux-gnu", "X86_64-Linux"]
+            rustdoc_args = ["--cfg", "docsrs"]
+
+            [features]
+            foo = []
+
+            [dependencies]
+            serde = { version = "1", optional = true }
+        "#;
+        let problems = lint_metadata(manifest, None);
+        assert_eq!(
+            problems,
+            vec![
+                MetadataProblem::UnknownKey {
+                    key: "rustdoc_args".into()
+                },
+                MetadataProblem::InvalidTarget {
+                    target: "X86_64-Linux".into()
+                },
+                MetadataProblem::IgnoredWithAllFeatures {
+                    key: "features".into()
+                },
+                MetadataProblem::UnknownFeature {
+                    feature: "bar".into()
+                },
+            ]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "unknown key `rustdoc_args`, did you mean `rustdoc-args`?"
+        );
+
+        let known_targets = HashSet::from(["x86_64-unknown-linux-gnu".to_owned()]);
+        assert_eq!(
+            lint_metadata(
+                r#"
+                [package.metadata."docs.rs"]
+                default-target = "x86_64-unknown-linux-gun"
+                "#,
+                Some(&known_targets)
+            ),
+            vec![MetadataProblem::InvalidTarget {
+                target: "x86_64-unknown-linux-gun".into()
+            }]
+        );
+
+        assert!(matches!(
+            lint_metadata("[package.metadata.docs.rs]\nall-features = \"yes\"", None)[..],
+            [MetadataProblem::Invalid { .. }]
+        ));
+        assert!(lint_metadata("[package]\nname = \"krate\"", None).is_empty());
+    }
+
+    #[test]
+    fn test_lint_metadata_proc_macro_is_unknown() {
+        // `proc-macro` is read from `[lib]`, not from the docs.rs metadata
+        assert_eq!(
+            lint_metadata("[package.metadata.docs.rs]\nproc-macro = true", None),
+            vec![MetadataProblem::UnknownKey {
+                key: "proc-macro".into()
+            }]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{db::Overrides, error::Result, Config};
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 const GB: usize = 1024 * 1024 * 1024;
@@ -9,8 +10,12 @@ pub(crate) struct Limits {
     memory: usize,
     targets: usize,
     timeout: Duration,
+    target_timeouts: BTreeMap<String, Duration>,
     networking: bool,
     max_log_size: usize,
+    max_doc_size: Option<usize>,
+    cpus: Option<u32>,
+    custom_css: Option<String>,
 }
 
 impl Limits {
@@ -19,9 +24,13 @@ impl Limits {
             // 3 GB default default
             memory: config.build_default_memory_limit.unwrap_or(3 * GB),
             timeout: Duration::from_secs(15 * 60), // 15 minutes
+            target_timeouts: BTreeMap::new(),
             targets: crate::DEFAULT_MAX_TARGETS,
             networking: false,
             max_log_size: 100 * 1024, // 100 KB
+            max_doc_size: config.build_default_max_doc_size,
+            cpus: config.build_cpu_limit,
+            custom_css: None,
         }
     }
 
@@ -42,8 +51,12 @@ impl Limits {
                 .or(overrides.timeout.map(|_| 1))
                 .unwrap_or(default.targets),
             timeout: overrides.timeout.unwrap_or(default.timeout),
+            target_timeouts: overrides.target_timeouts,
             networking: default.networking,
             max_log_size: default.max_log_size,
+            max_doc_size: overrides.max_doc_size.or(default.max_doc_size),
+            cpus: overrides.cpus.or(default.cpus),
+            custom_css: overrides.custom_css,
         })
     }
 
@@ -51,8 +64,12 @@ impl Limits {
         self.memory
     }
 
-    pub(crate) fn timeout(&self) -> Duration {
-        self.timeout
+    /// The timeout for building the given target, falls back to the crate's timeout
+    pub(crate) fn timeout_for_target(&self, target: &str) -> Duration {
+        self.target_timeouts
+            .get(target)
+            .copied()
+            .unwrap_or(self.timeout)
     }
 
     pub(crate) fn networking(&self) -> bool {
@@ -66,6 +83,21 @@ impl Limits {
     pub(crate) fn targets(&self) -> usize {
         self.targets
     }
+
+    /// The maximum size of the generated documentation, `None` means unlimited
+    pub(crate) fn max_doc_size(&self) -> Option<usize> {
+        self.max_doc_size
+    }
+
+    /// The number of CPUs the build may use, `None` means unlimited
+    pub(crate) fn cpus(&self) -> Option<u32> {
+        self.cpus
+    }
+
+    /// The storage path of the CSS file added to the documentation
+    pub(crate) fn custom_css(&self) -> Option<&str> {
+        self.custom_css.as_deref()
+    }
 }
 
 #[cfg(test)]
@@ -101,7 +133,7 @@ mod test {
                 hexponent,
                 Limits {
                     targets: 15,
-                    ..defaults
+                    ..defaults.clone()
                 }
             );
 
@@ -120,6 +152,7 @@ mod test {
                     memory: Some(limits.memory),
                     targets: Some(limits.targets),
                     timeout: Some(limits.timeout),
+                    ..Overrides::default()
                 },
             )
             .await?;
@@ -153,6 +186,125 @@ mod test {
         })
     }
 
+    #[test]
+    fn target_timeouts_override_timeout() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+            let krate = "hexponent";
+            Overrides::save(
+                &mut conn,
+                krate,
+                Overrides {
+                    timeout: Some(Duration::from_secs(20 * 60)),
+                    target_timeouts: [(
+                        "aarch64-apple-darwin".into(),
+                        Duration::from_secs(60 * 60),
+                    )]
+                    .into(),
+                    ..Overrides::default()
+                },
+            )
+            .await?;
+            let limits = Limits::for_crate(&env.config(), &mut conn, krate).await?;
+            assert_eq!(
+                limits.timeout_for_target("aarch64-apple-darwin"),
+                Duration::from_secs(60 * 60)
+            );
+            assert_eq!(
+                limits.timeout_for_target("x86_64-unknown-linux-gnu"),
+                Duration::from_secs(20 * 60)
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn max_doc_size_override() {
+        async_wrapper(|env| async move {
+            env.override_config(|config| {
+                config.build_default_max_doc_size = Some(GB);
+            });
+
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            Overrides::save(
+                &mut conn,
+                "krate",
+                Overrides {
+                    max_doc_size: Some(2 * GB),
+                    ..Overrides::default()
+                },
+            )
+            .await?;
+
+            let limits = Limits::for_crate(&env.config(), &mut conn, "krate").await?;
+            assert_eq!(limits.max_doc_size(), Some(2 * GB));
+            // crates without an override keep the configured default
+            let limits = Limits::for_crate(&env.config(), &mut conn, "other").await?;
+            assert_eq!(limits.max_doc_size(), Some(GB));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn cpus_override() {
+        async_wrapper(|env| async move {
+            env.override_config(|config| {
+                config.build_cpu_limit = Some(4);
+            });
+
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            Overrides::save(
+                &mut conn,
+                "krate",
+                Overrides {
+                    cpus: Some(2),
+                    ..Overrides::default()
+                },
+            )
+            .await?;
+
+            let limits = Limits::for_crate(&env.config(), &mut conn, "krate").await?;
+            assert_eq!(limits.cpus(), Some(2));
+            // crates without an override keep the configured limit
+            let limits = Limits::for_crate(&env.config(), &mut conn, "other").await?;
+            assert_eq!(limits.cpus(), Some(4));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn custom_css_override() {
+        async_wrapper(|env| async move {
+            let db = env.async_db().await;
+            let mut conn = db.async_conn().await;
+
+            Overrides::save(
+                &mut conn,
+                "krate",
+                Overrides {
+                    custom_css: Some("custom-css/krate.css".into()),
+                    ..Overrides::default()
+                },
+            )
+            .await?;
+
+            let limits = Limits::for_crate(&env.config(), &mut conn, "krate").await?;
+            assert_eq!(limits.custom_css(), Some("custom-css/krate.css"));
+            let limits = Limits::for_crate(&env.config(), &mut conn, "other").await?;
+            assert_eq!(limits.custom_css(), None);
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn config_default_memory_limit() {
         async_wrapper(|env| async move {
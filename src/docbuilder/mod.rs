@@ -1,6 +1,22 @@
+mod build_profile;
+mod crate_file;
+mod docs_comparison;
+mod docs_metadata;
 mod limits;
+mod rustflags;
 mod rustwide_builder;
+mod toolchain_file;
 
+pub(crate) use self::build_profile::{load_build_profiles, BuildProfile};
+pub use self::crate_file::unpack_crate_file;
+pub use self::docs_comparison::{ChangedFile, DocsComparison};
+pub use self::docs_metadata::{
+    lint_metadata, lint_release_metadata, resolve_local_metadata, resolve_release_metadata,
+    MetadataProblem, ResolvedMetadata,
+};
 pub(crate) use self::limits::Limits;
 pub(crate) use self::rustwide_builder::DocCoverage;
-pub use self::rustwide_builder::{PackageKind, RustwideBuilder};
+pub use self::rustwide_builder::{
+    BuildErrorCategory, BuildReport, PackageKind, RustwideBuilder, TargetBuildReport,
+};
+pub(crate) use self::toolchain_file::read_toolchain_file;
@@ -0,0 +1,121 @@
+use anyhow::{bail, Result};
+
+/// Flags that take a value, either attached (`--cfg=foo`, `-Awarnings`) or as the next
+/// argument.
+const FLAGS_WITH_VALUE: &[&str] = &[
+    "--cfg",
+    "--check-cfg",
+    "--cap-lints",
+    "-A",
+    "--allow",
+    "-W",
+    "--warn",
+    "-D",
+    "--deny",
+    "-F",
+    "--forbid",
+    "-C",
+    "--codegen",
+];
+
+/// The codegen options that only change the generated code, all other `-C` options could
+/// pass files or programs to the compiler, like `-C linker`.
+const ALLOWED_CODEGEN_OPTIONS: &[&str] = &[
+    "target-feature",
+    "target-cpu",
+    "opt-level",
+    "debuginfo",
+    "debug-assertions",
+    "overflow-checks",
+    "panic",
+];
+
+/// Splits and validates the `RUSTFLAGS` of `build crate --rustflags`.
+///
+/// Only flags that change which code is documented or how lints are reported are
+/// allowed: `--cfg`, `--check-cfg`, lint levels and a few codegen options. Everything
+/// else is rejected, since flags like `-C linker`, `-L` or `--extern` could make the
+/// build use files or programs from outside the crate.
+pub(crate) fn validate_rustflags(rustflags: &str) -> Result<Vec<String>> {
+    let mut args = rustflags.split_whitespace();
+    let mut validated = Vec::new();
+    while let Some(arg) = args.next() {
+        let Some((flag, value)) = split_flag(arg) else {
+            bail!("RUSTFLAGS `{arg}` is not allowed");
+        };
+        let value = match value {
+            Some(value) => value,
+            None => args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("RUSTFLAGS `{flag}` is missing a value"))?,
+        };
+        if value.is_empty() || value.starts_with('-') {
+            bail!("RUSTFLAGS `{flag}` is missing a value");
+        }
+        if matches!(flag, "-C" | "--codegen") {
+            let option = value.split_once('=').map_or(value, |(option, _)| option);
+            if !ALLOWED_CODEGEN_OPTIONS.contains(&option) {
+                bail!("RUSTFLAGS `{flag} {option}` is not allowed");
+            }
+        }
+        validated.push(flag.to_owned());
+        validated.push(value.to_owned());
+    }
+    Ok(validated)
+}
+
+/// Splits `arg` into one of [`FLAGS_WITH_VALUE`] and its attached value.
+fn split_flag(arg: &str) -> Option<(&str, Option<&str>)> {
+    FLAGS_WITH_VALUE.iter().find_map(|&flag| {
+        let rest = arg.strip_prefix(flag)?;
+        if rest.is_empty() {
+            Some((flag, None))
+        } else if let Some(value) = rest.strip_prefix('=') {
+            Some((flag, Some(value)))
+        } else if !flag.starts_with("--") {
+            // short flags take their value directly, like `-Awarnings`
+            Some((flag, Some(rest)))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rustflags() {
+        assert_eq!(
+            validate_rustflags(
+                "--cfg docsrs --cfg=feature=\"foo\" -Awarnings -C target-feature=+avx2"
+            )
+            .unwrap(),
+            vec![
+                "--cfg",
+                "docsrs",
+                "--cfg",
+                "feature=\"foo\"",
+                "-A",
+                "warnings",
+                "-C",
+                "target-feature=+avx2"
+            ]
+        );
+        assert!(validate_rustflags("").unwrap().is_empty());
+
+        for invalid in [
+            "-C linker=/bin/sh",
+            "-Clink-arg=-Wl,foo",
+            "-L /etc",
+            "--extern foo=/tmp/libfoo.rlib",
+            "--cfg",
+            "--cfg --cfg",
+            "--cfgfoo",
+            "-Zunstable-options",
+        ] {
+            assert!(validate_rustflags(invalid).is_err(), "{invalid}");
+        }
+    }
+}
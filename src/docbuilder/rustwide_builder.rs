@@ -1,13 +1,19 @@
 use crate::db::file::add_path_into_database;
 use crate::db::{
     add_doc_coverage, add_package_into_database, add_path_into_remote_archive, finish_build,
-    initialize_build, initialize_crate, initialize_release, types::BuildStatus,
+    initialize_build, initialize_crate, initialize_release, previous_documentation_hash,
+    set_build_documentation_hash, set_build_error_category, types::BuildStatus,
     update_build_with_error, update_crate_data_in_database, Pool,
 };
-use crate::docbuilder::Limits;
+use crate::docbuilder::{
+    docs_comparison::compare_docs, read_toolchain_file, rustflags::validate_rustflags,
+    unpack_crate_file, BuildProfile, DocsComparison, Limits,
+};
 use crate::error::Result;
 use crate::repositories::RepositoryStatsUpdater;
-use crate::storage::{rustdoc_archive_path, source_archive_path};
+use crate::storage::{
+    rustdoc_archive_path, rustdoc_json_path, source_archive_path, PathNotFoundError,
+};
 use crate::utils::{
     copy_dir_all, get_config, parse_rustc_version, report_error, set_config, CargoMetadata,
     ConfigName,
@@ -23,9 +29,10 @@ use rustwide::cmd::{Command, CommandError, SandboxBuilder, SandboxImage};
 use rustwide::logging::{self, LogStorage};
 use rustwide::toolchain::ToolchainError;
 use rustwide::{AlternativeRegistry, Build, Crate, Toolchain, Workspace, WorkspaceBuilder};
+use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::runtime::Runtime;
@@ -35,19 +42,43 @@ const USER_AGENT: &str = "docs.rs builder (https://github.com/rust-lang/docs.rs)
 const COMPONENTS: &[&str] = &["llvm-tools-preview", "rustc-dev", "rustfmt"];
 const DUMMY_CRATE_NAME: &str = "empty-library";
 const DUMMY_CRATE_VERSION: &str = "1.0.0";
+/// Where the custom CSS of a crate is put in the source directory, see [`Limits::custom_css`]
+const CUSTOM_CSS_FILE: &str = ".docsrs-custom.css";
+
+/// Finds the `.crate` file of a release in the registry cache of `cargo_home`.
+fn cached_crate_file(cargo_home: &Path, name: &str, version: &str) -> Result<PathBuf> {
+    let cache = cargo_home.join("registry").join("cache");
+    let file_name = format!("{name}-{version}.crate");
+    if cache.is_dir() {
+        for registry in fs::read_dir(&cache)? {
+            let path = registry?.path().join(&file_name);
+            if path.is_file() {
+                return Ok(path);
+            }
+        }
+    }
+    bail!(
+        "{name} {version} is not in the cargo cache at {}, it can't be built offline",
+        cache.display()
+    )
+}
 
 fn get_configured_toolchain(conn: &mut Client) -> Result<Toolchain> {
     let name: String = get_config(conn, ConfigName::Toolchain)?.unwrap_or_else(|| "nightly".into());
 
+    Ok(toolchain_from_name(&name))
+}
+
+fn toolchain_from_name(name: &str) -> Toolchain {
     // If the toolchain is all hex, assume it references an artifact from
     // CI, for instance an `@bors try` build.
     let re = Regex::new(r"^[a-fA-F0-9]+$").unwrap();
-    if re.is_match(&name) {
-        debug!("using CI build {}", &name);
-        Ok(Toolchain::ci(&name, false))
+    if re.is_match(name) {
+        debug!("using CI build {}", name);
+        Toolchain::ci(name, false)
     } else {
-        debug!("using toolchain {}", &name);
-        Ok(Toolchain::dist(&name))
+        debug!("using toolchain {}", name);
+        Toolchain::dist(name)
     }
 }
 
@@ -92,6 +123,20 @@ pub struct RustwideBuilder {
     registry_api: Arc<RegistryApi>,
     repository_stats_updater: Arc<RepositoryStatsUpdater>,
     workspace_initialize_time: Instant,
+    offline: bool,
+    rustdoc_json: bool,
+    ignore_metadata: bool,
+    skip_source_archive: bool,
+    allow_blacklisted: bool,
+    capture_timings: bool,
+    verbose_cargo: bool,
+    build_profile: Option<String>,
+    toolchain_from_file: bool,
+    compare_with_stored: bool,
+    promote_compared: bool,
+    rustflags: Option<String>,
+    no_default_targets: bool,
+    out_dir: Option<PathBuf>,
 }
 
 impl RustwideBuilder {
@@ -103,6 +148,8 @@ impl RustwideBuilder {
         Ok(RustwideBuilder {
             workspace: build_workspace(context)?,
             toolchain: get_configured_toolchain(&mut *pool.get()?)?,
+            rustdoc_json: config.build_rustdoc_json,
+            skip_source_archive: config.skip_source_archive,
             config,
             db: pool,
             runtime: runtime.clone(),
@@ -112,9 +159,137 @@ impl RustwideBuilder {
             registry_api: context.registry_api()?,
             repository_stats_updater: context.repository_stats_updater()?,
             workspace_initialize_time: Instant::now(),
+            offline: false,
+            ignore_metadata: false,
+            allow_blacklisted: false,
+            capture_timings: false,
+            verbose_cargo: false,
+            build_profile: None,
+            toolchain_from_file: false,
+            compare_with_stored: false,
+            promote_compared: false,
+            rustflags: None,
+            no_default_targets: false,
+            out_dir: None,
         })
     }
 
+    /// Build without network access, relying on a pre-populated cargo cache
+    /// or vendored registry.
+    ///
+    /// The crate itself is unpacked from the cargo cache of the workspace, and its lockfile
+    /// is generated offline before rustwide prepares the build. The cargo commands started
+    /// by docs.rs run with `CARGO_NET_OFFLINE`. This also skips the registry API calls done
+    /// after a build.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Also build the rustdoc JSON output for the default target, and store it
+    /// at [`rustdoc_json_path`].
+    ///
+    /// Defaults to `DOCSRS_BUILD_RUSTDOC_JSON`.
+    pub fn set_rustdoc_json(&mut self, rustdoc_json: bool) {
+        self.rustdoc_json = rustdoc_json;
+    }
+
+    /// Don't store the source archive, the source view of the release won't be available.
+    ///
+    /// Defaults to `DOCSRS_SKIP_SOURCE_ARCHIVE`.
+    pub fn set_skip_source_archive(&mut self, skip_source_archive: bool) {
+        self.skip_source_archive = skip_source_archive;
+    }
+
+    /// Ignore the `[package.metadata.docs.rs]` section of the crates, and build
+    /// them with the default features, targets and flags.
+    ///
+    /// Useful to find out if the metadata is the reason a build fails.
+    pub fn set_ignore_metadata(&mut self, ignore_metadata: bool) {
+        self.ignore_metadata = ignore_metadata;
+    }
+
+    /// Pass `--timings` to cargo and store the timing report of every target next to
+    /// its build log.
+    pub fn set_capture_timings(&mut self, capture_timings: bool) {
+        self.capture_timings = capture_timings;
+    }
+
+    /// Run cargo with `-vv` and store the build logs without truncating them, to debug
+    /// issues with linkers or build scripts.
+    pub fn set_verbose_cargo(&mut self, verbose_cargo: bool) {
+        self.verbose_cargo = verbose_cargo;
+    }
+
+    /// Build crates even if they are on the blacklist.
+    pub fn set_allow_blacklisted(&mut self, allow_blacklisted: bool) {
+        self.allow_blacklisted = allow_blacklisted;
+    }
+
+    /// Build crates with the named build profile from the config, instead of their
+    /// `[package.metadata.docs.rs]` section.
+    pub fn set_build_profile(&mut self, build_profile: Option<String>) {
+        self.build_profile = build_profile;
+    }
+
+    /// Build local packages with the toolchain pinned in their `rust-toolchain.toml` or
+    /// `rust-toolchain` file, when they have one.
+    pub fn set_toolchain_from_file(&mut self, toolchain_from_file: bool) {
+        self.toolchain_from_file = toolchain_from_file;
+    }
+
+    /// Compare the built documentation with the stored documentation of the release, the
+    /// differences are added to the build report.
+    ///
+    /// Unless `promote` is set, nothing is written to the database or the storage, the
+    /// stored documentation, sources, release data and builds are kept as they are.
+    pub fn set_compare_with_stored(&mut self, compare: bool, promote: bool) {
+        self.compare_with_stored = compare;
+        self.promote_compared = promote;
+    }
+
+    /// Set `RUSTFLAGS` for the builds, to document crates with additional `--cfg` flags.
+    ///
+    /// This replaces the `rustc-args` of the crate's metadata. Only some flags are allowed,
+    /// see [`validate_rustflags`].
+    pub fn set_rustflags(&mut self, rustflags: Option<&str>) -> Result<()> {
+        self.rustflags = rustflags
+            .map(|rustflags| validate_rustflags(rustflags).map(|flags| flags.join(" ")))
+            .transpose()?;
+        Ok(())
+    }
+
+    /// Only build the documentation for the default target, which is the host target
+    /// unless the crate sets `default-target` or `targets`, and skip all other targets.
+    ///
+    /// The release is stored with only this target, this is meant for quick local builds.
+    pub fn set_no_default_targets(&mut self, no_default_targets: bool) {
+        self.no_default_targets = no_default_targets;
+    }
+
+    /// Also copy the generated documentation of a successful build into this directory,
+    /// to look at it without the web server.
+    pub fn set_out_dir(&mut self, out_dir: Option<PathBuf>) {
+        self.out_dir = out_dir;
+    }
+
+    /// Whether the built release should be left untouched, see
+    /// [`RustwideBuilder::set_compare_with_stored`].
+    fn keep_stored_release(&self) -> bool {
+        self.compare_with_stored && !self.promote_compared
+    }
+
+    fn build_profile(&self) -> Result<Option<&BuildProfile>> {
+        self.build_profile
+            .as_deref()
+            .map(|name| {
+                self.config
+                    .build_profiles
+                    .get(name)
+                    .with_context(|| format!("unknown build profile {name}"))
+            })
+            .transpose()
+    }
+
     pub fn reinitialize_workspace_if_interval_passed(
         &mut self,
         context: &dyn Context,
@@ -132,9 +307,12 @@ impl RustwideBuilder {
     #[instrument(skip(self))]
     fn prepare_sandbox(&self, limits: &Limits) -> SandboxBuilder {
         SandboxBuilder::new()
-            .cpu_limit(self.config.build_cpu_limit.map(|limit| limit as f32))
+            .cpu_limit(limits.cpus().map(|limit| limit as f32))
             .memory_limit(Some(limits.memory()))
-            .enable_networking(limits.networking())
+            .enable_networking(
+                limits.networking()
+                    || matches!(self.build_profile(), Ok(Some(profile)) if profile.networking),
+            )
     }
 
     pub fn purge_caches(&self) -> Result<()> {
@@ -216,6 +394,31 @@ impl RustwideBuilder {
         Ok(has_changed)
     }
 
+    /// Checks that the toolchain with the given name is installed, and tries to install it
+    /// if it isn't.
+    pub fn verify_toolchain(&self, name: &str) -> Result<()> {
+        let toolchain = toolchain_from_name(name);
+
+        match toolchain.installed_targets(&self.workspace) {
+            Ok(_) => {
+                info!("toolchain {name} is installed");
+                Ok(())
+            }
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<ToolchainError>(),
+                    Some(&ToolchainError::NotInstalled)
+                ) =>
+            {
+                info!("toolchain {name} is not installed, trying to install it");
+                toolchain
+                    .install(&self.workspace)
+                    .with_context(|| format!("toolchain {name} can't be installed"))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     fn rustc_version(&self) -> Result<String> {
         let version = self
             .toolchain
@@ -259,6 +462,19 @@ impl RustwideBuilder {
         })
     }
 
+    /// Updates the toolchain, purges the caches and adds the essential files for the
+    /// installed version.
+    ///
+    /// The stored rustc version is only changed by [`RustwideBuilder::add_essential_files`],
+    /// once all steps succeeded, so the next run knows when an update didn't finish.
+    pub fn update_toolchain_and_add_essential_files(&mut self) -> Result<()> {
+        self.update_toolchain()
+            .context("failed to update toolchain")?;
+        self.purge_caches().context("failed to purge caches")?;
+        self.add_essential_files()
+            .context("failed to add essential files")
+    }
+
     pub fn add_essential_files(&mut self) -> Result<()> {
         let rustc_version = self.rustc_version()?;
         let parsed_rustc_version = parse_rustc_version(&rustc_version)?;
@@ -332,42 +548,139 @@ impl RustwideBuilder {
     }
 
     pub fn build_local_package(&mut self, path: &Path) -> Result<bool> {
-        let metadata = CargoMetadata::load_from_rustwide(&self.workspace, &self.toolchain, path)
-            .map_err(|err| {
-                err.context(format!("failed to load local package {}", path.display()))
-            })?;
+        Ok(self.build_local_package_with_report(path)?.successful)
+    }
+
+    /// Like [`RustwideBuilder::build_local_package`], but returns a report of the build.
+    pub fn build_local_package_with_report(&mut self, path: &Path) -> Result<BuildReport> {
+        let metadata = CargoMetadata::load_from_rustwide(self.cargo(), path).map_err(|err| {
+            err.context(format!("failed to load local package {}", path.display()))
+        })?;
         let package = metadata.root();
-        self.build_package(&package.name, &package.version, PackageKind::Local(path))
+
+        if self.toolchain_from_file {
+            if let Some(toolchain) = read_toolchain_file(path)? {
+                info!(
+                    "building {} {} with {}, pinned by the package",
+                    package.name, package.version, toolchain
+                );
+                return self.with_toolchain(&toolchain, |builder| {
+                    builder.build_package_with_report(
+                        &package.name,
+                        &package.version,
+                        PackageKind::Local(path),
+                    )
+                });
+            }
+        }
+
+        self.build_package_with_report(&package.name, &package.version, PackageKind::Local(path))
     }
 
-    #[instrument(name = "docbuilder.build_package", parent = None, skip(self))]
     pub fn build_package(
         &mut self,
         name: &str,
         version: &str,
         kind: PackageKind<'_>,
     ) -> Result<bool> {
-        let build_id = self.runtime.block_on(async {
-            let mut conn = self.db.get_async().await?;
-            let crate_id = initialize_crate(&mut conn, name).await?;
-            let release_id = initialize_release(&mut conn, crate_id, version).await?;
-            let build_id = initialize_build(&mut conn, release_id).await?;
-            Ok::<i32, Error>(build_id)
-        })?;
+        Ok(self
+            .build_package_with_report(name, version, kind)?
+            .successful)
+    }
 
-        match self.build_package_inner(name, version, kind, build_id) {
-            Ok(successful) => Ok(successful),
+    /// Like [`RustwideBuilder::build_package`], but builds with the given toolchain instead of
+    /// the configured one, installing it when needed.
+    ///
+    /// The essential files of the toolchain are not added, old toolchains can only be used
+    /// when their essential files were stored before.
+    pub fn build_package_with_toolchain(
+        &mut self,
+        name: &str,
+        version: &str,
+        kind: PackageKind<'_>,
+        toolchain: &str,
+    ) -> Result<bool> {
+        self.with_toolchain(toolchain, |builder| {
+            builder.build_package(name, version, kind)
+        })
+    }
+
+    /// Runs `f` with the given toolchain instead of the configured one, installing it
+    /// when needed.
+    pub(crate) fn with_toolchain<T>(
+        &mut self,
+        toolchain: &str,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let pinned = toolchain_from_name(toolchain);
+        pinned
+            .install(&self.workspace)
+            .with_context(|| format!("failed to install toolchain {toolchain}"))?;
+
+        let configured = std::mem::replace(&mut self.toolchain, pinned);
+        let result = f(self);
+        self.toolchain = configured;
+        result
+    }
+
+    /// Like [`RustwideBuilder::build_package`], but returns a report of the build.
+    #[instrument(name = "docbuilder.build_package", parent = None, skip(self))]
+    pub fn build_package_with_report(
+        &mut self,
+        name: &str,
+        version: &str,
+        kind: PackageKind<'_>,
+    ) -> Result<BuildReport> {
+        let start = Instant::now();
+        // compared builds aren't recorded, see `set_compare_with_stored`
+        let build_id = if self.keep_stored_release() {
+            None
+        } else {
+            Some(self.runtime.block_on(async {
+                let mut conn = self.db.get_async().await?;
+                let crate_id = initialize_crate(&mut conn, name).await?;
+                let release_id = initialize_release(&mut conn, crate_id, version).await?;
+                let build_id = initialize_build(&mut conn, release_id).await?;
+                Ok::<i32, Error>(build_id)
+            })?)
+        };
+
+        let mut report = match self.build_package_inner(name, version, kind, build_id) {
+            Ok(report) => report,
             Err(err) => self.runtime.block_on(async {
                 // NOTE: this might hide some errors from us, while only surfacing them in the build
                 // result.
                 // At some point we might introduce a special error type which additionally reports
                 // to sentry.
-                let mut conn = self.db.get_async().await?;
-                update_build_with_error(&mut conn, build_id, Some(&format!("{:?}", err))).await?;
+                if let Some(build_id) = build_id {
+                    let mut conn = self.db.get_async().await?;
+                    update_build_with_error(&mut conn, build_id, Some(&format!("{:?}", err)))
+                        .await?;
+                }
 
-                Ok(false)
-            }),
+                Ok::<_, Error>(BuildReport {
+                    rustc_version: self.rustc_version().ok(),
+                    error: Some(format!("{err:#}")),
+                    error_category: Some(BuildErrorCategory::Preparation),
+                    ..BuildReport::new(name, version)
+                })
+            })?,
+        };
+        report.duration_secs = start.elapsed().as_secs_f64();
+
+        if let Some(build_id) = build_id {
+            self.runtime.block_on(async {
+                let mut conn = self.db.get_async().await?;
+                set_build_error_category(
+                    &mut conn,
+                    build_id,
+                    report.error_category.map(|c| c.to_string()).as_deref(),
+                )
+                .await
+            })?;
         }
+
+        Ok(report)
     }
 
     fn build_package_inner(
@@ -375,14 +688,23 @@ impl RustwideBuilder {
         name: &str,
         version: &str,
         kind: PackageKind<'_>,
-        build_id: i32,
-    ) -> Result<bool> {
+        build_id: Option<i32>,
+    ) -> Result<BuildReport> {
         let mut conn = self.db.get()?;
         info!("building package {} {}", name, version);
 
         if is_blacklisted(&mut conn, name)? {
-            info!("skipping build of {}, crate has been blacklisted", name);
-            return Ok(false);
+            if !self.allow_blacklisted {
+                info!("skipping build of {}, crate has been blacklisted", name);
+                return Ok(BuildReport {
+                    error_category: Some(BuildErrorCategory::Blacklisted),
+                    ..BuildReport::new(name, version)
+                });
+            }
+            warn!(
+                "building {} {} even though the crate has been blacklisted",
+                name, version
+            );
         }
 
         let limits = self.get_limits(name)?;
@@ -420,13 +742,31 @@ impl RustwideBuilder {
         let mut build_dir = self.workspace.build_dir(&format!("{name}-{version}"));
 
         let is_local = matches!(kind, PackageKind::Local(_));
+        // offline builds can't download the crate, they unpack it from the cargo cache instead
+        let offline_source = if self.offline && !is_local {
+            let crate_file = cached_crate_file(
+                &self.config.rustwide_workspace.join("cargo-home"),
+                name,
+                version,
+            )?;
+            let dir = tempfile::tempdir()?;
+            let path = unpack_crate_file(&crate_file, dir.path())?;
+            // rustwide would generate a missing lockfile with network access
+            if !path.join("Cargo.lock").exists() {
+                self.cargo().cd(&path).args(&["generate-lockfile"]).run()?;
+            }
+            Some((dir, path))
+        } else {
+            None
+        };
         let krate = {
             let _span = info_span!("krate.fetch").entered();
 
-            let krate = match kind {
-                PackageKind::Local(path) => Crate::local(path),
-                PackageKind::CratesIo => Crate::crates_io(name, version),
-                PackageKind::Registry(registry) => {
+            let krate = match (kind, &offline_source) {
+                (_, Some((_, path))) => Crate::local(path),
+                (PackageKind::Local(path), None) => Crate::local(path),
+                (PackageKind::CratesIo, None) => Crate::crates_io(name, version),
+                (PackageKind::Registry(registry), None) => {
                     Crate::registry(AlternativeRegistry::new(registry), name, version)
                 }
             };
@@ -437,13 +777,16 @@ impl RustwideBuilder {
         fs::create_dir_all(&self.config.temp_dir)?;
         let local_storage = tempfile::tempdir_in(&self.config.temp_dir)?;
 
-        let successful = build_dir
+        let report = build_dir
             .build(&self.toolchain, &krate, self.prepare_sandbox(&limits))
             .run(|build| {
                 let mut algs = HashSet::new();
 
-                debug!("adding sources into database");
-                let files_list = {
+                let files_list = if self.skip_source_archive || self.keep_stored_release() {
+                    // without a file list the source view shows a "not found" page
+                    serde_json::Value::Null
+                } else {
+                    debug!("adding sources into database");
                     let (files_list, new_alg) =
                         self.runtime.block_on(add_path_into_remote_archive(
                             &self.async_storage,
@@ -454,11 +797,21 @@ impl RustwideBuilder {
                     algs.insert(new_alg);
                     files_list
                 };
-                let metadata = Metadata::from_crate_root(build.host_source_dir())?;
+                let metadata = self.load_metadata(&build.host_source_dir())?;
+                if let Some(css_path) = limits.custom_css() {
+                    let css = self
+                        .storage
+                        .get(css_path, self.config.max_file_size)
+                        .with_context(|| format!("could not load the custom CSS {css_path}"))?;
+                    std::fs::write(build.host_source_dir().join(CUSTOM_CSS_FILE), css.content)?;
+                }
                 let BuildTargets {
                     default_target,
-                    other_targets,
+                    mut other_targets,
                 } = metadata.targets(self.config.include_default_targets);
+                if self.no_default_targets {
+                    other_targets.clear();
+                }
                 let mut targets = vec![default_target];
                 targets.extend(&other_targets);
 
@@ -470,6 +823,11 @@ impl RustwideBuilder {
 
                 let mut has_docs = false;
                 let mut successful_targets = Vec::new();
+                let mut target_reports = Vec::new();
+                let mut doc_size = None;
+                let mut documentation_hash = None;
+                let mut docs_unchanged = false;
+                let mut docs_comparison = None;
 
                 // Perform an initial build
                 let mut res =
@@ -482,14 +840,14 @@ impl RustwideBuilder {
                     std::fs::remove_file(cargo_lock)?;
                     {
                         let _span = info_span!("cargo_generate_lockfile").entered();
-                        Command::new(&self.workspace, self.toolchain.cargo())
+                        self.cargo()
                             .cd(build.host_source_dir())
                             .args(&["generate-lockfile"])
                             .run()?;
                     }
                     {
                         let _span = info_span!("cargo fetch --locked").entered();
-                        Command::new(&self.workspace, self.toolchain.cargo())
+                        self.cargo()
                             .cd(build.host_source_dir())
                             .args(&["fetch", "--locked"])
                             .run()?;
@@ -509,7 +867,16 @@ impl RustwideBuilder {
                     }
                 }
 
+                target_reports.push(TargetBuildReport {
+                    target: res.target.clone(),
+                    successful: res.result.successful,
+                });
+
                 let mut target_build_logs = HashMap::new();
+                let mut build_timings: Vec<(String, Vec<u8>)> = Vec::new();
+                if let Some(timings) = res.timings.take() {
+                    build_timings.push((res.target.clone(), timings));
+                }
                 if has_docs {
                     debug!("adding documentation for the default target to the database");
                     self.copy_docs(
@@ -525,7 +892,7 @@ impl RustwideBuilder {
                     // Limit the number of targets so that no one can try to build all 200000 possible targets
                     for target in other_targets.into_iter().take(limits.targets()) {
                         debug!("building package {} {} for {}", name, version, target);
-                        let target_res = self.build_target(
+                        let mut target_res = self.build_target(
                             target,
                             build,
                             &limits,
@@ -533,87 +900,192 @@ impl RustwideBuilder {
                             &mut successful_targets,
                             &metadata,
                         )?;
+                        if let Some(timings) = target_res.timings.take() {
+                            build_timings.push((target.to_string(), timings));
+                        }
+                        target_reports.push(TargetBuildReport {
+                            target: target.to_string(),
+                            successful: target_res.result.successful,
+                        });
                         target_build_logs.insert(target, target_res.build_log);
                     }
-                    let (_, new_alg) = self.runtime.block_on(add_path_into_remote_archive(
-                        &self.async_storage,
-                        &rustdoc_archive_path(name, version),
-                        local_storage.path(),
-                        true,
-                    ))?;
-                    algs.insert(new_alg);
+                    let size = directory_size(local_storage.path())?;
+                    doc_size = Some(size);
+
+                    if let Some(max_doc_size) = limits
+                        .max_doc_size()
+                        .filter(|&max_doc_size| size > max_doc_size as u64)
+                    {
+                        warn!(
+                            "documentation of {} {} is too large ({} > {} bytes), not uploading it",
+                            name, version, size, max_doc_size
+                        );
+                        res.build_log.push_str(&format!(
+                            "\n[docs.rs] the generated documentation is {size} bytes, \
+                             more than the limit of {max_doc_size} bytes\n"
+                        ));
+                        has_docs = false;
+                        successful_targets.clear();
+                        res.result.successful = false;
+                        res.error_category = Some(BuildErrorCategory::DocsTooLarge);
+                    } else {
+                        if let Some(out_dir) = &self.out_dir {
+                            copy_dir_all(local_storage.path(), out_dir).with_context(|| {
+                                format!("failed to copy the documentation to {}", out_dir.display())
+                            })?;
+                        }
+
+                        // this has to happen after the HTML docs were copied, since
+                        // the JSON build replaces the doc folder.
+                        if self.rustdoc_json && !self.keep_stored_release() {
+                            if let Some(library_name) = res.cargo_metadata.root().library_name() {
+                                if let Err(err) = self.build_rustdoc_json(
+                                    name,
+                                    version,
+                                    default_target,
+                                    &library_name,
+                                    build,
+                                    &limits,
+                                    &metadata,
+                                ) {
+                                    warn!("error when trying to build rustdoc JSON: {:?}", err);
+                                    info!("continuing anyways.");
+                                }
+                            }
+                        }
+                        if self.compare_with_stored {
+                            let comparison =
+                                self.compare_with_stored_docs(name, version, local_storage.path())?;
+                            info!(
+                                "documentation of {} {} compared with the stored one: {} added, {} removed, {} changed",
+                                name,
+                                version,
+                                comparison.added.len(),
+                                comparison.removed.len(),
+                                comparison.changed.len(),
+                            );
+                            docs_comparison = Some(comparison);
+                        }
+
+                        if let Some(build_id) = build_id {
+                            let hash = hash_directory(local_storage.path())?;
+                            let previous_hash = self.runtime.block_on(async {
+                                let mut conn = self.db.get_async().await?;
+                                previous_documentation_hash(&mut conn, name, version, build_id)
+                                    .await
+                            })?;
+                            if previous_hash.as_deref() == Some(hash.as_str())
+                                && self.storage.exists(&rustdoc_archive_path(name, version))?
+                            {
+                                info!(
+                                    "documentation of {} {} is unchanged since the last build, not uploading it",
+                                    name, version
+                                );
+                                docs_unchanged = true;
+                            } else {
+                                let (_, new_alg) =
+                                    self.runtime.block_on(add_path_into_remote_archive(
+                                        &self.async_storage,
+                                        &rustdoc_archive_path(name, version),
+                                        local_storage.path(),
+                                        true,
+                                    ))?;
+                                algs.insert(new_alg);
+                            }
+                            documentation_hash = Some(hash);
+                        } else {
+                            info!(
+                                "keeping the stored documentation of {} {}, it's only compared",
+                                name, version
+                            );
+                        }
+                    }
                 };
 
                 let has_examples = build.host_source_dir().join("examples").is_dir();
-                if res.result.successful {
+                let error_category = if res.result.successful {
                     self.metrics.successful_builds.inc();
+                    None
                 } else if res.cargo_metadata.root().is_library() {
                     self.metrics.failed_builds.inc();
+                    res.error_category
                 } else {
                     self.metrics.non_library_builds.inc();
-                }
+                    Some(BuildErrorCategory::NotALibrary)
+                };
 
-                let release_data = if !is_local {
-                    match self
-                        .runtime
-                        .block_on(self.registry_api.get_release_data(name, version))
-                        .with_context(|| {
-                            format!("could not fetch releases-data for {name}-{version}")
-                        }) {
-                        Ok(data) => Some(data),
-                        Err(err) => {
-                            report_error(&err);
-                            None
+                let mut async_conn = self.runtime.block_on(self.db.get_async())?;
+
+                // when the docs are only compared, the release keeps its stored state
+                if !self.keep_stored_release() {
+                    let release_data = if !is_local && !self.offline {
+                        match self
+                            .runtime
+                            .block_on(self.registry_api.get_release_data(name, version))
+                            .with_context(|| {
+                                format!("could not fetch releases-data for {name}-{version}")
+                            }) {
+                            Ok(data) => Some(data),
+                            Err(err) => {
+                                report_error(&err);
+                                None
+                            }
                         }
+                    } else {
+                        None
                     }
-                } else {
-                    None
-                }
-                .unwrap_or_default();
-
-                let cargo_metadata = res.cargo_metadata.root();
-                let repository = self.get_repo(cargo_metadata)?;
+                    .unwrap_or_default();
 
-                let mut async_conn = self.runtime.block_on(self.db.get_async())?;
+                    let cargo_metadata = res.cargo_metadata.root();
+                    let repository = self.get_repo(cargo_metadata)?;
 
-                let release_id = self.runtime.block_on(add_package_into_database(
-                    &mut async_conn,
-                    cargo_metadata,
-                    &build.host_source_dir(),
-                    &res.target,
-                    files_list,
-                    successful_targets,
-                    &release_data,
-                    has_docs,
-                    has_examples,
-                    algs,
-                    repository,
-                    true,
-                ))?;
-
-                if let Some(doc_coverage) = res.doc_coverage {
-                    self.runtime.block_on(add_doc_coverage(
+                    let release_id = self.runtime.block_on(add_package_into_database(
                         &mut async_conn,
-                        release_id,
-                        doc_coverage,
+                        cargo_metadata,
+                        &build.host_source_dir(),
+                        &res.target,
+                        files_list,
+                        successful_targets,
+                        &release_data,
+                        has_docs,
+                        has_examples,
+                        algs,
+                        repository,
+                        true,
                     ))?;
+
+                    if let Some(doc_coverage) = res.doc_coverage {
+                        self.runtime.block_on(add_doc_coverage(
+                            &mut async_conn,
+                            release_id,
+                            doc_coverage,
+                        ))?;
+                    }
                 }
 
-                let build_status = if res.result.successful {
-                    BuildStatus::Success
-                } else {
-                    BuildStatus::Failure
-                };
-                self.runtime.block_on(finish_build(
-                    &mut async_conn,
-                    build_id,
-                    &res.result.rustc_version,
-                    &res.result.docsrs_version,
-                    build_status,
-                    None,
-                ))?;
+                // compared builds aren't recorded, they only end up in the report
+                if let Some(build_id) = build_id {
+                    let build_status = if res.result.successful {
+                        BuildStatus::Success
+                    } else {
+                        BuildStatus::Failure
+                    };
+                    self.runtime.block_on(finish_build(
+                        &mut async_conn,
+                        build_id,
+                        &res.result.rustc_version,
+                        &res.result.docsrs_version,
+                        build_status,
+                        None,
+                    ))?;
+                    if let Some(hash) = &documentation_hash {
+                        self.runtime.block_on(set_build_documentation_hash(
+                            &mut async_conn,
+                            build_id,
+                            hash,
+                        ))?;
+                    }
 
-                {
                     let _span = info_span!("store_build_logs").entered();
                     let build_log_path = format!("build-logs/{build_id}/{default_target}.txt");
                     self.storage.store_one(build_log_path, res.build_log)?;
@@ -621,10 +1093,14 @@ impl RustwideBuilder {
                         let build_log_path = format!("build-logs/{build_id}/{target}.txt");
                         self.storage.store_one(build_log_path, log)?;
                     }
+                    for (target, timings) in build_timings {
+                        let timings_path = format!("build-logs/{build_id}/{target}-timings.html");
+                        self.storage.store_one(timings_path, timings)?;
+                    }
                 }
 
                 // Some crates.io crate data is mutable, so we proactively update it during a release
-                if !is_local {
+                if !is_local && !self.offline && !self.keep_stored_release() {
                     match self
                         .runtime
                         .block_on(self.registry_api.get_crate_data(name))
@@ -638,7 +1114,7 @@ impl RustwideBuilder {
                     }
                 }
 
-                if res.result.successful {
+                if res.result.successful && !self.keep_stored_release() {
                     // delete eventually existing files from pre-archive storage.
                     // we're doing this in the end so eventual problems in the build
                     // won't lead to non-existing docs.
@@ -655,7 +1131,16 @@ impl RustwideBuilder {
                     drop(async_conn);
                 });
 
-                Ok(res.result.successful)
+                Ok(BuildReport {
+                    successful: res.result.successful,
+                    rustc_version: Some(res.result.rustc_version),
+                    targets: target_reports,
+                    doc_size,
+                    docs_unchanged,
+                    docs_comparison,
+                    error_category,
+                    ..BuildReport::new(name, version)
+                })
             })?;
 
         {
@@ -663,7 +1148,58 @@ impl RustwideBuilder {
             krate.purge_from_cache(&self.workspace)?;
             local_storage.close()?;
         }
-        Ok(successful)
+        Ok(report)
+    }
+
+    /// Loads the docs.rs metadata of the crate, the settings of the build profile when one
+    /// is set, or the defaults when the metadata is ignored.
+    fn load_metadata(&self, source_dir: &Path) -> Result<Metadata> {
+        let metadata = Metadata::from_crate_root(source_dir)?;
+        if let Some(profile) = self.build_profile()? {
+            Ok(Metadata {
+                proc_macro: metadata.proc_macro,
+                ..profile.metadata.clone()
+            })
+        } else if self.ignore_metadata {
+            // `proc_macro` comes from the `[lib]` section and not from the
+            // docs.rs metadata, we can't build proc-macros as normal libraries.
+            Ok(Metadata {
+                proc_macro: metadata.proc_macro,
+                ..Metadata::default()
+            })
+        } else {
+            Ok(metadata)
+        }
+    }
+
+    /// Compares the documentation in `local_storage` with the stored rustdoc archive of
+    /// the release.
+    fn compare_with_stored_docs(
+        &self,
+        name: &str,
+        version: &str,
+        local_storage: &Path,
+    ) -> Result<DocsComparison> {
+        let stored = match self
+            .storage
+            .get(&rustdoc_archive_path(name, version), usize::MAX)
+        {
+            Ok(blob) => Some(blob.content),
+            Err(err) if err.downcast_ref::<PathNotFoundError>().is_some() => None,
+            Err(err) => return Err(err),
+        };
+        compare_docs(local_storage, stored.as_deref())
+    }
+
+    /// A cargo command outside of the sandbox, which doesn't access the network when
+    /// building offline.
+    fn cargo(&self) -> Command<'_, '_> {
+        let command = Command::new(&self.workspace, self.toolchain.cargo());
+        if self.offline {
+            command.env("CARGO_NET_OFFLINE", "true")
+        } else {
+            command
+        }
     }
 
     #[instrument(skip(self, build))]
@@ -690,6 +1226,37 @@ impl RustwideBuilder {
         Ok(target_res)
     }
 
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, build))]
+    fn build_rustdoc_json(
+        &self,
+        name: &str,
+        version: &str,
+        target: &str,
+        library_name: &str,
+        build: &Build,
+        limits: &Limits,
+        metadata: &Metadata,
+    ) -> Result<()> {
+        let rustdoc_flags = vec!["--output-format".to_string(), "json".to_string()];
+
+        self.prepare_command(build, target, metadata, limits, rustdoc_flags)?
+            .log_output(false)
+            .run()?;
+
+        // proc-macros are built without `--target`, see `prepare_command`.
+        let doc_dir = if metadata.proc_macro {
+            build.host_target_dir().join("doc")
+        } else {
+            build.host_target_dir().join(target).join("doc")
+        };
+        let json = fs::read(doc_dir.join(format!("{library_name}.json")))?;
+
+        self.storage
+            .store_one(rustdoc_json_path(name, version, target), json)?;
+        Ok(())
+    }
+
     #[instrument(skip(self, build))]
     fn get_coverage(
         &self,
@@ -756,11 +1323,8 @@ impl RustwideBuilder {
         metadata: &Metadata,
         create_essential_files: bool,
     ) -> Result<FullBuildResult> {
-        let cargo_metadata = CargoMetadata::load_from_rustwide(
-            &self.workspace,
-            &self.toolchain,
-            &build.host_source_dir(),
-        )?;
+        let cargo_metadata =
+            CargoMetadata::load_from_rustwide(self.cargo(), &build.host_source_dir())?;
 
         let mut rustdoc_flags = vec![if create_essential_files {
             "--emit=unversioned-shared-resources,toolchain-shared-resources"
@@ -772,9 +1336,15 @@ impl RustwideBuilder {
             "--resource-suffix".to_string(),
             format!("-{}", parse_rustc_version(self.rustc_version()?)?),
         ]);
+        if limits.custom_css().is_some() {
+            // relative to the package root, where cargo runs rustdoc
+            rustdoc_flags.extend(["--extend-css".to_string(), CUSTOM_CSS_FILE.to_string()]);
+        }
 
         let mut storage = LogStorage::new(log::LevelFilter::Info);
-        storage.set_max_size(limits.max_log_size());
+        if !self.verbose_cargo {
+            storage.set_max_size(limits.max_log_size());
+        }
 
         // we have to run coverage before the doc-build because currently it
         // deletes the doc-target folder.
@@ -788,14 +1358,34 @@ impl RustwideBuilder {
             }
         };
 
-        let successful = {
+        let build_error = {
             let _span = info_span!("cargo_build", target = %target, is_default_target).entered();
             logging::capture(&storage, || {
                 self.prepare_command(build, target, metadata, limits, rustdoc_flags)
+                    .map(|command| {
+                        if self.capture_timings {
+                            command.args(&["--timings"])
+                        } else {
+                            command
+                        }
+                    })
+                    .map(|command| {
+                        if self.verbose_cargo {
+                            command.args(&["-vv"])
+                        } else {
+                            command
+                        }
+                    })
                     .and_then(|command| command.run().map_err(Error::from))
-                    .is_ok()
+                    .err()
             })
         };
+        let successful = build_error.is_none();
+
+        let mut build_log = storage.to_string();
+        if let Some(rustflags) = &self.rustflags {
+            build_log.insert_str(0, &format!("[docs.rs] built with RUSTFLAGS={rustflags}\n"));
+        }
 
         // For proc-macros, cargo will put the output in `target/doc`.
         // Move it to the target-specific directory for consistency with other builds.
@@ -814,6 +1404,19 @@ impl RustwideBuilder {
             std::fs::rename(old_dir, new_dir)?;
         }
 
+        let timings = if self.capture_timings {
+            // cargo writes the report to `cargo-timing.html` and a copy with a timestamp,
+            // remove both so the next target doesn't pick up this report.
+            let timings_dir = build.host_target_dir().join("cargo-timings");
+            let report = std::fs::read(timings_dir.join("cargo-timing.html")).ok();
+            if timings_dir.is_dir() {
+                std::fs::remove_dir_all(&timings_dir)?;
+            }
+            report
+        } else {
+            None
+        };
+
         Ok(FullBuildResult {
             result: BuildResult {
                 rustc_version: self.rustc_version()?,
@@ -822,8 +1425,10 @@ impl RustwideBuilder {
             },
             doc_coverage,
             cargo_metadata,
-            build_log: storage.to_string(),
+            build_log,
             target: target.to_string(),
+            error_category: build_error.as_ref().map(BuildErrorCategory::from_error),
+            timings,
         })
     }
 
@@ -855,7 +1460,7 @@ impl RustwideBuilder {
             // docs.rs, but once it's stable we can remove this flag.
             "-Zrustdoc-scrape-examples".into(),
         ];
-        if let Some(cpu_limit) = self.config.build_cpu_limit {
+        if let Some(cpu_limit) = limits.cpus() {
             cargo_args.push(format!("-j{cpu_limit}"));
         }
         // Cargo has a series of frightening bugs around cross-compiling proc-macros:
@@ -894,12 +1499,25 @@ impl RustwideBuilder {
 
         let mut command = build
             .cargo()
-            .timeout(Some(limits.timeout()))
+            .timeout(Some(limits.timeout_for_target(target)))
             .no_output_timeout(None);
 
         for (key, val) in metadata.environment_variables() {
             command = command.env(key, val);
         }
+        if let Some(profile) = self.build_profile()? {
+            for (key, val) in &profile.env {
+                command = command.env(key, val);
+            }
+        }
+        if let Some(rustflags) = &self.rustflags {
+            info!("building with RUSTFLAGS={}", rustflags);
+            command = command.env("RUSTFLAGS", rustflags);
+        }
+        if self.offline {
+            // `--offline` doesn't reach the cargo started for `-Zbuild-std`
+            command = command.env("CARGO_NET_OFFLINE", "true");
+        }
 
         Ok(command.args(&cargo_args))
     }
@@ -935,12 +1553,121 @@ impl RustwideBuilder {
     }
 }
 
+/// Returns the summed up size of all files in a directory.
+fn directory_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Hashes the relative paths and contents of all files in `path`, in a stable order.
+fn hash_directory(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    for entry in walkdir::WalkDir::new(path).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(path)?;
+            let content = fs::read(entry.path())?;
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update(b"\0");
+            hasher.update((content.len() as u64).to_le_bytes());
+            hasher.update(&content);
+        }
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
 struct FullBuildResult {
     result: BuildResult,
     target: String,
     cargo_metadata: CargoMetadata,
     doc_coverage: Option<DocCoverage>,
     build_log: String,
+    error_category: Option<BuildErrorCategory>,
+    /// The cargo timing report, when [`RustwideBuilder::set_capture_timings`] is enabled
+    timings: Option<Vec<u8>>,
+}
+
+/// Summary of a single build, printed by `build crate --json`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BuildReport {
+    pub name: String,
+    pub version: String,
+    pub successful: bool,
+    pub rustc_version: Option<String>,
+    pub targets: Vec<TargetBuildReport>,
+    pub duration_secs: f64,
+    /// Size of the generated documentation in bytes, before compression.
+    pub doc_size: Option<u64>,
+    /// Whether the documentation was identical to the stored one, and wasn't uploaded again.
+    pub docs_unchanged: bool,
+    /// The differences to the stored documentation, see
+    /// [`RustwideBuilder::set_compare_with_stored`].
+    pub docs_comparison: Option<DocsComparison>,
+    pub error: Option<String>,
+    pub error_category: Option<BuildErrorCategory>,
+}
+
+impl BuildReport {
+    fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            successful: false,
+            rustc_version: None,
+            targets: Vec::new(),
+            duration_secs: 0.0,
+            doc_size: None,
+            docs_unchanged: false,
+            docs_comparison: None,
+            error: None,
+            error_category: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TargetBuildReport {
+    pub target: String,
+    pub successful: bool,
+}
+
+/// Rough classification of why a build failed.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BuildErrorCategory {
+    /// The crate is on the blacklist.
+    Blacklisted,
+    /// The build failed before rustdoc could run, for example while fetching the crate.
+    Preparation,
+    /// The crate doesn't contain a library that could be documented.
+    NotALibrary,
+    /// The sandbox ran out of memory.
+    OutOfMemory,
+    /// The build exceeded its timeout, or didn't produce any output for too long.
+    Timeout,
+    /// cargo or rustdoc exited with an error.
+    BuildFailed,
+    /// The generated documentation exceeded the size limit and wasn't uploaded.
+    DocsTooLarge,
+}
+
+impl BuildErrorCategory {
+    fn from_error(err: &Error) -> Self {
+        match err.downcast_ref::<CommandError>() {
+            Some(CommandError::SandboxOOM) => Self::OutOfMemory,
+            Some(CommandError::Timeout(_) | CommandError::NoOutputFor(_)) => Self::Timeout,
+            _ => Self::BuildFailed,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1000,6 +1727,102 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_hash_directory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("krate"))?;
+        fs::write(dir.path().join("krate/index.html"), "docs")?;
+        fs::write(dir.path().join("search-index.js"), "index")?;
+        let hash = hash_directory(dir.path())?;
+        assert_eq!(hash, hash_directory(dir.path())?);
+
+        fs::write(dir.path().join("search-index.js"), "changed")?;
+        assert_ne!(hash, hash_directory(dir.path())?);
+
+        fs::write(dir.path().join("search-index.js"), "index")?;
+        fs::rename(
+            dir.path().join("krate/index.html"),
+            dir.path().join("krate/all.html"),
+        )?;
+        assert_ne!(hash, hash_directory(dir.path())?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rebuild_with_unchanged_docs() {
+        wrapper(|env| {
+            let crate_ = DUMMY_CRATE_NAME;
+            let version = DUMMY_CRATE_VERSION;
+
+            let mut builder = RustwideBuilder::init(env).unwrap();
+            builder.update_toolchain()?;
+            let first =
+                builder.build_package_with_report(crate_, version, PackageKind::CratesIo)?;
+            assert!(first.successful);
+            assert!(!first.docs_unchanged);
+
+            let second =
+                builder.build_package_with_report(crate_, version, PackageKind::CratesIo)?;
+            assert!(second.successful);
+            assert!(second.docs_unchanged);
+            assert!(env
+                .storage()
+                .exists(&rustdoc_archive_path(crate_, version))?);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_compare_with_stored() {
+        wrapper(|env| {
+            let crate_ = DUMMY_CRATE_NAME;
+            let version = DUMMY_CRATE_VERSION;
+
+            let mut builder = RustwideBuilder::init(env).unwrap();
+            builder.update_toolchain()?;
+            builder.set_compare_with_stored(true, false);
+            let first =
+                builder.build_package_with_report(crate_, version, PackageKind::CratesIo)?;
+            assert!(first.successful);
+            let comparison = first.docs_comparison.unwrap();
+            assert!(!comparison.had_stored_docs);
+            assert!(!comparison.added.is_empty());
+            // without promoting, nothing is stored
+            assert!(!env
+                .storage()
+                .exists(&rustdoc_archive_path(crate_, version))?);
+            let count_builds = || -> Result<i64> {
+                Ok(env
+                    .db()
+                    .conn()
+                    .query_one("SELECT COUNT(*) FROM builds", &[])?
+                    .get(0))
+            };
+            assert_eq!(count_builds()?, 0);
+
+            builder.set_compare_with_stored(true, true);
+            builder.build_package_with_report(crate_, version, PackageKind::CratesIo)?;
+            assert!(env
+                .storage()
+                .exists(&rustdoc_archive_path(crate_, version))?);
+
+            builder.set_compare_with_stored(true, false);
+            let third =
+                builder.build_package_with_report(crate_, version, PackageKind::CratesIo)?;
+            let comparison = third.docs_comparison.unwrap();
+            assert!(comparison.had_stored_docs);
+            assert!(comparison.unchanged > 0);
+            // only the promoted build is recorded
+            assert_eq!(count_builds()?, 1);
+
+            Ok(())
+        });
+    }
+
     #[test]
     #[ignore]
     fn test_build_crate() {
@@ -1203,6 +2026,116 @@ mod tests {
         })
     }
 
+    #[test]
+    #[ignore]
+    fn test_skip_source_archive() {
+        wrapper(|env| {
+            let crate_ = DUMMY_CRATE_NAME;
+            let version = DUMMY_CRATE_VERSION;
+            let mut builder = RustwideBuilder::init(env).unwrap();
+            builder.update_toolchain()?;
+            builder.set_skip_source_archive(true);
+            assert!(builder.build_package(crate_, version, PackageKind::CratesIo)?);
+
+            let storage = env.storage();
+            assert!(storage.exists(&rustdoc_archive_path(crate_, version))?);
+            assert!(!storage.exists(&source_archive_path(crate_, version))?);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_capture_timings() {
+        wrapper(|env| {
+            let crate_ = DUMMY_CRATE_NAME;
+            let version = DUMMY_CRATE_VERSION;
+            let mut builder = RustwideBuilder::init(env).unwrap();
+            builder.update_toolchain()?;
+            builder.set_capture_timings(true);
+            assert!(builder.build_package(crate_, version, PackageKind::CratesIo)?);
+
+            let build_id: i32 = env
+                .db()
+                .conn()
+                .query_one(
+                    "SELECT builds.id
+                 FROM builds
+                 INNER JOIN releases ON releases.id = builds.rid
+                 INNER JOIN crates ON crates.id = releases.crate_id
+                 WHERE crates.name = $1 AND releases.version = $2",
+                    &[&crate_, &version],
+                )?
+                .get(0);
+            assert!(env.storage().exists(&format!(
+                "build-logs/{build_id}/x86_64-unknown-linux-gnu-timings.html"
+            ))?);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_verbose_cargo() {
+        wrapper(|env| {
+            let crate_ = DUMMY_CRATE_NAME;
+            let version = DUMMY_CRATE_VERSION;
+            let mut builder = RustwideBuilder::init(env).unwrap();
+            builder.update_toolchain()?;
+            builder.set_verbose_cargo(true);
+            assert!(builder.build_package(crate_, version, PackageKind::CratesIo)?);
+
+            let build_id: i32 = env
+                .db()
+                .conn()
+                .query_one(
+                    "SELECT builds.id
+                 FROM builds
+                 INNER JOIN releases ON releases.id = builds.rid
+                 INNER JOIN crates ON crates.id = releases.crate_id
+                 WHERE crates.name = $1 AND releases.version = $2",
+                    &[&crate_, &version],
+                )?
+                .get(0);
+            let log = env.storage().get(
+                &format!("build-logs/{build_id}/x86_64-unknown-linux-gnu.txt"),
+                usize::MAX,
+            )?;
+            // cargo only prints the rustdoc invocation when it's very verbose
+            assert!(String::from_utf8(log.content)?.contains("Running `"));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn test_max_doc_size() {
+        wrapper(|env| {
+            env.override_config(|cfg| cfg.build_default_max_doc_size = Some(1));
+
+            let crate_ = DUMMY_CRATE_NAME;
+            let version = DUMMY_CRATE_VERSION;
+            let mut builder = RustwideBuilder::init(env).unwrap();
+            builder.update_toolchain()?;
+            let report =
+                builder.build_package_with_report(crate_, version, PackageKind::CratesIo)?;
+            assert!(!report.successful);
+            assert_eq!(
+                report.error_category,
+                Some(BuildErrorCategory::DocsTooLarge)
+            );
+
+            assert!(!env
+                .storage()
+                .exists(&rustdoc_archive_path(crate_, version))?);
+
+            Ok(())
+        });
+    }
+
     #[test]
     #[ignore]
     fn test_proc_macro() {
@@ -1317,6 +2250,67 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_cached_crate_file() -> Result<()> {
+        let cargo_home = tempfile::tempdir()?;
+        assert!(cached_crate_file(cargo_home.path(), "foo", "1.0.0").is_err());
+
+        let cache = cargo_home
+            .path()
+            .join("registry/cache/index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(&cache)?;
+        fs::write(cache.join("foo-1.0.0.crate"), "")?;
+        assert_eq!(
+            cached_crate_file(cargo_home.path(), "foo", "1.0.0")?,
+            cache.join("foo-1.0.0.crate")
+        );
+        assert!(cached_crate_file(cargo_home.path(), "foo", "1.0.1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn test_build_offline() {
+        wrapper(|env| {
+            // put a crate without dependencies into the cargo cache, like `cargo fetch` would
+            let cache = env
+                .config()
+                .rustwide_workspace
+                .join("cargo-home/registry/cache/index.crates.io-6f17d22bba15001f");
+            fs::create_dir_all(&cache)?;
+            let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+                fs::File::create(cache.join("offline-test-0.1.0.crate"))?,
+                flate2::Compression::default(),
+            ));
+            for (path, content) in [
+                (
+                    "offline-test-0.1.0/Cargo.toml",
+                    "[package]\nname = \"offline-test\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+                ),
+                ("offline-test-0.1.0/src/lib.rs", "//! offline docs\n"),
+            ] {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                archive.append_data(&mut header, path, content.as_bytes())?;
+            }
+            archive.into_inner()?.finish()?;
+
+            let mut builder = RustwideBuilder::init(env).unwrap();
+            builder.update_toolchain()?;
+            builder.set_offline(true);
+            assert!(builder.build_package("offline-test", "0.1.0", PackageKind::CratesIo)?);
+            assert!(std::env::var_os("CARGO_NET_OFFLINE").is_none());
+
+            // not in the cargo cache
+            assert!(!builder.build_package("offline-test", "0.2.0", PackageKind::CratesIo)?);
+
+            Ok(())
+        });
+    }
+
     #[test]
     #[ignore]
     fn test_rustflags_are_passed_to_build_script() {
@@ -0,0 +1,68 @@
+use anyhow::{Context as _, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+struct ToolchainFile {
+    toolchain: ToolchainSection,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolchainSection {
+    channel: Option<String>,
+}
+
+/// Reads the toolchain a package pins in a `rust-toolchain` or `rust-toolchain.toml` file,
+/// the way rustup would.
+///
+/// Returns `None` when there is no such file, or when it doesn't set a channel.
+pub(crate) fn read_toolchain_file(dir: &Path) -> Result<Option<String>> {
+    // rustup prefers the legacy file when both exist
+    for name in ["rust-toolchain", "rust-toolchain.toml"] {
+        let path = dir.join(name);
+        if !path.is_file() {
+            continue;
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        return match toml::from_str::<ToolchainFile>(&content) {
+            Ok(file) => Ok(file.toolchain.channel),
+            // the legacy file can also contain just the name of the toolchain
+            Err(_) if name == "rust-toolchain" && !content.trim().contains(char::is_whitespace) => {
+                Ok(Some(content.trim().to_owned()).filter(|channel| !channel.is_empty()))
+            }
+            Err(err) => Err(err).with_context(|| format!("invalid {}", path.display())),
+        };
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_toolchain_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert_eq!(read_toolchain_file(dir.path())?, None);
+
+        let toml = dir.path().join("rust-toolchain.toml");
+        std::fs::write(&toml, "[toolchain]\nchannel = \"1.70.0\"\n")?;
+        assert_eq!(read_toolchain_file(dir.path())?.as_deref(), Some("1.70.0"));
+
+        std::fs::write(&toml, "[toolchain]\ncomponents = [\"rustfmt\"]\n")?;
+        assert_eq!(read_toolchain_file(dir.path())?, None);
+
+        std::fs::write(&toml, "channel = 1")?;
+        assert!(read_toolchain_file(dir.path()).is_err());
+
+        std::fs::write(dir.path().join("rust-toolchain"), "nightly-2024-01-01\n")?;
+        assert_eq!(
+            read_toolchain_file(dir.path())?.as_deref(),
+            Some("nightly-2024-01-01")
+        );
+
+        Ok(())
+    }
+}
@@ -70,6 +70,66 @@ impl Index {
         Ok(index)
     }
 
+    /// Returns the file names of all crates in the index at the given commit.
+    ///
+    /// The index stores crates in lowercase, so the names might differ in case from the
+    /// crate names.
+    pub(crate) fn crate_file_names(&self, commit: gix::ObjectId) -> Result<Vec<String>> {
+        let repo = gix::open(&self.path).context("opening registry index repository")?;
+        let tree = repo
+            .find_object(commit)?
+            .peel_to_tree()
+            .context("finding the tree of the index commit")?;
+
+        Ok(tree
+            .traverse()
+            .breadthfirst
+            .files()?
+            .into_iter()
+            // crates are stored in directories, files in the root like `config.json`
+            // and hidden directories like `.github` are not crates.
+            .filter(|entry| {
+                entry.mode.is_blob()
+                    && entry.filepath.contains(&b'/')
+                    && !entry.filepath.starts_with(b".")
+            })
+            .filter_map(|entry| {
+                let path = entry.filepath.to_string();
+                path.rsplit('/').next().map(ToOwned::to_owned)
+            })
+            .collect())
+    }
+
+    /// Fetches the index like [`crates_index_diff::Index::peek_changes`] does, and returns
+    /// its latest commit without computing the changes.
+    pub(crate) fn fetch_latest_commit(&self) -> Result<gix::ObjectId> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.path)
+            .args([
+                "fetch",
+                "--quiet",
+                "origin",
+                "+refs/heads/master:refs/remotes/origin/master",
+            ])
+            .output()
+            .context("failed to run `git fetch`")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "failed to fetch the registry index: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let repo = gix::open(&self.path).context("opening registry index repository")?;
+        let id = repo
+            .find_reference("refs/remotes/origin/master")?
+            .peel_to_id_in_place()
+            .context("finding the fetched index commit")?
+            .detach();
+        Ok(id)
+    }
+
     pub fn run_git_gc(&self) {
         let gc = Command::new("git")
             .arg("-C")
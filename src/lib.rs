@@ -2,16 +2,27 @@
 //! documentation of crates for the Rust Programming Language.
 #![allow(clippy::cognitive_complexity)]
 
-pub use self::build_queue::BuildQueue;
+pub use self::build_queue::{
+    parse_release_list, BuildQueue, DeadLetter, IdempotencyKey, ListedRelease, QueueOptions,
+    QueueSnapshotEntry, QueueSource, QueuedCrate, StuckCrate, StuckReason,
+};
 pub use self::config::Config;
 pub use self::context::Context;
+pub use self::docbuilder::BuildErrorCategory;
 pub use self::docbuilder::PackageKind;
 pub use self::docbuilder::RustwideBuilder;
+pub use self::docbuilder::{
+    lint_metadata, lint_release_metadata, resolve_local_metadata, resolve_release_metadata,
+    unpack_crate_file, MetadataProblem, ResolvedMetadata,
+};
 pub use self::index::Index;
 pub use self::metrics::{InstanceMetrics, ServiceMetrics};
 pub use self::registry_api::RegistryApi;
 pub use self::storage::{AsyncStorage, Storage};
-pub use self::web::{start_background_metrics_webserver, start_web_server};
+pub use self::web::{
+    apply_docsrs_theme, start_background_metrics_webserver, start_web_server, write_sitemap,
+    TlsConfig,
+};
 
 mod build_queue;
 pub mod cdn;
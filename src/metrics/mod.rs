@@ -4,6 +4,7 @@ mod macros;
 use self::macros::MetricFromOpts;
 use crate::{cdn, db::Pool, target::TargetAtom, BuildQueue, Config};
 use anyhow::Error;
+use chrono::Utc;
 use dashmap::DashMap;
 use prometheus::proto::MetricFamily;
 use std::{
@@ -108,6 +109,10 @@ metrics! {
 
         /// Number of files uploaded to the storage backend
         pub(crate) uploaded_files_total: IntCounter,
+        /// The latency of storage backend operations
+        pub(crate) storage_operation_time: HistogramVec["operation"],
+        /// Number of failed storage backend operations, not counting missing files
+        pub(crate) failed_storage_operations: IntCounterVec["operation"],
 
         /// The number of attempted files that failed due to a memory limit
         pub(crate) html_rewrite_ooms: IntCounter,
@@ -243,6 +248,7 @@ pub struct ServiceMetrics {
     pub prioritized_crates_count: IntGauge,
     pub failed_crates_count: IntGauge,
     pub queue_is_locked: IntGauge,
+    pub queue_locked_seconds: IntGauge,
     pub queued_crates_count_by_priority: IntGaugeVec,
     pub queued_cdn_invalidations_by_distribution: IntGaugeVec,
 
@@ -278,6 +284,12 @@ impl ServiceMetrics {
                 "Whether the build queue is locked",
                 None,
             )?,
+            queue_locked_seconds: metric_from_opts(
+                &registry,
+                "queue_locked_seconds",
+                "How long the build queue has been locked, 0 when it's not locked",
+                None,
+            )?,
             queued_crates_count_by_priority: metric_from_opts(
                 &registry,
                 "queued_crates_count_by_priority",
@@ -300,6 +312,11 @@ impl ServiceMetrics {
         config: &Config,
     ) -> Result<Vec<MetricFamily>, Error> {
         self.queue_is_locked.set(queue.is_locked()? as i64);
+        self.queue_locked_seconds.set(
+            queue
+                .locked_since()?
+                .map_or(0, |since| (Utc::now() - since).num_seconds().max(0)),
+        );
         self.queued_crates_count.set(queue.pending_count()? as i64);
         self.prioritized_crates_count
             .set(queue.prioritized_count()? as i64);
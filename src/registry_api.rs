@@ -25,6 +25,14 @@ pub struct CrateData {
     pub(crate) owners: Vec<CrateOwner>,
 }
 
+/// The description, keywords and categories of a crate, as currently shown by the registry.
+#[derive(Debug, Default)]
+pub struct CrateMetadata {
+    pub(crate) description: Option<String>,
+    pub(crate) keywords: Vec<String>,
+    pub(crate) categories: Vec<String>,
+}
+
 #[derive(Debug)]
 pub(crate) struct ReleaseData {
     pub(crate) release_time: DateTime<Utc>,
@@ -103,6 +111,88 @@ impl RegistryApi {
         })
     }
 
+    /// Get the total download count of a crate from the registry's API
+    #[instrument(skip(self))]
+    pub async fn get_crate_downloads(&self, name: &str) -> Result<i64> {
+        let url = {
+            let mut url = self.api_base.clone();
+            url.path_segments_mut()
+                .map_err(|()| anyhow!("Invalid API url"))?
+                .extend(&["api", "v1", "crates", name]);
+            url
+        };
+
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "crate")]
+            krate: CrateResponse,
+        }
+
+        #[derive(Deserialize)]
+        struct CrateResponse {
+            #[serde(default)]
+            downloads: i64,
+        }
+
+        let response: Response = retry_async(
+            || async {
+                Ok(self
+                    .client
+                    .get(url.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?)
+            },
+            self.max_retries,
+        )
+        .await?
+        .json()
+        .await
+        .context(format!("Failed to get download count for {name}"))?;
+
+        Ok(response.krate.downloads)
+    }
+
+    /// Get all published versions of a crate from the registry's API, including yanked ones
+    #[instrument(skip(self))]
+    pub async fn get_crate_versions(&self, name: &str) -> Result<Vec<Version>> {
+        let url = {
+            let mut url = self.api_base.clone();
+            url.path_segments_mut()
+                .map_err(|()| anyhow!("Invalid API url"))?
+                .extend(&["api", "v1", "crates", name, "versions"]);
+            url
+        };
+
+        #[derive(Deserialize)]
+        struct Response {
+            versions: Vec<VersionData>,
+        }
+
+        #[derive(Deserialize)]
+        struct VersionData {
+            num: Version,
+        }
+
+        let response: Response = retry_async(
+            || async {
+                Ok(self
+                    .client
+                    .get(url.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?)
+            },
+            self.max_retries,
+        )
+        .await?
+        .json()
+        .await
+        .context(format!("Failed to get versions for {name}"))?;
+
+        Ok(response.versions.into_iter().map(|data| data.num).collect())
+    }
+
     /// Get release_time, yanked and downloads from the registry's API
     async fn get_release_time_yanked_downloads(
         &self,
@@ -158,6 +248,52 @@ impl RegistryApi {
         Ok((version.created_at, version.yanked, version.downloads))
     }
 
+    /// Get the description, keywords and categories of a crate from the registry's API
+    #[instrument(skip(self))]
+    pub async fn get_crate_metadata(&self, name: &str) -> Result<CrateMetadata> {
+        let url = {
+            let mut url = self.api_base.clone();
+            url.path_segments_mut()
+                .map_err(|()| anyhow!("Invalid API url"))?
+                .extend(&["api", "v1", "crates", name]);
+            url
+        };
+
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(rename = "crate")]
+            krate: CrateResponse,
+        }
+
+        #[derive(Deserialize)]
+        struct CrateResponse {
+            description: Option<String>,
+            keywords: Option<Vec<String>>,
+            categories: Option<Vec<String>>,
+        }
+
+        let response: Response = retry_async(
+            || async {
+                Ok(self
+                    .client
+                    .get(url.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?)
+            },
+            self.max_retries,
+        )
+        .await?
+        .json()
+        .await?;
+
+        Ok(CrateMetadata {
+            description: response.krate.description,
+            keywords: response.krate.keywords.unwrap_or_default(),
+            categories: response.krate.categories.unwrap_or_default(),
+        })
+    }
+
     /// Fetch owners from the registry's API
     async fn get_owners(&self, name: &str) -> Result<Vec<CrateOwner>> {
         let url = {
@@ -10,8 +10,8 @@ use serde::Deserialize;
 use tracing::{trace, warn};
 
 use crate::repositories::{
-    FetchRepositoriesResult, RateLimitReached, Repository, RepositoryForge, RepositoryName,
-    APP_USER_AGENT,
+    rate_limit_error, FetchRepositoriesResult, RateLimitReached, Repository, RepositoryForge,
+    RepositoryName, APP_USER_AGENT,
 };
 
 const GRAPHQL_UPDATE: &str = "query($ids: [ID!]!) {
@@ -144,7 +144,7 @@ impl RepositoryForge for GitHub {
                 data.rate_limit.remaining
             );
             if data.rate_limit.remaining < self.github_updater_min_rate_limit {
-                return Err(RateLimitReached.into());
+                return Err(RateLimitReached::default().into());
             }
         }
 
@@ -157,7 +157,7 @@ impl RepositoryForge for GitHub {
                     ret.missing.push(node_ids[*idx as usize].clone());
                 }
                 ("RATE_LIMITED", []) => {
-                    return Err(RateLimitReached.into());
+                    return Err(RateLimitReached::default().into());
                 }
                 _ => anyhow::bail!("error updating repositories: {}", error.message),
             }
@@ -191,7 +191,7 @@ impl GitHub {
         query: &str,
         variables: impl serde::Serialize,
     ) -> Result<GraphResponse<T>> {
-        Ok(self
+        let response = self
             .client
             .post(&self.endpoint)
             .json(&serde_json::json!({
@@ -199,10 +199,12 @@ impl GitHub {
                 "variables": variables,
             }))
             .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+            .await?;
+        if let Some(err) = rate_limit_error(&response) {
+            return Err(err.into());
+        }
+
+        Ok(response.error_for_status()?.json().await?)
     }
 }
 
@@ -323,6 +325,37 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_rate_limit_status() {
+        crate::test::async_wrapper(|env| async move {
+            let mut config = env.base_config();
+            config.github_accesstoken = Some("qsjdnfqdq".to_owned());
+            let (mut server, updater) = mock_server_and_github(&config).await;
+
+            let _m1 = server
+                .mock("POST", "/graphql")
+                .with_status(429)
+                .with_header("retry-after", "30")
+                .create();
+
+            match updater
+                .fetch_repository(
+                    &repository_name("https://github.com/foo/bar").expect("repository_name failed"),
+                )
+                .await
+            {
+                Err(e) => assert_eq!(
+                    e.downcast_ref::<RateLimitReached>()
+                        .expect("Expected Err(RateLimitReached)")
+                        .retry_after,
+                    Some(std::time::Duration::from_secs(30))
+                ),
+                x => panic!("Expected Err(RateLimitReached), found: {x:?}"),
+            }
+            Ok(())
+        });
+    }
+
     #[test]
     fn not_found() {
         crate::test::async_wrapper(|env| async move {
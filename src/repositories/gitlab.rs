@@ -11,8 +11,8 @@ use std::str::FromStr;
 use tracing::warn;
 
 use crate::repositories::{
-    FetchRepositoriesResult, RateLimitReached, Repository, RepositoryForge, RepositoryName,
-    APP_USER_AGENT,
+    rate_limit_error, FetchRepositoriesResult, RateLimitReached, Repository, RepositoryForge,
+    RepositoryName, APP_USER_AGENT,
 };
 
 const GRAPHQL_UPDATE: &str = "query($ids: [ID!]!) {
@@ -119,7 +119,7 @@ impl RepositoryForge for GitLab {
                 issues: repo.open_issues_count.unwrap_or(0),
             }))
         } else if rate_limit.map(|x| x < 1).unwrap_or(false) {
-            Err(RateLimitReached.into())
+            Err(RateLimitReached::default().into())
         } else {
             Ok(None)
         }
@@ -163,7 +163,7 @@ impl RepositoryForge for GitLab {
             }
 
             if ret.present.is_empty() && rate_limit.map(|x| x < 1).unwrap_or(false) {
-                return Err(RateLimitReached.into());
+                return Err(RateLimitReached::default().into());
             }
 
             // Those nodes were not returned by gitlab, meaning they don't exist (anymore?).
@@ -171,7 +171,7 @@ impl RepositoryForge for GitLab {
 
             Ok(ret)
         } else if rate_limit.map(|x| x < 1).unwrap_or(false) {
-            Err(RateLimitReached.into())
+            Err(RateLimitReached::default().into())
         } else {
             anyhow::bail!("no data")
         }
@@ -192,8 +192,11 @@ impl GitLab {
                 "variables": variables,
             }))
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
+        if let Some(err) = rate_limit_error(&res) {
+            return Err(err.into());
+        }
+        let res = res.error_for_status()?;
         // There are a few other header values that might interesting so keeping them here:
         // * RateLimit-Observed: '1'
         // * RateLimit-Remaining: '1999'
@@ -309,6 +312,25 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_rate_limit_forbidden() {
+        crate::test::async_wrapper(|_env| async move {
+            let (mut server, updater) = mock_server_and_gitlab().await;
+
+            let _m1 = server
+                .mock("POST", "/api/graphql")
+                .with_status(403)
+                .with_header("RateLimit-Remaining", "0")
+                .create();
+
+            match updater.fetch_repositories(&[String::new()]).await {
+                Err(e) if e.downcast_ref::<RateLimitReached>().is_some() => {}
+                x => panic!("Expected Err(RateLimitReached), found: {x:?}"),
+            }
+            Ok(())
+        })
+    }
+
     #[test]
     fn not_found() {
         crate::test::async_wrapper(|_env| async move {
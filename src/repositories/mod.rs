@@ -11,9 +11,44 @@ pub const APP_USER_AGENT: &str = concat!(
     include_str!(concat!(env!("OUT_DIR"), "/git_version"))
 );
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Default, thiserror::Error)]
 #[error("rate limit reached")]
-struct RateLimitReached;
+struct RateLimitReached {
+    /// How long to wait before the API can be used again, when the API told us
+    retry_after: Option<std::time::Duration>,
+}
+
+/// Returns the error of an API response that was rejected because of the rate limit, with a
+/// 429 status, or a 403 status and rate limit headers.
+///
+/// The time to wait is taken from the `Retry-After` header, or from the reset time of the
+/// rate limit in the `X-RateLimit-Reset` (GitHub) or `RateLimit-Reset` (GitLab) header.
+fn rate_limit_error(response: &reqwest::Response) -> Option<RateLimitReached> {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    let retry_after = header("retry-after")
+        .map(std::time::Duration::from_secs)
+        .or_else(|| {
+            let reset = header("x-ratelimit-reset").or_else(|| header("ratelimit-reset"))?;
+            let now = u64::try_from(chrono::Utc::now().timestamp()).unwrap_or(0);
+            Some(std::time::Duration::from_secs(reset.saturating_sub(now)))
+        });
+    let exhausted =
+        header("x-ratelimit-remaining") == Some(0) || header("ratelimit-remaining") == Some(0);
+
+    let rate_limited = match response.status() {
+        reqwest::StatusCode::TOO_MANY_REQUESTS => true,
+        reqwest::StatusCode::FORBIDDEN => retry_after.is_some() || exhausted,
+        _ => false,
+    };
+    rate_limited.then_some(RateLimitReached { retry_after })
+}
 
 mod github;
 mod gitlab;
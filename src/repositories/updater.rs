@@ -4,13 +4,23 @@ use crate::utils::MetadataPackage;
 use crate::{db::Pool, Config};
 use axum::async_trait;
 use chrono::{DateTime, Utc};
-use futures_util::stream::TryStreamExt;
+use futures_util::stream::{self, TryStreamExt};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tracing::{debug, info, trace, warn};
 
+/// How often `backfill_repositories` retries a repository after the rate limit was reached.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// How long `backfill_repositories` waits after the rate limit was reached, when the API
+/// doesn't say how long.
+const DEFAULT_RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+
 #[async_trait]
 pub trait RepositoryForge {
     /// Result used both as the `host` column in the DB and to match repository URLs during
@@ -130,7 +140,10 @@ impl RepositoryStatsUpdater {
             };
             return match res {
                 Ok(repo_id) => Ok(Some(repo_id)),
-                Err(err) => anyhow::bail!("failed to collect `{}` stats: {}", updater.host(), err),
+                // keep the error, so a reached rate limit can be detected
+                Err(err) => {
+                    Err(err.context(format!("failed to collect `{}` stats", updater.host())))
+                }
             };
         }
         // It means that none of our updaters have a matching host.
@@ -190,7 +203,22 @@ impl RepositoryStatsUpdater {
         Ok(())
     }
 
-    pub async fn backfill_repositories(&self) -> Result<()> {
+    /// Loads the repository stats of releases that don't have any yet, fetching up to
+    /// `concurrency` repositories at the same time.
+    ///
+    /// When the rate limit of the API is reached, all fetches wait until the API can be used
+    /// again, and the repository is retried up to [`MAX_RATE_LIMIT_RETRIES`] times.
+    ///
+    /// No new repositories are fetched after `deadline`, the ones that are being fetched are
+    /// still finished. Returns how many repositories were loaded.
+    pub async fn backfill_repositories(
+        &self,
+        concurrency: usize,
+        deadline: Option<Instant>,
+    ) -> Result<usize> {
+        let loaded = &AtomicUsize::new(0);
+        // when the fetches can continue after the rate limit was reached
+        let backoff_until = &Mutex::new(None::<Instant>);
         let mut conn = self.pool.get_async().await?;
         for updater in &self.updaters {
             info!(
@@ -208,46 +236,95 @@ impl RepositoryStatsUpdater {
             .fetch_all(&mut *conn)
             .await?;
 
-            let mut missing_urls = HashSet::new();
+            // releases pointing to the same repository only need to load it once.
+            let mut releases_by_url: HashMap<&str, Vec<_>> = HashMap::new();
             for row in &needs_backfilling {
-                let url = if let Some(ref url) = row.repository_url {
-                    url
-                } else {
-                    continue;
-                };
-
-                if missing_urls.contains(&url) {
-                    debug!(
-                        "{} {} points to a known missing repo",
-                        row.name, row.version
-                    );
-                } else if let Some(node_id) = self.load_repository_inner(&mut conn, url).await? {
-                    sqlx::query!(
-                        "UPDATE releases SET repository_id = $1 WHERE id = $2;",
-                        node_id,
-                        row.id,
-                    )
-                    .execute(&mut *conn)
-                    .await?;
-                    info!(
-                        "backfilled `{}` repositories for {} {}",
-                        updater.host(),
-                        row.name,
-                        row.version,
-                    );
-                } else {
-                    debug!(
-                        "{} {} does not point to a {} repository",
-                        row.name,
-                        row.version,
-                        updater.host(),
-                    );
-                    missing_urls.insert(url);
+                if let Some(ref url) = row.repository_url {
+                    releases_by_url.entry(url).or_default().push(row);
                 }
             }
+
+            stream::iter(releases_by_url.into_iter().map(Ok::<_, anyhow::Error>))
+                .try_for_each_concurrent(concurrency, |(url, rows)| async move {
+                    let mut retries = 0;
+                    let (mut conn, loaded_repository) = loop {
+                        let backoff = backoff_until
+                            .lock()
+                            .unwrap()
+                            .map(|until| until.saturating_duration_since(Instant::now()));
+                        if let Some(backoff) = backoff.filter(|backoff| !backoff.is_zero()) {
+                            let backoff = deadline.map_or(backoff, |deadline| {
+                                backoff.min(deadline.saturating_duration_since(Instant::now()))
+                            });
+                            tokio::time::sleep(backoff).await;
+                        }
+                        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                            return Ok(());
+                        }
+                        let mut conn = self.pool.get_async().await?;
+
+                        match self.load_repository_inner(&mut conn, url).await {
+                            Err(err)
+                                if retries < MAX_RATE_LIMIT_RETRIES
+                                    && err.is::<RateLimitReached>() =>
+                            {
+                                let backoff = err
+                                    .downcast_ref::<RateLimitReached>()
+                                    .and_then(|err| err.retry_after)
+                                    .unwrap_or(DEFAULT_RATE_LIMIT_BACKOFF);
+                                warn!(
+                                    "`{}` rate limit reached, waiting {}s before fetching {url} again",
+                                    updater.host(),
+                                    backoff.as_secs(),
+                                );
+                                let until = Instant::now() + backoff;
+                                let mut current = backoff_until.lock().unwrap();
+                                *current = Some(current.map_or(until, |current| current.max(until)));
+                                retries += 1;
+                            }
+                            result => break (conn, result?),
+                        }
+                    };
+
+                    let node_id = match loaded_repository {
+                        Some(node_id) => {
+                            loaded.fetch_add(1, Ordering::Relaxed);
+                            node_id
+                        }
+                        None => {
+                            for row in rows {
+                                debug!(
+                                    "{} {} does not point to a {} repository",
+                                    row.name,
+                                    row.version,
+                                    updater.host(),
+                                );
+                            }
+                            return Ok(());
+                        }
+                    };
+
+                    for row in rows {
+                        sqlx::query!(
+                            "UPDATE releases SET repository_id = $1 WHERE id = $2;",
+                            node_id,
+                            row.id,
+                        )
+                        .execute(&mut *conn)
+                        .await?;
+                        info!(
+                            "backfilled `{}` repositories for {} {}",
+                            updater.host(),
+                            row.name,
+                            row.version,
+                        );
+                    }
+                    Ok(())
+                })
+                .await?;
         }
 
-        Ok(())
+        Ok(loaded.load(Ordering::Relaxed))
     }
 
     async fn store_repository(
@@ -4,7 +4,7 @@ use bzip2::Compression;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashSet,
-    io::{self, Read},
+    io::{self, Read, Write},
 };
 use strum::{Display, EnumIter, EnumString, FromRepr};
 
@@ -77,6 +77,17 @@ pub fn decompress(
     Ok(buffer.into_inner())
 }
 
+/// Wraps `writer` so the content written to it is decompressed on the fly.
+pub(super) fn decompressing_writer<'a>(
+    writer: impl Write + Send + 'a,
+    algorithm: CompressionAlgorithm,
+) -> Result<Box<dyn Write + Send + 'a>, Error> {
+    Ok(match algorithm {
+        CompressionAlgorithm::Zstd => Box::new(zstd::stream::write::Decoder::new(writer)?),
+        CompressionAlgorithm::Bzip2 => Box::new(bzip2::write::BzDecoder::new(writer)),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,4 +1,4 @@
-use super::{Blob, FileRange};
+use super::{Blob, FileRange, StreamingBlob};
 use crate::{db::Pool, error::Result, InstanceMetrics};
 use chrono::{DateTime, Utc};
 use futures_util::stream::{Stream, TryStreamExt};
@@ -165,6 +165,54 @@ impl DatabaseBackend {
         Ok(())
     }
 
+    /// Postgres can't stream the content of a single row, so the whole file is loaded
+    /// and returned as a single chunk.
+    pub(super) async fn get_stream(&self, path: &str) -> Result<StreamingBlob<'static>> {
+        let file = sqlx::query!(
+            "SELECT compression, content FROM files WHERE path = $1",
+            path
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(super::PathNotFoundError)?;
+
+        Ok(StreamingBlob {
+            compression: file.compression.map(|i| {
+                i.try_into()
+                    .expect("invalid compression algorithm stored in database")
+            }),
+            content: Box::pin(futures_util::stream::once(async {
+                Ok(file.content.unwrap_or_default())
+            })),
+        })
+    }
+
+    pub(super) async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let copied = sqlx::query!(
+            "INSERT INTO files (path, mime, content, compression, public)
+             SELECT $2, mime, content, compression, public
+             FROM files
+             WHERE path = $1
+             ON CONFLICT (path) DO UPDATE
+                SET
+                    mime = EXCLUDED.mime,
+                    content = EXCLUDED.content,
+                    compression = EXCLUDED.compression,
+                    public = EXCLUDED.public,
+                    date_updated = NOW()",
+            from,
+            to,
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if copied == 0 {
+            return Err(super::PathNotFoundError.into());
+        }
+        Ok(())
+    }
+
     pub(super) async fn list_prefix<'a>(
         &'a self,
         prefix: &'a str,
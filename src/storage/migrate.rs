@@ -0,0 +1,94 @@
+use super::{AsyncStorage, StorageBackend};
+use crate::error::Result;
+use futures_util::stream::TryStreamExt;
+use tracing::{info, instrument, warn};
+
+/// What [`migrate_storage`] did.
+#[derive(Debug, Default)]
+pub struct StorageMigrationSummary {
+    pub copied_objects: u64,
+    /// The stored size, files are copied without decompressing them.
+    pub copied_bytes: u64,
+    /// Files that already existed in the destination, with `resume`.
+    pub skipped_objects: u64,
+    /// The paths that couldn't be copied, with their error.
+    pub failed: Vec<(String, anyhow::Error)>,
+}
+
+/// Copies all files under `prefix` from one storage backend to another, up to `concurrency`
+/// files at the same time.
+///
+/// With `resume`, files that already exist in the destination are skipped, so an aborted
+/// migration can be continued. Failed files are collected instead of aborting the migration.
+/// Public access isn't copied, it's set again when the files are requested.
+#[instrument(skip(from, to))]
+pub async fn migrate_storage(
+    from: &AsyncStorage,
+    to: &AsyncStorage,
+    prefix: &str,
+    concurrency: usize,
+    resume: bool,
+) -> Result<StorageMigrationSummary> {
+    let mut summary = StorageMigrationSummary::default();
+
+    let mut results = from
+        .list_prefix(prefix)
+        .await
+        .map_ok(|path| async move {
+            let result = copy_file(from, to, &path, resume).await;
+            Ok::<_, anyhow::Error>((path, result))
+        })
+        .try_buffer_unordered(concurrency);
+
+    while let Some((path, result)) = results.try_next().await? {
+        match result {
+            Ok(Some(bytes)) => {
+                summary.copied_objects += 1;
+                summary.copied_bytes += bytes;
+            }
+            Ok(None) => summary.skipped_objects += 1,
+            Err(err) => {
+                warn!(%path, ?err, "failed to migrate file");
+                summary.failed.push((path, err));
+            }
+        }
+
+        let processed = summary.copied_objects + summary.skipped_objects;
+        if processed > 0 && processed % 10_000 == 0 {
+            info!(
+                copied = summary.copied_objects,
+                skipped = summary.skipped_objects,
+                failed = summary.failed.len(),
+                "migrating storage"
+            );
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Returns the stored size of the copied file, or `None` when it was skipped.
+async fn copy_file(
+    from: &AsyncStorage,
+    to: &AsyncStorage,
+    path: &str,
+    resume: bool,
+) -> Result<Option<u64>> {
+    if resume && to.exists(path).await? {
+        return Ok(None);
+    }
+
+    // keep the stored compression, the files don't have to be recompressed
+    let blob = from
+        .instrumented("get", async {
+            match &from.backend {
+                StorageBackend::Database(db) => db.get(path, usize::MAX, None).await,
+                StorageBackend::S3(s3) => s3.get(path, usize::MAX, None).await,
+            }
+        })
+        .await?;
+    let bytes = blob.content.len() as u64;
+    to.store_inner(vec![blob]).await?;
+
+    Ok(Some(bytes))
+}
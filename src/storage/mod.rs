@@ -1,25 +1,33 @@
 mod archive_index;
 mod compression;
 mod database;
+mod migrate;
 mod s3;
 
+use self::compression::decompressing_writer;
 pub use self::compression::{compress, decompress, CompressionAlgorithm, CompressionAlgorithms};
 use self::database::DatabaseBackend;
+pub use self::migrate::{migrate_storage, StorageMigrationSummary};
 use self::s3::S3Backend;
-use crate::{db::Pool, error::Result, utils::spawn_blocking, Config, InstanceMetrics};
+use crate::{
+    db::Pool, error::Result, metrics::duration_to_seconds, utils::spawn_blocking, Config,
+    InstanceMetrics,
+};
 use anyhow::{anyhow, ensure};
 use chrono::{DateTime, Utc};
 use fn_error_context::context;
-use futures_util::stream::BoxStream;
+use futures_util::stream::{BoxStream, TryStreamExt};
 use path_slash::PathExt;
 use std::{
     collections::{HashMap, HashSet},
     ffi::OsStr,
     fmt, fs,
-    io::{self, BufReader},
+    future::Future,
+    io::{self, BufReader, Write},
     ops::RangeInclusive,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Instant,
 };
 use tokio::{io::AsyncWriteExt, runtime::Runtime};
 use tracing::{error, info_span, instrument, trace};
@@ -30,6 +38,12 @@ type FileRange = RangeInclusive<u64>;
 #[error("path not found")]
 pub struct PathNotFoundError;
 
+/// The storage backend can't be reached or is overloaded, the operation can be retried
+/// later. Attached as context to the error of the backend.
+#[derive(Debug, thiserror::Error)]
+#[error("the storage is unavailable")]
+pub struct StorageUnavailableError;
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct Blob {
     pub(crate) path: String,
@@ -45,6 +59,12 @@ impl Blob {
     }
 }
 
+/// A stored file whose content is read in chunks, instead of all at once.
+pub(crate) struct StreamingBlob<'a> {
+    pub(crate) compression: Option<CompressionAlgorithm>,
+    pub(crate) content: BoxStream<'a, Result<Vec<u8>>>,
+}
+
 fn get_file_list_from_dir<P: AsRef<Path>>(path: P, files: &mut Vec<PathBuf>) -> Result<()> {
     let path = path.as_ref();
 
@@ -83,10 +103,10 @@ pub fn get_file_list<P: AsRef<Path> + std::fmt::Debug>(path: P) -> Result<Vec<Pa
 
 #[derive(Debug, thiserror::Error)]
 #[error("invalid storage backend")]
-pub(crate) struct InvalidStorageBackendError;
+pub struct InvalidStorageBackendError;
 
-#[derive(Debug)]
-pub(crate) enum StorageKind {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
     Database,
     S3,
 }
@@ -111,6 +131,7 @@ enum StorageBackend {
 pub struct AsyncStorage {
     backend: StorageBackend,
     config: Arc<Config>,
+    metrics: Arc<InstanceMetrics>,
 }
 
 impl AsyncStorage {
@@ -118,42 +139,89 @@ impl AsyncStorage {
         pool: Pool,
         metrics: Arc<InstanceMetrics>,
         config: Arc<Config>,
+    ) -> Result<Self> {
+        let kind = config.storage_backend;
+        Self::with_backend(kind, pool, metrics, config).await
+    }
+
+    /// Like [`AsyncStorage::new`], but uses `kind` instead of the configured backend.
+    pub async fn with_backend(
+        kind: StorageKind,
+        pool: Pool,
+        metrics: Arc<InstanceMetrics>,
+        config: Arc<Config>,
     ) -> Result<Self> {
         Ok(Self {
             config: config.clone(),
-            backend: match config.storage_backend {
+            backend: match kind {
                 StorageKind::Database => {
                     StorageBackend::Database(DatabaseBackend::new(pool, metrics))
                 }
                 StorageKind::S3 => {
-                    StorageBackend::S3(Box::new(S3Backend::new(metrics, &config).await?))
+                    StorageBackend::S3(Box::new(S3Backend::new(metrics.clone(), &config).await?))
                 }
             },
+            metrics,
         })
     }
 
+    /// Runs a call to the storage backend, recording its latency and failure in the metrics.
+    async fn instrumented<T>(
+        &self,
+        operation: &str,
+        call: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let result = call.await;
+
+        self.metrics
+            .storage_operation_time
+            .with_label_values(&[operation])
+            .observe(duration_to_seconds(start.elapsed()));
+        if let Err(err) = &result {
+            // missing files are expected, they aren't a problem with the backend
+            if !err.is::<PathNotFoundError>() {
+                self.metrics
+                    .failed_storage_operations
+                    .with_label_values(&[operation])
+                    .inc();
+            }
+        }
+
+        result
+    }
+
     #[instrument]
     pub(crate) async fn exists(&self, path: &str) -> Result<bool> {
-        match &self.backend {
-            StorageBackend::Database(db) => db.exists(path).await,
-            StorageBackend::S3(s3) => s3.exists(path).await,
-        }
+        self.instrumented("exists", async {
+            match &self.backend {
+                StorageBackend::Database(db) => db.exists(path).await,
+                StorageBackend::S3(s3) => s3.exists(path).await,
+            }
+        })
+        .await
     }
 
     #[instrument]
     pub(crate) async fn get_public_access(&self, path: &str) -> Result<bool> {
-        match &self.backend {
-            StorageBackend::Database(db) => db.get_public_access(path).await,
-            StorageBackend::S3(s3) => s3.get_public_access(path).await,
-        }
+        self.instrumented("get_public_access", async {
+            match &self.backend {
+                StorageBackend::Database(db) => db.get_public_access(path).await,
+                StorageBackend::S3(s3) => s3.get_public_access(path).await,
+            }
+        })
+        .await
     }
 
     #[instrument]
     pub(crate) async fn set_public_access(&self, path: &str, public: bool) -> Result<()> {
-        match &self.backend {
-            StorageBackend::Database(db) => db.set_public_access(path, public).await,
-            StorageBackend::S3(s3) => s3.set_public_access(path, public).await,
-        }
+        self.instrumented("set_public_access", async {
+            match &self.backend {
+                StorageBackend::Database(db) => db.set_public_access(path, public).await,
+                StorageBackend::S3(s3) => s3.set_public_access(path, public).await,
+            }
+        })
+        .await
     }
 
     fn max_file_size_for(&self, path: &str) -> usize {
@@ -270,10 +338,14 @@ impl AsyncStorage {
 
     #[instrument]
     pub(crate) async fn get(&self, path: &str, max_size: usize) -> Result<Blob> {
-        let mut blob = match &self.backend {
-            StorageBackend::Database(db) => db.get(path, max_size, None).await,
-            StorageBackend::S3(s3) => s3.get(path, max_size, None).await,
-        }?;
+        let mut blob = self
+            .instrumented("get", async {
+                match &self.backend {
+                    StorageBackend::Database(db) => db.get(path, max_size, None).await,
+                    StorageBackend::S3(s3) => s3.get(path, max_size, None).await,
+                }
+            })
+            .await?;
         if let Some(alg) = blob.compression {
             blob.content = decompress(blob.content.as_slice(), alg, max_size)?;
             blob.compression = None;
@@ -281,6 +353,31 @@ impl AsyncStorage {
         Ok(blob)
     }
 
+    /// Writes the decompressed content of a file to `writer`, without loading the whole file
+    /// into memory.
+    #[instrument(skip(writer))]
+    pub(crate) async fn copy_to(&self, path: &str, writer: &mut (dyn Write + Send)) -> Result<()> {
+        let mut blob = self
+            .instrumented("get_stream", async {
+                match &self.backend {
+                    StorageBackend::Database(db) => db.get_stream(path).await,
+                    StorageBackend::S3(s3) => s3.get_stream(path).await,
+                }
+            })
+            .await?;
+
+        let mut writer: Box<dyn Write + Send> = match blob.compression {
+            Some(alg) => decompressing_writer(writer, alg)?,
+            None => Box::new(writer),
+        };
+        while let Some(chunk) = blob.content.try_next().await? {
+            writer.write_all(&chunk)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
     #[instrument]
     pub(super) async fn get_range(
         &self,
@@ -289,10 +386,14 @@ impl AsyncStorage {
         range: FileRange,
         compression: Option<CompressionAlgorithm>,
     ) -> Result<Blob> {
-        let mut blob = match &self.backend {
-            StorageBackend::Database(db) => db.get(path, max_size, Some(range)).await,
-            StorageBackend::S3(s3) => s3.get(path, max_size, Some(range)).await,
-        }?;
+        let mut blob = self
+            .instrumented("get_range", async {
+                match &self.backend {
+                    StorageBackend::Database(db) => db.get(path, max_size, Some(range)).await,
+                    StorageBackend::S3(s3) => s3.get(path, max_size, Some(range)).await,
+                }
+            })
+            .await?;
         // `compression` represents the compression of the file-stream inside the archive.
         // We don't compress the whole archive, so the encoding of the archive's blob is irrelevant
         // here.
@@ -428,16 +529,11 @@ impl AsyncStorage {
                     let alg = CompressionAlgorithm::default();
                     let compressed_index_content = {
                         let _span = info_span!("create_archive_index", %remote_index_path).entered();
-
-                        fs::create_dir_all(&temp_dir)?;
-                        let local_index_path =
-                            tempfile::NamedTempFile::new_in(&temp_dir)?.into_temp_path();
-                        archive_index::create(
+                        create_compressed_archive_index(
                             &mut io::Cursor::new(&mut zip_content),
-                            &local_index_path,
-                        )?;
-
-                        compress(BufReader::new(fs::File::open(&local_index_path)?), alg)?
+                            &temp_dir,
+                            alg,
+                        )?
                     };
                     Ok((
                         zip_content,
@@ -472,6 +568,65 @@ impl AsyncStorage {
         Ok((file_paths, file_alg))
     }
 
+    /// Recreates the index of an archive that is already stored, from the archive itself.
+    /// Returns the paths of the files in the new index.
+    ///
+    /// Locally cached copies of the old index are removed. Other servers keep their copies
+    /// until the `latest_build_id` they are cached for changes, callers have to update
+    /// `releases.archive_index_regeneration` for that.
+    #[instrument(skip(self))]
+    pub async fn regenerate_archive_index(&self, archive_path: &str) -> Result<Vec<String>> {
+        let zip_content = self.get(archive_path, usize::MAX).await?.content;
+
+        let alg = CompressionAlgorithm::default();
+        let (compressed_index_content, file_paths) = spawn_blocking({
+            let temp_dir = self.config.temp_dir.clone();
+            move || {
+                let mut zip = io::Cursor::new(zip_content);
+                let mut file_paths: Vec<String> = zip::ZipArchive::new(&mut zip)?
+                    .file_names()
+                    .map(ToOwned::to_owned)
+                    .collect();
+                file_paths.sort();
+                let index = create_compressed_archive_index(&mut zip, &temp_dir, alg)?;
+                Ok((index, file_paths))
+            }
+        })
+        .await?;
+
+        self.store_inner(vec![Blob {
+            path: format!("{archive_path}.index"),
+            mime: "application/octet-stream".to_owned(),
+            content: compressed_index_content,
+            compression: Some(alg),
+            date_updated: Utc::now(),
+        }])
+        .await?;
+
+        // the local copies are named `{archive_path}.{build_id}.index`
+        let local_archive_path = self.config.local_archive_cache_path.join(archive_path);
+        if let (Some(dir), Some(file_name)) = (
+            local_archive_path.parent(),
+            local_archive_path.file_name().and_then(OsStr::to_str),
+        ) {
+            let prefix = format!("{file_name}.");
+            let mut entries = match tokio::fs::read_dir(dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(file_paths),
+                Err(err) => return Err(err.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if name.starts_with(&prefix) && name.ends_with(".index") {
+                    tokio::fs::remove_file(entry.path()).await?;
+                }
+            }
+        }
+
+        Ok(file_paths)
+    }
+
     // Store all files in `root_dir` into the backend under `prefix`.
     //
     // This returns (map<filename, mime type>, set<compression algorithms>).
@@ -557,11 +712,22 @@ impl AsyncStorage {
         Ok(alg)
     }
 
+    /// Stores a CSS file that's added to the rustdoc output of `name`, returns the path
+    /// to use for [`Overrides::custom_css`](crate::db::Overrides::custom_css).
+    pub async fn store_custom_css(&self, name: &str, css: Vec<u8>) -> Result<String> {
+        let path = custom_css_path(name);
+        self.store_one(path.clone(), css).await?;
+        Ok(path)
+    }
+
     async fn store_inner(&self, batch: Vec<Blob>) -> Result<()> {
-        match &self.backend {
-            StorageBackend::Database(db) => db.store_batch(batch).await,
-            StorageBackend::S3(s3) => s3.store_batch(batch).await,
-        }
+        self.instrumented("store", async {
+            match &self.backend {
+                StorageBackend::Database(db) => db.store_batch(batch).await,
+                StorageBackend::S3(s3) => s3.store_batch(batch).await,
+            }
+        })
+        .await
     }
 
     pub(super) async fn list_prefix<'a>(
@@ -574,11 +740,26 @@ impl AsyncStorage {
         }
     }
 
+    /// Copies a file to another path inside the backend, replacing an existing file there.
+    #[instrument(skip(self))]
+    pub async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        self.instrumented("copy", async {
+            match &self.backend {
+                StorageBackend::Database(db) => db.copy(from, to).await,
+                StorageBackend::S3(s3) => s3.copy(from, to).await,
+            }
+        })
+        .await
+    }
+
     pub(crate) async fn delete_prefix(&self, prefix: &str) -> Result<()> {
-        match &self.backend {
-            StorageBackend::Database(db) => db.delete_prefix(prefix).await,
-            StorageBackend::S3(s3) => s3.delete_prefix(prefix).await,
-        }
+        self.instrumented("delete_prefix", async {
+            match &self.backend {
+                StorageBackend::Database(db) => db.delete_prefix(prefix).await,
+                StorageBackend::S3(s3) => s3.delete_prefix(prefix).await,
+            }
+        })
+        .await
     }
 
     // We're using `&self` instead of consuming `self` or creating a Drop impl because during tests
@@ -690,6 +871,12 @@ impl Storage {
         )
     }
 
+    /// Writes the decompressed content of a file to `writer`, without loading the whole file
+    /// into memory.
+    pub fn copy_to(&self, path: &str, writer: &mut (dyn Write + Send)) -> Result<()> {
+        self.runtime.block_on(self.inner.copy_to(path, writer))
+    }
+
     pub(crate) fn get(&self, path: &str, max_size: usize) -> Result<Blob> {
         self.runtime.block_on(self.inner.get(path, max_size))
     }
@@ -740,6 +927,11 @@ impl Storage {
             .block_on(self.inner.store_all_in_archive(archive_path, root_dir))
     }
 
+    pub fn regenerate_archive_index(&self, archive_path: &str) -> Result<Vec<String>> {
+        self.runtime
+            .block_on(self.inner.regenerate_archive_index(archive_path))
+    }
+
     pub(crate) fn store_all(
         &self,
         prefix: &Path,
@@ -781,6 +973,10 @@ impl Storage {
             .into_iter()
     }
 
+    pub fn copy(&self, from: &str, to: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.copy(from, to))
+    }
+
     #[instrument(skip(self))]
     pub(crate) fn delete_prefix(&self, prefix: &str) -> Result<()> {
         self.runtime.block_on(self.inner.delete_prefix(prefix))
@@ -801,6 +997,19 @@ impl std::fmt::Debug for Storage {
     }
 }
 
+/// Creates the index of a ZIP archive, compressed with `alg`.
+fn create_compressed_archive_index(
+    zip: &mut (impl io::Read + io::Seek),
+    temp_dir: &Path,
+    alg: CompressionAlgorithm,
+) -> Result<Vec<u8>> {
+    fs::create_dir_all(temp_dir)?;
+    let local_index_path = tempfile::NamedTempFile::new_in(temp_dir)?.into_temp_path();
+    archive_index::create(zip, &local_index_path)?;
+
+    compress(BufReader::new(fs::File::open(&local_index_path)?), alg)
+}
+
 fn detect_mime(file_path: impl AsRef<Path>) -> &'static str {
     let mime = mime_guess::from_path(file_path.as_ref())
         .first_raw()
@@ -831,6 +1040,14 @@ pub fn source_archive_path(name: &str, version: &str) -> String {
     format!("sources/{name}/{version}.zip")
 }
 
+pub fn custom_css_path(name: &str) -> String {
+    format!("custom-css/{name}.css")
+}
+
+pub fn rustdoc_json_path(name: &str, version: &str, target: &str) -> String {
+    format!("rustdoc-json/{name}/{version}/{target}.json")
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -922,6 +1139,79 @@ mod backend_tests {
         Ok(())
     }
 
+    fn test_copy(storage: &Storage) -> Result<()> {
+        storage.store_blobs(vec![Blob {
+            path: "foo/bar.js".into(),
+            mime: "text/javascript".into(),
+            date_updated: Utc::now(),
+            compression: Some(CompressionAlgorithm::Zstd),
+            content: compress(&b"test content\n"[..], CompressionAlgorithm::Zstd)?,
+        }])?;
+        storage.set_public_access("foo/bar.js", true)?;
+
+        storage.copy("foo/bar.js", "foo bar/baz+qux.js")?;
+        let copy = storage.get("foo bar/baz+qux.js", usize::MAX)?;
+        assert_eq!(copy.content, b"test content\n");
+        assert_eq!(copy.mime, "text/javascript");
+        assert!(storage.get_public_access("foo bar/baz+qux.js")?);
+        // the original is kept
+        assert!(storage.exists("foo/bar.js")?);
+
+        assert!(storage
+            .copy("foo/missing.js", "foo/copy.js")
+            .unwrap_err()
+            .downcast_ref::<PathNotFoundError>()
+            .is_some());
+        assert!(!storage.exists("foo/copy.js")?);
+
+        Ok(())
+    }
+
+    fn test_migrate_storage(storage: &Storage) -> Result<()> {
+        let blobs: Vec<_> = ["migrate/a.txt", "migrate/b/c.txt", "other.txt"]
+            .into_iter()
+            .map(|path| Blob {
+                path: path.into(),
+                mime: "text/plain".into(),
+                date_updated: Utc::now(),
+                compression: Some(CompressionAlgorithm::Zstd),
+                content: compress(&b"content"[..], CompressionAlgorithm::Zstd).unwrap(),
+            })
+            .collect();
+        let stored_size = blobs[0].content.len() as u64;
+        storage.store_blobs(blobs)?;
+
+        // the backends of the test are the same, so every file already exists
+        let summary = storage.runtime.block_on(migrate_storage(
+            &storage.inner,
+            &storage.inner,
+            "migrate/",
+            2,
+            true,
+        ))?;
+        assert_eq!(summary.skipped_objects, 2);
+        assert_eq!(summary.copied_objects, 0);
+
+        let summary = storage.runtime.block_on(migrate_storage(
+            &storage.inner,
+            &storage.inner,
+            "migrate/",
+            2,
+            false,
+        ))?;
+        assert_eq!(summary.copied_objects, 2);
+        assert_eq!(summary.copied_bytes, 2 * stored_size);
+        assert!(summary.failed.is_empty());
+
+        // the stored compression is kept
+        assert_eq!(
+            storage.get("migrate/b/c.txt", usize::MAX)?.content,
+            b"content"
+        );
+
+        Ok(())
+    }
+
     fn test_get_object(storage: &Storage) -> Result<()> {
         let path: &str = "foo/bar.txt";
         let blob = Blob {
@@ -1037,6 +1327,32 @@ mod backend_tests {
         Ok(())
     }
 
+    fn test_copy_to(storage: &Storage) -> Result<()> {
+        let content = b"Hello world!".repeat(100);
+        storage.store_one("compressed.txt", content.clone())?;
+        storage.store_blobs(vec![Blob {
+            path: "plain.txt".into(),
+            mime: "text/plain".into(),
+            date_updated: Utc::now(),
+            compression: None,
+            content: content.clone(),
+        }])?;
+
+        for path in ["compressed.txt", "plain.txt"] {
+            let mut output = Vec::new();
+            storage.copy_to(path, &mut output)?;
+            assert_eq!(output, content);
+        }
+
+        assert!(storage
+            .copy_to("missing.txt", &mut Vec::new())
+            .unwrap_err()
+            .downcast_ref::<PathNotFoundError>()
+            .is_some());
+
+        Ok(())
+    }
+
     fn test_get_too_big(storage: &Storage) -> Result<()> {
         const MAX_SIZE: usize = 1024;
 
@@ -1104,6 +1420,32 @@ mod backend_tests {
         Ok(())
     }
 
+    fn test_storage_operation_metrics(storage: &Storage, metrics: &InstanceMetrics) -> Result<()> {
+        storage.store_one("foo.txt", b"foo".to_vec())?;
+        storage.get("foo.txt", usize::MAX)?;
+        assert!(storage.get("bar.txt", usize::MAX).is_err());
+
+        let count = |operation: &str| {
+            metrics
+                .storage_operation_time
+                .with_label_values(&[operation])
+                .get_sample_count()
+        };
+        assert_eq!(count("store"), 1);
+        assert_eq!(count("get"), 2);
+
+        // a missing file is not a failed operation
+        assert_eq!(
+            metrics
+                .failed_storage_operations
+                .with_label_values(&["get"])
+                .get(),
+            0
+        );
+
+        Ok(())
+    }
+
     fn test_exists_without_remote_archive(storage: &Storage) -> Result<()> {
         // when remote and local index don't exist, any `exists_in_archive`  should
         // return `false`
@@ -1178,6 +1520,45 @@ mod backend_tests {
         Ok(())
     }
 
+    fn test_regenerate_archive_index(storage: &Storage) -> Result<()> {
+        let dir = tempfile::Builder::new()
+            .prefix("docs.rs-regenerate-index-test")
+            .tempdir()?;
+        fs::write(dir.path().join("search-index.js"), "data")?;
+        storage.store_all_in_archive("folder/test.zip", dir.path())?;
+
+        // cache the index locally, then break the stored one
+        assert!(storage.exists_in_archive("folder/test.zip", 0, "search-index.js")?);
+        storage.store_blobs(vec![Blob {
+            path: "folder/test.zip.index".into(),
+            mime: "application/octet-stream".into(),
+            content: b"broken".to_vec(),
+            compression: None,
+            date_updated: Utc::now(),
+        }])?;
+
+        assert_eq!(
+            storage.regenerate_archive_index("folder/test.zip")?,
+            vec!["search-index.js".to_owned()]
+        );
+
+        let local_index_location = storage
+            .inner
+            .config
+            .local_archive_cache_path
+            .join("folder/test.zip.0.index");
+        assert!(!local_index_location.exists());
+
+        let file = storage.get_from_archive("folder/test.zip", 0, "search-index.js", usize::MAX)?;
+        assert_eq!(file.content, b"data");
+
+        assert!(storage
+            .regenerate_archive_index("folder/missing.zip")
+            .is_err());
+
+        Ok(())
+    }
+
     fn test_store_all(storage: &Storage, metrics: &InstanceMetrics) -> Result<()> {
         let dir = tempfile::Builder::new()
             .prefix("docs.rs-upload-test")
@@ -1374,6 +1755,7 @@ mod backend_tests {
 
         tests {
             test_batched_uploads,
+            test_copy_to,
             test_exists,
             test_get_object,
             test_get_range,
@@ -1385,9 +1767,13 @@ mod backend_tests {
             test_delete_percent,
             test_exists_without_remote_archive,
             test_set_public,
+            test_regenerate_archive_index,
+            test_copy,
+            test_migrate_storage,
         }
 
         tests_with_metrics {
+            test_storage_operation_metrics,
             test_store_blobs,
             test_store_all,
             test_store_all_in_archive,
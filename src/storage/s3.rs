@@ -1,4 +1,4 @@
-use super::{Blob, FileRange};
+use super::{Blob, FileRange, StreamingBlob};
 use crate::{Config, InstanceMetrics};
 use anyhow::{Context as _, Error};
 use async_stream::try_stream;
@@ -16,12 +16,21 @@ use futures_util::{
     pin_mut,
     stream::{FuturesUnordered, Stream, StreamExt},
 };
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use std::{io::Write, sync::Arc};
 use tracing::{error, warn};
 
 const PUBLIC_ACCESS_TAG: &str = "static-cloudfront-access";
 const PUBLIC_ACCESS_VALUE: &str = "allow";
 
+// characters that have to be encoded in the `x-amz-copy-source` header
+const COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
 // error codes to check for when trying to determaine if an error is
 // a "NOT FOUND" error.
 // Definition taken from the S3 rust SDK,
@@ -57,13 +66,26 @@ where
                     }
                 }
 
-                if let SdkError::ServiceError(err) = &err {
-                    if err.raw().status().as_u16() == http::StatusCode::NOT_FOUND.as_u16() {
-                        return Err(super::PathNotFoundError.into());
+                let unavailable = match &err {
+                    SdkError::ServiceError(err) => {
+                        let status = err.raw().status().as_u16();
+                        if status == http::StatusCode::NOT_FOUND.as_u16() {
+                            return Err(super::PathNotFoundError.into());
+                        }
+                        // includes `SlowDown`, which S3 returns when it's overloaded
+                        status >= http::StatusCode::INTERNAL_SERVER_ERROR.as_u16()
                     }
+                    SdkError::TimeoutError(_)
+                    | SdkError::DispatchFailure(_)
+                    | SdkError::ResponseError(_) => true,
+                    _ => false,
+                };
+
+                if unavailable {
+                    Err(anyhow::Error::from(err).context(super::StorageUnavailableError))
+                } else {
+                    Err(err.into())
                 }
-
-                Err(err.into())
             }
         }
     }
@@ -72,6 +94,8 @@ where
 pub(super) struct S3Backend {
     client: Client,
     bucket: String,
+    /// Prepended to all keys, empty or ending with `/`
+    prefix: String,
     metrics: Arc<InstanceMetrics>,
     #[cfg(test)]
     temporary: bool,
@@ -110,17 +134,26 @@ impl S3Backend {
             client,
             metrics,
             bucket: config.s3_bucket.clone(),
+            prefix: match config.s3_prefix.as_deref().map(|p| p.trim_matches('/')) {
+                Some(prefix) if !prefix.is_empty() => format!("{prefix}/"),
+                _ => String::new(),
+            },
             #[cfg(test)]
             temporary: config.s3_bucket_is_temporary,
         })
     }
 
+    /// The key of `path` in the bucket.
+    fn key(&self, path: &str) -> String {
+        format!("{}{path}", self.prefix)
+    }
+
     pub(super) async fn exists(&self, path: &str) -> Result<bool, Error> {
         match self
             .client
             .head_object()
             .bucket(&self.bucket)
-            .key(path)
+            .key(self.key(path))
             .send()
             .await
             .convert_errors()
@@ -136,7 +169,7 @@ impl S3Backend {
             .client
             .get_object_tagging()
             .bucket(&self.bucket)
-            .key(path)
+            .key(self.key(path))
             .send()
             .await
             .convert_errors()?
@@ -150,7 +183,7 @@ impl S3Backend {
         self.client
             .put_object_tagging()
             .bucket(&self.bucket)
-            .key(path)
+            .key(self.key(path))
             .tagging(if public {
                 Tagging::builder()
                     .tag_set(
@@ -184,7 +217,7 @@ impl S3Backend {
             .client
             .get_object()
             .bucket(&self.bucket)
-            .key(path)
+            .key(self.key(path))
             .set_range(range.map(|r| format!("bytes={}-{}", r.start(), r.end())))
             .send()
             .await
@@ -221,6 +254,29 @@ impl S3Backend {
         })
     }
 
+    pub(super) async fn get_stream(&self, path: &str) -> Result<StreamingBlob<'static>, Error> {
+        let res = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(path))
+            .send()
+            .await
+            .convert_errors()?;
+
+        let compression = res.content_encoding.and_then(|s| s.parse().ok());
+        let content = futures_util::stream::unfold(res.body, |mut body| async move {
+            body.next()
+                .await
+                .map(|data| (data.map(|data| data.to_vec()).map_err(Error::from), body))
+        });
+
+        Ok(StreamingBlob {
+            compression,
+            content: Box::pin(content),
+        })
+    }
+
     pub(super) async fn store_batch(&self, mut batch: Vec<Blob>) -> Result<(), Error> {
         // Attempt to upload the batch 3 times
         for _ in 0..3 {
@@ -230,7 +286,7 @@ impl S3Backend {
                     self.client
                         .put_object()
                         .bucket(&self.bucket)
-                        .key(&blob.path)
+                        .key(self.key(&blob.path))
                         .body(blob.content.clone().into())
                         .content_type(&blob.mime)
                         .set_content_encoding(blob.compression.map(|alg| alg.to_string()))
@@ -262,6 +318,24 @@ impl S3Backend {
         panic!("failed to upload 3 times, exiting");
     }
 
+    /// Copies an object inside the bucket, without downloading it. The content encoding and
+    /// the tags are copied as well. S3 only copies objects up to 5 GB in a single request.
+    pub(super) async fn copy(&self, from: &str, to: &str) -> Result<(), Error> {
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!(
+                "{}/{}",
+                self.bucket,
+                utf8_percent_encode(&self.key(from), COPY_SOURCE)
+            ))
+            .key(self.key(to))
+            .send()
+            .await
+            .convert_errors()
+            .map(|_| ())
+    }
+
     pub(super) async fn list_prefix<'a>(
         &'a self,
         prefix: &'a str,
@@ -273,15 +347,16 @@ impl S3Backend {
                     .client
                     .list_objects_v2()
                     .bucket(&self.bucket)
-                    .prefix(prefix)
+                    .prefix(self.key(prefix))
                     .set_continuation_token(continuation_token)
                     .send()
                     .await?;
 
                 if let Some(contents) = list.contents {
                     for obj in contents {
-                        if let Some(key) = obj.key() {
-                            yield key.to_owned();
+                        let path = obj.key().and_then(|key| key.strip_prefix(&self.prefix));
+                        if let Some(path) = path {
+                            yield path.to_owned();
                         }
                     }
                 }
@@ -306,7 +381,12 @@ impl S3Backend {
                 .set_objects(Some(
                     batch
                         .into_iter()
-                        .filter_map(|k| ObjectIdentifier::builder().key(k).build().ok())
+                        .filter_map(|path| {
+                            ObjectIdentifier::builder()
+                                .key(self.key(&path))
+                                .build()
+                                .ok()
+                        })
                         .collect(),
                 ))
                 .build()
@@ -360,4 +440,60 @@ mod tests {
     // NOTE: trying to upload a file ending with `/` will behave differently in test and prod.
     // NOTE: On s3, it will succeed and create a file called `/`.
     // NOTE: On min.io, it will fail with 'Object name contains unsupported characters.'
+
+    use super::*;
+    use crate::storage::{PathNotFoundError, StorageBackend, StorageKind};
+    use crate::test::async_wrapper;
+    use futures_util::TryStreamExt;
+
+    #[test]
+    fn test_prefix() {
+        async_wrapper(|env| async move {
+            env.override_config(|config| {
+                config.storage_backend = StorageKind::S3;
+                config.s3_prefix = Some("/staging/".into());
+            });
+            let storage = env.async_storage().await;
+            let StorageBackend::S3(s3) = &storage.backend else {
+                unreachable!("the storage is configured to use S3");
+            };
+            assert_eq!(s3.prefix, "staging/");
+
+            storage
+                .store_one("foo/bar.txt", b"content".to_vec())
+                .await?;
+            assert!(storage.exists("foo/bar.txt").await?);
+            assert_eq!(
+                storage
+                    .list_prefix("foo/")
+                    .await
+                    .try_collect::<Vec<_>>()
+                    .await?,
+                vec!["foo/bar.txt"]
+            );
+
+            // the object is only stored below the prefix
+            s3.client
+                .head_object()
+                .bucket(&s3.bucket)
+                .key("staging/foo/bar.txt")
+                .send()
+                .await?;
+            assert!(s3
+                .client
+                .head_object()
+                .bucket(&s3.bucket)
+                .key("foo/bar.txt")
+                .send()
+                .await
+                .convert_errors()
+                .unwrap_err()
+                .is::<PathNotFoundError>());
+
+            storage.delete_prefix("foo/").await?;
+            assert!(!storage.exists("foo/bar.txt").await?);
+
+            Ok(())
+        })
+    }
 }
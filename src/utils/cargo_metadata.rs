@@ -1,6 +1,6 @@
 use crate::error::Result;
 use anyhow::{bail, Context};
-use rustwide::{cmd::Command, Toolchain, Workspace};
+use rustwide::cmd::Command;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
@@ -10,12 +10,9 @@ pub(crate) struct CargoMetadata {
 }
 
 impl CargoMetadata {
-    pub(crate) fn load_from_rustwide(
-        workspace: &Workspace,
-        toolchain: &Toolchain,
-        source_dir: &Path,
-    ) -> Result<Self> {
-        let res = Command::new(workspace, toolchain.cargo())
+    /// Runs `cargo metadata` with `cargo`, a cargo command in the rustwide workspace.
+    pub(crate) fn load_from_rustwide(cargo: Command<'_, '_>, source_dir: &Path) -> Result<Self> {
+        let res = cargo
             .args(&["metadata", "--format-version", "1"])
             .cd(source_dir)
             .log_output(false)
@@ -0,0 +1,109 @@
+use crate::Context;
+use anyhow::{bail, Context as _, Result};
+use url::Url;
+
+/// The outcome of a single check of [`check_config`].
+#[derive(Debug)]
+pub struct ConfigCheck {
+    pub name: &'static str,
+    pub result: Result<()>,
+}
+
+/// Loads the configuration and verifies that the services it points to are usable.
+///
+/// When the configuration itself can't be loaded, no other checks are run.
+pub fn check_config(ctx: &dyn Context) -> Vec<ConfigCheck> {
+    let config = match ctx.config() {
+        Ok(config) => config,
+        Err(err) => {
+            return vec![ConfigCheck {
+                name: "configuration",
+                result: Err(err),
+            }]
+        }
+    };
+
+    let mut checks = vec![ConfigCheck {
+        name: "configuration",
+        result: Ok(()),
+    }];
+
+    checks.push(ConfigCheck {
+        name: "database",
+        result: (|| {
+            ctx.pool()?
+                .get()?
+                .query_one("SELECT 1", &[])
+                .context("failed to query the database")?;
+            Ok(())
+        })(),
+    });
+
+    checks.push(ConfigCheck {
+        name: "storage",
+        result: (|| {
+            // the path doesn't have to exist, only the storage backend has to answer
+            ctx.storage()?
+                .exists("docsrs-config-check")
+                .context("failed to query the storage")?;
+            Ok(())
+        })(),
+    });
+
+    checks.push(ConfigCheck {
+        name: "registry url",
+        result: config
+            .registry_url
+            .as_deref()
+            .map_or(Ok(()), check_registry_url),
+    });
+
+    checks
+}
+
+fn check_registry_url(url: &str) -> Result<()> {
+    let parsed = Url::parse(url).with_context(|| format!("invalid registry url {url}"))?;
+    if !matches!(parsed.scheme(), "http" | "https" | "file") {
+        bail!("unsupported scheme in registry url {url}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+
+    #[test]
+    fn test_check_config() {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.registry_url = Some("not a url".into());
+            });
+
+            let checks = check_config(env);
+            assert_eq!(
+                checks
+                    .iter()
+                    .map(|check| (check.name, check.result.is_ok()))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("configuration", true),
+                    ("database", true),
+                    ("storage", true),
+                    ("registry url", false),
+                ]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_check_registry_url() {
+        assert!(check_registry_url("https://github.com/rust-lang/crates.io-index").is_ok());
+        assert!(check_registry_url("file:///srv/index").is_ok());
+        assert!(check_registry_url("ssh://git@github.com/foo/bar").is_err());
+        assert!(check_registry_url("crates.io-index").is_err());
+    }
+}
@@ -63,11 +63,13 @@ pub(super) fn load(conn: &mut postgres::Client, config: &Config) -> Result<Crate
 mod tests {
     use super::*;
     use crate::test::wrapper;
+    use crate::QueueSource;
 
     #[test]
     fn test_load() {
         wrapper(|env| {
-            env.build_queue().add_crate("queued", "0.0.1", 0, None)?;
+            env.build_queue()
+                .add_crate("queued", "0.0.1", 0, None, QueueSource::Manual)?;
             env.fake_release().name("krate").version("0.0.2").create()?;
             env.fake_release()
                 .name("krate")
@@ -0,0 +1,230 @@
+use crate::{
+    storage::{rustdoc_archive_path, source_archive_path},
+    Context,
+};
+use anyhow::{Context as _, Result};
+use tracing::warn;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct CheckCounts {
+    pub(super) passed: u32,
+    pub(super) failed: u32,
+}
+
+impl CheckCounts {
+    fn record(&mut self, passed: bool) {
+        if passed {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct IntegrityReport {
+    pub(super) releases: u32,
+    /// the release has a build, and a successful one when it has documentation
+    pub(super) database: CheckCounts,
+    /// the archives of the release exist in storage, only checked for archive storage. The
+    /// source archive is only checked when the release stored its source files.
+    pub(super) storage: CheckCounts,
+    /// the release exists in the index
+    pub(super) index: CheckCounts,
+}
+
+/// integrity report
+///
+/// cross-checks a random sample of releases (or all releases when `sample` is `None`) between
+/// our database, the storage and the local crates.io index, and prints how many releases
+/// passed and failed each check.
+///
+/// Unlike [`super::run_check`], nothing is changed, failing releases are only logged.
+pub fn run_integrity_report(ctx: &dyn Context, sample: Option<usize>) -> Result<()> {
+    let index = ctx.index()?.crates()?;
+
+    let report = build_report(ctx, sample, |name, version| {
+        Ok(index
+            .crate_(name)
+            .map(|krate| krate.versions().iter().any(|v| v.version() == version))
+            .unwrap_or(false))
+    })?;
+
+    println!("============");
+    println!("INTEGRITY REPORT");
+    println!("============");
+    println!("releases checked: {:6}", report.releases);
+    println!("check       passed   failed");
+    for (name, counts) in [
+        ("database", report.database),
+        ("storage", report.storage),
+        ("index", report.index),
+    ] {
+        println!("{name:10} {:7}  {:7}", counts.passed, counts.failed);
+    }
+
+    Ok(())
+}
+
+fn build_report(
+    ctx: &dyn Context,
+    sample: Option<usize>,
+    in_index: impl Fn(&str, &str) -> Result<bool>,
+) -> Result<IntegrityReport> {
+    let mut conn = ctx.pool()?.get()?;
+    let storage = ctx.storage()?;
+
+    let rows = conn
+        .query(
+            "SELECT
+                crates.name,
+                releases.version,
+                COALESCE(releases.rustdoc_status, false) AS rustdoc_status,
+                COALESCE(releases.archive_storage, false) AS archive_storage,
+                -- releases built with `skip_source_archive` have no source files
+                releases.files IS NOT NULL AS has_source_files,
+                EXISTS (
+                    SELECT 1 FROM builds WHERE builds.rid = releases.id
+                ) AS has_build,
+                EXISTS (
+                    SELECT 1 FROM builds
+                    WHERE builds.rid = releases.id AND builds.build_status = 'success'
+                ) AS has_successful_build
+             FROM releases
+             INNER JOIN crates ON crates.id = releases.crate_id
+             ORDER BY random()
+             LIMIT $1",
+            &[&sample.map(|sample| sample as i64)],
+        )
+        .context("failed to load the release sample")?;
+
+    let mut report = IntegrityReport::default();
+
+    for row in rows {
+        let name: String = row.get("name");
+        let version: String = row.get("version");
+        let rustdoc_status: bool = row.get("rustdoc_status");
+        report.releases += 1;
+
+        let db_ok = row.get::<_, bool>("has_build")
+            && (!rustdoc_status || row.get::<_, bool>("has_successful_build"));
+        if !db_ok {
+            warn!(%name, %version, "release has no matching build in the database");
+        }
+        report.database.record(db_ok);
+
+        if row.get("archive_storage") {
+            let mut storage_ok = true;
+            if row.get("has_source_files") {
+                storage_ok &= storage.exists(&source_archive_path(&name, &version))?;
+            }
+            if rustdoc_status {
+                storage_ok &= storage.exists(&rustdoc_archive_path(&name, &version))?;
+            }
+            if !storage_ok {
+                warn!(%name, %version, "release archives are missing in storage");
+            }
+            report.storage.record(storage_ok);
+        }
+
+        let index_ok = in_index(&name, &version)?;
+        if !index_ok {
+            warn!(%name, %version, "release is missing in the index");
+        }
+        report.index.record(index_ok);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+
+    #[test]
+    fn test_integrity_report() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("krate")
+                .version("0.1.0")
+                .archive_storage(true)
+                .create()?;
+            env.fake_release()
+                .name("krate")
+                .version("0.2.0")
+                .archive_storage(true)
+                .create()?;
+            env.fake_release().name("other").version("0.1.0").create()?;
+
+            env.storage()
+                .delete_prefix(&rustdoc_archive_path("krate", "0.2.0"))?;
+
+            let report = build_report(env, None, |name, _| Ok(name == "krate"))?;
+
+            assert_eq!(
+                report,
+                IntegrityReport {
+                    releases: 3,
+                    database: CheckCounts {
+                        passed: 3,
+                        failed: 0
+                    },
+                    storage: CheckCounts {
+                        passed: 1,
+                        failed: 1
+                    },
+                    index: CheckCounts {
+                        passed: 2,
+                        failed: 1
+                    },
+                }
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_integrity_report_without_source_archive() {
+        wrapper(|env| {
+            let release_id = env
+                .fake_release()
+                .name("krate")
+                .version("0.1.0")
+                .archive_storage(true)
+                .create()?;
+
+            // like a build with `skip_source_archive`
+            env.storage()
+                .delete_prefix(&source_archive_path("krate", "0.1.0"))?;
+            env.db().conn().execute(
+                "UPDATE releases SET files = NULL WHERE id = $1",
+                &[&release_id],
+            )?;
+
+            let report = build_report(env, None, |_, _| Ok(true))?;
+            assert_eq!(
+                report.storage,
+                CheckCounts {
+                    passed: 1,
+                    failed: 0
+                }
+            );
+
+            // the rustdoc archive is still checked
+            env.storage()
+                .delete_prefix(&rustdoc_archive_path("krate", "0.1.0"))?;
+            let report = build_report(env, None, |_, _| Ok(true))?;
+            assert_eq!(
+                report.storage,
+                CheckCounts {
+                    passed: 0,
+                    failed: 1
+                }
+            );
+
+            Ok(())
+        })
+    }
+}
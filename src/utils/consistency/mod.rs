@@ -1,4 +1,4 @@
-use crate::{db::delete, Context};
+use crate::{db::delete, Context, QueueSource};
 use anyhow::{Context as _, Result};
 use itertools::Itertools;
 use tracing::{info, warn};
@@ -7,6 +7,11 @@ mod data;
 mod db;
 mod diff;
 mod index;
+mod integrity;
+mod yanks;
+
+pub use integrity::run_integrity_report;
+pub use yanks::reconcile_yanks;
 
 const BUILD_PRIORITY: i32 = 15;
 
@@ -24,7 +29,9 @@ const BUILD_PRIORITY: i32 = 15;
 ///
 /// Even when activities fail, the command can just be re-run. While the diff calculation will
 /// be repeated, we won't re-execute fixing activities.
-pub fn run_check(ctx: &dyn Context, dry_run: bool) -> Result<()> {
+///
+/// Returns the number of differences found.
+pub fn run_check(ctx: &dyn Context, dry_run: bool) -> Result<usize> {
     let mut conn = ctx.pool()?.get()?;
     let index = ctx.index()?;
 
@@ -64,7 +71,7 @@ pub fn run_check(ctx: &dyn Context, dry_run: bool) -> Result<()> {
     println!("releases deleted: {:4}", result.releases_deleted);
     println!("yanks corrected:  {:4}", result.yanks_corrected);
 
-    Ok(())
+    Ok(diff.len())
 }
 
 #[derive(Default)]
@@ -101,8 +108,13 @@ where
             diff::Difference::CrateNotInDb(name, versions) => {
                 for version in versions {
                     if !dry_run {
-                        if let Err(err) = build_queue.add_crate(name, version, BUILD_PRIORITY, None)
-                        {
+                        if let Err(err) = build_queue.add_crate(
+                            name,
+                            version,
+                            BUILD_PRIORITY,
+                            None,
+                            QueueSource::ConsistencyCheck,
+                        ) {
                             warn!("{:?}", err);
                         }
                     }
@@ -111,9 +123,14 @@ where
             }
             diff::Difference::ReleaseNotInIndex(name, version) => {
                 if !dry_run {
-                    if let Err(err) =
-                        delete::delete_version(&mut conn, &storage, &config, name, version)
-                    {
+                    if let Err(err) = delete::delete_version(
+                        &mut conn,
+                        &storage,
+                        &config,
+                        name,
+                        version,
+                        delete::DeleteVersionOptions::default(),
+                    ) {
                         warn!("{:?}", err);
                     }
                 }
@@ -121,7 +138,13 @@ where
             }
             diff::Difference::ReleaseNotInDb(name, version) => {
                 if !dry_run {
-                    if let Err(err) = build_queue.add_crate(name, version, BUILD_PRIORITY, None) {
+                    if let Err(err) = build_queue.add_crate(
+                        name,
+                        version,
+                        BUILD_PRIORITY,
+                        None,
+                        QueueSource::ConsistencyCheck,
+                    ) {
                         warn!("{:?}", err);
                     }
                 }
@@ -0,0 +1,137 @@
+use crate::Context;
+use anyhow::{Context as _, Result};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// reconcile yanks
+///
+/// updates the yank status of all releases (or only the releases of `name`) in our database
+/// to the yank status in the local crates.io index, and returns how many releases changed.
+///
+/// Releases of crates that are missing in the index are left alone, [`super::run_check`]
+/// handles those.
+pub fn reconcile_yanks(ctx: &dyn Context, name: Option<&str>) -> Result<usize> {
+    let index = ctx.index()?.crates()?;
+
+    reconcile(ctx, name, |name| {
+        Ok(index.crate_(name).map(|krate| {
+            krate
+                .versions()
+                .iter()
+                .map(|version| (version.version().to_owned(), version.is_yanked()))
+                .collect()
+        }))
+    })
+}
+
+fn reconcile(
+    ctx: &dyn Context,
+    name: Option<&str>,
+    index_yanks: impl Fn(&str) -> Result<Option<HashMap<String, bool>>>,
+) -> Result<usize> {
+    let mut conn = ctx.pool()?.get()?;
+    let build_queue = ctx.build_queue()?;
+
+    let rows = conn
+        .query(
+            "SELECT crates.name, releases.version, releases.yanked
+             FROM releases
+             INNER JOIN crates ON crates.id = releases.crate_id
+             WHERE $1::TEXT IS NULL OR crates.name = $1
+             ORDER BY crates.name, releases.id",
+            &[&name],
+        )
+        .context("failed to load the releases")?;
+
+    let mut changed = 0;
+    let mut current_crate: Option<(String, Option<HashMap<String, bool>>)> = None;
+
+    for row in rows {
+        let name: String = row.get("name");
+        let version: String = row.get("version");
+        let yanked: Option<bool> = row.get("yanked");
+
+        if current_crate.as_ref().map(|(krate, _)| krate) != Some(&name) {
+            let yanks = index_yanks(&name)?;
+            if yanks.is_none() {
+                warn!(%name, "crate is missing in the index, skipping it");
+            }
+            current_crate = Some((name.clone(), yanks));
+        }
+
+        let Some((_, Some(yanks))) = &current_crate else {
+            continue;
+        };
+        let Some(&index_yanked) = yanks.get(&version) else {
+            warn!(%name, %version, "release is missing in the index, skipping it");
+            continue;
+        };
+
+        if yanked != Some(index_yanked) {
+            build_queue.set_yanked(&mut conn, &name, &version, index_yanked)?;
+            changed += 1;
+        }
+    }
+
+    info!("updated the yank status of {changed} releases");
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+
+    #[test]
+    fn test_reconcile_yanks() {
+        wrapper(|env| {
+            env.fake_release().name("krate").version("0.1.0").create()?;
+            env.fake_release()
+                .name("krate")
+                .version("0.2.0")
+                .yanked(true)
+                .create()?;
+            env.fake_release().name("krate").version("0.3.0").create()?;
+            env.fake_release().name("other").version("0.1.0").create()?;
+
+            let index_yanks = |name: &str| {
+                Ok((name == "krate").then(|| {
+                    HashMap::from([
+                        ("0.1.0".to_owned(), true),
+                        ("0.2.0".to_owned(), false),
+                        ("0.3.0".to_owned(), false),
+                    ])
+                }))
+            };
+
+            assert_eq!(reconcile(env, Some("other"), index_yanks)?, 0);
+            assert_eq!(reconcile(env, None, index_yanks)?, 2);
+            assert_eq!(reconcile(env, None, index_yanks)?, 0);
+
+            let yanked: Vec<(String, String, bool)> = env
+                .db()
+                .conn()
+                .query(
+                    "SELECT crates.name, releases.version, releases.yanked
+                     FROM releases
+                     INNER JOIN crates ON crates.id = releases.crate_id
+                     ORDER BY crates.name, releases.version",
+                    &[],
+                )?
+                .into_iter()
+                .map(|row| (row.get(0), row.get(1), row.get(2)))
+                .collect();
+            assert_eq!(
+                yanked,
+                vec![
+                    ("krate".into(), "0.1.0".into(), true),
+                    ("krate".into(), "0.2.0".into(), false),
+                    ("krate".into(), "0.3.0".into(), false),
+                    ("other".into(), "0.1.0".into(), false),
+                ]
+            );
+
+            Ok(())
+        })
+    }
+}
@@ -19,10 +19,16 @@ use tracing::{debug, info};
 /// Run the registry watcher
 /// NOTE: this should only be run once, otherwise crates would be added
 /// to the queue multiple times.
+///
+/// With a `batch_size`, the last seen reference of the index is stored after every
+/// batch of changes, and with an `enqueue_batch_size` the added releases are queued in
+/// transactions of that size, see [`BuildQueue::get_new_crates_in_batches`].
 pub fn watch_registry(
     build_queue: Arc<BuildQueue>,
     config: Arc<Config>,
     index: Arc<Index>,
+    batch_size: Option<usize>,
+    enqueue_batch_size: Option<usize>,
 ) -> Result<(), Error> {
     let mut last_gc = Instant::now();
 
@@ -32,7 +38,7 @@ pub fn watch_registry(
         } else {
             debug!("Checking new crates");
             match build_queue
-                .get_new_crates(&index)
+                .get_new_crates_in_batches(&index, batch_size, enqueue_batch_size)
                 .context("Failed to get new crates")
             {
                 Ok(n) => debug!("{} crates added to queue", n),
@@ -59,7 +65,7 @@ fn start_registry_watcher(context: &dyn Context) -> Result<(), Error> {
             // space this out to prevent it from clashing against the queue-builder thread on launch
             thread::sleep(Duration::from_secs(30));
 
-            watch_registry(build_queue, config, index)
+            watch_registry(build_queue, config, index, None, None)
         })?;
 
     Ok(())
@@ -107,6 +113,8 @@ pub fn start_background_cdn_invalidator(context: &dyn Context) -> Result<(), Err
 
     cron("cdn invalidator", Duration::from_secs(60), move || {
         let mut conn = pool.get()?;
+        cdn::queue_deferred_invalidation(&mut conn, &config)
+            .context("error queueing deferred CDN invalidation")?;
         if let Some(distribution_id) = config.cloudfront_distribution_id_web.as_ref() {
             cdn::handle_queued_invalidation_requests(&cdn, &metrics, &mut *conn, distribution_id)
                 .context("error handling queued invalidations for web CDN invalidation")?;
@@ -131,7 +139,7 @@ pub fn start_daemon<C: Context + Send + Sync + 'static>(
     info!("Starting web server");
     let webserver_thread = thread::spawn({
         let context = context.clone();
-        move || start_web_server(None, &*context)
+        move || start_web_server(None, None, &*context)
     });
 
     if enable_registry_watcher {
@@ -0,0 +1,75 @@
+use crate::Context;
+use anyhow::{Context as _, Result};
+use std::collections::BTreeSet;
+
+/// The crates that only exist on one side of the database and the index.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IndexDrift {
+    pub not_in_db: Vec<String>,
+    pub not_in_index: Vec<String>,
+}
+
+/// Compares the crate names in our database with the index at the last commit the
+/// registry watcher processed.
+///
+/// Unlike the consistency check, this only looks at crate names, doesn't need the
+/// `consistency_check` feature and never changes anything.
+/// Crates that are queued for their first build count as known to the database.
+pub fn index_drift(ctx: &dyn Context) -> Result<IndexDrift> {
+    let last_seen_reference = ctx
+        .build_queue()?
+        .last_seen_reference()?
+        .context("no last_seen_reference set in database")?;
+
+    let index_names = ctx
+        .index()?
+        .crate_file_names(last_seen_reference)
+        .context("failed to load the crates from the index")?;
+
+    let db_names: Vec<String> = ctx
+        .pool()?
+        .get()?
+        .query(
+            "SELECT name FROM crates
+             UNION
+             SELECT name FROM queue WHERE attempt < $1",
+            &[&(ctx.config()?.build_attempts as i32)],
+        )?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    Ok(calculate_drift(db_names, index_names))
+}
+
+fn calculate_drift(db_names: Vec<String>, index_names: Vec<String>) -> IndexDrift {
+    // crates.io names are unique regardless of case, and the index stores them lowercase.
+    let db: BTreeSet<String> = db_names.into_iter().map(|n| n.to_lowercase()).collect();
+    let index: BTreeSet<String> = index_names.into_iter().map(|n| n.to_lowercase()).collect();
+
+    IndexDrift {
+        not_in_db: index.difference(&db).cloned().collect(),
+        not_in_index: db.difference(&index).cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_drift() {
+        let drift = calculate_drift(
+            vec!["Serde".into(), "krate".into(), "deleted".into()],
+            vec!["serde".into(), "krate".into(), "new".into()],
+        );
+
+        assert_eq!(
+            drift,
+            IndexDrift {
+                not_in_db: vec!["new".into()],
+                not_in_index: vec!["deleted".into()],
+            }
+        );
+    }
+}
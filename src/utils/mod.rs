@@ -1,27 +1,37 @@
 //! Various utilities for docs.rs
 
 pub(crate) use self::cargo_metadata::{CargoMetadata, Package as MetadataPackage};
+pub use self::check_config::{check_config, ConfigCheck};
 pub(crate) use self::copy::copy_dir_all;
 pub use self::daemon::{start_daemon, watch_registry};
 pub(crate) use self::html::rewrite_lol;
+pub use self::index_drift::{index_drift, IndexDrift};
 pub use self::queue::{
-    get_crate_pattern_and_priority, get_crate_priority, list_crate_priorities,
-    remove_crate_priority, set_crate_priority,
+    find_priority_overlaps, get_crate_pattern_and_priority, get_crate_priority,
+    list_crate_priorities, remove_crate_priority, set_crate_priority, PriorityOverlap,
 };
 pub use self::queue_builder::queue_builder;
-pub(crate) use self::rustc_version::{get_correct_docsrs_style_file, parse_rustc_version};
+pub use self::regenerate_indexes::{
+    regenerate_archive_index, regenerate_release_indexes, RegeneratedIndexes,
+};
+pub(crate) use self::rustc_version::{
+    get_correct_docsrs_style_file, parse_rustc_date, parse_rustc_version,
+};
 
 #[cfg(test)]
 pub(crate) use self::cargo_metadata::{Dependency, Target};
 
 mod cargo_metadata;
+mod check_config;
 #[cfg(feature = "consistency_check")]
 pub mod consistency;
 mod copy;
 pub mod daemon;
 mod html;
+mod index_drift;
 mod queue;
 pub(crate) mod queue_builder;
+mod regenerate_indexes;
 mod rustc_version;
 use anyhow::Result;
 use postgres::Client;
@@ -55,7 +65,11 @@ pub enum ConfigName {
     RustcVersion,
     LastSeenIndexReference,
     QueueLocked,
+    QueueLockMessage,
+    QueueLockedSince,
+    DeferredCdnInvalidation,
     Toolchain,
+    YankedLatestVersion,
 }
 
 pub fn set_config(
@@ -185,6 +199,7 @@ mod tests {
     #[test_case(ConfigName::RustcVersion, "rustc_version")]
     #[test_case(ConfigName::QueueLocked, "queue_locked")]
     #[test_case(ConfigName::LastSeenIndexReference, "last_seen_index_reference")]
+    #[test_case(ConfigName::YankedLatestVersion, "yanked_latest_version")]
     fn test_configname_variants(variant: ConfigName, expected: &'static str) {
         let name: &'static str = variant.into();
         assert_eq!(name, expected);
@@ -53,6 +53,51 @@ pub fn set_crate_priority(conn: &mut Client, pattern: &str, priority: i32) -> Re
     Ok(())
 }
 
+/// An existing priority pattern that matches some of the same crates as another pattern.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PriorityOverlap {
+    pub pattern: String,
+    pub priority: i32,
+    /// the known crates matching both patterns
+    pub crates: Vec<String>,
+}
+
+/// Find the existing priority patterns that overlap with `pattern`
+///
+/// Only crates we know about (built or queued) are checked, so patterns that would only
+/// overlap for crates that don't exist yet aren't found.
+///
+/// Only the first matching pattern is used for a crate, so crates in both patterns get an
+/// unpredictable priority.
+pub fn find_priority_overlaps(conn: &mut Client, pattern: &str) -> Result<Vec<PriorityOverlap>> {
+    Ok(conn
+        .query(
+            "SELECT
+                crate_priorities.pattern,
+                crate_priorities.priority,
+                ARRAY_AGG(names.name ORDER BY names.name)
+             FROM crate_priorities
+             INNER JOIN (
+                SELECT name FROM crates
+                UNION
+                SELECT name FROM queue
+             ) AS names ON names.name LIKE crate_priorities.pattern
+             WHERE
+                crate_priorities.pattern <> $1 AND
+                names.name LIKE $1
+             GROUP BY crate_priorities.pattern, crate_priorities.priority
+             ORDER BY crate_priorities.pattern",
+            &[&pattern],
+        )?
+        .into_iter()
+        .map(|row| PriorityOverlap {
+            pattern: row.get(0),
+            priority: row.get(1),
+            crates: row.get(2),
+        })
+        .collect())
+}
+
 /// Remove a pattern from the priority table, returning the priority that it was associated with or `None`
 /// if nothing was removed
 pub fn remove_crate_priority(conn: &mut Client, pattern: &str) -> Result<Option<i32>> {
@@ -67,7 +112,7 @@ pub fn remove_crate_priority(conn: &mut Client, pattern: &str) -> Result<Option<
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test::wrapper;
+    use crate::{test::wrapper, QueueSource};
 
     #[test]
     fn set_priority() {
@@ -150,6 +195,38 @@ mod tests {
         })
     }
 
+    #[test]
+    fn priority_overlaps() {
+        wrapper(|env| {
+            env.fake_release().name("docsrs-database").create()?;
+            env.fake_release().name("docsrs-web").create()?;
+            env.fake_release().name("other").create()?;
+            env.build_queue()
+                .add_crate("docsrs-queued", "1.0.0", 0, None, QueueSource::Manual)?;
+
+            let mut conn = env.db().conn();
+            set_crate_priority(&mut conn, "docsrs-%", -100)?;
+            set_crate_priority(&mut conn, "other", 10)?;
+
+            assert_eq!(
+                find_priority_overlaps(&mut conn, "%-database")?,
+                vec![PriorityOverlap {
+                    pattern: "docsrs-%".into(),
+                    priority: -100,
+                    crates: vec!["docsrs-database".into()],
+                }]
+            );
+            assert_eq!(find_priority_overlaps(&mut conn, "docsrs-%")?, Vec::new());
+            assert_eq!(
+                find_priority_overlaps(&mut conn, "%s-%")?[0].crates,
+                vec!["docsrs-database", "docsrs-queued", "docsrs-web"]
+            );
+            assert!(find_priority_overlaps(&mut conn, "unrelated-%")?.is_empty());
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn get_default_priority() {
         wrapper(|env| {
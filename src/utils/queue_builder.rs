@@ -13,6 +13,26 @@ pub fn queue_builder(
     build_queue: Arc<BuildQueue>,
     config: Arc<Config>,
 ) -> Result<(), Error> {
+    // same name as stored in the builds of this server
+    let hostname = hostname::get()?.to_str().unwrap_or("").to_owned();
+
+    // a build server that just started can't have any running builds
+    build_queue.send_heartbeat(&hostname)?;
+    build_queue.fail_orphaned_builds(Some(&hostname))?;
+
+    thread::Builder::new()
+        .name("build server heartbeat".into())
+        .spawn({
+            let build_queue = build_queue.clone();
+            let interval = config.build_server_heartbeat_interval;
+            move || loop {
+                thread::sleep(interval);
+                if let Err(err) = build_queue.send_heartbeat(&hostname) {
+                    report_error(&err.context("failed to send build server heartbeat"));
+                }
+            }
+        })?;
+
     loop {
         if let Err(e) = remove_tempdirs(&config.temp_dir) {
             report_error(&anyhow::anyhow!(e).context(format!(
@@ -24,7 +44,12 @@ pub fn queue_builder(
         // check lock file
         match build_queue.is_locked().context("could not get queue lock") {
             Ok(true) => {
-                warn!("Build queue is locked, skipping building new crates");
+                match build_queue.lock_message() {
+                    Ok(Some(message)) => {
+                        warn!(%message, "Build queue is locked, skipping building new crates")
+                    }
+                    _ => warn!("Build queue is locked, skipping building new crates"),
+                }
                 thread::sleep(Duration::from_secs(60));
                 continue;
             }
@@ -36,6 +61,10 @@ pub fn queue_builder(
             }
         }
 
+        if let Err(err) = build_queue.fail_orphaned_builds(None) {
+            report_error(&err.context("failed to mark orphaned builds as failed"));
+        }
+
         // If a panic occurs while building a crate, lock the queue until an admin has a chance to look at it.
         debug!("Checking build queue");
         let res = catch_unwind(AssertUnwindSafe(|| {
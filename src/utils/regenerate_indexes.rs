@@ -0,0 +1,252 @@
+use crate::{
+    storage::{rustdoc_archive_path, source_archive_path, PathNotFoundError},
+    AsyncStorage,
+};
+use anyhow::{Context as _, Result};
+
+/// The indexes [`regenerate_release_indexes`] recreated.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RegeneratedIndexes {
+    /// The path of each archive, with the number of files in its new index.
+    pub archives: Vec<(String, usize)>,
+    /// The `search-index.js` files in the rustdoc archive.
+    pub search_indexes: Vec<String>,
+}
+
+/// Recreates the indexes of the stored archives of a release from the archives themselves,
+/// without rebuilding its documentation.
+///
+/// `search-index.js` is generated by rustdoc and served from the rustdoc archive, so it is
+/// regenerated together with the archive index. Since it is cached forever in the CDN,
+/// callers have to invalidate the CDN for the crate when any `search_indexes` were found.
+pub async fn regenerate_release_indexes(
+    conn: &mut sqlx::PgConnection,
+    storage: &AsyncStorage,
+    name: &str,
+    version: &str,
+) -> Result<RegeneratedIndexes> {
+    let mut regenerated = RegeneratedIndexes::default();
+
+    let rustdoc_archive = rustdoc_archive_path(name, version);
+    for archive_path in [rustdoc_archive.clone(), source_archive_path(name, version)] {
+        let file_paths = match storage.regenerate_archive_index(&archive_path).await {
+            Ok(file_paths) => file_paths,
+            Err(err) if err.downcast_ref::<PathNotFoundError>().is_some() => continue,
+            Err(err) => {
+                return Err(err.context(format!("failed to regenerate the index of {archive_path}")))
+            }
+        };
+
+        if archive_path == rustdoc_archive {
+            regenerated.search_indexes = file_paths
+                .iter()
+                .filter(|path| is_search_index(path))
+                .cloned()
+                .collect();
+        }
+        regenerated.archives.push((archive_path, file_paths.len()));
+    }
+
+    if regenerated.archives.is_empty() {
+        anyhow::bail!("{name}-{version} has no archives in storage");
+    }
+
+    invalidate_cached_archive_indexes(conn, name, version).await?;
+
+    Ok(regenerated)
+}
+
+/// Recreates the index of the stored archive at `archive_path` from the archive itself,
+/// and returns the number of files in the new index.
+///
+/// When the archive belongs to a release, the web servers are made to drop their cached
+/// copies of the old index too.
+pub async fn regenerate_archive_index(
+    conn: &mut sqlx::PgConnection,
+    storage: &AsyncStorage,
+    archive_path: &str,
+) -> Result<usize> {
+    let file_count = storage
+        .regenerate_archive_index(archive_path)
+        .await
+        .with_context(|| format!("failed to regenerate the index of {archive_path}"))?
+        .len();
+
+    if let Some((name, version)) = release_of_archive(archive_path) {
+        invalidate_cached_archive_indexes(conn, name, version).await?;
+    }
+
+    Ok(file_count)
+}
+
+/// The web servers keep using their cached copies of the old indexes until the cache key
+/// of the release changes.
+async fn invalidate_cached_archive_indexes(
+    conn: &mut sqlx::PgConnection,
+    name: &str,
+    version: &str,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE releases
+         SET archive_index_regeneration = nextval('builds_id_seq')
+         FROM crates
+         WHERE
+            crates.id = releases.crate_id AND
+            crates.name = $1 AND
+            releases.version = $2",
+        name,
+        version,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// The name and version of the release an archive path like `rustdoc/{name}/{version}.zip`
+/// belongs to.
+fn release_of_archive(archive_path: &str) -> Option<(&str, &str)> {
+    archive_path
+        .strip_prefix("rustdoc/")
+        .or_else(|| archive_path.strip_prefix("sources/"))?
+        .strip_suffix(".zip")?
+        .split_once('/')
+}
+
+fn is_search_index(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    file_name.starts_with("search-index") && file_name.ends_with(".js")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::Blob, test::async_wrapper};
+    use chrono::Utc;
+
+    async fn archive_index_regeneration(
+        conn: &mut sqlx::PgConnection,
+        name: &str,
+    ) -> Result<Option<i32>> {
+        Ok(sqlx::query_scalar!(
+            "SELECT releases.archive_index_regeneration
+             FROM releases
+             INNER JOIN crates ON crates.id = releases.crate_id
+             WHERE crates.name = $1",
+            name
+        )
+        .fetch_one(&mut *conn)
+        .await?)
+    }
+
+    #[test]
+    fn test_release_of_archive() {
+        assert_eq!(
+            release_of_archive("rustdoc/foo/0.1.0.zip"),
+            Some(("foo", "0.1.0"))
+        );
+        assert_eq!(
+            release_of_archive("sources/foo/0.1.0.zip"),
+            Some(("foo", "0.1.0"))
+        );
+        assert_eq!(release_of_archive("folder/test.zip"), None);
+        assert_eq!(release_of_archive("rustdoc/foo/0.1.0.zip.index"), None);
+    }
+
+    #[test]
+    fn test_regenerate_release_indexes() {
+        async_wrapper(|env| async move {
+            env.async_fake_release()
+                .await
+                .name("foo")
+                .version("0.1.0")
+                .archive_storage(true)
+                .rustdoc_file("search-index.js")
+                .create_async()
+                .await?;
+
+            let mut conn = env.async_db().await.async_conn().await;
+            assert_eq!(archive_index_regeneration(&mut conn, "foo").await?, None);
+
+            let regenerated =
+                regenerate_release_indexes(&mut conn, &*env.async_storage().await, "foo", "0.1.0")
+                    .await?;
+
+            assert_eq!(
+                regenerated
+                    .archives
+                    .iter()
+                    .map(|(path, _)| path.as_str())
+                    .collect::<Vec<_>>(),
+                ["rustdoc/foo/0.1.0.zip", "sources/foo/0.1.0.zip"]
+            );
+            assert_eq!(regenerated.search_indexes, ["search-index.js"]);
+            assert!(archive_index_regeneration(&mut conn, "foo")
+                .await?
+                .is_some());
+
+            assert!(regenerate_release_indexes(
+                &mut conn,
+                &*env.async_storage().await,
+                "foo",
+                "0.2.0"
+            )
+            .await
+            .is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_regenerate_archive_index_by_path() {
+        async_wrapper(|env| async move {
+            env.async_fake_release()
+                .await
+                .name("foo")
+                .version("0.1.0")
+                .archive_storage(true)
+                .rustdoc_file("search-index.js")
+                .create_async()
+                .await?;
+
+            let storage = env.async_storage().await;
+            storage
+                .store_blobs(vec![Blob {
+                    path: "rustdoc/foo/0.1.0.zip.index".into(),
+                    mime: "application/octet-stream".into(),
+                    content: b"broken".to_vec(),
+                    compression: None,
+                    date_updated: Utc::now(),
+                }])
+                .await?;
+
+            let mut conn = env.async_db().await.async_conn().await;
+            assert_eq!(
+                regenerate_archive_index(&mut conn, &storage, "rustdoc/foo/0.1.0.zip").await?,
+                2
+            );
+
+            let latest_build_id = archive_index_regeneration(&mut conn, "foo")
+                .await?
+                .expect("the cache key of the release was not changed");
+            let file = storage
+                .get_from_archive(
+                    "rustdoc/foo/0.1.0.zip",
+                    latest_build_id,
+                    "search-index.js",
+                    usize::MAX,
+                )
+                .await?;
+            assert_eq!(file.path, "rustdoc/foo/0.1.0.zip/search-index.js");
+
+            assert!(
+                regenerate_archive_index(&mut conn, &storage, "rustdoc/foo/0.2.0.zip")
+                    .await
+                    .is_err()
+            );
+
+            Ok(())
+        })
+    }
+}
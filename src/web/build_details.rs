@@ -17,6 +17,9 @@ use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+/// Suffix of the cargo timing reports the builder stores next to the build logs.
+const TIMING_REPORT_SUFFIX: &str = "-timings.html";
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub(crate) struct BuildDetails {
     id: i32,
@@ -34,6 +37,7 @@ struct BuildDetailsPage {
     build_details: BuildDetails,
     use_direct_platform_links: bool,
     all_log_filenames: Vec<String>,
+    timing_reports: Vec<String>,
     current_filename: Option<String>,
 }
 
@@ -78,40 +82,55 @@ pub(crate) async fn build_details_handler(
     .await?
     .ok_or(AxumNope::BuildNotFound)?;
 
-    let (output, all_log_filenames, current_filename) = if let Some(output) = row.output {
+    let prefix = format!("build-logs/{}/", id);
+
+    // cargo timing reports are stored next to the build logs, and are complete
+    // HTML pages, so we serve them as they are.
+    if let Some(filename) = params
+        .filename
+        .as_ref()
+        .filter(|filename| filename.ends_with(TIMING_REPORT_SUFFIX))
+    {
+        let path = format!("{prefix}{filename}");
+        return Ok(File::from_path(&storage, &path, &config)
+            .await?
+            .into_response());
+    }
+
+    let (output, all_filenames, current_filename) = if let Some(output) = row.output {
         (output, Vec::new(), None)
+    } else if let Some(current_filename) = params
+        .filename
+        .or(row.default_target.map(|target| format!("{}.txt", target)))
+    {
+        let path = format!("{prefix}{current_filename}");
+        let file = File::from_path(&storage, &path, &config).await?;
+        (
+            String::from_utf8(file.0.content).context("non utf8")?,
+            storage
+                .list_prefix(&prefix) // the result from S3 is ordered by key
+                .await
+                .map_ok(|path| {
+                    path.strip_prefix(&prefix)
+                        .expect("since we query for the prefix, it has to be always there")
+                        .to_owned()
+                })
+                .try_collect()
+                .await?,
+            Some(current_filename),
+        )
     } else {
-        let prefix = format!("build-logs/{}/", id);
-
-        if let Some(current_filename) = params
-            .filename
-            .or(row.default_target.map(|target| format!("{}.txt", target)))
-        {
-            let path = format!("{prefix}{current_filename}");
-            let file = File::from_path(&storage, &path, &config).await?;
-            (
-                String::from_utf8(file.0.content).context("non utf8")?,
-                storage
-                    .list_prefix(&prefix) // the result from S3 is ordered by key
-                    .await
-                    .map_ok(|path| {
-                        path.strip_prefix(&prefix)
-                            .expect("since we query for the prefix, it has to be always there")
-                            .to_owned()
-                    })
-                    .try_collect()
-                    .await?,
-                Some(current_filename),
-            )
-        } else {
-            // this can only happen when `releases.default_target` is NULL,
-            // which is the case for in-progress builds or builds which errored
-            // before we could determine the target.
-            // For the "error" case we show `row.errors`, which should contain what we need to see.
-            ("".into(), Vec::new(), None)
-        }
+        // this can only happen when `releases.default_target` is NULL,
+        // which is the case for in-progress builds or builds which errored
+        // before we could determine the target.
+        // For the "error" case we show `row.errors`, which should contain what we need to see.
+        ("".into(), Vec::new(), None)
     };
 
+    let (timing_reports, all_log_filenames) = all_filenames
+        .into_iter()
+        .partition(|filename: &String| filename.ends_with(TIMING_REPORT_SUFFIX));
+
     Ok(BuildDetailsPage {
         metadata: MetaData::from_crate(&mut conn, &params.name, &params.version, None).await?,
         build_details: BuildDetails {
@@ -125,6 +144,7 @@ pub(crate) async fn build_details_handler(
         },
         use_direct_platform_links: true,
         all_log_filenames,
+        timing_reports,
         current_filename,
     }
     .into_response())
@@ -329,6 +349,70 @@ mod tests {
         });
     }
 
+    #[test]
+    fn timing_report_is_linked() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .builds(vec![FakeBuild::default().s3_build_log("A build log")])
+                .create()?;
+
+            let page = kuchikiki::parse_html().one(
+                env.frontend()
+                    .get("/crate/foo/0.1.0/builds")
+                    .send()?
+                    .text()?,
+            );
+
+            let node = page.select("ul > li a.release").unwrap().next().unwrap();
+            let attrs = node.attributes.borrow();
+            let build_url = attrs.get("href").unwrap();
+            let build_id = build_url.rsplit('/').next().unwrap();
+
+            env.storage().store_one(
+                format!("build-logs/{build_id}/x86_64-unknown-linux-gnu-timings.html"),
+                "<html><body>Cargo Build Timings</body></html>",
+            )?;
+
+            let page = kuchikiki::parse_html().one(env.frontend().get(build_url).send()?.text()?);
+
+            let timing_links: Vec<_> = page
+                .select("ul > li a.timing-report")
+                .unwrap()
+                .map(|el| el.attributes.borrow().get("href").unwrap().to_string())
+                .collect();
+            assert_eq!(
+                timing_links,
+                vec![format!("{build_url}/x86_64-unknown-linux-gnu-timings.html")]
+            );
+
+            let all_log_links = get_all_log_links(&page);
+            assert_eq!(
+                all_log_links[0],
+                (
+                    "x86_64-unknown-linux-gnu.txt".into(),
+                    format!("{build_url}/x86_64-unknown-linux-gnu.txt"),
+                )
+            );
+
+            let response = env.frontend().get(&timing_links[0]).send()?;
+            assert!(response.status().is_success());
+            assert!(response
+                .headers()
+                .get("content-type")
+                .unwrap()
+                .to_str()?
+                .starts_with("text/html"));
+            assert_eq!(
+                response.text()?,
+                "<html><body>Cargo Build Timings</body></html>"
+            );
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn both_build_logs() {
         wrapper(|env| {
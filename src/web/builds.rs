@@ -13,7 +13,7 @@ use crate::{
         extractors::{DbConnection, Path},
         match_version, MetaData, ReqVersion,
     },
-    BuildQueue, Config,
+    BuildQueue, Config, QueueSource,
 };
 use anyhow::{anyhow, Result};
 use axum::{
@@ -203,6 +203,31 @@ pub(crate) async fn build_trigger_rebuild_handler(
         .await
         .map_err(JsonAxumNope)?;
 
+    let last_trigger = spawn_blocking({
+        let name = name.clone();
+        let version_string = version.to_string();
+        let build_queue = build_queue.clone();
+        move || build_queue.record_web_trigger(&name, &version_string)
+    })
+    .await
+    .map_err(|e| JsonAxumNope(e.into()))?;
+    if let Some(last_trigger) = last_trigger {
+        let next_trigger = last_trigger
+            + chrono::Duration::from_std(config.web_rebuild_trigger_interval)
+                .map_err(|e| JsonAxumNope(AxumNope::InternalError(e.into())))?;
+        let retry_after = (next_trigger - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        return Err(JsonAxumNope(AxumNope::TooManyRequests(
+            anyhow!(
+                "crate {name} {version} was already rebuilt recently, \
+                 the next rebuild can be triggered after {}",
+                next_trigger.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+            ),
+            retry_after,
+        )));
+    }
+
     spawn_blocking({
         let name = name.clone();
         let version_string = version.to_string();
@@ -212,6 +237,7 @@ pub(crate) async fn build_trigger_rebuild_handler(
                 &version_string,
                 TRIGGERED_REBUILD_PRIORITY,
                 None, /* because crates.io is the only service that calls this endpoint */
+                QueueSource::WebTrigger,
             )
         }
     })
@@ -532,6 +558,52 @@ mod tests {
         });
     }
 
+    #[test]
+    fn build_trigger_rebuild_rate_limited() {
+        wrapper(|env| {
+            let correct_token = "foo137";
+            env.override_config(|config| {
+                config.cratesio_token = Some(correct_token.into());
+                config.web_rebuild_trigger_interval = std::time::Duration::from_secs(3600);
+            });
+
+            env.fake_release().name("foo").version("0.1.0").create()?;
+
+            let response = env
+                .frontend()
+                .post("/crate/foo/0.1.0/rebuild")
+                .bearer_auth(correct_token)
+                .send()?;
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            // the release was built, but can't be rebuilt again right away
+            env.db().conn().execute("DELETE FROM queue", &[])?;
+            let response = env
+                .frontend()
+                .post("/crate/foo/0.1.0/rebuild")
+                .bearer_auth(correct_token)
+                .send()?;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            let retry_after: u64 = response
+                .headers()
+                .get("retry-after")
+                .expect("missing retry-after header")
+                .to_str()?
+                .parse()?;
+            assert!((3500..=3600).contains(&retry_after), "{retry_after}");
+            let json: serde_json::Value = response.json()?;
+            assert_eq!(json["title"], "Too many requests");
+            assert!(json["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("crate foo 0.1.0 was already rebuilt recently"));
+
+            assert_eq!(env.build_queue().pending_count()?, 0);
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn build_empty_list() {
         wrapper(|env| {
@@ -145,16 +145,20 @@ impl CrateDetails {
                 releases.description_long,
                 releases.release_time,
                 release_build_status.build_status as "build_status!: BuildStatus",
-                (
+                GREATEST(
                     -- this is the latest build ID that generated content
                     -- it's used to invalidate some blob storage related caches.
-                    SELECT id
-                    FROM builds
-                    WHERE
-                        builds.rid = releases.id AND
-                        builds.build_status = 'success'
-                    ORDER BY build_time DESC
-                    LIMIT 1
+                    (
+                        SELECT id
+                        FROM builds
+                        WHERE
+                            builds.rid = releases.id AND
+                            builds.build_status = 'success'
+                        ORDER BY build_time DESC
+                        LIMIT 1
+                    ),
+                    -- the archive indexes might have been regenerated since then
+                    releases.archive_index_regeneration
                 ) AS latest_build_id,
                 releases.rustdoc_status,
                 releases.archive_storage,
@@ -452,7 +456,12 @@ pub(crate) async fn crate_details_handler(
 
     let matched_release = match_version(&mut conn, &params.name, &req_version)
         .await?
-        .assume_exact_name()?
+        .into_exactly_named_or_else(|corrected_name, req_version| {
+            AxumNope::Redirect(
+                encode_url_path(&format!("/crate/{corrected_name}/{req_version}")),
+                CachePolicy::NoCaching,
+            )
+        })?
         .into_canonical_req_version_or_else(|version| {
             AxumNope::Redirect(
                 format!("/crate/{}/{}", &params.name, version),
@@ -1218,6 +1227,37 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_latest_build_id_changes_with_regenerated_archive_index() {
+        async_wrapper(|env| async move {
+            env.async_fake_release()
+                .await
+                .name("foo")
+                .version("0.1.0")
+                .create_async()
+                .await?;
+
+            let mut conn = env.async_db().await.async_conn().await;
+            let build_id = crate_details(&mut conn, "foo", "0.1.0", None)
+                .await
+                .latest_build_id
+                .unwrap();
+
+            sqlx::query!(
+                "UPDATE releases SET archive_index_regeneration = nextval('builds_id_seq')"
+            )
+            .execute(&mut *conn)
+            .await?;
+            let cache_key = crate_details(&mut conn, "foo", "0.1.0", None)
+                .await
+                .latest_build_id
+                .unwrap();
+            assert!(cache_key > build_id);
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn releases_dropdowns_show_binary_warning() {
         wrapper(|env| {
@@ -1666,6 +1706,27 @@ mod tests {
         });
     }
 
+    #[test]
+    fn crate_alias_redirects_to_target() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("new-name")
+                .version("0.1.0")
+                .create()?;
+            crate::db::crate_aliases::add_alias(&mut env.db().conn(), "old-name", "new-name")?;
+
+            let web = env.frontend();
+            assert_redirect("/crate/old-name/latest", "/crate/new-name/latest", web)?;
+            assert_redirect("/crate/old-name/0.1.0", "/crate/new-name/0.1.0", web)?;
+            assert_eq!(
+                web.get("/crate/unknown-name/latest").send()?.status(),
+                StatusCode::NOT_FOUND
+            );
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn check_crate_name_in_redirect() {
         fn check_links(env: &TestEnvironment, url: &str, links: Vec<String>) {
@@ -1,16 +1,16 @@
 use crate::{
     db::PoolError,
-    storage::PathNotFoundError,
+    storage::{PathNotFoundError, StorageUnavailableError},
     web::{cache::CachePolicy, encode_url_path, releases::Search},
 };
 use anyhow::anyhow;
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response as AxumResponse},
     Json,
 };
 use std::borrow::Cow;
-use tracing::error;
+use tracing::{error, warn};
 
 use super::AxumErrorPage;
 
@@ -32,8 +32,13 @@ pub enum AxumNope {
     Unauthorized(&'static str),
     #[error("internal error")]
     InternalError(anyhow::Error),
+    #[error("storage unavailable")]
+    StorageUnavailable(anyhow::Error),
     #[error("bad request")]
     BadRequest(anyhow::Error),
+    /// the request was rate limited, and can be retried after the duration
+    #[error("too many requests")]
+    TooManyRequests(anyhow::Error, std::time::Duration),
     #[error("redirect")]
     Redirect(String, CachePolicy),
 }
@@ -91,6 +96,11 @@ impl AxumNope {
                 message: Cow::Owned(source.to_string()),
                 status: StatusCode::BAD_REQUEST,
             },
+            AxumNope::TooManyRequests(source, _retry_after) => ErrorInfo {
+                title: "Too many requests",
+                message: Cow::Owned(source.to_string()),
+                status: StatusCode::TOO_MANY_REQUESTS,
+            },
             AxumNope::Unauthorized(what) => ErrorInfo {
                 title: "Unauthorized",
                 message: what.into(),
@@ -104,11 +114,40 @@ impl AxumNope {
                     status: StatusCode::INTERNAL_SERVER_ERROR,
                 }
             }
+            AxumNope::StorageUnavailable(source) => {
+                // not reported, an outage would report every request
+                warn!(?source, "storage unavailable");
+                ErrorInfo {
+                    title: "Service Unavailable",
+                    message: "the documentation storage is temporarily unavailable, please try again in a minute".into(),
+                    status: StatusCode::SERVICE_UNAVAILABLE,
+                }
+            }
             AxumNope::Redirect(_target, _cache_policy) => unreachable!(),
         }
     }
 }
 
+/// Tells clients and the CDN when to retry a request that failed because the storage
+/// was unavailable. The response is cached shortly, so an outage doesn't put even more
+/// load on the backend.
+fn add_retry_hint(response: &mut AxumResponse) {
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from_static("60"));
+    response
+        .extensions_mut()
+        .insert(CachePolicy::ShortInCdnAndBrowser);
+}
+
+/// Tells clients after how many seconds they can retry a rate limited request.
+fn add_retry_after(response: &mut AxumResponse, retry_after: std::time::Duration) {
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(retry_after.as_secs().max(1)),
+    );
+}
+
 struct ErrorInfo {
     // For the title of the page
     pub title: &'static str,
@@ -139,17 +178,29 @@ impl IntoResponse for AxumNope {
             }
             AxumNope::Redirect(target, cache_policy) => redirect_with_policy(target, cache_policy),
             _ => {
+                let unavailable = matches!(self, AxumNope::StorageUnavailable(_));
+                let retry_after = match &self {
+                    AxumNope::TooManyRequests(_, retry_after) => Some(*retry_after),
+                    _ => None,
+                };
                 let ErrorInfo {
                     title,
                     message,
                     status,
                 } = self.into_error_info();
-                AxumErrorPage {
+                let mut response = AxumErrorPage {
                     title,
                     message,
                     status,
                 }
-                .into_response()
+                .into_response();
+                if unavailable {
+                    add_retry_hint(&mut response);
+                }
+                if let Some(retry_after) = retry_after {
+                    add_retry_after(&mut response, retry_after);
+                }
+                response
             }
         }
     }
@@ -168,19 +219,31 @@ impl IntoResponse for JsonAxumNope {
             }
             AxumNope::Redirect(target, cache_policy) => redirect_with_policy(target, cache_policy),
             _ => {
+                let unavailable = matches!(self.0, AxumNope::StorageUnavailable(_));
+                let retry_after = match &self.0 {
+                    AxumNope::TooManyRequests(_, retry_after) => Some(*retry_after),
+                    _ => None,
+                };
                 let ErrorInfo {
                     title,
                     message,
                     status,
                 } = self.0.into_error_info();
-                (
+                let mut response = (
                     status,
                     Json(serde_json::json!({
                         "title": title,
                         "message": message,
                     })),
                 )
-                    .into_response()
+                    .into_response();
+                if unavailable {
+                    add_retry_hint(&mut response);
+                }
+                if let Some(retry_after) = retry_after {
+                    add_retry_after(&mut response, retry_after);
+                }
+                response
             }
         }
     }
@@ -192,6 +255,9 @@ impl From<anyhow::Error> for AxumNope {
             Ok(axum_nope) => axum_nope,
             Err(err) => match err.downcast::<PathNotFoundError>() {
                 Ok(_) => AxumNope::ResourceNotFound,
+                Err(err) if err.is::<StorageUnavailableError>() => {
+                    AxumNope::StorageUnavailable(err)
+                }
                 Err(err) => AxumNope::InternalError(err),
             },
         }
@@ -216,6 +282,7 @@ pub(crate) type JsonAxumResult<T> = Result<T, JsonAxumNope>;
 #[cfg(test)]
 mod tests {
     use super::{AxumNope, IntoResponse};
+    use crate::storage::StorageUnavailableError;
     use crate::{test::wrapper, web::cache::CachePolicy};
     use kuchikiki::traits::TendrilSink;
 
@@ -229,6 +296,19 @@ mod tests {
         assert_eq!(response.headers().get("Location").unwrap(), "/something%3E");
     }
 
+    #[test]
+    fn test_storage_unavailable_error() {
+        let err = anyhow::anyhow!("timeout").context(StorageUnavailableError);
+        let response = AxumNope::from(err).into_response();
+
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "60");
+        assert!(matches!(
+            response.extensions().get::<CachePolicy>(),
+            Some(CachePolicy::ShortInCdnAndBrowser)
+        ));
+    }
+
     #[test]
     fn check_404_page_content_crate() {
         wrapper(|env| {
@@ -113,7 +113,9 @@ pub(crate) async fn request_recorder(
 #[cfg(test)]
 mod tests {
     use crate::test::wrapper;
+    use crate::utils::{set_config, ConfigName};
     use crate::Context;
+    use chrono::Utc;
     use std::collections::HashMap;
 
     #[test]
@@ -236,6 +238,32 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_metrics_page_compression() {
+        wrapper(|env| {
+            let response = env
+                .frontend()
+                .get("/about/metrics")
+                .header("Accept-Encoding", "zstd")
+                .send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["Content-Encoding"], "zstd");
+
+            let body = String::from_utf8(zstd::decode_all(&*response.bytes()?)?)?;
+            assert!(body.contains("docsrs_failed_builds"), "{}", body);
+
+            let response = env
+                .frontend()
+                .get("/about/metrics")
+                .header("Accept-Encoding", "identity")
+                .send()?;
+            assert!(response.status().is_success());
+            assert!(response.headers().get("Content-Encoding").is_none());
+            assert!(response.text()?.contains("docsrs_failed_builds"));
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_service_metrics_page_success() {
         wrapper(|env| {
@@ -249,6 +277,41 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_service_metrics_queue_locked_seconds() {
+        wrapper(|env| {
+            let body = env
+                .frontend()
+                .get("/about/metrics/service")
+                .send()?
+                .text()?;
+            assert!(body.contains("docsrs_queue_locked_seconds 0"), "{}", body);
+
+            env.build_queue().lock(None)?;
+            set_config(
+                &mut env.db().conn(),
+                ConfigName::QueueLockedSince,
+                Utc::now() - chrono::Duration::hours(1),
+            )?;
+            let body = env
+                .frontend()
+                .get("/about/metrics/service")
+                .send()?
+                .text()?;
+            assert!(body.contains("docsrs_queue_is_locked 1"), "{}", body);
+            assert!(body.contains("docsrs_queue_locked_seconds 360"), "{}", body);
+
+            env.build_queue().unlock()?;
+            let body = env
+                .frontend()
+                .get("/about/metrics/service")
+                .send()?
+                .text()?;
+            assert!(body.contains("docsrs_queue_locked_seconds 0"), "{}", body);
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_instance_metrics_page_success() {
         wrapper(|env| {
@@ -7,6 +7,7 @@ use crate::utils::get_correct_docsrs_style_file;
 use crate::utils::report_error;
 use anyhow::{anyhow, bail, Context as _, Result};
 use axum_extra::middleware::option_layer;
+use axum_server::tls_rustls::RustlsConfig;
 use serde_json::Value;
 use tracing::{info, instrument};
 
@@ -34,7 +35,7 @@ mod status;
 use crate::{impl_axum_webpage, Context};
 use anyhow::Error;
 use axum::{
-    extract::{Extension, MatchedPath, Request as AxumRequest},
+    extract::{Extension, MatchedPath, Request as AxumRequest, State},
     http::StatusCode,
     middleware,
     middleware::Next,
@@ -52,14 +53,20 @@ use std::{
     borrow::{Borrow, Cow},
     fmt::{self, Display},
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
     str::FromStr,
     sync::Arc,
 };
 use tower::ServiceBuilder;
-use tower_http::{catch_panic::CatchPanicLayer, timeout::TimeoutLayer, trace::TraceLayer};
+use tower_http::{
+    catch_panic::CatchPanicLayer, limit::RequestBodyLimitLayer, timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
 use url::form_urlencoded;
 
 use self::crate_details::Release;
+pub use self::rustdoc::apply_docsrs_theme;
+pub use self::sitemap::write_sitemap;
 
 // from https://github.com/servo/rust-url/blob/master/url/src/parser.rs
 // and https://github.com/tokio-rs/axum/blob/main/axum-extra/src/lib.rs
@@ -120,7 +127,7 @@ pub(crate) struct MatchedRelease {
 
     /// The crate name that was found when attempting to load a crate release.
     /// `match_version` will attempt to match a provided crate name against similar crate names with
-    /// dashes (`-`) replaced with underscores (`_`) and vice versa.
+    /// dashes (`-`) replaced with underscores (`_`) and vice versa, and against crate aliases.
     pub corrected_name: Option<String>,
 
     /// what kind of version did we get in the request? ("latest", semver, exact)
@@ -240,8 +247,9 @@ impl MatchedRelease {
 /// will indicate whether the given version exactly matched a version number from the database.
 ///
 /// This function will also check for crates where dashes in the name (`-`) have been replaced with
-/// underscores (`_`) and vice-versa. The return value will indicate whether the crate name has
-/// been matched exactly, or if there has been a "correction" in the name that matched instead.
+/// underscores (`_`) and vice-versa, and for crate aliases pointing to another crate. The return
+/// value will indicate whether the crate name has been matched exactly, or if there has been a
+/// "correction" in the name that matched instead.
 #[instrument(skip(conn))]
 async fn match_version(
     conn: &mut sqlx::PgConnection,
@@ -258,12 +266,28 @@ async fn match_version(
         .fetch_optional(&mut *conn)
         .await
         .context("error fetching crate")?
-        .ok_or(AxumNope::CrateNotFound)?;
+        .map(|row| (row.id, row.name));
+
+        let (id, found_name) = match row {
+            Some(row) => row,
+            None => sqlx::query!(
+                "SELECT crates.id, crates.name
+                 FROM crate_aliases
+                 INNER JOIN crates ON crates.name = crate_aliases.target
+                 WHERE normalize_crate_name(crate_aliases.name) = normalize_crate_name($1)",
+                name,
+            )
+            .fetch_optional(&mut *conn)
+            .await
+            .context("error fetching crate alias")?
+            .map(|row| (row.id, row.name))
+            .ok_or(AxumNope::CrateNotFound)?,
+        };
 
-        if row.name != name {
-            (row.id, Some(row.name))
+        if found_name != name {
+            (id, Some(found_name))
         } else {
-            (row.id, None)
+            (id, None)
         }
     };
 
@@ -358,6 +382,19 @@ async fn log_timeouts_to_sentry(req: AxumRequest, next: Next) -> AxumResponse {
     response
 }
 
+/// Rejects requests with an URI longer than the configured maximum.
+async fn limit_uri_length(
+    State(max_uri_length): State<usize>,
+    req: AxumRequest,
+    next: Next,
+) -> AxumResponse {
+    if req.uri().to_string().len() > max_uri_length {
+        return StatusCode::URI_TOO_LONG.into_response();
+    }
+
+    next.run(req).await
+}
+
 async fn set_sentry_transaction_name_from_axum_route(
     request: AxumRequest,
     next: Next,
@@ -383,35 +420,41 @@ fn apply_middleware(
     let config = context.config()?;
     let has_templates = template_data.is_some();
     let async_storage = context.runtime()?.block_on(context.async_storage())?;
-    Ok(router.layer(
-        ServiceBuilder::new()
-            .layer(TraceLayer::new_for_http())
-            .layer(sentry_tower::NewSentryLayer::new_from_top())
-            .layer(sentry_tower::SentryHttpLayer::with_transaction())
-            .layer(middleware::from_fn(
-                set_sentry_transaction_name_from_axum_route,
-            ))
-            .layer(CatchPanicLayer::new())
-            .layer(option_layer(
-                config
-                    .report_request_timeouts
-                    .then_some(middleware::from_fn(log_timeouts_to_sentry)),
-            ))
-            .layer(option_layer(config.request_timeout.map(TimeoutLayer::new)))
-            .layer(Extension(context.pool()?))
-            .layer(Extension(context.build_queue()?))
-            .layer(Extension(context.service_metrics()?))
-            .layer(Extension(context.instance_metrics()?))
-            .layer(Extension(context.config()?))
-            .layer(Extension(context.storage()?))
-            .layer(Extension(async_storage))
-            .layer(option_layer(template_data.map(Extension)))
-            .layer(middleware::from_fn(csp::csp_middleware))
-            .layer(option_layer(has_templates.then_some(middleware::from_fn(
-                page::web_page::render_templates_middleware,
-            ))))
-            .layer(middleware::from_fn(cache::cache_middleware)),
-    ))
+    Ok(router
+        .layer(RequestBodyLimitLayer::new(config.max_request_body_size))
+        .layer(
+            ServiceBuilder::new()
+                .layer(TraceLayer::new_for_http())
+                .layer(middleware::from_fn_with_state(
+                    config.max_uri_length,
+                    limit_uri_length,
+                ))
+                .layer(sentry_tower::NewSentryLayer::new_from_top())
+                .layer(sentry_tower::SentryHttpLayer::with_transaction())
+                .layer(middleware::from_fn(
+                    set_sentry_transaction_name_from_axum_route,
+                ))
+                .layer(CatchPanicLayer::new())
+                .layer(option_layer(
+                    config
+                        .report_request_timeouts
+                        .then_some(middleware::from_fn(log_timeouts_to_sentry)),
+                ))
+                .layer(option_layer(config.request_timeout.map(TimeoutLayer::new)))
+                .layer(Extension(context.pool()?))
+                .layer(Extension(context.build_queue()?))
+                .layer(Extension(context.service_metrics()?))
+                .layer(Extension(context.instance_metrics()?))
+                .layer(Extension(context.config()?))
+                .layer(Extension(context.storage()?))
+                .layer(Extension(async_storage))
+                .layer(option_layer(template_data.map(Extension)))
+                .layer(middleware::from_fn(csp::csp_middleware))
+                .layer(option_layer(has_templates.then_some(middleware::from_fn(
+                    page::web_page::render_templates_middleware,
+                ))))
+                .layer(middleware::from_fn(cache::cache_middleware)),
+        ))
 }
 
 pub(crate) fn build_axum_app(
@@ -462,16 +505,30 @@ pub fn start_background_metrics_webserver(
     Ok(())
 }
 
+/// Certificate and private key used to terminate TLS in the web server.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// Path to the PEM encoded certificate chain
+    pub cert: PathBuf,
+    /// Path to the PEM encoded private key
+    pub key: PathBuf,
+}
+
 #[instrument(skip_all)]
-pub fn start_web_server(addr: Option<SocketAddr>, context: &dyn Context) -> Result<(), Error> {
+pub fn start_web_server(
+    addr: Option<SocketAddr>,
+    tls: Option<TlsConfig>,
+    context: &dyn Context,
+) -> Result<(), Error> {
     let template_data = Arc::new(TemplateData::new(context.config()?.render_threads)?);
 
     let axum_addr = addr.unwrap_or(DEFAULT_BIND);
 
     tracing::info!(
-        "Starting web server on `{}:{}`",
+        "Starting web server on `{}:{}`{}",
         axum_addr.ip(),
-        axum_addr.port()
+        axum_addr.port(),
+        if tls.is_some() { " with TLS" } else { "" },
     );
 
     // initialize the storage and the repo-updater in sync context
@@ -482,13 +539,40 @@ pub fn start_web_server(addr: Option<SocketAddr>, context: &dyn Context) -> Resu
 
     let app = build_axum_app(context, template_data)?.into_make_service();
     context.runtime()?.block_on(async {
-        let listener = tokio::net::TcpListener::bind(axum_addr)
-            .await
-            .context("error binding socket for metrics web server")?;
+        if let Some(tls) = tls {
+            let rustls_config = RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+                .await
+                .with_context(|| {
+                    format!(
+                        "error loading TLS certificate {} and key {}",
+                        tls.cert.display(),
+                        tls.key.display()
+                    )
+                })?;
+
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown_signal().await;
+                    handle.graceful_shutdown(None);
+                }
+            });
 
-        axum::serve(listener, app)
-            .with_graceful_shutdown(shutdown_signal())
-            .await?;
+            axum_server::bind_rustls(axum_addr, rustls_config)
+                .handle(handle)
+                .serve(app)
+                .await
+                .context("error running the TLS web server")?;
+        } else {
+            let listener = tokio::net::TcpListener::bind(axum_addr)
+                .await
+                .context("error binding socket for metrics web server")?;
+
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await?;
+        }
         Ok::<(), Error>(())
     })?;
 
@@ -1198,6 +1282,29 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_request_size_limits() {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.max_request_body_size = 16;
+                config.max_uri_length = 32;
+            });
+            let web = env.frontend();
+
+            let response = web
+                .post("/crate/regex/1.3.1/rebuild")
+                .body("x".repeat(17))
+                .send()?;
+            assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+            let response = web.get(&format!("/{}", "a".repeat(32))).send()?;
+            assert_eq!(response.status(), StatusCode::URI_TOO_LONG);
+
+            assert_success("/", web)?;
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_axum_redirect() {
         let response = axum_redirect("/something").unwrap().into_response();
@@ -740,6 +740,8 @@ struct BuildQueuePage {
     description: &'static str,
     queue: Vec<QueuedCrate>,
     active_deployments: Vec<String>,
+    locked: bool,
+    lock_message: Option<String>,
 }
 
 impl_axum_webpage! {
@@ -750,7 +752,7 @@ pub(crate) async fn build_queue_handler(
     Extension(build_queue): Extension<Arc<BuildQueue>>,
     Extension(pool): Extension<Pool>,
 ) -> AxumResult<impl IntoResponse> {
-    let (queue, active_deployments) = spawn_blocking(move || {
+    let (queue, active_deployments, locked, lock_message) = spawn_blocking(move || {
         let mut queue = build_queue.queued_crates()?;
         for krate in queue.iter_mut() {
             // The priority here is inverted: in the database if a crate has a higher priority it
@@ -772,7 +774,10 @@ pub(crate) async fn build_queue_handler(
         // reverse the list, so the oldest comes first
         active_deployments.reverse();
 
-        Ok((queue, active_deployments))
+        let locked = build_queue.is_locked()?;
+        let lock_message = build_queue.lock_message()?;
+
+        Ok((queue, active_deployments, locked, lock_message))
     })
     .await?;
 
@@ -780,6 +785,8 @@ pub(crate) async fn build_queue_handler(
         description: "crate documentation scheduled to build & deploy",
         queue,
         active_deployments,
+        locked,
+        lock_message,
     })
 }
 
@@ -793,6 +800,7 @@ mod tests {
         assert_cache_control, assert_redirect, assert_redirect_unchecked, assert_success,
         async_wrapper, fake_release_that_failed_before_build, wrapper, FakeBuild, TestFrontend,
     };
+    use crate::QueueSource;
     use anyhow::Error;
     use chrono::{Duration, TimeZone};
     use kuchikiki::traits::TendrilSink;
@@ -1668,6 +1676,27 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_releases_queue_locked() {
+        wrapper(|env| {
+            let web = env.frontend();
+
+            let page = web.get("/releases/queue").send()?.text()?;
+            assert!(!page.contains("Builds are paused"));
+
+            env.build_queue().lock(Some("planned maintenance"))?;
+            let page = kuchikiki::parse_html().one(web.get("/releases/queue").send()?.text()?);
+            let notice = page
+                .select_first(".queue-locked")
+                .expect("missing lock notice")
+                .text_contents();
+            assert!(notice.contains("Builds are paused"));
+            assert!(notice.contains("planned maintenance"));
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_releases_queue() {
         wrapper(|env| {
@@ -1685,9 +1714,9 @@ mod tests {
                 .expect("missing heading")
                 .any(|el| el.text_contents().contains("active CDN deployments")));
 
-            queue.add_crate("foo", "1.0.0", 0, None)?;
-            queue.add_crate("bar", "0.1.0", -10, None)?;
-            queue.add_crate("baz", "0.0.1", 10, None)?;
+            queue.add_crate("foo", "1.0.0", 0, None, QueueSource::Manual)?;
+            queue.add_crate("bar", "0.1.0", -10, None, QueueSource::Manual)?;
+            queue.add_crate("baz", "0.0.1", 10, None, QueueSource::Manual)?;
 
             let full = kuchikiki::parse_html().one(web.get("/releases/queue").send()?.text()?);
             let items = full
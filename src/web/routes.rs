@@ -11,6 +11,7 @@ use axum::{
 };
 use axum_extra::routing::RouterExt;
 use std::convert::Infallible;
+use tower_http::compression::CompressionLayer;
 use tracing::{debug, instrument};
 
 const INTERNAL_PREFIXES: &[&str] = &["-", "about", "crate", "releases", "sitemap.xml"];
@@ -99,6 +100,8 @@ pub(super) fn build_metric_routes() -> AxumRouter {
             "/about/metrics",
             get_internal(super::metrics::metrics_handler),
         )
+        // the payload gets large with many series, compress it when the scraper accepts it.
+        .layer(CompressionLayer::new())
 }
 
 pub(super) fn build_axum_routes() -> AxumRouter {
@@ -634,6 +634,95 @@ pub(crate) async fn rustdoc_html_server_handler(
         .await?
 }
 
+/// Rewrites the HTML files of a local copy of the documentation of `name` `version` in `dir`
+/// the way [`rustdoc_html_server_handler`] serves them, with the docs.rs header and theme.
+///
+/// The release has to be in the database, e.g. after `build crate --local`. The pages still
+/// load the docs.rs assets from their absolute `/-/static/` and `/-/rustdoc.static/` paths.
+/// Returns the number of rewritten files.
+pub async fn apply_docsrs_theme(
+    conn: &mut sqlx::PgConnection,
+    config: &Config,
+    name: &str,
+    version: &str,
+    dir: &std::path::Path,
+) -> anyhow::Result<usize> {
+    let version: Version = version
+        .parse()
+        .with_context(|| format!("invalid version {version}"))?;
+    let matched_release = match_version(&mut *conn, name, &ReqVersion::Exact(version)).await?;
+    let krate = CrateDetails::from_matched_release(&mut *conn, matched_release).await?;
+    let latest_version = krate.latest_release()?.version.clone();
+    let templates = Arc::new(TemplateData::new(1)?);
+    let max_parse_memory = config.max_parse_memory;
+    let dir = dir.to_path_buf();
+
+    templates
+        .render_in_threadpool(move |templates| {
+            let doc_targets = krate.metadata.doc_targets.clone().unwrap_or_default();
+            let mut count = 0;
+            for entry in walkdir::WalkDir::new(&dir) {
+                let entry = entry?;
+                if !entry.file_type().is_file()
+                    || entry.path().extension().map_or(true, |ext| ext != "html")
+                {
+                    continue;
+                }
+                let relative = entry.path().strip_prefix(&dir)?;
+                let mut inner_path: Vec<_> = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                let target = if inner_path.len() > 1 && doc_targets.contains(&inner_path[0]) {
+                    inner_path.remove(0)
+                } else {
+                    String::new()
+                };
+                let inner_path = inner_path.join("/");
+                let current_target = if target.is_empty() {
+                    krate.metadata.default_target.clone().unwrap_or_default()
+                } else {
+                    target.clone()
+                };
+
+                let page = RustdocPage {
+                    latest_path: format!(
+                        "/crate/{}/latest/target-redirect/{current_target}/{inner_path}",
+                        krate.metadata.name,
+                    ),
+                    permalink_path: format!(
+                        "/{}/{}/{}",
+                        krate.metadata.name, latest_version, inner_path
+                    ),
+                    latest_version: latest_version.to_string(),
+                    target: if target.is_empty() {
+                        target
+                    } else {
+                        format!("{target}/")
+                    },
+                    inner_path,
+                    is_latest_version: latest_version == krate.version,
+                    is_latest_url: false,
+                    is_prerelease: !krate.version.pre.is_empty(),
+                    metadata: krate.metadata.clone(),
+                    krate: krate.clone(),
+                    current_target,
+                };
+                let mut ctx =
+                    tera::Context::from_serialize(page).context("error creating tera context")?;
+                ctx.insert("DEFAULT_MAX_TARGETS", &crate::DEFAULT_MAX_TARGETS);
+
+                let html = std::fs::read(entry.path())?;
+                let html = utils::rewrite_lol(&html, max_parse_memory, ctx, templates)
+                    .with_context(|| format!("error rewriting {}", relative.display()))?;
+                std::fs::write(entry.path(), html)?;
+                count += 1;
+            }
+            Ok(count)
+        })
+        .await
+}
+
 /// Checks whether the given path exists.
 /// The crate's `target_name` is used to confirm whether a platform triple is part of the path.
 ///
@@ -2747,4 +2836,40 @@ mod test {
             Ok(())
         })
     }
+
+    #[test]
+    fn apply_docsrs_theme_to_local_docs() {
+        async_wrapper(|env| async move {
+            env.async_fake_release()
+                .await
+                .name("dummy")
+                .version("0.1.0")
+                .create_async()
+                .await?;
+
+            let dir = tempfile::tempdir()?;
+            std::fs::create_dir(dir.path().join("dummy"))?;
+            std::fs::write(
+                dir.path().join("dummy/index.html"),
+                "<html><head></head><body><p>dummy docs</p></body></html>",
+            )?;
+            std::fs::write(dir.path().join("dummy/search-index.js"), "var x = 1;")?;
+
+            let mut conn = env.async_db().await.async_conn().await;
+            let count =
+                super::apply_docsrs_theme(&mut conn, &env.config(), "dummy", "0.1.0", dir.path())
+                    .await?;
+            assert_eq!(count, 1);
+
+            let html = std::fs::read_to_string(dir.path().join("dummy/index.html"))?;
+            assert!(html.contains("id=\"crate-title\""));
+            assert!(html.contains("dummy docs"));
+            assert_eq!(
+                std::fs::read_to_string(dir.path().join("dummy/search-index.js"))?,
+                "var x = 1;"
+            );
+
+            Ok(())
+        })
+    }
 }
@@ -10,11 +10,16 @@ use crate::{
     },
     Config,
 };
+use anyhow::Result;
 use axum::{extract::Extension, http::StatusCode, response::IntoResponse};
 use chrono::{TimeZone, Utc};
 use futures_util::stream::TryStreamExt;
 use serde::Serialize;
 use std::sync::Arc;
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+};
 
 /// sitemap index
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -96,6 +101,107 @@ pub(crate) async fn sitemap_handler(
     Ok(SitemapXml { releases })
 }
 
+/// The maximum number of URLs in a single sitemap file, from
+/// <https://www.sitemaps.org/protocol.html>.
+const MAX_SITEMAP_URLS: usize = 50_000;
+
+/// Writes sitemaps of the documentation roots of all successfully documented releases into
+/// `dir`, split into `sitemap-1.xml`, `sitemap-2.xml`, … with at most 50,000 URLs each,
+/// and a `sitemap-index.xml` listing them under `base_url`.
+///
+/// The releases are streamed from the database, so they are never all loaded into memory.
+/// Returns the number of written URLs.
+pub async fn write_sitemap(
+    conn: &mut sqlx::PgConnection,
+    dir: &std::path::Path,
+    base_url: &str,
+) -> Result<usize> {
+    write_sitemap_files(conn, dir, base_url, MAX_SITEMAP_URLS).await
+}
+
+async fn write_sitemap_files(
+    conn: &mut sqlx::PgConnection,
+    dir: &std::path::Path,
+    base_url: &str,
+    max_urls_per_file: usize,
+) -> Result<usize> {
+    async fn finish_sitemap(mut writer: BufWriter<File>) -> Result<()> {
+        writer.write_all(b"</urlset>\n").await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    tokio::fs::create_dir_all(dir).await?;
+
+    let mut releases = sqlx::query!(
+        "SELECT crates.name, releases.version, releases.target_name, releases.release_time
+         FROM crates
+         INNER JOIN releases ON releases.crate_id = crates.id
+         WHERE releases.rustdoc_status = true
+         ORDER BY crates.name, releases.id",
+    )
+    .fetch(&mut *conn);
+
+    let mut sitemaps = Vec::new();
+    let mut writer = None;
+    let mut count = 0;
+    while let Some(row) = releases.try_next().await? {
+        let (name, version) = (row.name, row.version);
+        let Some(target_name) = row.target_name else {
+            continue;
+        };
+
+        if count % max_urls_per_file == 0 {
+            if let Some(full) = writer.take() {
+                finish_sitemap(full).await?;
+            }
+
+            let file_name = format!("sitemap-{}.xml", sitemaps.len() + 1);
+            let mut file = BufWriter::new(File::create(dir.join(&file_name)).await?);
+            file.write_all(
+                b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                  <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+            )
+            .await?;
+            sitemaps.push(file_name);
+            writer = Some(file);
+        }
+        let writer = writer.as_mut().expect("opened a sitemap above");
+
+        let mut url = format!(
+            "    <url>\n        <loc>https://docs.rs/{name}/{version}/{target_name}/</loc>\n"
+        );
+        if let Some(release_time) = row.release_time {
+            url.push_str(&format!(
+                "        <lastmod>{}</lastmod>\n",
+                release_time.format("%+")
+            ));
+        }
+        url.push_str("    </url>\n");
+
+        writer.write_all(url.as_bytes()).await?;
+        count += 1;
+    }
+    if let Some(writer) = writer {
+        finish_sitemap(writer).await?;
+    }
+
+    let mut index = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for file_name in &sitemaps {
+        index.push_str(&format!(
+            "    <sitemap>\n        <loc>{}/{file_name}</loc>\n    </sitemap>\n",
+            base_url.trim_end_matches('/')
+        ));
+    }
+    index.push_str("</sitemapindex>\n");
+    tokio::fs::write(dir.join("sitemap-index.xml"), index).await?;
+
+    Ok(count)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 struct AboutBuilds {
     /// The current version of rustc that docs.rs is using to build crates
@@ -164,9 +270,93 @@ pub(crate) async fn about_handler(subpage: Option<Path<String>>) -> AxumResult<i
 
 #[cfg(test)]
 mod tests {
-    use crate::test::{assert_success, wrapper};
+    use super::{write_sitemap, write_sitemap_files};
+    use crate::test::{assert_success, async_wrapper, wrapper};
     use reqwest::StatusCode;
 
+    #[test]
+    fn export_sitemap() {
+        async_wrapper(|env| async move {
+            env.async_fake_release()
+                .await
+                .name("foo")
+                .version("0.1.0")
+                .create_async()
+                .await?;
+            env.async_fake_release()
+                .await
+                .name("foo")
+                .version("0.2.0")
+                .create_async()
+                .await?;
+            env.async_fake_release()
+                .await
+                .name("failed")
+                .version("0.1.0")
+                .build_result_failed()
+                .create_async()
+                .await?;
+
+            let mut conn = env.async_db().await.async_conn().await;
+            let dir = tempfile::tempdir()?;
+            assert_eq!(
+                write_sitemap(&mut conn, dir.path(), "https://docs.rs").await?,
+                2
+            );
+
+            let sitemap = std::fs::read_to_string(dir.path().join("sitemap-1.xml"))?;
+            assert!(sitemap.starts_with("<?xml"));
+            assert!(sitemap.contains("<loc>https://docs.rs/foo/0.1.0/foo/</loc>"));
+            assert!(sitemap.contains("<loc>https://docs.rs/foo/0.2.0/foo/</loc>"));
+            assert!(!sitemap.contains("failed"));
+            assert!(sitemap.ends_with("</urlset>\n"));
+
+            let index = std::fs::read_to_string(dir.path().join("sitemap-index.xml"))?;
+            assert!(index.contains("<loc>https://docs.rs/sitemap-1.xml</loc>"));
+            assert!(!index.contains("sitemap-2.xml"));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn export_sitemap_split_into_files() {
+        async_wrapper(|env| async move {
+            for version in ["0.1.0", "0.2.0", "0.3.0"] {
+                env.async_fake_release()
+                    .await
+                    .name("foo")
+                    .version(version)
+                    .create_async()
+                    .await?;
+            }
+
+            let mut conn = env.async_db().await.async_conn().await;
+            let dir = tempfile::tempdir()?;
+            assert_eq!(
+                write_sitemap_files(&mut conn, dir.path(), "https://example.com/sitemaps/", 2)
+                    .await?,
+                3
+            );
+
+            let first = std::fs::read_to_string(dir.path().join("sitemap-1.xml"))?;
+            assert!(first.contains("/foo/0.1.0/foo/"));
+            assert!(first.contains("/foo/0.2.0/foo/"));
+            assert!(first.ends_with("</urlset>\n"));
+            let second = std::fs::read_to_string(dir.path().join("sitemap-2.xml"))?;
+            assert!(second.contains("/foo/0.3.0/foo/"));
+            assert!(second.ends_with("</urlset>\n"));
+            assert!(!dir.path().join("sitemap-3.xml").exists());
+
+            let index = std::fs::read_to_string(dir.path().join("sitemap-index.xml"))?;
+            assert!(index.contains("<loc>https://example.com/sitemaps/sitemap-1.xml</loc>"));
+            assert!(index.contains("<loc>https://example.com/sitemaps/sitemap-2.xml</loc>"));
+            assert!(index.ends_with("</sitemapindex>\n"));
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn sitemap_index() {
         wrapper(|env| {
@@ -204,14 +204,17 @@ pub(crate) async fn source_browser_handler(
     let row = sqlx::query!(
         "SELECT
             releases.archive_storage,
-            (
-                SELECT id
-                FROM builds
-                WHERE
-                    builds.rid = releases.id AND
-                    builds.build_status = 'success'
-                ORDER BY build_time DESC
-                LIMIT 1
+            GREATEST(
+                (
+                    SELECT id
+                    FROM builds
+                    WHERE
+                        builds.rid = releases.id AND
+                        builds.build_status = 'success'
+                    ORDER BY build_time DESC
+                    LIMIT 1
+                ),
+                releases.archive_index_regeneration
             ) AS latest_build_id
          FROM releases
          INNER JOIN crates ON releases.crate_id = crates.id